@@ -0,0 +1,77 @@
+//! Rendering and storing a long list, with `cargo bench`.
+//!
+//! The bot is a binary only, so it is compiled in here as it is, with the
+//! benchmarks as the entry point instead of its `main`.
+
+#![no_main]
+#![allow(dead_code, unused_imports)]
+
+include!("../src/main.rs");
+
+use criterion::{criterion_group, BenchmarkId, Criterion};
+
+/// A household list with sections, checked and assigned items and recipes.
+fn long_list(items: usize) -> Data {
+    let mut data = Data::default();
+    for (id, name) in [(1, "Anna"), (2, "Ben")].iter() {
+        data.users.insert(*id, KnownUser { first_name: name.to_string(), username: None, profile: Default::default() });
+    }
+    for i in 0..items {
+        if i % 25 == 0 {
+            let mut header = Item::new(format!("Abschnitt {}", i / 25));
+            header.header = true;
+            data.list.items.push(header);
+        }
+        let mut item = Item::new(format!("Artikel {}", i));
+        item.id = i as u64 + 1;
+        item.done = i % 3 == 0;
+        item.quantity = 1 + (i % 4) as u32;
+        item.assignee = Some(1 + (i % 2) as i64).filter(|_| i % 5 == 0);
+        item.category = Some(spending::CATEGORIES[i % spending::CATEGORIES.len()]);
+        data.list.items.push(item);
+    }
+    for i in 0..items / 2 {
+        let ingredients = (0..8).map(|j| format!("Zutat {}", i * 8 + j)).collect();
+        data.list.recipes.insert(format!("Rezept {}", i), Recipe::new(ingredients));
+    }
+    data
+}
+
+fn render(c: &mut Criterion) {
+    for items in [50, 500].iter() {
+        let data = long_list(*items);
+        c.bench_with_input(BenchmarkId::new("list text", items), &data, |b, data| {
+            b.iter(|| data.get_shopping_list_message_text())
+        });
+        c.bench_with_input(BenchmarkId::new("list markup", items), &data, |b, data| {
+            b.iter(|| data.get_list_markup())
+        });
+        let mut grid = data.clone();
+        grid.settings.columns = 3;
+        c.bench_with_input(BenchmarkId::new("list markup, 3 columns", items), &grid, |b, data| {
+            b.iter(|| data.get_list_markup())
+        });
+    }
+}
+
+fn serialization(c: &mut Criterion) {
+    for items in [50, 500].iter() {
+        let data = long_list(*items);
+        let json = serde_json::to_string(&data).unwrap();
+        c.bench_with_input(BenchmarkId::new("serialize", items), &data, |b, data| {
+            b.iter(|| serde_json::to_string(data).unwrap())
+        });
+        c.bench_with_input(BenchmarkId::new("deserialize", items), &json, |b, json| {
+            b.iter(|| serde_json::from_str::<Data>(json).unwrap())
+        });
+    }
+}
+
+criterion_group!(benches, render, serialization);
+
+#[export_name = "main"]
+extern "C" fn bench_main(_argc: i32, _argv: *const *const u8) -> i32 {
+    benches();
+    Criterion::default().configure_from_args().final_summary();
+    0
+}
@@ -0,0 +1,11 @@
+fn main() {
+    // The gRPC service is generated from its proto file, with a bundled protoc
+    #[cfg(feature = "grpc")]
+    {
+        std::env::set_var("PROTOC", protoc_bin_vendored::protoc_bin_path().expect("No bundled protoc for this platform"));
+        tonic_build::configure()
+            .build_client(false)
+            .compile(&["proto/shopping_list.proto"], &["proto"])
+            .expect("Couldn't compile proto/shopping_list.proto");
+    }
+}
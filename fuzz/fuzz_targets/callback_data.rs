@@ -0,0 +1,13 @@
+//! Callback data of a button, which clients can send with any content.
+
+#![no_main]
+
+use libfuzzer_sys::fuzz_target;
+use shopping_bot_fuzz::callback::Action;
+
+fuzz_target!(|data: &str| {
+    if let Some(action) = Action::decode(data) {
+        // What is understood has to survive the trip through a button
+        assert_eq!(Action::decode(&action.encode()), Some(action));
+    }
+});
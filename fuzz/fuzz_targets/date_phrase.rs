@@ -0,0 +1,16 @@
+//! Due dates like "morgen abend" or "in 2 Tagen".
+
+#![no_main]
+
+use chrono::{NaiveDate, TimeZone};
+use chrono_tz::Europe::Berlin;
+use libfuzzer_sys::fuzz_target;
+use shopping_bot_fuzz::date_phrase;
+
+fuzz_target!(|text: &str| {
+    let today = NaiveDate::from_ymd_opt(2024, 2, 29).unwrap();
+    let now = Berlin.from_local_datetime(&today.and_hms_opt(23, 59, 0).unwrap()).unwrap();
+    date_phrase::parse(text, now);
+    date_phrase::parse_day(text, today);
+    date_phrase::parse_weekday(text);
+});
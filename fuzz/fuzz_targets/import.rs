@@ -0,0 +1,13 @@
+//! Files sent for `/import` and lists pasted as text.
+
+#![no_main]
+
+use libfuzzer_sys::fuzz_target;
+use shopping_bot_fuzz::import;
+
+fuzz_target!(|content: &str| {
+    for name in ["bring", "anylist", "keep"].iter() {
+        let _ = import::convert(import::Format::parse(name).unwrap(), content);
+    }
+    import::pasted(content);
+});
@@ -0,0 +1,13 @@
+//! New items as typed, like "3x Milch" or "2*500g Mehl".
+
+#![no_main]
+
+use libfuzzer_sys::fuzz_target;
+use shopping_bot_fuzz::{i18n, normalize};
+
+fuzz_target!(|text: &str| {
+    normalize::quantity(text);
+    for language in i18n::LANGUAGES.iter() {
+        normalize::item(text, *language);
+    }
+});
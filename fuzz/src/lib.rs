@@ -0,0 +1,28 @@
+//! The parsers of the bot, for the fuzz targets. The bot is a binary only, so
+//! its modules that parse user input are compiled in here as they are. They
+//! must not panic on any input, since a panic takes down the handler.
+//!
+//! Run a target with `cargo fuzz run quantity` from the repository root.
+
+#![allow(dead_code)]
+
+#[path = "../../src/callback.rs"]
+pub mod callback;
+#[path = "../../src/config.rs"]
+pub mod config;
+#[path = "../../src/date_phrase.rs"]
+pub mod date_phrase;
+#[path = "../../src/date_picker.rs"]
+pub mod date_picker;
+#[path = "../../src/features.rs"]
+pub mod features;
+#[path = "../../src/i18n.rs"]
+pub mod i18n;
+#[path = "../../src/import.rs"]
+pub mod import;
+#[path = "../../src/normalize.rs"]
+pub mod normalize;
+#[path = "../../src/search.rs"]
+pub mod search;
+#[path = "../../src/units.rs"]
+pub mod units;
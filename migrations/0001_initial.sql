@@ -0,0 +1,77 @@
+-- Tables of the postgres-storage feature, see src/postgres.rs. Each list is
+-- identified by the name of the data file it replaces. The columns besides
+-- `record` are copies for reporting in SQL, the bot reads the records.
+
+CREATE TABLE lists (
+    list TEXT PRIMARY KEY,
+    -- Bumped with every write, replicas only write on top of the version they read
+    version BIGINT NOT NULL,
+    updated TIMESTAMPTZ NOT NULL DEFAULT now(),
+    -- Everything not kept in the tables below
+    data JSONB NOT NULL
+);
+
+CREATE TABLE settings (
+    list TEXT NOT NULL REFERENCES lists ON DELETE CASCADE,
+    name TEXT NOT NULL,
+    value JSONB NOT NULL,
+    PRIMARY KEY (list, name)
+);
+
+-- Language, timezone and the other settings of each chat
+CREATE TABLE chats (
+    list TEXT NOT NULL REFERENCES lists ON DELETE CASCADE,
+    chat_id BIGINT NOT NULL,
+    state JSONB NOT NULL,
+    PRIMARY KEY (list, chat_id)
+);
+
+CREATE TABLE items (
+    list TEXT NOT NULL REFERENCES lists ON DELETE CASCADE,
+    position INTEGER NOT NULL,
+    id BIGINT NOT NULL,
+    name TEXT NOT NULL,
+    done BOOLEAN NOT NULL,
+    header BOOLEAN NOT NULL,
+    quantity INTEGER NOT NULL,
+    assignee BIGINT,
+    due TIMESTAMPTZ,
+    price BIGINT,
+    category TEXT,
+    record JSONB NOT NULL,
+    PRIMARY KEY (list, position)
+);
+
+CREATE TABLE recipes (
+    list TEXT NOT NULL REFERENCES lists ON DELETE CASCADE,
+    name TEXT NOT NULL,
+    PRIMARY KEY (list, name)
+);
+
+CREATE TABLE recipe_ingredients (
+    list TEXT NOT NULL,
+    recipe TEXT NOT NULL,
+    position INTEGER NOT NULL,
+    ingredient TEXT NOT NULL,
+    PRIMARY KEY (list, recipe, position),
+    FOREIGN KEY (list, recipe) REFERENCES recipes ON DELETE CASCADE
+);
+
+-- Items that left the list: bought, or removed without buying them
+CREATE TABLE history (
+    list TEXT NOT NULL REFERENCES lists ON DELETE CASCADE,
+    position INTEGER NOT NULL,
+    name TEXT NOT NULL,
+    at TIMESTAMPTZ NOT NULL,
+    event TEXT NOT NULL,
+    bought_by BIGINT,
+    quantity INTEGER NOT NULL,
+    price BIGINT,
+    category TEXT,
+    recipe TEXT,
+    record JSONB NOT NULL,
+    PRIMARY KEY (list, position)
+);
+
+CREATE INDEX history_at ON history (list, at);
+CREATE INDEX history_name ON history (list, lower(name));
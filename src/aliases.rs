@@ -0,0 +1,79 @@
+//! Slash commands of a chat's own, set by admins with `/alias`:
+//! `/alias wocheneinkauf template Wocheneinkauf` adds what is missing of the
+//! template with `/wocheneinkauf`, `/alias milch add Milch` puts Milch on the
+//! list with `/milch`. Built-in commands win over an alias of the same name.
+//!
+//! The aliases show up in the command menu of the bot. The menu is the same in
+//! every chat of the bot, so it has the aliases of all its chats.
+
+use std::collections::HashMap;
+
+use serde::{Deserialize, Serialize};
+use teloxide::types::BotCommand;
+
+/// Longest command Telegram accepts.
+const MAX_NAME_LENGTH: usize = 32;
+/// Longest description of a command in the menu.
+const MAX_DESCRIPTION_LENGTH: usize = 256;
+
+#[derive(Serialize, Deserialize, Clone, PartialEq)]
+#[serde(rename_all = "snake_case")]
+pub enum Target {
+    /// Adds the items of the template that aren't on the list.
+    Template(String),
+    /// Adds an item, or the ingredients of a recipe.
+    Add(String),
+}
+
+impl Target {
+    pub fn parse(kind: &str, value: String) -> Option<Self> {
+        match kind {
+            "template" => Some(Target::Template(value)),
+            "add" => Some(Target::Add(value)),
+            _ => None,
+        }
+    }
+
+    pub fn describe(&self) -> String {
+        match self {
+            Target::Template(name) => format!("Vorlage {} übernehmen", name),
+            Target::Add(item) => format!("{} hinzufügen", item),
+        }
+    }
+}
+
+/// Whether Telegram takes the name as a command: lowercase letters, digits and underscores.
+pub fn valid_name(name: &str) -> bool {
+    !name.is_empty()
+        && name.len() <= MAX_NAME_LENGTH
+        && name.chars().all(|c| c.is_ascii_lowercase() || c.is_ascii_digit() || c == '_')
+}
+
+/// The aliases of one chat for `/alias`, sorted by name.
+pub fn describe(aliases: Option<&HashMap<String, Target>>) -> String {
+    let mut aliases: Vec<(&String, &Target)> = aliases.into_iter().flatten().collect();
+    if aliases.is_empty() {
+        return "Noch keine eigenen Befehle, z.B. /alias milch add Milch".to_string();
+    }
+    aliases.sort_by_key(|(name, _)| *name);
+    aliases.iter().fold("Eigene Befehle:".to_string(), |text, (name, target)| {
+        format!("{}\n/{}: {}", text, name, target.describe())
+    })
+}
+
+/// The command menu with the aliases of every chat, the first one wins if two chats use the same name.
+pub fn menu(aliases: &HashMap<i64, HashMap<String, Target>>) -> Vec<BotCommand> {
+    let mut chats: Vec<&i64> = aliases.keys().collect();
+    chats.sort();
+    let mut commands: Vec<BotCommand> = Vec::new();
+    for chat_id in chats {
+        for (name, target) in aliases[chat_id].iter() {
+            if !commands.iter().any(|command| &command.command == name) {
+                let description: String = target.describe().chars().take(MAX_DESCRIPTION_LENGTH).collect();
+                commands.push(BotCommand::new(name.clone(), description));
+            }
+        }
+    }
+    commands.sort_by(|a, b| a.command.cmp(&b.command));
+    commands
+}
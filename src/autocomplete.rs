@@ -0,0 +1,45 @@
+//! Suggestions for `@bot mi…` inline queries, so items are spelled the same
+//! every time and dedup and statistics keep working.
+//!
+//! Inline mode has to be enabled for the bot with BotFather's `/setinline`.
+
+use std::collections::HashMap;
+
+use crate::history::Purchase;
+use crate::{seasons, synonyms};
+
+/// Telegram shows at most 50 results.
+const MAX_SUGGESTIONS: usize = 20;
+
+/// Names starting with the query before names containing it, the ones bought
+/// most often first, then the dictionary. With how often each was bought.
+pub fn suggestions(query: &str, history: &[Purchase]) -> Vec<(String, usize)> {
+    let query = query.trim().to_lowercase();
+    let mut counts: HashMap<String, (String, usize)> = HashMap::new();
+    for purchase in history.iter().filter(|purchase| purchase.bought()) {
+        let entry = counts.entry(purchase.name.to_lowercase()).or_insert_with(|| (purchase.name.clone(), 0));
+        entry.1 += 1;
+    }
+    let dictionary = synonyms::words().chain(seasons::produce_names()).map(capitalize);
+    for name in dictionary {
+        counts.entry(name.to_lowercase()).or_insert((name, 0));
+    }
+    let mut suggestions: Vec<(bool, String, usize)> = counts.into_iter()
+        .filter(|(key, _)| key.contains(&query))
+        .map(|(key, (name, count))| (!key.starts_with(&query), name, count))
+        .collect();
+    suggestions.sort_by(|a, b| a.0.cmp(&b.0).then(b.2.cmp(&a.2)).then(a.1.cmp(&b.1)));
+    suggestions.into_iter()
+        .take(MAX_SUGGESTIONS)
+        .map(|(_, name, count)| (name, count))
+        .collect()
+}
+
+/// The dictionary is lowercase, items are nouns.
+fn capitalize(word: &str) -> String {
+    let mut chars = word.chars();
+    match chars.next() {
+        Some(first) => first.to_uppercase().chain(chars).collect(),
+        None => String::new(),
+    }
+}
@@ -0,0 +1,88 @@
+//! Scripts in Rhai that admins add with `/script`, run for every change to the
+//! list, see `webhooks::Event`. A script sees `event` ("item_added", …), `item`
+//! and `open`, the number of open items, and changes the list with `add`,
+//! `check` and `remove`:
+//!
+//! `if event == "item_added" && item == "Bier" { add("Chips") }`
+//! `if event == "item_added" && open > 40 { remove(item) }`
+//!
+//! Scripts can't reach anything but the list, and their operations are limited.
+
+use std::cell::RefCell;
+use std::rc::Rc;
+
+use rhai::{Engine, Scope, AST};
+use serde::{Deserialize, Serialize};
+
+use crate::webhooks;
+
+/// Operations a script may take for one event, like loop iterations and calls.
+const MAX_OPERATIONS: u64 = 10_000;
+/// Changes all scripts may make for one store of the data.
+const MAX_EFFECTS: usize = 20;
+
+#[derive(Serialize, Deserialize, Clone)]
+pub struct Script {
+    pub name: String,
+    pub source: String,
+}
+
+/// A change to the list a script asked for.
+pub enum Effect {
+    Add(String),
+    Check(String),
+    Remove(String),
+}
+
+fn engine(effects: Rc<RefCell<Vec<Effect>>>) -> Engine {
+    let mut engine = Engine::new();
+    engine.set_max_operations(MAX_OPERATIONS);
+    engine.set_max_call_levels(16);
+    engine.set_max_expr_depths(32, 16);
+    engine.set_max_string_size(1000);
+    engine.set_max_array_size(100);
+    engine.set_max_map_size(100);
+    engine.disable_symbol("eval");
+    engine.on_print(|text| log::info!("Script: {}", text));
+    engine.on_debug(|text, _, _| log::debug!("Script: {}", text));
+    let add = effects.clone();
+    engine.register_fn("add", move |item: &str| add.borrow_mut().push(Effect::Add(item.to_string())));
+    let check = effects.clone();
+    engine.register_fn("check", move |item: &str| check.borrow_mut().push(Effect::Check(item.to_string())));
+    engine.register_fn("remove", move |item: &str| effects.borrow_mut().push(Effect::Remove(item.to_string())));
+    engine
+}
+
+/// Whether the source compiles, the error otherwise.
+pub fn compile(source: &str) -> Result<(), String> {
+    engine(Rc::default()).compile(source).map(|_| ()).map_err(|error| error.to_string())
+}
+
+/// Runs every script for every event, returns the changes they asked for.
+pub fn run(scripts: &[Script], events: &[webhooks::Event], open: usize) -> Vec<Effect> {
+    let effects: Rc<RefCell<Vec<Effect>>> = Rc::default();
+    let engine = engine(effects.clone());
+    let compiled: Vec<(&str, AST)> = scripts.iter()
+        .filter_map(|script| match engine.compile(&script.source) {
+            Ok(ast) => Some((script.name.as_str(), ast)),
+            Err(error) => {
+                log::warn!("Script {} doesn't compile: {}", script.name, error);
+                None
+            }
+        })
+        .collect();
+    for event in events {
+        for (name, ast) in compiled.iter() {
+            let mut scope = Scope::new();
+            scope.push_constant("event", event.name().to_string());
+            scope.push_constant("item", event.subject().to_string());
+            scope.push_constant("open", open as i64);
+            if let Err(error) = engine.run_ast_with_scope(&mut scope, ast) {
+                log::warn!("Script {} failed on {}: {}", name, event.name(), error);
+            }
+        }
+    }
+    let mut effects = effects.take();
+    effects.truncate(MAX_EFFECTS);
+    effects
+}
@@ -0,0 +1,96 @@
+//! Changes an integration recorded while it was offline, POSTed as a batch to
+//! `/api/<token>/batch` with the token from `/api`:
+//!
+//! `{"operations": [{"id": "a1", "at": "2021-06-01T10:00:00Z", "kind": "add", "item": "Milch"}]}`
+//!
+//! Operations are applied in the order of their time and each ID only once, so
+//! a client can send a batch again until it got an answer. They go through the
+//! same log of changes as everything done in Telegram, see `storage`.
+
+use chrono::{DateTime, Utc};
+use serde::{Deserialize, Serialize};
+use teloxide::prelude::*;
+use utoipa::ToSchema;
+
+use crate::{store_data, Instance};
+
+/// Operation IDs remembered to skip repeated ones.
+pub const REMEMBERED_IDS: usize = 1000;
+/// Upper bound of a batch, in bytes.
+pub const MAX_BYTES: u64 = 256 * 1024;
+
+#[derive(Deserialize, Clone, Copy, PartialEq, Debug, ToSchema)]
+#[serde(rename_all = "lowercase")]
+pub enum Kind {
+    Add,
+    Check,
+    Uncheck,
+    Delete,
+}
+
+#[derive(Deserialize, ToSchema)]
+pub struct Operation {
+    /// Chosen by the client, unique per operation.
+    pub id: String,
+    pub at: DateTime<Utc>,
+    pub kind: Kind,
+    pub item: String,
+    #[serde(default)]
+    pub quantity: Option<u32>,
+}
+
+impl Operation {
+    /// An operation of an integration that is online, like Signal or gRPC, which isn't sent again.
+    pub fn now(kind: Kind, item: &str) -> Self {
+        Operation { id: String::new(), at: Utc::now(), kind, item: item.to_string(), quantity: None }
+    }
+}
+
+#[derive(Deserialize, ToSchema)]
+pub struct Batch {
+    pub operations: Vec<Operation>,
+}
+
+/// What became of a batch, the answer to the client.
+#[derive(Serialize, Default, ToSchema)]
+pub struct Outcome {
+    pub applied: usize,
+    /// Sent before.
+    pub repeated: usize,
+    /// IDs of operations that no longer fit the list, like checking an item that is gone.
+    pub skipped: Vec<String>,
+    /// Operations over the limits of the list, like an item that is too long.
+    #[serde(skip_serializing_if = "Vec::is_empty")]
+    pub rejected: Vec<Rejected>,
+}
+
+#[derive(Serialize, ToSchema)]
+pub struct Rejected {
+    pub id: String,
+    pub reason: String,
+}
+
+/// Applies operations of an integration that is online and shows the changes
+/// in the list message. Returns the operations that fit the list, and why
+/// those over its limits were rejected.
+pub async fn apply(bot: &Bot, instance: &Instance, operations: Vec<Operation>) -> (Vec<Operation>, Vec<String>) {
+    let mut applied = Vec::new();
+    let mut rejected = Vec::new();
+    {
+        let mut data = instance.data.lock().await;
+        for operation in operations {
+            match data.apply_operation(&operation) {
+                Ok(true) => applied.push(operation),
+                Ok(false) => {}
+                Err(error) => rejected.push(format!("{}: {}", operation.item, error)),
+            }
+        }
+        if !applied.is_empty() {
+            data.show_outside_change(bot, "an integration").await;
+        }
+    }
+    if !applied.is_empty() {
+        store_data(instance).await;
+    }
+    (applied, rejected)
+}
@@ -0,0 +1,30 @@
+use std::time::Duration;
+
+use teloxide::prelude::*;
+use teloxide::types::ChatId;
+
+use crate::ENVIRONMENT;
+use crate::outbox::SendQueued;
+
+/// Stays well below Telegram's limit of 30 messages per second.
+pub const PACE: Duration = Duration::from_millis(100);
+
+/// Sends `text` to every chat one after another and reports the result to `reply_chat`.
+pub async fn send(bot: Bot, chats: Vec<i64>, text: String, reply_chat: i64) {
+    let mut delivered = 0;
+    let mut failed = 0;
+    for chat_id in chats.iter() {
+        match bot.send_message(ChatId::Id(*chat_id), ENVIRONMENT.decorate(text.clone())).send_queued(&bot).await {
+            Ok(_) => delivered += 1,
+            Err(error) => {
+                log::warn!("Broadcast to {} failed: {:?}", chat_id, error);
+                failed += 1;
+            }
+        }
+        tokio::time::sleep(PACE).await;
+    }
+    let report = format!("Rundnachricht an {} Chats gesendet, {} fehlgeschlagen.", delivered, failed);
+    if let Err(error) = bot.send_message(ChatId::Id(reply_chat), ENVIRONMENT.decorate(report)).send_queued(&bot).await {
+        log::error!("Couldn't report broadcast result: {:?}", error);
+    }
+}
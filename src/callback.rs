@@ -0,0 +1,374 @@
+//! Compact callback data for inline buttons.
+//!
+//! Telegram limits callback data to 64 bytes, so actions are encoded as a short
+//! code followed by a number, e.g. `t3` to toggle the fourth item. Recipes and
+//! templates are referenced by an ID from the `Registry` instead of their name.
+
+use std::collections::HashMap;
+
+use serde::{Deserialize, Serialize};
+use teloxide::types::InlineKeyboardButton;
+use teloxide::types::InlineKeyboardButtonKind::CallbackData;
+
+use crate::date_picker;
+
+pub const STALE_TEXT: &str = "Dieser Knopf ist veraltet.";
+
+/// Gives recipe and template names short, stable IDs for callback data.
+#[derive(Serialize, Deserialize, Clone, Default)]
+pub struct Registry {
+    names: HashMap<u32, String>,
+    next_id: u32,
+}
+
+impl Registry {
+    pub fn register(&mut self, name: &str) -> u32 {
+        if let Some(id) = self.id(name) {
+            return id;
+        }
+        let id = self.next_id;
+        self.next_id += 1;
+        self.names.insert(id, name.to_string());
+        id
+    }
+
+    /// Forgets the name, so buttons still showing it become stale.
+    pub fn release(&mut self, name: &str) {
+        self.names.retain(|_, registered| registered != name);
+    }
+
+    pub fn id(&self, name: &str) -> Option<u32> {
+        self.names.iter().find(|(_, registered)| *registered == name).map(|(id, _)| *id)
+    }
+
+    pub fn name(&self, id: u32) -> Option<&str> {
+        self.names.get(&id).map(|name| name.as_str())
+    }
+}
+
+#[derive(Debug, PartialEq)]
+pub enum Action {
+    StartRecipe,
+    StartRemove,
+    RecipeDone,
+    /// Adds the ingredients of the recipe being saved to the similar recipe, by its ID in the `Registry`, see `duplicates`.
+    MergeRecipe(u32),
+    /// Saves the ingredients of the recipe being saved as the similar recipe, by its ID in the `Registry`.
+    OverwriteRecipe(u32),
+    /// Saves the recipe next to the similar one, under a new name if it is the same.
+    KeepBothRecipes,
+    /// Checks or unchecks the item with that `id`, so the button still fits after the list changed.
+    ToggleItem(u64),
+    /// Changes the quantity of the item with that `id`, like `ToggleItem`.
+    Increment(u64),
+    Decrement(u64),
+    /// Removes the item with that `id` without checking it, e.g. when it was added by mistake.
+    DeleteItem(u64),
+    /// Hides the item with that `id` until the checked items are cleared.
+    Snooze(u64),
+    /// Gives the wish with that `id` another star, starting over after the last, see `wishes`.
+    WishPriority(u64),
+    /// The store didn't have the item with that `id`, see `stock_out`.
+    NotAvailable(u64),
+    /// Hide or show checked items, staying in the edit view if `list` is set.
+    ToggleView { list: bool },
+    /// Removes the header with that `id`.
+    RemoveHeader(u64),
+    /// Asks for a search, see `search`.
+    Search,
+    ClearFilter,
+    /// Switches the edit view between open and checked items.
+    EditTab { done: bool },
+    RemoveDone,
+    /// Shows the actions on every item at once.
+    ShowBulk,
+    CheckAll,
+    UncheckAll,
+    /// Asks whether to empty the list, `ConfirmClear` does it.
+    ClearList,
+    ConfirmClear,
+    ListRecipes,
+    /// Shows the ingredients of the recipe to leave out what is at home, `AddIngredients` adds the rest.
+    AddRecipe(u32),
+    /// Leaves the ingredient with the index out of the recipe being added, or takes it back in.
+    ExcludeIngredient(usize),
+    AddIngredients,
+    ManageRecipes,
+    /// Moves the recipe out of the recipe buttons, or back in.
+    ArchiveRecipe(u32),
+    /// Asks for a name and copies the recipe, e.g. for a variant.
+    DuplicateRecipe(u32),
+    /// Asks for the servings, preparation time and source of the recipe, see `recipe_info`.
+    EditRecipeInfo(u32),
+    DeleteRecipe(u32),
+    /// Recipe ID and 1 to 5 stars, encoded together as `id * 10 + stars`.
+    Rate(u32, u8),
+    /// Saves a `/discover` result, by its TheMealDB ID.
+    SaveDiscovered(u32),
+    /// Puts the ingredients of a recipe that aren't in the pantry on the list.
+    AddMissing(u32),
+    /// Puts the pantry product at that index on the list, it is running low.
+    RunningLow(usize),
+    /// Deletes the shortcut at that index of the sorted shortcuts, see `shortcuts`.
+    DeleteShortcut(usize),
+    /// Removes the leftover at that index, it was eaten.
+    UseLeftover(usize),
+    /// Replaces the generated meal of that day.
+    SwapMeal(usize),
+    AcceptPlan,
+    DiscardPlan,
+    /// Checks the item with that `id` from a message other than the list.
+    CheckItem(u64),
+    /// Sends the list as plain text in a separate message.
+    ShareText,
+    /// Takes over the items and recipes of an `/import`.
+    ConfirmImport,
+    DiscardImport,
+    /// Adds produce from the `seasons` calendar, by its index.
+    AddSeasonal(usize),
+    /// Step of the `onboarding` dialogue and the chosen option, both single digits.
+    Onboarding(u8, u8),
+    /// Removes what the demo of the `onboarding` added.
+    ClearDemo,
+    /// Shows another page of a message that is too long for Telegram.
+    Page(usize),
+    ListTemplates,
+    SaveTemplate,
+    ApplyTemplate(u32),
+    MergeTemplate(u32),
+    DeleteTemplate(u32),
+    /// Leaves the current view, see `View`.
+    Back,
+    /// Answers to the weekly reset, see `reset`.
+    ResetKeep,
+    ResetDrop,
+    /// Puts the items of a past trip on the list again, by its ID, see `trips`.
+    RepeatTrip(u32),
+    /// Puts the items of the list at a moment on the list again, by the Unix time of the moment, see `timeline`.
+    CopyAsOf(i64),
+    /// Starts timing a trip, or ends it with a summary.
+    TripTimer,
+    /// Turns a kind of mentions on or off for the user, by index into `notifications::KINDS`.
+    ToggleNotification(usize),
+    /// Shows the items that can be moved to another list, see `transfer`.
+    ShowMovable,
+    /// Picks the item with that `id` to move or copy.
+    TransferItem(u64),
+    /// Moves the picked item to the list with that key, see `transfer`.
+    MoveTo(usize),
+    CopyTo(usize),
+    ReturnToMainList,
+    /// Claims the item with that `id` for an event, or gives it back.
+    Claim(u64),
+    /// Adds or drops the suggestion of a restricted user with that `id`.
+    Approve(u64),
+    Reject(u64),
+    /// A button of the date picker, see `date_picker`.
+    DatePicker(date_picker::Picker, date_picker::Step),
+    /// Everything after `p `, the name of a plugin and what it encoded, see `plugins`.
+    Plugin(String),
+}
+
+impl Action {
+    pub fn encode(&self) -> String {
+        match self {
+            Action::StartRecipe => "nr".to_string(),
+            Action::StartRemove => "ls".to_string(),
+            Action::RecipeDone => "rd".to_string(),
+            Action::MergeRecipe(id) => format!("dm{}", id),
+            Action::OverwriteRecipe(id) => format!("do{}", id),
+            Action::KeepBothRecipes => "db".to_string(),
+            // The codes of the buttons by index were `t`, `i`, `d`, `x`, `sz`, `wp`,
+            // `na`, `h`, `pi`, `c`, `ok` and `no`, those are stale now
+            Action::ToggleItem(id) => format!("ti{}", id),
+            Action::Increment(id) => format!("ii{}", id),
+            Action::Decrement(id) => format!("di{}", id),
+            Action::DeleteItem(id) => format!("xi{}", id),
+            Action::Snooze(id) => format!("szi{}", id),
+            Action::WishPriority(id) => format!("wpi{}", id),
+            Action::NotAvailable(id) => format!("nai{}", id),
+            Action::ToggleView { list: true } => "vl".to_string(),
+            Action::ToggleView { list: false } => "vm".to_string(),
+            Action::RemoveHeader(id) => format!("hi{}", id),
+            Action::Search => "fs".to_string(),
+            Action::ClearFilter => "fx".to_string(),
+            Action::EditTab { done: false } => "eo".to_string(),
+            Action::EditTab { done: true } => "ed".to_string(),
+            Action::RemoveDone => "cd".to_string(),
+            Action::ShowBulk => "bu".to_string(),
+            Action::CheckAll => "bc".to_string(),
+            Action::UncheckAll => "bo".to_string(),
+            Action::ClearList => "bx".to_string(),
+            Action::ConfirmClear => "by".to_string(),
+            Action::ListRecipes => "lr".to_string(),
+            Action::AddRecipe(id) => format!("a{}", id),
+            Action::ExcludeIngredient(index) => format!("ie{}", index),
+            Action::AddIngredients => "ia".to_string(),
+            Action::ManageRecipes => "mr".to_string(),
+            Action::ArchiveRecipe(id) => format!("ra{}", id),
+            Action::DuplicateRecipe(id) => format!("rc{}", id),
+            Action::EditRecipeInfo(id) => format!("ri{}", id),
+            Action::DeleteRecipe(id) => format!("rx{}", id),
+            Action::Rate(id, stars) => format!("rt{}", id * 10 + *stars as u32),
+            Action::SaveDiscovered(id) => format!("sd{}", id),
+            Action::AddMissing(id) => format!("am{}", id),
+            Action::RunningLow(index) => format!("pl{}", index),
+            Action::DeleteShortcut(index) => format!("kx{}", index),
+            Action::UseLeftover(index) => format!("lu{}", index),
+            Action::SwapMeal(day) => format!("ws{}", day),
+            Action::AcceptPlan => "wa".to_string(),
+            Action::DiscardPlan => "wd".to_string(),
+            Action::CheckItem(id) => format!("ck{}", id),
+            Action::ShareText => "sx".to_string(),
+            Action::ConfirmImport => "ic".to_string(),
+            Action::DiscardImport => "ix".to_string(),
+            Action::AddSeasonal(index) => format!("se{}", index),
+            Action::Onboarding(step, choice) => format!("ob{}{}", step, choice),
+            Action::ClearDemo => "dx".to_string(),
+            Action::Page(page) => format!("pg{}", page),
+            Action::ListTemplates => "lt".to_string(),
+            Action::SaveTemplate => "st".to_string(),
+            Action::ApplyTemplate(id) => format!("ta{}", id),
+            Action::MergeTemplate(id) => format!("tm{}", id),
+            Action::DeleteTemplate(id) => format!("td{}", id),
+            Action::Back => "bk".to_string(),
+            Action::ResetKeep => "rk".to_string(),
+            Action::ResetDrop => "rz".to_string(),
+            Action::RepeatTrip(id) => format!("rp{}", id),
+            Action::TripTimer => "ts".to_string(),
+            Action::CopyAsOf(timestamp) => format!("ao{}", timestamp),
+            Action::ToggleNotification(index) => format!("nt{}", index),
+            Action::ShowMovable => "mv".to_string(),
+            Action::TransferItem(id) => format!("pii{}", id),
+            // By index into the lists the codes were `mo` and `cp`, those are stale now
+            Action::MoveTo(key) => format!("mt{}", key),
+            Action::CopyTo(key) => format!("ct{}", key),
+            Action::ReturnToMainList => "m".to_string(),
+            Action::Claim(id) => format!("ci{}", id),
+            Action::Approve(id) => format!("oki{}", id),
+            Action::Reject(id) => format!("noi{}", id),
+            Action::DatePicker(picker, step) => format!("dp {}", date_picker::encode(picker, step)),
+            Action::Plugin(rest) => format!("p {}", rest),
+        }
+    }
+
+    /// `None` for data this build doesn't understand, e.g. buttons from before an update.
+    pub fn decode(data: &str) -> Option<Self> {
+        if let Some(rest) = data.strip_prefix("dp ") {
+            let (picker, step) = date_picker::parse(rest)?;
+            return Some(Action::DatePicker(picker, step));
+        }
+        if let Some(rest) = data.strip_prefix("p ") {
+            return Some(Action::Plugin(rest.to_string()));
+        }
+        let code = data.trim_end_matches(|c: char| c.is_ascii_digit());
+        let number = &data[code.len()..];
+        let index = || number.parse::<usize>().ok();
+        let id = || number.parse::<u32>().ok();
+        Some(match code {
+            "nr" => Action::StartRecipe,
+            "ls" => Action::StartRemove,
+            "rd" => Action::RecipeDone,
+            "dm" => Action::MergeRecipe(id()?),
+            "do" => Action::OverwriteRecipe(id()?),
+            "db" => Action::KeepBothRecipes,
+            "ti" => Action::ToggleItem(number.parse().ok()?),
+            "ii" => Action::Increment(number.parse().ok()?),
+            "di" => Action::Decrement(number.parse().ok()?),
+            "xi" => Action::DeleteItem(number.parse().ok()?),
+            "szi" => Action::Snooze(number.parse().ok()?),
+            "wpi" => Action::WishPriority(number.parse().ok()?),
+            "nai" => Action::NotAvailable(number.parse().ok()?),
+            "vl" => Action::ToggleView { list: true },
+            "vm" => Action::ToggleView { list: false },
+            "hi" => Action::RemoveHeader(number.parse().ok()?),
+            "fs" => Action::Search,
+            "fx" => Action::ClearFilter,
+            "eo" => Action::EditTab { done: false },
+            "ed" => Action::EditTab { done: true },
+            "cd" => Action::RemoveDone,
+            "bu" => Action::ShowBulk,
+            "bc" => Action::CheckAll,
+            "bo" => Action::UncheckAll,
+            "bx" => Action::ClearList,
+            "by" => Action::ConfirmClear,
+            "lr" => Action::ListRecipes,
+            "a" => Action::AddRecipe(id()?),
+            "ie" => Action::ExcludeIngredient(index()?),
+            "ia" => Action::AddIngredients,
+            "mr" => Action::ManageRecipes,
+            "ra" => Action::ArchiveRecipe(id()?),
+            "rc" => Action::DuplicateRecipe(id()?),
+            "ri" => Action::EditRecipeInfo(id()?),
+            "rx" => Action::DeleteRecipe(id()?),
+            "rt" => Action::Rate(id()? / 10, (id()? % 10) as u8),
+            "sd" => Action::SaveDiscovered(id()?),
+            "am" => Action::AddMissing(id()?),
+            "pl" => Action::RunningLow(index()?),
+            "kx" => Action::DeleteShortcut(index()?),
+            "lu" => Action::UseLeftover(index()?),
+            "ws" => Action::SwapMeal(index()?),
+            "wa" => Action::AcceptPlan,
+            "wd" => Action::DiscardPlan,
+            "ck" => Action::CheckItem(number.parse().ok()?),
+            "sx" => Action::ShareText,
+            "ic" => Action::ConfirmImport,
+            "ix" => Action::DiscardImport,
+            "se" => Action::AddSeasonal(index()?),
+            "pg" => Action::Page(index()?),
+            "ob" if number.len() == 2 => Action::Onboarding(number[..1].parse().ok()?, number[1..].parse().ok()?),
+            "dx" => Action::ClearDemo,
+            "lt" => Action::ListTemplates,
+            "st" => Action::SaveTemplate,
+            "ta" => Action::ApplyTemplate(id()?),
+            "tm" => Action::MergeTemplate(id()?),
+            "td" => Action::DeleteTemplate(id()?),
+            "bk" => Action::Back,
+            "rk" => Action::ResetKeep,
+            "rz" => Action::ResetDrop,
+            "rp" => Action::RepeatTrip(id()?),
+            "ts" => Action::TripTimer,
+            "ao" => Action::CopyAsOf(number.parse().ok()?),
+            "nt" => Action::ToggleNotification(index()?),
+            "mv" => Action::ShowMovable,
+            "pii" => Action::TransferItem(number.parse().ok()?),
+            "mt" => Action::MoveTo(index()?),
+            "ct" => Action::CopyTo(index()?),
+            "m" => Action::ReturnToMainList,
+            "ci" => Action::Claim(number.parse().ok()?),
+            "oki" => Action::Approve(number.parse().ok()?),
+            "noi" => Action::Reject(number.parse().ok()?),
+            _ => return None,
+        })
+    }
+
+    pub fn button<T: Into<String>>(&self, text: T) -> InlineKeyboardButton {
+        InlineKeyboardButton::new(crate::config::current().label(text.into()), CallbackData(self.encode()))
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn stale_quantity_buttons() {
+        assert_eq!(Action::decode(&Action::Increment(3).encode()), Some(Action::Increment(3)));
+        assert_eq!(Action::decode(&Action::DeleteItem(3).encode()), Some(Action::DeleteItem(3)));
+        // Buttons by index from before and broken data get the stale answer instead of failing
+        assert_eq!(Action::decode("i3"), None);
+        assert_eq!(Action::decode("d3"), None);
+        assert_eq!(Action::decode("ii"), None);
+        assert_eq!(Action::decode("ii99999999999999999999999"), None);
+    }
+
+    #[test]
+    fn stale_buttons_by_position() {
+        for action in [Action::Snooze(3), Action::WishPriority(3), Action::NotAvailable(3), Action::RemoveHeader(3), Action::TransferItem(3), Action::Claim(3), Action::Approve(3), Action::Reject(3)] {
+            assert_eq!(Action::decode(&action.encode()), Some(action));
+        }
+        for data in ["t3", "sz3", "wp3", "na3", "h3", "pi3", "c3", "ok3", "no3"] {
+            assert_eq!(Action::decode(data), None, "{}", data);
+        }
+    }
+}
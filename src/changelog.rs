@@ -0,0 +1,92 @@
+//! What's new, posted once to every chat after the bot was upgraded. Each chat
+//! remembers the last version it was told about; chats that never were, like
+//! new ones, only start counting from the version running now.
+
+use std::sync::Arc;
+
+use teloxide::prelude::*;
+use teloxide::types::ChatId;
+
+use crate::i18n::Language;
+use crate::outbox::SendQueued;
+use crate::{broadcast, store_data, Instance, ENVIRONMENT};
+
+pub const VERSION: &str = env!("CARGO_PKG_VERSION");
+
+struct Release {
+    version: &'static str,
+    german: &'static [&'static str],
+    english: &'static [&'static str],
+}
+
+/// Newest release first, a release is only announced if it has an entry here.
+const RELEASES: &[Release] = &[
+    Release {
+        version: "0.1.0",
+        german: &[
+            "Rezepte haben Portionen, Zubereitungszeit und einen Link zur Quelle, unter ℹ️ beim Verwalten.",
+            "Beim Hinzufügen eines Rezepts lasst ihr weg, was ihr schon da habt.",
+            "Mit /shortcut setzt ein einzelnes Emoji einen Eintrag auf die Liste.",
+        ],
+        english: &[
+            "Recipes have servings, preparation time and a link to their source, under ℹ️ when managing them.",
+            "When adding a recipe, leave out what you have at home already.",
+            "With /shortcut, a single emoji puts an item on the list.",
+        ],
+    },
+];
+
+/// `0.1.0` as numbers, so `0.10.0` comes after `0.9.0`.
+fn parse(version: &str) -> Vec<u32> {
+    version.split('.').map(|part| part.parse().unwrap_or(0)).collect()
+}
+
+/// The changes since the version the chat was told about last, `None` if there are none.
+pub fn news_since(seen: &str, language: Language) -> Option<String> {
+    let (seen, current) = (parse(seen), parse(VERSION));
+    let lines: Vec<String> = RELEASES.iter()
+        .filter(|release| parse(release.version) > seen && parse(release.version) <= current)
+        .flat_map(|release| match language {
+            Language::German => release.german,
+            Language::English => release.english,
+        })
+        .map(|change| format!("• {}", change))
+        .collect();
+    if lines.is_empty() {
+        return None;
+    }
+    let title = match language {
+        Language::German => format!("Neu in Version {}:", VERSION),
+        Language::English => format!("New in version {}:", VERSION),
+    };
+    Some(format!("{}\n{}", title, lines.join("\n")))
+}
+
+/// Tells every chat of the bot what changed since it was last told, one after another.
+pub async fn announce(bot: Bot, instance: Arc<Instance>) {
+    let announcements: Vec<(i64, String)> = {
+        let mut data = instance.data.lock().await;
+        let chats: Vec<i64> = data.known_chats.iter()
+            .copied()
+            // Paused chats hear about it on the first start after the pause
+            .filter(|chat_id| !data.is_paused(*chat_id))
+            .collect();
+        let mut announcements = Vec::new();
+        for chat_id in chats {
+            let seen = data.seen_versions.insert(chat_id, VERSION.to_string());
+            let language = data.languages.get(&chat_id).copied().unwrap_or_default();
+            if let Some(news) = seen.and_then(|seen| news_since(&seen, language)) {
+                announcements.push((chat_id, news));
+            }
+        }
+        announcements
+    };
+    store_data(&instance).await;
+
+    for (chat_id, news) in announcements {
+        if let Err(error) = bot.send_message(ChatId::Id(chat_id), ENVIRONMENT.decorate(news)).send_queued(&bot).await {
+            log::warn!("Couldn't announce version {} to {}: {:?}", VERSION, chat_id, error);
+        }
+        tokio::time::sleep(broadcast::PACE).await;
+    }
+}
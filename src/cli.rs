@@ -0,0 +1,98 @@
+use tokio::io::{AsyncBufReadExt, AsyncWriteExt, BufReader};
+use tokio::net::UnixStream;
+
+use crate::{console, Data, storage};
+
+async fn load_existing(path: &str) -> anyhow::Result<Data> {
+    storage::load(path).await?
+        .ok_or_else(|| anyhow::anyhow!("{} does not exist", path))
+}
+
+/// Writes the data file at `path` to `target`, or to stdout.
+pub async fn export(path: &str, target: Option<&str>) -> anyhow::Result<()> {
+    let data = load_existing(path).await?;
+    match target {
+        Some(target) => storage::save(target, &data).await?,
+        None => {
+            let string = serde_json::to_string_pretty(&data)?;
+            tokio::io::stdout().write_all(string.as_bytes()).await?;
+        }
+    }
+    Ok(())
+}
+
+/// Replaces the data file with `source`, keeping the old one as `.bak`.
+pub async fn import(path: &str, source: &str) -> anyhow::Result<()> {
+    let mut data = load_existing(source).await?;
+    storage::migrate(&mut data);
+    let problems = storage::validate(&data);
+    if !problems.is_empty() {
+        anyhow::bail!("Refusing to import {}:\n{}", source, problems.join("\n"));
+    }
+    if let Some(old) = storage::load(path).await? {
+        let backup = format!("{}.bak", path);
+        storage::save(&backup, &old).await?;
+        println!("Previous data saved to {}", backup);
+    }
+    storage::save(path, &data).await?;
+    println!("Imported {} items and {} recipes", data.list.items.len(), data.list.recipes.len());
+    Ok(())
+}
+
+pub async fn migrate(path: &str) -> anyhow::Result<()> {
+    let mut data = load_existing(path).await?;
+    let steps = storage::migrate(&mut data);
+    if steps.is_empty() {
+        println!("Already at schema version {}", data.version);
+        return Ok(());
+    }
+    for step in steps {
+        println!("{}", step);
+    }
+    storage::save(path, &data).await
+}
+
+pub async fn validate(path: &str) -> anyhow::Result<()> {
+    let data = load_existing(path).await?;
+    let problems = storage::validate(&data);
+    if problems.is_empty() {
+        println!("{} is valid", path);
+        return Ok(());
+    }
+    anyhow::bail!("{} problem(s) found:\n{}", problems.len(), problems.join("\n"))
+}
+
+/// Sends a command to the console of the running bot, see `console`.
+pub async fn admin(namespace: &str, command: &[&str]) -> anyhow::Result<()> {
+    let path = console::socket_path().ok_or_else(|| anyhow::anyhow!("ADMIN_SOCKET is not set"))?;
+    let stream = UnixStream::connect(&path).await
+        .map_err(|error| anyhow::anyhow!("Couldn't connect to {}, is the bot running? {}", path, error))?;
+    let (reader, mut writer) = stream.into_split();
+    let command = match command {
+        [] => "help".to_string(),
+        command => command.join(" "),
+    };
+    writer.write_all(format!("bot {}\n{}\nquit\n", namespace, command).as_bytes()).await?;
+    let mut lines = BufReader::new(reader).lines();
+    // The answer to `bot` comes first
+    let mut answers = 0;
+    let mut failed = false;
+    while let Some(line) = lines.next_line().await? {
+        if line.is_empty() {
+            answers += 1;
+            continue;
+        }
+        if answers == 0 {
+            if line.starts_with("Error: ") {
+                anyhow::bail!("{}", line.trim_start_matches("Error: "));
+            }
+            continue;
+        }
+        failed |= line.starts_with("Error: ");
+        println!("{}", line);
+    }
+    if failed {
+        std::process::exit(1);
+    }
+    Ok(())
+}
@@ -0,0 +1,199 @@
+//! Settings that can change while the bot runs, from the TOML file in
+//! `CONFIG_FILE`. The file is watched and applied again on every change, an
+//! invalid file keeps the previous settings:
+//!
+//! ```toml
+//! log_level = "debug"
+//! nudge_lead_minutes = 45
+//! rating_days = 3
+//! history_retention_months = 24
+//! disabled_commands = ["voice", "mail"]
+//! default_features = ["meal_planner"]
+//!
+//! [labels]
+//! "🧹 Aufräumen" = "Erledigte entfernen"
+//! ```
+//!
+//! Everything that decides how the bot is set up, like its tokens, data files
+//! and addresses, stays in the environment, see `environment`.
+
+use std::collections::{HashMap, HashSet};
+use std::path::{Path, PathBuf};
+use std::sync::{mpsc, Arc, RwLock};
+use std::time::Duration;
+
+use lazy_static::lazy_static;
+use log::LevelFilter;
+use notify::{RecursiveMode, Watcher};
+use serde::Deserialize;
+
+use crate::features::Feature;
+
+/// Editors write a file in several steps, this waits for the last one.
+const SETTLE: Duration = Duration::from_millis(500);
+/// The largest values that make sense, larger ones are likely typos and would
+/// overflow the date calculations.
+const MAX_NUDGE_LEAD_MINUTES: i64 = 7 * 24 * 60;
+const MAX_RATING_DAYS: i64 = 30;
+pub const MAX_HISTORY_RETENTION_MONTHS: u32 = 100 * 12;
+
+#[derive(Deserialize, Clone, PartialEq)]
+#[serde(default, deny_unknown_fields)]
+pub struct Config {
+    /// `off`, `error`, `warn`, `info`, `debug` or `trace`.
+    pub log_level: String,
+    /// How long before a due date an item is nudged.
+    pub nudge_lead_minutes: i64,
+    /// How many days after a planned meal the chat is still asked to rate it.
+    pub rating_days: i64,
+    /// Purchases older than this are folded into monthly summaries, 0 keeps them all, see `history::compact`.
+    pub history_retention_months: u32,
+    /// Commands answered as switched off, without the slash.
+    pub disabled_commands: HashSet<String>,
+    /// Features on in chats an admin didn't turn them on or off for, see `features`.
+    pub default_features: HashSet<Feature>,
+    /// Texts of buttons by the text the bot would show.
+    pub labels: HashMap<String, String>,
+}
+
+impl Default for Config {
+    fn default() -> Self {
+        Config {
+            log_level: "trace".to_string(),
+            nudge_lead_minutes: 30,
+            rating_days: 2,
+            history_retention_months: 12,
+            disabled_commands: HashSet::new(),
+            default_features: HashSet::new(),
+            labels: HashMap::new(),
+        }
+    }
+}
+
+impl Config {
+    fn parse(text: &str) -> anyhow::Result<Self> {
+        let config: Config = toml::from_str(text)?;
+        config.log_level()?;
+        if !(0..=MAX_NUDGE_LEAD_MINUTES).contains(&config.nudge_lead_minutes) {
+            anyhow::bail!("nudge_lead_minutes has to be between 0 and {}", MAX_NUDGE_LEAD_MINUTES);
+        }
+        if !(0..=MAX_RATING_DAYS).contains(&config.rating_days) {
+            anyhow::bail!("rating_days has to be between 0 and {}", MAX_RATING_DAYS);
+        }
+        if config.history_retention_months > MAX_HISTORY_RETENTION_MONTHS {
+            anyhow::bail!("history_retention_months can be {} at most", MAX_HISTORY_RETENTION_MONTHS);
+        }
+        Ok(config)
+    }
+
+    fn log_level(&self) -> anyhow::Result<LevelFilter> {
+        self.log_level.parse().map_err(|_| anyhow::anyhow!("Unknown log_level {:?}", self.log_level))
+    }
+
+    pub fn is_disabled(&self, command: &str) -> bool {
+        self.disabled_commands.contains(command)
+    }
+
+    pub fn label(&self, text: String) -> String {
+        self.labels.get(&text).cloned().unwrap_or(text)
+    }
+
+    /// What changed from `self` to `newer`, for the log.
+    fn changes(&self, newer: &Config) -> Vec<String> {
+        let mut changes = Vec::new();
+        if self.log_level != newer.log_level {
+            changes.push(format!("log_level {} → {}", self.log_level, newer.log_level));
+        }
+        if self.nudge_lead_minutes != newer.nudge_lead_minutes {
+            changes.push(format!("nudge_lead_minutes {} → {}", self.nudge_lead_minutes, newer.nudge_lead_minutes));
+        }
+        if self.rating_days != newer.rating_days {
+            changes.push(format!("rating_days {} → {}", self.rating_days, newer.rating_days));
+        }
+        if self.history_retention_months != newer.history_retention_months {
+            changes.push(format!("history_retention_months {} → {}", self.history_retention_months, newer.history_retention_months));
+        }
+        if self.disabled_commands != newer.disabled_commands {
+            let mut commands: Vec<&String> = newer.disabled_commands.iter().collect();
+            commands.sort();
+            changes.push(format!("disabled_commands → {:?}", commands));
+        }
+        if self.default_features != newer.default_features {
+            let mut features: Vec<&str> = newer.default_features.iter().map(|feature| feature.name()).collect();
+            features.sort_unstable();
+            changes.push(format!("default_features → {:?}", features));
+        }
+        if self.labels != newer.labels {
+            changes.push(format!("labels → {} replaced", newer.labels.len()));
+        }
+        changes
+    }
+}
+
+lazy_static! {
+    static ref CURRENT: RwLock<Arc<Config>> = RwLock::new(Arc::new(Config::default()));
+}
+
+/// The settings in effect, keep them only for one use, they may change.
+pub fn current() -> Arc<Config> {
+    CURRENT.read().unwrap().clone()
+}
+
+fn apply(config: Config) {
+    // Checked by `Config::parse`
+    if let Ok(level) = config.log_level() {
+        log::set_max_level(level);
+    }
+    *CURRENT.write().unwrap() = Arc::new(config);
+}
+
+fn reload(path: &Path) {
+    let config = match std::fs::read_to_string(path).map_err(anyhow::Error::from).and_then(|text| Config::parse(&text)) {
+        Ok(config) => config,
+        Err(error) => {
+            log::error!("Invalid config file {}, keeping the previous settings: {:?}", path.display(), error);
+            return;
+        }
+    };
+    let changes = current().changes(&config);
+    if changes.is_empty() {
+        return;
+    }
+    log::info!("Config file {} changed: {}", path.display(), changes.join(", "));
+    apply(config);
+}
+
+/// Reads the config file if there is one and applies every change to it.
+pub fn spawn() {
+    let path = match std::env::var("CONFIG_FILE") {
+        Ok(path) => PathBuf::from(path),
+        Err(_) => return,
+    };
+    reload(&path);
+    std::thread::spawn(move || {
+        if let Err(error) = watch(&path) {
+            log::error!("Stopped watching config file {}: {:?}", path.display(), error);
+        }
+    });
+}
+
+fn watch(path: &Path) -> anyhow::Result<()> {
+    let (sender, receiver) = mpsc::channel();
+    let mut watcher = notify::recommended_watcher(sender)?;
+    // The directory, editors replace the file instead of writing to it
+    let directory = match path.parent() {
+        Some(directory) if !directory.as_os_str().is_empty() => directory,
+        _ => Path::new("."),
+    };
+    watcher.watch(directory, RecursiveMode::NonRecursive)?;
+    let name = path.file_name();
+    loop {
+        let event: notify::Event = receiver.recv()??;
+        if !event.paths.iter().any(|changed| changed.file_name() == name) {
+            continue;
+        }
+        std::thread::sleep(SETTLE);
+        while receiver.try_recv().is_ok() {}
+        reload(path);
+    }
+}
@@ -0,0 +1,187 @@
+//! Console for operators on the Unix socket `ADMIN_SOCKET`, to look at and fix
+//! the state of a running bot instead of editing the data file and restarting.
+//! `shopping_bot admin <command>` sends a command, `socat - UNIX-CONNECT:<path>`
+//! works as well. One command per line, `help` lists them.
+
+use std::os::unix::fs::PermissionsExt;
+use std::sync::Arc;
+
+use serde_json::json;
+use teloxide::prelude::*;
+use teloxide::types::ChatId;
+use tokio::io::{AsyncBufReadExt, AsyncWriteExt, BufReader};
+use tokio::net::{UnixListener, UnixStream};
+
+use crate::{store_data, storage, Instance, View};
+
+const HELP: &str = "\
+bots                 lists the bots, `bot <namespace>` picks one (default: the first)
+chats                lists the chats the bot knows
+dump [<chat id>]     prints the state of a chat, or all data
+clear-recipe         drops the recipe being composed and leaves the composer
+clear-active         forgets the list message, the next change sends a new one
+quit                 closes the connection";
+
+/// The socket the console listens on, from `ADMIN_SOCKET`.
+pub fn socket_path() -> Option<String> {
+    std::env::var("ADMIN_SOCKET").ok().filter(|path| !path.is_empty())
+}
+
+/// Listens on the socket if it is configured.
+pub fn spawn(bots: &[(Bot, Arc<Instance>)]) {
+    let path = match socket_path() {
+        Some(path) => path,
+        None => return,
+    };
+    // Left behind by a previous run
+    let _ = std::fs::remove_file(&path);
+    let listener = match UnixListener::bind(&path) {
+        Ok(listener) => listener,
+        Err(error) => {
+            log::error!("Couldn't listen on {}: {:?}", path, error);
+            return;
+        }
+    };
+    // Whoever can connect can read every list
+    if let Err(error) = std::fs::set_permissions(&path, std::fs::Permissions::from_mode(0o600)) {
+        log::error!("Couldn't restrict {}: {:?}", path, error);
+        return;
+    }
+    log::info!("Operator console on {}", path);
+    let bots = bots.to_vec();
+    tokio::spawn(async move {
+        loop {
+            match listener.accept().await {
+                Ok((stream, _)) => {
+                    tokio::spawn(serve(stream, bots.clone()));
+                }
+                Err(error) => log::error!("Couldn't accept a console connection: {:?}", error),
+            }
+        }
+    });
+}
+
+async fn serve(stream: UnixStream, bots: Vec<(Bot, Arc<Instance>)>) {
+    let (reader, mut writer) = stream.into_split();
+    let mut lines = BufReader::new(reader).lines();
+    let mut selected = 0;
+    while let Ok(Some(line)) = lines.next_line().await {
+        let mut words = line.split_whitespace();
+        let answer = match words.next() {
+            None => continue,
+            Some("quit") => break,
+            Some("bot") => {
+                let namespace = words.next().unwrap_or_default();
+                match bots.iter().position(|(_, instance)| instance.namespace == namespace) {
+                    Some(position) => {
+                        selected = position;
+                        Ok(format!("Using bot {:?}", namespace))
+                    }
+                    None => Err(anyhow::anyhow!("No bot {:?}", namespace)),
+                }
+            }
+            Some("bots") => Ok(bots.iter()
+                .map(|(_, instance)| format!("{:?}", instance.namespace))
+                .collect::<Vec<_>>()
+                .join("\n")),
+            Some(command) => {
+                let (bot, instance) = &bots[selected];
+                run(bot, instance, command, words.next()).await
+            }
+        };
+        let answer = match answer {
+            Ok(answer) => answer,
+            Err(error) => format!("Error: {}", error),
+        };
+        log::info!("Console command {:?}", line);
+        // An empty line ends each answer, so clients know when to stop reading
+        if writer.write_all(format!("{}\n\n", answer.trim_end()).as_bytes()).await.is_err() {
+            break;
+        }
+    }
+}
+
+async fn run(bot: &Bot, instance: &Instance, command: &str, argument: Option<&str>) -> anyhow::Result<String> {
+    match command {
+        "help" => Ok(HELP.to_string()),
+        "chats" => {
+            let data = instance.snapshot.load_full();
+            // Archived chats are no longer among the known ones
+            let mut chats: Vec<i64> = data.known_chats.iter().chain(data.archived_chats.keys()).copied().collect();
+            chats.sort_unstable();
+            chats.dedup();
+            let lines: Vec<String> = chats.iter().map(|chat_id| {
+                let mut line = chat_id.to_string();
+                if data.active_message.map(|(chat, _)| chat) == Some(*chat_id) {
+                    line.push_str(" (list)");
+                }
+                if data.is_paused(*chat_id) {
+                    line.push_str(" (paused)");
+                }
+                if data.archived_chats.contains_key(chat_id) {
+                    line.push_str(" (archived)");
+                }
+                line
+            }).collect();
+            Ok(format!("{} chat(s)\n{}", lines.len(), lines.join("\n")))
+        }
+        "dump" => {
+            let data = instance.snapshot.load_full();
+            let chat_id = match argument {
+                Some(chat_id) => chat_id.parse::<i64>().map_err(|_| anyhow::anyhow!("Usage: dump [<chat id>]"))?,
+                None => return Ok(serde_json::to_string_pretty(&*data)?),
+            };
+            let in_chat = |message: Option<(i64, i32)>| message.filter(|(chat, _)| *chat == chat_id);
+            let state = json!({
+                "known": data.known_chats.contains(&chat_id),
+                "settings": storage::chat_state(&data, chat_id),
+                "active_message": in_chat(data.active_message),
+                "active_photo": data.active_photo,
+                "menu_message": in_chat(data.menu_message),
+                "menu_view": data.menu_view,
+                "navigation": data.navigation,
+                "current_recipe": data.current_recipe,
+                "adding_recipe": data.adding_recipe,
+                "searching": data.searching,
+            });
+            Ok(serde_json::to_string_pretty(&state)?)
+        }
+        "clear-recipe" => {
+            {
+                let mut data = instance.data.lock().await;
+                if data.current_recipe.take().is_none() && !data.navigation.contains(&View::RecipeComposer) {
+                    return Ok("No recipe is being composed".to_string());
+                }
+                data.navigation.retain(|view| *view != View::RecipeComposer);
+                // The composer stays on screen until the menu is shown again
+                if let (Some(View::RecipeComposer), Some((chat_id, _))) = (data.menu_view, data.menu_message) {
+                    let ctx = UpdateWithCx { requester: bot.clone(), update: ChatId::Id(chat_id) };
+                    let result = match data.navigation.last().copied() {
+                        Some(view) => data.render_view(&ctx, view).await,
+                        None => data.update_shopping_list(&ctx).await,
+                    };
+                    if let Err(error) = result {
+                        log::warn!("Couldn't show the menu again: {:?}", error);
+                    }
+                }
+            }
+            store_data(instance).await;
+            Ok("Recipe dropped".to_string())
+        }
+        "clear-active" => {
+            {
+                let mut data = instance.data.lock().await;
+                let message = match data.active_message.take() {
+                    Some(message) => message,
+                    None => return Ok("There is no list message".to_string()),
+                };
+                data.rendered = None;
+                data.active_photo = false;
+                log::warn!("Forgot the list message {:?} of {:?} on request", message, instance.namespace);
+            }
+            store_data(instance).await;
+            Ok("List message forgotten".to_string())
+        }
+        _ => anyhow::bail!("Unknown command {:?}, try help", command),
+    }
+}
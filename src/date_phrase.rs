@@ -0,0 +1,268 @@
+//! Small parser for date phrases like "Samstag", "morgen abend", "in 2 Tagen"
+//! or "tomorrow 9am", in German and English.
+
+use chrono::{DateTime, Datelike, Duration, NaiveDate, NaiveTime, TimeZone, Utc, Weekday};
+use chrono_tz::Tz;
+
+/// Used when only a day is given.
+const DEFAULT_HOUR: u32 = 18;
+
+const WEEKDAYS: [(&str, &str, Weekday); 7] = [
+    ("montag", "monday", Weekday::Mon),
+    ("dienstag", "tuesday", Weekday::Tue),
+    ("mittwoch", "wednesday", Weekday::Wed),
+    ("donnerstag", "thursday", Weekday::Thu),
+    ("freitag", "friday", Weekday::Fri),
+    ("samstag", "saturday", Weekday::Sat),
+    ("sonntag", "sunday", Weekday::Sun),
+];
+
+const PARTS_OF_DAY: [(&str, u32); 14] = [
+    ("früh", 8),
+    ("morgens", 8),
+    ("morning", 8),
+    ("vormittag", 10),
+    ("vormittags", 10),
+    ("mittag", 12),
+    ("mittags", 12),
+    ("noon", 12),
+    ("nachmittag", 15),
+    ("nachmittags", 15),
+    ("afternoon", 15),
+    ("abend", 19),
+    ("abends", 19),
+    ("evening", 19),
+];
+
+/// The furthest ahead `in ...` goes, about ten years.
+const MAX_RELATIVE_MINUTES: i64 = 3650 * 24 * 60;
+
+/// Words that carry no meaning of their own, as in "am Samstag um 10 Uhr".
+const FILLERS: [&str; 6] = ["am", "um", "at", "on", "bis", "by"];
+
+/// A weekday name in German or English, abbreviations like `sa` included.
+pub fn parse_weekday(text: &str) -> Option<Weekday> {
+    let text = text.to_lowercase();
+    if text.len() < 2 {
+        return None;
+    }
+    WEEKDAYS.iter()
+        .find(|(german, english, _)| german.starts_with(&text) || english.starts_with(&text))
+        .map(|(_, _, weekday)| *weekday)
+}
+
+/// The German name, e.g. "sonntag".
+pub fn weekday_name(weekday: Weekday) -> &'static str {
+    WEEKDAYS.iter().find(|(_, _, day)| *day == weekday).map(|(german, _, _)| *german).unwrap_or_default()
+}
+
+/// Accepts `heute`, `morgen`, `übermorgen`, weekday names (next occurrence,
+/// German or English, abbreviations like `sa`) and `DD.MM.` or `DD.MM.YYYY`.
+pub fn parse_day(text: &str, today: NaiveDate) -> Option<NaiveDate> {
+    let text = text.to_lowercase();
+    match text.as_str() {
+        "heute" | "today" | "tonight" => return Some(today),
+        "morgen" | "tomorrow" => return Some(today + Duration::days(1)),
+        "übermorgen" => return Some(today + Duration::days(2)),
+        _ => {}
+    }
+    if let Some(weekday) = parse_weekday(&text) {
+        let ahead = (weekday.num_days_from_monday() + 7 - today.weekday().num_days_from_monday()) % 7;
+        return Some(today + Duration::days(ahead as i64));
+    }
+    if let Ok(date) = NaiveDate::parse_from_str(&text, "%d.%m.%Y") {
+        return Some(date);
+    }
+    let date = NaiveDate::parse_from_str(&format!("{}{}", text, today.year()), "%d.%m.%Y").ok()?;
+    // A date that already passed this year means next year
+    if date < today {
+        NaiveDate::from_ymd_opt(date.year() + 1, date.month(), date.day())
+    } else {
+        Some(date)
+    }
+}
+
+/// `18:30`, `18`, `6pm`, `9am` or a part of the day like `abends`.
+fn parse_time(word: &str) -> Option<NaiveTime> {
+    if let Ok(time) = NaiveTime::parse_from_str(word, "%H:%M") {
+        return Some(time);
+    }
+    if let Some((_, hour)) = PARTS_OF_DAY.iter().find(|(name, _)| *name == word) {
+        return NaiveTime::from_hms_opt(*hour, 0, 0);
+    }
+    let (number, offset) = if let Some(hour) = word.strip_suffix("pm") {
+        (hour, 12)
+    } else if let Some(hour) = word.strip_suffix("am") {
+        (hour, 0)
+    } else {
+        (word, 0)
+    };
+    let hour: u32 = number.parse().ok()?;
+    if hour == 12 && number.len() < word.len() {
+        // 12am is midnight, 12pm noon
+        return NaiveTime::from_hms_opt(offset, 0, 0);
+    }
+    NaiveTime::from_hms_opt(hour.checked_add(offset)?, 0, 0)
+}
+
+/// `in 2 Tagen`, `in 3 hours`, `in 30 Minuten`.
+fn parse_relative(words: &[&str], now: DateTime<Tz>) -> Option<DateTime<Tz>> {
+    if words.len() != 3 || words[0] != "in" {
+        return None;
+    }
+    let amount: i64 = match words[1] {
+        "einem" | "einer" | "a" | "an" => 1,
+        number => number.parse().ok()?,
+    };
+    let unit = words[2];
+    let minutes = if unit.starts_with("min") {
+        1
+    } else if unit.starts_with("stunde") || unit.starts_with("hour") {
+        60
+    } else if unit.starts_with("tag") || unit.starts_with("day") {
+        24 * 60
+    } else if unit.starts_with("woche") || unit.starts_with("week") {
+        7 * 24 * 60
+    } else {
+        return None;
+    };
+    let minutes = amount.checked_mul(minutes).filter(|minutes| (0..=MAX_RELATIVE_MINUTES).contains(minutes))?;
+    now.checked_add_signed(Duration::minutes(minutes))
+}
+
+/// Resolves `text` relative to `now` in the chat's timezone.
+///
+/// Every word has to be understood, so typos fail instead of guessing.
+pub fn parse(text: &str, now: DateTime<Tz>) -> Option<DateTime<Utc>> {
+    let text = text.to_lowercase().replace("uhr", " ").replace(',', " ");
+    let words: Vec<&str> = text.split_whitespace()
+        .filter(|word| !FILLERS.contains(word))
+        .collect();
+    if words.is_empty() {
+        return None;
+    }
+    if let Some(due) = parse_relative(&words, now) {
+        return Some(due.with_timezone(&Utc));
+    }
+
+    let today = now.naive_local().date();
+    let mut day = None;
+    let mut time = None;
+    let mut i = 0;
+    while i < words.len() {
+        let word = words[i];
+        let next = words.get(i + 1).copied();
+        if matches!((word, next), ("nächste", Some("woche")) | ("next", Some("week"))) {
+            day = Some(today + Duration::weeks(1));
+            i += 2;
+            continue;
+        }
+        if word == "tonight" && day.is_none() && time.is_none() {
+            day = Some(today);
+            time = NaiveTime::from_hms_opt(20, 0, 0);
+        } else if let Some(parsed) = parse_time(word).filter(|_| time.is_none()) {
+            time = Some(parsed);
+        } else if let Some(parsed) = parse_day(word, today).filter(|_| day.is_none()) {
+            day = Some(parsed);
+        } else {
+            return None;
+        }
+        i += 1;
+    }
+
+    let time = time.or_else(|| NaiveTime::from_hms_opt(DEFAULT_HOUR, 0, 0))?;
+    let mut date = day.unwrap_or(today);
+    // Only a time that already passed today means tomorrow
+    if day.is_none() && today.and_time(time) < now.naive_local() {
+        date += Duration::days(1);
+    }
+    let timezone = now.timezone();
+    timezone.from_local_datetime(&date.and_time(time))
+        .earliest()
+        .map(|due| due.with_timezone(&Utc))
+}
+
+#[cfg(test)]
+mod tests {
+    use chrono::Timelike;
+    use chrono_tz::Europe::Berlin;
+
+    use super::*;
+
+    fn date(year: i32, month: u32, day: u32) -> NaiveDate {
+        NaiveDate::from_ymd_opt(year, month, day).unwrap()
+    }
+
+    /// Wednesday, 10:00 in Berlin.
+    fn now() -> DateTime<Tz> {
+        Berlin.from_local_datetime(&date(2021, 6, 2).and_hms_opt(10, 0, 0).unwrap()).unwrap()
+    }
+
+    fn local(text: &str) -> Option<DateTime<Tz>> {
+        parse(text, now()).map(|due| due.with_timezone(&Berlin))
+    }
+
+    #[test]
+    fn weekday_with_part_of_day() {
+        let due = local("Samstag abend").unwrap();
+        assert_eq!(due.naive_local().date(), date(2021, 6, 5));
+        assert_eq!(due.hour(), 19);
+    }
+
+    #[test]
+    fn day_without_time_uses_the_default_hour() {
+        let due = local("morgen").unwrap();
+        assert_eq!(due.naive_local().date(), date(2021, 6, 3));
+        assert_eq!(due.hour(), DEFAULT_HOUR);
+    }
+
+    #[test]
+    fn passed_time_means_tomorrow() {
+        let due = local("9am").unwrap();
+        assert_eq!(due.naive_local().date(), date(2021, 6, 3));
+        assert_eq!(due.hour(), 9);
+        assert_eq!(local("um 18:30 Uhr").unwrap().minute(), 30);
+    }
+
+    #[test]
+    fn twelve_am_and_pm() {
+        assert_eq!(parse_time("12am"), NaiveTime::from_hms_opt(0, 0, 0));
+        assert_eq!(parse_time("12pm"), NaiveTime::from_hms_opt(12, 0, 0));
+        assert_eq!(parse_time("6pm"), NaiveTime::from_hms_opt(18, 0, 0));
+        assert_eq!(parse_time("13pm"), None);
+    }
+
+    #[test]
+    fn huge_hours_are_rejected() {
+        assert_eq!(parse_time("4294967290pm"), None);
+        assert_eq!(local("4294967290pm"), None);
+    }
+
+    #[test]
+    fn relative_phrases() {
+        assert_eq!(local("in 2 Tagen").unwrap(), now() + Duration::days(2));
+        assert_eq!(local("in einer Stunde").unwrap(), now() + Duration::hours(1));
+        assert_eq!(local("in 30 minutes").unwrap(), now() + Duration::minutes(30));
+    }
+
+    #[test]
+    fn huge_relative_amounts_are_rejected() {
+        assert_eq!(local("in 999999999999 wochen"), None);
+        assert_eq!(local(&format!("in {} minuten", i64::MAX)), None);
+        assert_eq!(local("in -2 tagen"), None);
+    }
+
+    #[test]
+    fn dates_that_passed_mean_next_year() {
+        let today = date(2021, 6, 2);
+        assert_eq!(parse_day("1.6.", today), Some(date(2022, 6, 1)));
+        assert_eq!(parse_day("24.12.", today), Some(date(2021, 12, 24)));
+        assert_eq!(parse_day("sa", today), Some(date(2021, 6, 5)));
+    }
+
+    #[test]
+    fn unknown_words_fail() {
+        assert_eq!(local("Samstga"), None);
+        assert_eq!(local(""), None);
+    }
+}
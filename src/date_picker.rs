@@ -0,0 +1,136 @@
+//! Inline keyboard to pick a day and optionally an hour, for when typing a date fails.
+//!
+//! Callback data looks like `dp <mode><purpose> <step>`, e.g. `dp ti3 d20261016`:
+//! mode `t` asks for an hour after the day, `d` only for the day, and the
+//! purpose tells the caller what the date is for. Steps are `m<YYYYMM>` (show
+//! month), `d<YYYYMMDD>` (day picked), `h<YYYYMMDDHH>` (hour picked) and `x`
+//! (cancel). `dp -` buttons do nothing. Buttons carry a `Picker` and a `Step`
+//! as `Action::DatePicker`, `encode` and `parse` convert them.
+
+use chrono::{Datelike, Duration, NaiveDate, NaiveDateTime, NaiveTime};
+use teloxide::types::{InlineKeyboardButton, InlineKeyboardMarkup};
+
+use crate::callback::Action;
+
+const MONTHS: [&str; 12] = [
+    "Januar", "Februar", "März", "April", "Mai", "Juni",
+    "Juli", "August", "September", "Oktober", "November", "Dezember",
+];
+const WEEKDAYS: [&str; 7] = ["Mo", "Di", "Mi", "Do", "Fr", "Sa", "So"];
+const NOOP: &str = "-";
+
+#[derive(Debug, PartialEq, Clone)]
+pub struct Picker {
+    pub purpose: String,
+    pub with_time: bool,
+}
+
+#[derive(Debug, PartialEq)]
+pub enum Step {
+    Month(i32, u32),
+    Day(NaiveDate),
+    Hour(NaiveDateTime),
+    Cancel,
+    Noop,
+}
+
+impl Picker {
+    pub fn new(purpose: &str, with_time: bool) -> Self {
+        Self { purpose: purpose.to_string(), with_time }
+    }
+
+    fn button(&self, text: &str, step: Step) -> InlineKeyboardButton {
+        Action::DatePicker(self.clone(), step).button(text)
+    }
+
+    pub fn month_markup(&self, year: i32, month: u32) -> InlineKeyboardMarkup {
+        let first = match NaiveDate::from_ymd_opt(year, month, 1) {
+            Some(first) => first,
+            None => return InlineKeyboardMarkup::default(),
+        };
+        let previous = first - Duration::days(1);
+        let next = first + Duration::days(31);
+        let mut markup = InlineKeyboardMarkup::default().append_row(vec![
+            self.button("◀️", Step::Month(previous.year(), previous.month())),
+            noop(&format!("{} {}", MONTHS[month as usize - 1], year)),
+            self.button("▶️", Step::Month(next.year(), next.month())),
+        ]);
+        markup = markup.append_row(WEEKDAYS.iter().map(|day| noop(day)));
+
+        let mut row: Vec<InlineKeyboardButton> = (0..first.weekday().num_days_from_monday())
+            .map(|_| noop(" "))
+            .collect();
+        let mut day = first;
+        while day.month() == month {
+            row.push(self.button(&day.day().to_string(), Step::Day(day)));
+            if row.len() == 7 {
+                markup = markup.append_row(std::mem::take(&mut row));
+            }
+            day += Duration::days(1);
+        }
+        if !row.is_empty() {
+            while row.len() < 7 {
+                row.push(noop(" "));
+            }
+            markup = markup.append_row(row);
+        }
+        markup.append_row(vec![self.button("❌", Step::Cancel)])
+    }
+
+    pub fn hour_markup(&self, date: NaiveDate) -> InlineKeyboardMarkup {
+        let mut markup = InlineKeyboardMarkup::default().append_row(vec![
+            self.button("◀️", Step::Month(date.year(), date.month())),
+            noop(&date.format("%d.%m.%Y").to_string()),
+        ]);
+        for hours in (6..24).collect::<Vec<u32>>().chunks(6) {
+            markup = markup.append_row(hours.iter()
+                .filter_map(|hour| Some(self.button(&format!("{}:00", hour), Step::Hour(date.and_hms_opt(*hour, 0, 0)?)))));
+        }
+        markup.append_row(vec![self.button("❌", Step::Cancel)])
+    }
+}
+
+/// The callback data after `dp ` of a picker button.
+pub fn encode(picker: &Picker, step: &Step) -> String {
+    let step = match step {
+        Step::Noop => return NOOP.to_string(),
+        Step::Month(year, month) => format!("m{:04}{:02}", year, month),
+        Step::Day(date) => format!("d{}", date.format("%Y%m%d")),
+        Step::Hour(picked) => format!("h{}", picked.format("%Y%m%d%H")),
+        Step::Cancel => "x".to_string(),
+    };
+    format!("{}{} {}", if picker.with_time { 't' } else { 'd' }, picker.purpose, step)
+}
+
+/// Parses the callback data after `dp ` of a picker button.
+pub fn parse(data: &str) -> Option<(Picker, Step)> {
+    let words: Vec<&str> = data.split_whitespace().collect();
+    let header = *words.first()?;
+    if header == NOOP {
+        return Some((Picker::new("", false), Step::Noop));
+    }
+    let with_time = header.starts_with('t');
+    let picker = Picker::new(header.get(1..)?, with_time);
+    let step = *words.get(1)?;
+    let value = step.get(1..).unwrap_or_default();
+    let step = match step.chars().next()? {
+        'm' => {
+            let date = NaiveDate::parse_from_str(&format!("{}01", value), "%Y%m%d").ok()?;
+            Step::Month(date.year(), date.month())
+        }
+        'd' => Step::Day(NaiveDate::parse_from_str(value, "%Y%m%d").ok()?),
+        'h' => {
+            let date = NaiveDate::parse_from_str(value.get(..8)?, "%Y%m%d").ok()?;
+            let hour = value.get(8..)?.parse().ok()?;
+            Step::Hour(date.and_time(NaiveTime::from_hms_opt(hour, 0, 0)?))
+        }
+        'x' => Step::Cancel,
+        _ => return None,
+    };
+    Some((picker, step))
+}
+
+/// A button for labels and padding.
+fn noop(text: &str) -> InlineKeyboardButton {
+    Action::DatePicker(Picker::new("", false), Step::Noop).button(text)
+}
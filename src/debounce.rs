@@ -0,0 +1,35 @@
+//! Coalesces bursts of re-renders, like checking off several items in a row,
+//! into a single edit of the list.
+
+use std::collections::HashMap;
+use std::sync::Mutex;
+use std::time::Duration;
+
+use lazy_static::lazy_static;
+
+/// How long a chat has to stop tapping before the list is rendered.
+const WINDOW: Duration = Duration::from_millis(800);
+
+lazy_static! {
+    /// Newest render requested per chat.
+    static ref GENERATIONS: Mutex<HashMap<i64, u64>> = Mutex::new(HashMap::new());
+}
+
+fn bump(chat: i64) -> u64 {
+    let mut generations = GENERATIONS.lock().unwrap();
+    let generation = generations.entry(chat).or_insert(0);
+    *generation += 1;
+    *generation
+}
+
+/// Drops renders of the chat that are still waiting, because the list was just rendered anyway.
+pub fn supersede(chat: i64) {
+    bump(chat);
+}
+
+/// Waits for the window to pass, `false` if another render of the chat was requested meanwhile.
+pub async fn settle(chat: i64) -> bool {
+    let generation = bump(chat);
+    tokio::time::sleep(WINDOW).await;
+    GENERATIONS.lock().unwrap().get(&chat) == Some(&generation)
+}
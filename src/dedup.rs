@@ -0,0 +1,54 @@
+//! Skips updates that were handled already. Telegram only drops updates once
+//! the next request for updates confirms them, so after a restart it sends
+//! again what was handled just before, which would add items twice.
+//!
+//! The newest update and callback query IDs are remembered rather than the
+//! highest update ID, as Telegram starts the IDs over at a random number when
+//! a bot got no updates for a week.
+
+use std::collections::VecDeque;
+
+use serde::{Deserialize, Serialize};
+use serde_json::Value;
+
+/// Updates remembered, more than a request for updates returns.
+const MAX_REMEMBERED: usize = 200;
+
+#[derive(Serialize, Deserialize, Clone, Default)]
+pub struct Seen {
+    #[serde(default)]
+    update_ids: VecDeque<i64>,
+    #[serde(default)]
+    callback_queries: VecDeque<String>,
+}
+
+impl Seen {
+    /// Whether the update wasn't handled before, it counts as handled from now on.
+    pub fn first_time(&mut self, update: &Value) -> bool {
+        let update_id = update["update_id"].as_i64();
+        let callback_query = update["callback_query"]["id"].as_str();
+        if update_id.is_some_and(|id| self.update_ids.contains(&id))
+            || callback_query.is_some_and(|id| self.callback_queries.iter().any(|seen| seen == id)) {
+            return false;
+        }
+        if let Some(id) = update_id {
+            remember(&mut self.update_ids, id);
+        }
+        if let Some(id) = callback_query {
+            remember(&mut self.callback_queries, id.to_string());
+        }
+        true
+    }
+
+    /// The newest update ID handled, for `serve --local-repl` to count on from.
+    pub fn last_update_id(&self) -> Option<i64> {
+        self.update_ids.back().copied()
+    }
+}
+
+fn remember<T>(seen: &mut VecDeque<T>, id: T) {
+    seen.push_back(id);
+    if seen.len() > MAX_REMEMBERED {
+        seen.pop_front();
+    }
+}
@@ -0,0 +1,90 @@
+use image::{DynamicImage, ImageOutputFormat, Luma};
+use qrcode::QrCode;
+use rand::distributions::Alphanumeric;
+use rand::Rng;
+
+/// Telegram's limit for `/start` payloads.
+const MAX_PAYLOAD_LENGTH: usize = 64;
+
+/// Decodes the item of a `/start item_<base64url>` payload.
+///
+/// Telegram only allows `A-Za-z0-9_-` in start payloads, hence the URL-safe
+/// base64. Older `/start add_<item>` links are read with underscores as spaces:
+/// `add_whole_milk` adds "whole milk".
+pub fn parse_add_payload(payload: &str) -> Option<String> {
+    let name = match (payload.strip_prefix("item_"), payload.strip_prefix("add_")) {
+        (Some(encoded), _) => String::from_utf8(base64::decode_config(encoded, base64::URL_SAFE_NO_PAD).ok()?).ok()?,
+        (None, Some(name)) => name.replace('_', " "),
+        (None, None) => return None,
+    };
+    let name = name.trim();
+    if name.is_empty() {
+        None
+    } else {
+        Some(name.to_string())
+    }
+}
+
+pub fn parse_guest_payload(payload: &str) -> Option<&str> {
+    payload.strip_prefix("guest_").filter(|token| !token.is_empty())
+}
+
+/// Link that lets another chat view the list until the token expires.
+pub fn guest_link(bot_name: &str, token: &str) -> String {
+    format!("https://t.me/{}?start=guest_{}", bot_name, token)
+}
+
+/// Link that opens the bot and shows the household list.
+pub fn share_link(bot_name: &str) -> String {
+    format!("https://t.me/{}?start", bot_name)
+}
+
+/// Link that adds `item` to the list when opened, the inverse of [`parse_add_payload`].
+pub fn quick_add_link(bot_name: &str, item: &str) -> anyhow::Result<String> {
+    let payload = format!("item_{}", base64::encode_config(item.trim(), base64::URL_SAFE_NO_PAD));
+    if payload.len() > MAX_PAYLOAD_LENGTH {
+        anyhow::bail!("{} ist zu lang für einen Link", item.trim());
+    }
+    Ok(format!("https://t.me/{}?start={}", bot_name, payload))
+}
+
+/// Renders `link` as a QR code PNG.
+pub fn qr_code_png(link: &str) -> anyhow::Result<Vec<u8>> {
+    let code = QrCode::new(link.as_bytes())?;
+    let image = code.render::<Luma<u8>>().min_dimensions(400, 400).build();
+    let mut png = Vec::new();
+    DynamicImage::ImageLuma8(image).write_to(&mut png, ImageOutputFormat::Png)?;
+    Ok(png)
+}
+
+/// Unguessable token for links that grant access without a Telegram account.
+pub fn random_token(length: usize) -> String {
+    rand::thread_rng()
+        .sample_iter(&Alphanumeric)
+        .take(length)
+        .map(char::from)
+        .collect()
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    fn payload(link: &str) -> &str {
+        link.split("?start=").nth(1).unwrap()
+    }
+
+    #[test]
+    fn quick_add_links() {
+        for item in ["Milch", "Vollkorn-Brot 500g", "Äpfel & Birnen", "🥛"].iter() {
+            let link = quick_add_link("bot", item).unwrap();
+            let payload = payload(&link);
+            assert!(payload.len() <= MAX_PAYLOAD_LENGTH);
+            assert!(payload.chars().all(|c| c.is_ascii_alphanumeric() || c == '_' || c == '-'), "{}", payload);
+            assert_eq!(parse_add_payload(payload).as_deref(), Some(*item));
+        }
+        assert!(quick_add_link("bot", &"Äpfel".repeat(10)).is_err());
+        assert_eq!(parse_add_payload("add_whole_milk").as_deref(), Some("whole milk"));
+        assert_eq!(parse_add_payload("item_"), None);
+    }
+}
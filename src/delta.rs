@@ -0,0 +1,171 @@
+//! Changes of a JSON value small enough for a log line: the entries of an
+//! object that were set or removed, or the elements of an array that changed,
+//! were dropped at the front or appended. See `storage::log`.
+
+use serde::{Deserialize, Serialize};
+use serde_json::{Map, Value};
+
+/// How far to look for the new first element when the front of an array was dropped.
+const MAX_DROPPED: usize = 1000;
+
+#[derive(Serialize, Deserialize, Debug, PartialEq)]
+#[serde(tag = "op", rename_all = "lowercase")]
+pub enum Delta {
+    Keys {
+        #[serde(default, skip_serializing_if = "Map::is_empty")]
+        set: Map<String, Value>,
+        #[serde(default, skip_serializing_if = "Vec::is_empty")]
+        remove: Vec<String>,
+    },
+    /// The new array is `old[skip..skip + keep]` with the elements in `set`
+    /// replaced, by their index in it, followed by `append`.
+    Items {
+        #[serde(default, skip_serializing_if = "is_zero")]
+        skip: usize,
+        keep: usize,
+        #[serde(default, skip_serializing_if = "Vec::is_empty")]
+        set: Vec<(usize, Value)>,
+        #[serde(default, skip_serializing_if = "Vec::is_empty")]
+        append: Vec<Value>,
+    },
+}
+
+fn is_zero(number: &usize) -> bool {
+    *number == 0
+}
+
+/// The change from `old` to `new`, `None` if writing `new` as a whole is shorter.
+pub fn diff(old: &Value, new: &Value) -> Option<Delta> {
+    let delta = match (old, new) {
+        (Value::Object(old), Value::Object(new)) => Delta::Keys {
+            set: new.iter()
+                .filter(|(key, value)| old.get(*key) != Some(value))
+                .map(|(key, value)| (key.clone(), value.clone()))
+                .collect(),
+            remove: old.keys().filter(|key| !new.contains_key(*key)).cloned().collect(),
+        },
+        (Value::Array(old), Value::Array(new)) => {
+            // Capped lists drop their oldest elements when new ones are added
+            let skip = match new.first() {
+                Some(first) if old.first() != Some(first) => old.iter()
+                    .take(MAX_DROPPED)
+                    .position(|element| element == first)
+                    .unwrap_or(0),
+                _ => 0,
+            };
+            let keep = (old.len() - skip).min(new.len());
+            Delta::Items {
+                skip,
+                keep,
+                set: (0..keep)
+                    .filter(|index| old[skip + index] != new[*index])
+                    .map(|index| (index, new[index].clone()))
+                    .collect(),
+                append: new[keep..].to_vec(),
+            }
+        }
+        _ => return None,
+    };
+    let delta_length = serde_json::to_string(&delta).map(|string| string.len()).unwrap_or(usize::MAX);
+    let full_length = serde_json::to_string(new).map(|string| string.len()).unwrap_or(0);
+    if delta_length < full_length {
+        Some(delta)
+    } else {
+        None
+    }
+}
+
+/// Applies a change made by `diff` to `old`.
+pub fn apply(old: &Value, delta: Delta) -> anyhow::Result<Value> {
+    match (old, delta) {
+        (Value::Object(old), Delta::Keys { set, remove }) => {
+            let mut new = old.clone();
+            for key in remove {
+                new.remove(&key);
+            }
+            new.extend(set);
+            Ok(Value::Object(new))
+        }
+        (Value::Array(old), Delta::Items { skip, keep, set, append }) => {
+            let mut new = old.get(skip..skip + keep)
+                .ok_or_else(|| anyhow::anyhow!("Can't keep {} of {} elements after {}", keep, old.len(), skip))?
+                .to_vec();
+            for (index, value) in set {
+                *new.get_mut(index).ok_or_else(|| anyhow::anyhow!("No element {} to replace", index))? = value;
+            }
+            new.extend(append);
+            Ok(Value::Array(new))
+        }
+        (old, delta) => anyhow::bail!("Can't apply {:?} to {}", delta, old),
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use serde_json::json;
+
+    use super::*;
+
+    fn round_trip(old: Value, new: Value) -> Option<Delta> {
+        let delta = diff(&old, &new)?;
+        let line = serde_json::to_string(&delta).unwrap();
+        assert_eq!(apply(&old, serde_json::from_str(&line).unwrap()).unwrap(), new);
+        Some(delta)
+    }
+
+    #[test]
+    fn appending_logs_only_the_new_elements() {
+        let old: Vec<u32> = (0..100).collect();
+        let mut new = old.clone();
+        new.push(100);
+        let delta = round_trip(json!(old), json!(new)).unwrap();
+        assert_eq!(delta, Delta::Items { skip: 0, keep: 100, set: Vec::new(), append: vec![json!(100)] });
+    }
+
+    #[test]
+    fn capped_lists_drop_the_front() {
+        let old: Vec<u32> = (0..100).collect();
+        let new: Vec<u32> = (3..103).collect();
+        let delta = round_trip(json!(old), json!(new)).unwrap();
+        assert_eq!(delta, Delta::Items { skip: 3, keep: 97, set: Vec::new(), append: vec![json!(100), json!(101), json!(102)] });
+    }
+
+    #[test]
+    fn changed_elements_are_replaced() {
+        let old: Vec<Value> = (0..50).map(|id| json!({ "id": id, "done": false })).collect();
+        let mut new = old.clone();
+        new[7] = json!({ "id": 7, "done": true });
+        let delta = round_trip(json!(old), json!(new)).unwrap();
+        match delta {
+            Delta::Items { set, append, .. } => {
+                assert_eq!(set, vec![(7, json!({ "id": 7, "done": true }))]);
+                assert!(append.is_empty());
+            }
+            delta => panic!("Unexpected {:?}", delta),
+        }
+    }
+
+    #[test]
+    fn removed_elements_shorten_the_array() {
+        let old: Vec<u32> = (0..60).collect();
+        let new: Vec<u32> = (0..59).collect();
+        round_trip(json!(old), json!(new)).unwrap();
+    }
+
+    #[test]
+    fn objects_log_changed_keys() {
+        let old = json!({ "a": 1, "b": (0..30).collect::<Vec<u32>>(), "c": "removed" });
+        let new = json!({ "a": 2, "b": (0..30).collect::<Vec<u32>>(), "d": true });
+        let delta = round_trip(old, new).unwrap();
+        assert_eq!(delta, Delta::Keys {
+            set: json!({ "a": 2, "d": true }).as_object().unwrap().clone(),
+            remove: vec!["c".to_string()],
+        });
+    }
+
+    #[test]
+    fn small_values_are_written_whole() {
+        assert_eq!(diff(&json!([1]), &json!([2])), None);
+        assert_eq!(diff(&json!(1), &json!(2)), None);
+    }
+}
@@ -0,0 +1,80 @@
+//! Recipe discovery on TheMealDB for `/discover`.
+
+use std::time::Duration;
+
+use serde_json::Value;
+
+const API_URL: &str = "https://www.themealdb.com/api/json/v1/1";
+const TIMEOUT: Duration = Duration::from_secs(5);
+/// TheMealDB has at most 20 ingredients per meal.
+const MAX_INGREDIENTS: usize = 20;
+
+/// A search result, without the ingredients.
+pub struct Found {
+    pub id: u32,
+    pub name: String,
+    pub photo: Option<String>,
+}
+
+/// Recipes using `ingredient` from TheMealDB, at most `limit` of them.
+///
+/// The API can be swapped via `RECIPE_API_URL`, as long as it answers like TheMealDB.
+pub async fn search(ingredient: &str, limit: usize) -> anyhow::Result<Vec<Found>> {
+    let json = get("filter.php", ingredient.trim().replace(' ', "_").as_str()).await?;
+    let meals = match json.get("meals").and_then(|meals| meals.as_array()) {
+        Some(meals) => meals,
+        None => return Ok(Vec::new()),
+    };
+    Ok(meals.iter()
+        .filter_map(|meal| Some(Found {
+            id: meal.get("idMeal")?.as_str()?.parse().ok()?,
+            name: meal.get("strMeal")?.as_str()?.to_string(),
+            photo: meal.get("strMealThumb").and_then(|photo| photo.as_str()).map(|photo| photo.to_string()),
+        }))
+        .take(limit)
+        .collect())
+}
+
+/// Name and normalized ingredients of the meal, `None` if it doesn't exist (anymore).
+pub async fn lookup(id: u32) -> anyhow::Result<Option<(String, Vec<String>)>> {
+    let json = get("lookup.php", &id.to_string()).await?;
+    let meal = match json.get("meals").and_then(|meals| meals.get(0)) {
+        Some(meal) => meal,
+        None => return Ok(None),
+    };
+    let name = match meal.get("strMeal").and_then(|name| name.as_str()) {
+        Some(name) => name.to_string(),
+        None => return Ok(None),
+    };
+    let field = |key: String| meal.get(key).and_then(|value| value.as_str()).unwrap_or_default();
+    let ingredients = (1..=MAX_INGREDIENTS)
+        .filter_map(|i| normalize(field(format!("strIngredient{}", i)), field(format!("strMeasure{}", i))))
+        .collect();
+    Ok(Some((name, ingredients)))
+}
+
+async fn get(endpoint: &str, argument: &str) -> anyhow::Result<Value> {
+    let url = std::env::var("RECIPE_API_URL").unwrap_or_else(|_| API_URL.to_string());
+    Ok(reqwest::Client::new()
+        .get(format!("{}/{}", url, endpoint).as_str())
+        .query(&[("i", argument)])
+        .timeout(TIMEOUT)
+        .send()
+        .await?
+        .json()
+        .await?)
+}
+
+/// "chicken breast" and "200g " become "Chicken breast (200g)", empty ingredients are dropped.
+fn normalize(ingredient: &str, measure: &str) -> Option<String> {
+    let ingredient = ingredient.split_whitespace().collect::<Vec<_>>().join(" ");
+    let mut chars = ingredient.chars();
+    let first = chars.next()?;
+    let ingredient = first.to_uppercase().chain(chars).collect::<String>();
+    let measure = measure.split_whitespace().collect::<Vec<_>>().join(" ");
+    if measure.is_empty() {
+        Some(ingredient)
+    } else {
+        Some(format!("{} ({})", ingredient, measure))
+    }
+}
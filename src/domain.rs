@@ -0,0 +1,705 @@
+//! The shopping list itself: items, recipes and the pantry, and the changes to
+//! them that don't involve Telegram. The chat handlers, the REST and gRPC APIs,
+//! WebSocket clients, mail and voice assistants all change the list through
+//! these and only show the result their own way. Nothing in here may depend on
+//! teloxide or the environment, limits and permissions are passed in.
+
+use std::collections::{HashMap, HashSet};
+use std::fmt::Write;
+
+use chrono::{DateTime, Utc};
+use chrono_tz::Tz;
+use serde::{Deserialize, Deserializer, Serialize};
+
+use crate::environment::InputLimits;
+use crate::word_filter::WordFilter;
+use crate::{batch, history, i18n, import, mail, money, normalize, profile, spending, synonyms, trips, voice, wishes, Data, ENVIRONMENT};
+
+#[derive(Serialize, Deserialize, Clone)]
+pub struct Item {
+    pub name: String,
+    pub done: bool,
+    pub assignee: Option<i64>,
+    pub due: Option<DateTime<Utc>>,
+    #[serde(default)]
+    pub nudged: bool,
+    #[serde(default)]
+    pub checked_by: Option<i64>,
+    #[serde(default = "default_quantity")]
+    pub quantity: u32,
+    /// Section headers like `# Drogerie` group the items below them.
+    #[serde(default)]
+    pub header: bool,
+    /// Who promised to bring the item in event mode.
+    #[serde(default)]
+    pub claimed_by: Option<i64>,
+    /// In cents, recorded with `/price`.
+    #[serde(default)]
+    pub price: Option<u64>,
+    #[serde(default)]
+    pub category: Option<spending::Category>,
+    /// The recipe this item came from, kept in the history for the recipe statistics.
+    #[serde(default)]
+    pub recipe: Option<String>,
+    /// Refers to the item from buttons outside the list, whose indices may be outdated. 0 until needed.
+    #[serde(default)]
+    pub id: u64,
+    /// Put off with 💤 until the checked items are cleared, e.g. because the store was out of it.
+    #[serde(default)]
+    pub snoozed: bool,
+    /// Link to the product on wish lists, opened with a button.
+    #[serde(default)]
+    pub url: Option<String>,
+    /// Stars of a wish, up to `wishes::MAX_PRIORITY`.
+    #[serde(default)]
+    pub priority: u8,
+}
+
+fn default_quantity() -> u32 {
+    1
+}
+
+impl Item {
+    pub fn new(name: String) -> Self {
+        Self {
+            name,
+            done: false,
+            assignee: None,
+            due: None,
+            nudged: false,
+            checked_by: None,
+            quantity: default_quantity(),
+            header: false,
+            claimed_by: None,
+            price: None,
+            category: None,
+            recipe: None,
+            id: 0,
+            snoozed: false,
+            url: None,
+            priority: 0,
+        }
+    }
+
+    /// Gives the item an ID from `next_id` if it doesn't have one yet.
+    pub fn ensure_id(&mut self, next_id: &mut u64) -> u64 {
+        if self.id == 0 {
+            *next_id += 1;
+            self.id = *next_id;
+        }
+        self.id
+    }
+
+    /// Creates a section header for text starting with `#`, an item otherwise.
+    pub fn from_text(text: String) -> Self {
+        match text.strip_prefix('#') {
+            Some(name) => Self { header: true, ..Item::new(name.trim().to_string()) },
+            None => Item::new(text),
+        }
+    }
+
+    /// An item for an ingredient of a recipe, "3× Eier" is three eggs.
+    pub fn from_ingredient(ingredient: &str) -> Self {
+        let (name, quantity) = normalize::quantity(ingredient);
+        Self { quantity: quantity.max(1), ..Item::new(name) }
+    }
+
+    /// Inverse of `from_text`, used to store items in templates.
+    pub fn to_text(&self) -> String {
+        if self.header {
+            format!("# {}", self.name)
+        } else {
+            self.name.clone()
+        }
+    }
+
+    /// Name prefixed with the quantity, if more than one is needed.
+    pub fn label(&self) -> String {
+        self.label_as(&self.name)
+    }
+
+    /// Like `label`, with the name shown differently, e.g. translated.
+    pub fn label_as(&self, name: &str) -> String {
+        if self.quantity > 1 {
+            format!("{}× {}", self.quantity, name)
+        } else {
+            name.to_string()
+        }
+    }
+
+    pub fn describe(&self, users: &HashMap<i64, KnownUser>, timezone: Tz, language: i18n::Language, money: &money::MoneyFormat) -> String {
+        let mut text = self.label();
+        if self.priority > 0 {
+            text = format!("{} {}", text, wishes::stars(self.priority));
+        }
+        if let Some(user) = self.assignee.and_then(|id| users.get(&id)) {
+            text = format!("{} → {}", text, user.name());
+        }
+        if let Some(user) = self.claimed_by.and_then(|id| users.get(&id)) {
+            text = format!("{} 🙋 {}", text, user.name());
+        }
+        if let Some(price) = self.price {
+            text = format!("{} [{}]", text, money.format(price));
+        }
+        if let Some(due) = self.due {
+            text = format!("{} ({})", text, due.with_timezone(&timezone).format(language.date_time_format()));
+        }
+        text
+    }
+}
+
+/// Items used to be stored as plain `(name, done)` tuples.
+#[derive(Deserialize)]
+#[serde(untagged)]
+enum StoredItem {
+    Legacy(String, bool),
+    Current(Item),
+}
+
+fn deserialize_items<'de, D: Deserializer<'de>>(deserializer: D) -> Result<Vec<Item>, D::Error> {
+    Ok(Vec::<StoredItem>::deserialize(deserializer)?
+        .into_iter()
+        .map(|item| match item {
+            StoredItem::Legacy(name, done) => Item { done, ..Item::new(name) },
+            StoredItem::Current(item) => item,
+        })
+        .collect())
+}
+
+#[derive(Serialize, Deserialize, Clone)]
+pub struct KnownUser {
+    pub first_name: String,
+    pub username: Option<String>,
+    /// Set with `/profile`.
+    #[serde(default)]
+    pub profile: profile::Profile,
+}
+
+impl KnownUser {
+    /// How the user appears in the chat, see `profile`.
+    pub fn name(&self) -> String {
+        self.profile.display(&self.first_name)
+    }
+}
+
+/// The ingredients of a recipe, one item each, like "3× Eier".
+#[derive(Serialize, Deserialize, Clone, Default, PartialEq, Debug)]
+#[serde(transparent)]
+pub struct Recipe {
+    pub ingredients: Vec<String>,
+}
+
+impl Recipe {
+    pub fn new(ingredients: Vec<String>) -> Self {
+        Recipe { ingredients }
+    }
+
+    /// Ingredients that have to be bought first.
+    pub fn missing(&self, pantry: &Pantry) -> Vec<&String> {
+        self.ingredients.iter().filter(|ingredient| !pantry.covers(ingredient)).collect()
+    }
+
+    /// Items for the ingredients but those with the excluded indices.
+    pub fn items(&self, name: &str, excluded: &HashSet<usize>) -> Vec<Item> {
+        self.ingredients.iter()
+            .enumerate()
+            .filter(|(index, _)| !excluded.contains(index))
+            .map(|(_, ingredient)| Item { recipe: Some(name.to_string()), ..Item::from_ingredient(ingredient) })
+            .collect()
+    }
+}
+
+/// What is already at home, maintained with `/pantry`. Products running low
+/// are put on the list from the pantry view and flagged until they are bought.
+#[derive(Serialize, Deserialize, Clone, Default)]
+pub struct Pantry {
+    products: Vec<String>,
+    /// Products put on the list with "fast leer", until they are bought.
+    #[serde(default)]
+    low: Vec<String>,
+}
+
+impl Pantry {
+    pub fn add(&mut self, name: &str) {
+        if !self.products.iter().any(|product| product.eq_ignore_ascii_case(name)) {
+            self.products.push(name.to_string());
+            self.products.sort_by_key(|product| product.to_lowercase());
+        }
+    }
+
+    /// Whether the product was in the pantry.
+    pub fn remove(&mut self, name: &str) -> bool {
+        let before = self.products.len();
+        self.products.retain(|product| !product.eq_ignore_ascii_case(name));
+        self.low.retain(|product| !product.eq_ignore_ascii_case(name));
+        self.products.len() != before
+    }
+
+    pub fn is_low(&self, name: &str) -> bool {
+        self.low.iter().any(|product| product.eq_ignore_ascii_case(name))
+    }
+
+    pub fn mark_low(&mut self, name: &str) {
+        if !self.is_low(name) {
+            self.low.push(name.to_string());
+        }
+    }
+
+    /// Clears the flag of a product that was bought.
+    pub fn restock(&mut self, name: &str) {
+        self.low.retain(|product| !product.eq_ignore_ascii_case(name));
+    }
+
+    pub fn products(&self) -> &[String] {
+        &self.products
+    }
+
+    /// Whether a product covers the ingredient, so "Milch" covers "Vollmilch (200ml)".
+    pub fn covers(&self, ingredient: &str) -> bool {
+        let name = ingredient.split(" (").next().unwrap_or(ingredient);
+        self.products.iter().map(|product| product.to_lowercase()).any(|product| {
+            name.to_lowercase() == product || synonyms::matches(name, &[product])
+        })
+    }
+}
+
+/// The items, recipes and pantry of a list, without the chats showing it.
+#[derive(Serialize, Deserialize, Clone, Default)]
+pub struct ShoppingList {
+    #[serde(deserialize_with = "deserialize_items")]
+    pub items: Vec<Item>,
+    pub recipes: HashMap<String, Recipe>,
+    /// Recipes hidden from the recipe buttons, see the recipe manager.
+    #[serde(default)]
+    pub archived_recipes: HashSet<String>,
+    /// Items that left the list.
+    #[serde(default)]
+    pub history: Vec<history::Purchase>,
+    /// Last ID given to an item, see `Item::ensure_id`.
+    #[serde(default)]
+    pub next_item_id: u64,
+    #[serde(default)]
+    pub pantry: Pantry,
+}
+
+impl ShoppingList {
+    /// Gives every item an ID, for buttons that refer to items by it.
+    pub fn assign_item_ids(&mut self) {
+        for item in self.items.iter_mut() {
+            item.ensure_id(&mut self.next_item_id);
+        }
+    }
+
+    /// Where the item or header with that ID is, `None` if it is gone.
+    pub fn item_position(&self, id: u64) -> Option<usize> {
+        self.items.iter().position(|item| id != 0 && item.id == id)
+    }
+
+    /// Checks or unchecks the item with that ID, `None` if it is gone.
+    pub fn toggle_item(&mut self, id: u64, user_id: i64) -> Option<&Item> {
+        let item = self.items.iter_mut().find(|item| id != 0 && item.id == id && !item.header)?;
+        item.done = !item.done;
+        item.checked_by = if item.done { Some(user_id) } else { None };
+        Some(item)
+    }
+
+    /// Adds an item, or all ingredients if `text` names a recipe.
+    pub fn add_text(&mut self, text: String) {
+        if self.recipes.contains_key(&text) {
+            self.add_recipe(&text, &HashSet::new());
+        } else {
+            self.items.push(Item::from_text(text));
+        }
+    }
+
+    /// Adds the ingredients of the recipe but those with the excluded indices, returns how many were added.
+    pub fn add_recipe(&mut self, name: &str, excluded: &HashSet<usize>) -> usize {
+        let items = match self.recipes.get(name) {
+            Some(recipe) => recipe.items(name, excluded),
+            None => return 0,
+        };
+        let added = items.len();
+        self.items.extend(items);
+        added
+    }
+
+    /// Moves the checked items into the history and restocks the pantry with
+    /// them, returns them in the order of the list.
+    pub fn clear_done(&mut self) -> Vec<Item> {
+        let (bought, open): (Vec<Item>, Vec<Item>) = std::mem::take(&mut self.items).into_iter().partition(|item| item.done);
+        self.items = open;
+        for item in bought.iter() {
+            self.pantry.restock(&item.name);
+            self.history.push(history::Purchase {
+                name: item.name.clone(),
+                bought_by: item.checked_by,
+                at: Utc::now(),
+                price: item.price,
+                category: item.category,
+                recipe: item.recipe.clone(),
+                event: history::Event::Bought,
+                quantity: item.quantity,
+            });
+        }
+        // Snoozed items are due on the next trip, which starts now
+        for item in self.items.iter_mut() {
+            item.snoozed = false;
+        }
+        bought
+    }
+
+    /// Whether the operation still fits the list, e.g. the item to check is
+    /// still there. Added items go through the word filter if there is one,
+    /// and fail if they are over the limits.
+    pub fn apply_operation(&mut self, operation: &batch::Operation, language: i18n::Language, limits: &InputLimits, filter: Option<&WordFilter>) -> anyhow::Result<bool> {
+        let name = normalize::item(&operation.item, language);
+        let position = |items: &[Item], done: bool| items.iter()
+            .position(|item| !item.header && item.done == done && item.name.eq_ignore_ascii_case(&name));
+        match operation.kind {
+            batch::Kind::Add => {
+                // Already on the list, or bought since it was noted
+                let bought_since = self.history.iter()
+                    .any(|purchase| purchase.bought() && purchase.at > operation.at && purchase.name.eq_ignore_ascii_case(&name));
+                if name.is_empty() || bought_since || position(&self.items, false).is_some() {
+                    return Ok(false);
+                }
+                limits.check_text(&name)?;
+                limits.check_item_count(self.items.len() + 1)?;
+                let name = match filter.map(|filter| filter.apply(&name)) {
+                    Some(Ok(name)) => name,
+                    Some(Err(_)) => return Ok(false),
+                    None => name,
+                };
+                let quantity = operation.quantity.unwrap_or(1).max(1);
+                self.items.push(Item { quantity, ..Item::from_text(name) });
+            }
+            batch::Kind::Check | batch::Kind::Uncheck => {
+                let check = operation.kind == batch::Kind::Check;
+                match position(&self.items, !check) {
+                    Some(index) => {
+                        self.items[index].done = check;
+                        self.items[index].checked_by = None;
+                    }
+                    None => return Ok(false),
+                }
+            }
+            batch::Kind::Delete => {
+                let index = match position(&self.items, false).or_else(|| position(&self.items, true)) {
+                    Some(index) => index,
+                    None => return Ok(false),
+                };
+                let item = self.items.remove(index);
+                self.history.push(history::Purchase {
+                    name: item.name,
+                    bought_by: None,
+                    at: operation.at,
+                    price: None,
+                    category: item.category,
+                    recipe: item.recipe,
+                    event: history::Event::Deleted,
+                    quantity: item.quantity,
+                });
+            }
+        }
+        Ok(true)
+    }
+
+    /// Adds the imported items to the list, recipes with a name that is already
+    /// taken are skipped. Nothing is added if the import is over the limits or
+    /// the word filter rejects any of it. Returns the names of the added recipes.
+    pub fn apply_import(&mut self, import: import::Import, limits: &InputLimits, filter: Option<&WordFilter>) -> anyhow::Result<Vec<String>> {
+        limits.check_item_count(self.items.len() + import.items.len())?;
+        let filtered = |text: &str| match filter {
+            Some(filter) => filter.apply(text),
+            None => Ok(text.to_string()),
+        };
+        let mut items = Vec::new();
+        for entry in import.items {
+            limits.check_text(&entry.name)?;
+            items.push(Item {
+                done: entry.done,
+                quantity: entry.quantity.max(1),
+                header: entry.header,
+                ..Item::new(filtered(&entry.name)?)
+            });
+        }
+        let mut recipes = Vec::new();
+        for (name, ingredients) in import.recipes {
+            let ingredients = ingredients.iter().map(|ingredient| filtered(ingredient)).collect::<anyhow::Result<_>>()?;
+            recipes.push((filtered(&name)?, Recipe::new(ingredients)));
+        }
+        self.items.extend(items);
+        let mut added = Vec::new();
+        for (name, recipe) in recipes {
+            if !self.recipes.contains_key(&name) {
+                self.recipes.insert(name.clone(), recipe);
+                added.push(name);
+            }
+        }
+        Ok(added)
+    }
+
+    /// Sets the quantity the item is usually bought in, returns its index and the quantity if there is one.
+    pub fn apply_usual_quantity(&mut self, index: usize) -> Option<(usize, u32)> {
+        let item = self.items.get(index).filter(|item| !item.header && item.quantity == 1)?;
+        let quantity = history::usual_quantity(&self.history, &item.name)?;
+        self.items[index].quantity = quantity;
+        Some((index, quantity))
+    }
+}
+
+impl Data {
+    /// Applies operations an integration recorded offline, skipping those applied before.
+    pub fn apply_batch(&mut self, mut batch: batch::Batch) -> batch::Outcome {
+        let mut outcome = batch::Outcome::default();
+        batch.operations.sort_by_key(|operation| operation.at);
+        for operation in batch.operations {
+            if self.applied_operations.contains(&operation.id) {
+                outcome.repeated += 1;
+                continue;
+            }
+            match self.apply_operation(&operation) {
+                Ok(true) => outcome.applied += 1,
+                Ok(false) => outcome.skipped.push(operation.id.clone()),
+                Err(error) => outcome.rejected.push(batch::Rejected { id: operation.id.clone(), reason: error.to_string() }),
+            }
+            self.applied_operations.push_back(operation.id);
+            if self.applied_operations.len() > batch::REMEMBERED_IDS {
+                self.applied_operations.pop_front();
+            }
+        }
+        outcome
+    }
+
+    /// Applies the operation of an integration, see `ShoppingList::apply_operation`.
+    pub fn apply_operation(&mut self, operation: &batch::Operation) -> anyhow::Result<bool> {
+        let language = self.language();
+        // No one is behind these to be an admin
+        self.list.apply_operation(operation, language, &ENVIRONMENT.input_limits, Some(&self.word_filter))
+    }
+
+    /// Adds the items of a mail, returns how many weren't on the list yet and
+    /// why the others over the limits weren't added.
+    pub fn add_from_mail(&mut self, mail: &mail::Mail) -> (usize, Vec<String>) {
+        let mut added = 0;
+        let mut rejected = Vec::new();
+        for item in mail.items() {
+            match self.apply_operation(&batch::Operation::now(batch::Kind::Add, &item)) {
+                Ok(true) => added += 1,
+                Ok(false) => {}
+                Err(error) => rejected.push(format!("{}: {}", item, error)),
+            }
+        }
+        (added, rejected)
+    }
+
+    /// What the voice assistant of a linked user answers, and whether the conversation ends.
+    pub fn answer_voice(&mut self, intent: voice::Intent) -> (String, bool) {
+        let operation = batch::Operation::now;
+        match intent {
+            voice::Intent::Add(item) => match self.apply_operation(&operation(batch::Kind::Add, &item)) {
+                Ok(true) => (format!("{} steht jetzt auf der Einkaufsliste.", item), true),
+                Ok(false) => (format!("{} steht schon auf der Einkaufsliste.", item), true),
+                Err(error) => (format!("{}.", error), true),
+            },
+            voice::Intent::Remove(item) => match self.apply_operation(&operation(batch::Kind::Delete, &item)) {
+                Ok(true) => (format!("{} ist von der Einkaufsliste genommen.", item), true),
+                _ => (format!("{} steht nicht auf der Einkaufsliste.", item), true),
+            },
+            voice::Intent::Check(item) => match self.apply_operation(&operation(batch::Kind::Check, &item)) {
+                Ok(true) => (format!("{} ist abgehakt.", item), true),
+                _ => (format!("{} steht nicht offen auf der Einkaufsliste.", item), true),
+            },
+            voice::Intent::List => {
+                let open: Vec<&str> = self.list.items.iter()
+                    .filter(|item| !item.header && !item.done)
+                    .map(|item| item.name.as_str())
+                    .collect();
+                let text = match open.split_last() {
+                    None => "Die Einkaufsliste ist leer.".to_string(),
+                    Some((only, [])) => format!("Auf der Einkaufsliste steht {}.", only),
+                    Some((last, rest)) => format!("Auf der Einkaufsliste stehen {} und {}.", rest.join(", "), last),
+                };
+                (text, true)
+            }
+            voice::Intent::Launch => ("Was soll auf die Einkaufsliste?".to_string(), false),
+            voice::Intent::Stop => ("Tschüss!".to_string(), true),
+            voice::Intent::Help | voice::Intent::Link(_) => (
+                "Sag zum Beispiel: füge Milch hinzu, hake Milch ab, oder: was steht auf der Liste?".to_string(),
+                false,
+            ),
+        }
+    }
+
+    /// Moves the checked items into the history and records them as a trip,
+    /// returns their labels in the order of the list.
+    pub fn clear_done(&mut self) -> Vec<String> {
+        let bought = self.list.clear_done();
+        let labels = bought.iter().map(Item::label).collect();
+        let mut trip = Vec::new();
+        let mut shoppers = Vec::new();
+        for item in bought {
+            if let Some(shopper) = item.checked_by.filter(|shopper| !shoppers.contains(shopper)) {
+                shoppers.push(shopper);
+            }
+            trip.push(trips::TripItem { name: item.name, quantity: item.quantity });
+        }
+        let store = self.store().map(str::to_string);
+        trips::record(&mut self.trips, store, shoppers, trip, self.trip_started.take());
+        labels
+    }
+
+    /// Ends the running trip: clears the checked items and sums the trip up.
+    pub fn finish_trip(&mut self) -> String {
+        let started = self.trip_started.unwrap_or_else(Utc::now);
+        let bought: Vec<&Item> = self.list.items.iter().filter(|item| item.done && !item.header).collect();
+        if bought.is_empty() {
+            self.trip_started = None;
+            return "Einkauf beendet, es wurde nichts abgehakt.".to_string();
+        }
+        let spend: u64 = bought.iter()
+            .filter_map(|item| {
+                let cents = item.price.or_else(|| self.price_book.get(&item.name.to_lowercase()).map(|entry| entry.cents))?;
+                Some(cents * item.quantity as u64)
+            })
+            .sum();
+        let mut shoppers: Vec<String> = Vec::new();
+        for user in bought.iter().filter_map(|item| self.users.get(&item.checked_by?)) {
+            if !shoppers.contains(&user.name()) {
+                shoppers.push(user.name());
+            }
+        }
+        let mut text = format!("🛒 Einkauf beendet nach {} min: {} Einträge", (Utc::now() - started).num_minutes(), bought.len());
+        if spend > 0 {
+            write!(text, ", ca. {}", self.money().format(spend)).unwrap();
+        }
+        if !shoppers.is_empty() {
+            write!(text, ", eingekauft von {}", shoppers.join(", ")).unwrap();
+        }
+        text.push('.');
+        self.clear_done();
+        text
+    }
+
+    /// The weekly reset: archives the checked items and keeps or drops the open ones.
+    pub fn reset_list(&mut self, keep_open: bool) {
+        self.clear_done();
+        if !keep_open {
+            self.list.items.clear();
+        }
+        self.reset_pending = false;
+    }
+
+    /// Adds the items the user imported, see `ShoppingList::apply_import`.
+    pub fn apply_import(&mut self, import: import::Import, limits: &InputLimits, is_admin: bool) -> anyhow::Result<()> {
+        let filter = Some(&self.word_filter).filter(|_| !is_admin);
+        for name in self.list.apply_import(import, limits, filter)? {
+            self.names.register(&name);
+        }
+        Ok(())
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    const LIMITS: InputLimits = InputLimits { max_items: 5, max_item_length: 20, max_recipe_ingredients: 10 };
+
+    fn list() -> ShoppingList {
+        let mut list = ShoppingList::default();
+        list.recipes.insert("Pfannkuchen".to_string(), Recipe::new(vec!["Mehl".to_string(), "3× Eier".to_string(), "Milch".to_string()]));
+        list.pantry.add("Mehl");
+        list
+    }
+
+    fn add(item: &str) -> batch::Operation {
+        batch::Operation::now(batch::Kind::Add, item)
+    }
+
+    #[test]
+    fn recipes() {
+        let mut list = list();
+        let recipe = &list.recipes["Pfannkuchen"];
+        assert_eq!(recipe.missing(&list.pantry), vec!["3× Eier", "Milch"]);
+        assert_eq!(list.add_recipe("Pfannkuchen", &[2].iter().copied().collect()), 2);
+        let items: Vec<(&str, u32)> = list.items.iter().map(|item| (item.name.as_str(), item.quantity)).collect();
+        assert_eq!(items, vec![("Mehl", 1), ("Eier", 3)]);
+        assert!(list.items.iter().all(|item| item.recipe.as_deref() == Some("Pfannkuchen")));
+        assert_eq!(list.add_recipe("Waffeln", &HashSet::new()), 0);
+    }
+
+    #[test]
+    fn clearing_checked_items() {
+        let mut list = list();
+        list.pantry.mark_low("Milch");
+        list.add_text("Milch".to_string());
+        list.add_text("Brot".to_string());
+        list.assign_item_ids();
+        let id = list.items[0].id;
+        assert!(list.toggle_item(id, 7).is_some_and(|item| item.done));
+        let bought = list.clear_done();
+        assert_eq!(bought.len(), 1);
+        assert_eq!(list.items.len(), 1);
+        assert_eq!(list.history.len(), 1);
+        assert_eq!(list.history[0].bought_by, Some(7));
+        assert!(!list.pantry.is_low("Milch"));
+        assert!(list.toggle_item(id, 7).is_none());
+    }
+
+    #[test]
+    fn operations() {
+        let mut list = list();
+        let language = i18n::Language::German;
+        assert!(list.apply_operation(&add("Milch"), language, &LIMITS, None).unwrap());
+        assert!(!list.apply_operation(&add("Milch"), language, &LIMITS, None).unwrap());
+        assert!(list.apply_operation(&batch::Operation::now(batch::Kind::Check, "Milch"), language, &LIMITS, None).unwrap());
+        assert!(list.items[0].done);
+        let filter = WordFilter { words: vec!["bier".to_string()], mask: false };
+        assert!(!list.apply_operation(&add("Bier"), language, &LIMITS, Some(&filter)).unwrap());
+        let filter = WordFilter { mask: true, ..filter };
+        assert!(list.apply_operation(&add("Bier"), language, &LIMITS, Some(&filter)).unwrap());
+        assert_eq!(list.items[1].name, "B***");
+    }
+
+    #[test]
+    fn operations_over_the_limits() {
+        let mut list = list();
+        let language = i18n::Language::German;
+        assert!(list.apply_operation(&add("Ein viel zu langer Eintrag"), language, &LIMITS, None).is_err());
+        for name in ["Äpfel", "Brot", "Butter", "Eier", "Käse"] {
+            list.apply_operation(&add(name), language, &LIMITS, None).unwrap();
+        }
+        assert!(list.apply_operation(&add("Milch"), language, &LIMITS, None).is_err());
+        assert_eq!(list.items.len(), LIMITS.max_items);
+    }
+
+    #[test]
+    fn imports() {
+        let mut list = list();
+        let import = |items: &[&str]| import::Import {
+            source: "Test".to_string(),
+            items: items.iter().map(|name| import::Entry { name: name.to_string(), done: false, quantity: 1, header: false }).collect(),
+            recipes: vec![
+                ("Pfannkuchen".to_string(), vec!["Mehl".to_string()]),
+                ("Toast".to_string(), vec!["Brot".to_string(), "Bier".to_string()]),
+            ],
+        };
+        let filter = WordFilter { words: vec!["bier".to_string()], mask: false };
+        assert!(list.apply_import(import(&["Milch"]), &LIMITS, Some(&filter)).is_err());
+        assert!(list.apply_import(import(&["Milch"; 6]), &LIMITS, None).is_err());
+        assert!(list.items.is_empty());
+        assert_eq!(list.apply_import(import(&["Milch"]), &LIMITS, None).unwrap(), vec!["Toast"]);
+        assert_eq!(list.items.len(), 1);
+        assert_eq!(list.recipes["Pfannkuchen"].ingredients.len(), 3);
+    }
+
+    #[test]
+    fn stored_format() {
+        let json = r#"{"items": [["Milch", true], {"name": "Brot", "done": false, "assignee": null, "due": null}], "recipes": {"Toast": ["Brot"]}}"#;
+        let list: ShoppingList = serde_json::from_str(json).unwrap();
+        assert!(list.items[0].done);
+        assert_eq!(list.items[1].name, "Brot");
+        assert_eq!(serde_json::to_value(&list.recipes).unwrap(), serde_json::json!({"Toast": ["Brot"]}));
+    }
+}
@@ -0,0 +1,64 @@
+//! Finds a recipe that a recipe about to be saved likely duplicates, by a name
+//! that differs only by a typo or by nearly the same ingredients. The composer
+//! then asks whether to merge, overwrite or keep both.
+
+use std::collections::{HashMap, HashSet};
+
+use crate::domain::Recipe;
+use crate::search;
+
+/// Share of the ingredients both recipes have, of all their ingredients.
+const SAME_INGREDIENTS: f64 = 0.8;
+/// Fewer ingredients match too many recipes by chance.
+const MIN_INGREDIENTS: usize = 3;
+
+fn ingredient_set(ingredients: &[String]) -> HashSet<String> {
+    ingredients.iter().map(|ingredient| ingredient.trim().to_lowercase()).collect()
+}
+
+fn similar_names(a: &str, b: &str) -> bool {
+    let (a, b) = (a.trim().to_lowercase(), b.trim().to_lowercase());
+    a == b || search::distance(&a, &b) <= search::tolerance(&a)
+}
+
+fn similar_ingredients(a: &HashSet<String>, b: &HashSet<String>) -> bool {
+    if a.len() < MIN_INGREDIENTS || b.len() < MIN_INGREDIENTS {
+        return false;
+    }
+    let shared = a.intersection(b).count();
+    shared as f64 / a.union(b).count() as f64 >= SAME_INGREDIENTS
+}
+
+/// The existing recipe closest to the new one, a similar name before similar ingredients.
+pub fn find<'a>(recipes: &'a HashMap<String, Recipe>, name: &str, ingredients: &[String]) -> Option<&'a str> {
+    let ingredients = ingredient_set(ingredients);
+    let mut names: Vec<&String> = recipes.keys().collect();
+    names.sort();
+    names.iter()
+        .find(|existing| similar_names(existing, name))
+        .or_else(|| names.iter().find(|existing| similar_ingredients(&ingredient_set(&recipes[**existing].ingredients), &ingredients)))
+        .map(|existing| existing.as_str())
+}
+
+/// The ingredients of both, those of `existing` first, without repeating one.
+pub fn merge(existing: &[String], new: &[String]) -> Vec<String> {
+    let mut merged = existing.to_vec();
+    let mut seen = ingredient_set(existing);
+    for ingredient in new {
+        if seen.insert(ingredient.trim().to_lowercase()) {
+            merged.push(ingredient.clone());
+        }
+    }
+    merged
+}
+
+/// `name`, or with a number after it if a recipe of that name exists.
+pub fn free_name(recipes: &HashMap<String, Recipe>, name: &str) -> String {
+    if !recipes.contains_key(name) {
+        return name.to_string();
+    }
+    (2..)
+        .map(|number| format!("{} ({})", name, number))
+        .find(|candidate| !recipes.contains_key(candidate))
+        .unwrap_or_default()
+}
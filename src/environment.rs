@@ -0,0 +1,158 @@
+use std::collections::HashSet;
+use std::net::SocketAddr;
+use std::str::FromStr;
+use std::time::Duration;
+
+use chrono_tz::Tz;
+
+use crate::rate_limit::Limits;
+
+/// Deployment settings read from environment variables at startup.
+pub struct Environment {
+    /// `BOT_ENV=staging` runs the bot against a separate data file and test chats only.
+    pub staging: bool,
+    /// The default bot from `TELOXIDE_TOKEN`, or every bot from `BOT_TOKENS`
+    /// (`namespace=token,...`) when several households share one process.
+    pub bots: Vec<BotConfig>,
+    /// Chats the staging bot is allowed to act in, from `TEST_CHAT_IDS` (comma separated).
+    pub test_chats: HashSet<i64>,
+    /// Users allowed to run admin commands, from `ADMIN_IDS` (comma separated).
+    pub admins: HashSet<i64>,
+    /// From `MAX_ITEMS`, `MAX_ITEM_LENGTH` and `MAX_RECIPE_INGREDIENTS`.
+    pub input_limits: InputLimits,
+    /// From `RATE_LIMIT_BURST`, `RATE_LIMIT_PER_MINUTE` and `RATE_LIMIT_MUTE_SECONDS`.
+    pub rate_limits: Limits,
+    /// Address for the embedded HTTP server (`/healthz`), from `HTTP_ADDR`.
+    pub http_addr: Option<SocketAddr>,
+    /// Used for chats that haven't set `/timezone`, from `DEFAULT_TIMEZONE`.
+    pub default_timezone: Tz,
+    /// How the HTTP server is reached from outside, e.g. `https://bot.example.org`,
+    /// from `PUBLIC_URL`.
+    pub public_url: Option<String>,
+    /// How long the settings of a chat the bot was removed from are kept, from
+    /// `ARCHIVE_RETENTION_DAYS`.
+    pub archive_retention_days: i64,
+}
+
+pub struct BotConfig {
+    /// Keeps the state of each bot apart, empty for the default bot.
+    pub namespace: String,
+    pub token: String,
+}
+
+/// Keeps the active message below Telegram's size limits and the data file small.
+pub struct InputLimits {
+    pub max_items: usize,
+    /// In characters.
+    pub max_item_length: usize,
+    pub max_recipe_ingredients: usize,
+}
+
+impl InputLimits {
+    pub fn check_text(&self, text: &str) -> anyhow::Result<()> {
+        if text.chars().count() > self.max_item_length {
+            anyhow::bail!("Zu lang, maximal {} Zeichen", self.max_item_length);
+        }
+        Ok(())
+    }
+
+    pub fn check_item_count(&self, count: usize) -> anyhow::Result<()> {
+        if count > self.max_items {
+            anyhow::bail!("Die Liste ist voll, maximal {} Einträge", self.max_items);
+        }
+        Ok(())
+    }
+
+    pub fn check_ingredient_count(&self, count: usize) -> anyhow::Result<()> {
+        if count > self.max_recipe_ingredients {
+            anyhow::bail!("Das Rezept ist voll, maximal {} Zutaten", self.max_recipe_ingredients);
+        }
+        Ok(())
+    }
+}
+
+impl Environment {
+    pub fn from_env() -> Self {
+        let staging = std::env::var("BOT_ENV").map(|env| env == "staging").unwrap_or(false);
+        Self {
+            staging,
+            bots: parse_bots(),
+            test_chats: parse_ids("TEST_CHAT_IDS"),
+            admins: parse_ids("ADMIN_IDS"),
+            input_limits: InputLimits {
+                max_items: parse_or("MAX_ITEMS", 100),
+                max_item_length: parse_or("MAX_ITEM_LENGTH", 100),
+                max_recipe_ingredients: parse_or("MAX_RECIPE_INGREDIENTS", 50),
+            },
+            rate_limits: Limits {
+                burst: parse_or("RATE_LIMIT_BURST", 20.0),
+                per_minute: parse_or("RATE_LIMIT_PER_MINUTE", 30.0),
+                mute: Duration::from_secs(parse_or("RATE_LIMIT_MUTE_SECONDS", 60)),
+            },
+            http_addr: std::env::var("HTTP_ADDR").ok().and_then(|addr| addr.parse().ok()),
+            default_timezone: parse_or("DEFAULT_TIMEZONE", chrono_tz::Europe::Berlin),
+            public_url: std::env::var("PUBLIC_URL").ok().map(|url| url.trim_end_matches('/').to_string()),
+            archive_retention_days: parse_or("ARCHIVE_RETENTION_DAYS", 30),
+        }
+    }
+
+    /// Every namespace gets its own data file next to the default one.
+    pub fn data_path(&self, namespace: &str) -> String {
+        let mut path = "./shopping_list_bot".to_string();
+        if !namespace.is_empty() {
+            path.push('.');
+            path.push_str(namespace);
+        }
+        if self.staging {
+            path.push_str(".staging");
+        }
+        path.push_str(".json");
+        path
+    }
+
+    pub fn is_admin(&self, user_id: i64) -> bool {
+        self.admins.contains(&user_id)
+    }
+
+    pub fn allows_chat(&self, chat_id: i64) -> bool {
+        !self.staging || self.test_chats.contains(&chat_id)
+    }
+
+    /// Marks every outgoing text of the staging bot.
+    pub fn decorate(&self, text: String) -> String {
+        if self.staging {
+            format!("[TEST] {}", text)
+        } else {
+            text
+        }
+    }
+}
+
+fn parse_ids(variable: &str) -> HashSet<i64> {
+    std::env::var(variable)
+        .unwrap_or_default()
+        .split(',')
+        .filter_map(|id| id.trim().parse().ok())
+        .collect()
+}
+
+fn parse_bots() -> Vec<BotConfig> {
+    match std::env::var("BOT_TOKENS") {
+        Ok(tokens) => tokens
+            .split(',')
+            .filter_map(|entry| entry.split_once('='))
+            .map(|(namespace, token)| BotConfig {
+                namespace: namespace.trim().to_string(),
+                token: token.trim().to_string(),
+            })
+            .collect(),
+        Err(_) => vec![BotConfig {
+            namespace: String::new(),
+            token: std::env::var("TELOXIDE_TOKEN").unwrap_or_default(),
+        }],
+    }
+}
+
+fn parse_or<T: FromStr>(variable: &str, default: T) -> T {
+    std::env::var(variable).ok().and_then(|value| value.parse().ok()).unwrap_or(default)
+}
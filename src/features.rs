@@ -0,0 +1,45 @@
+//! Experimental parts of the bot, off unless an admin turns them on for a chat
+//! with `/feature`, or for every chat with `default_features` in the config
+//! file, see `config`. This way they can be tried in one chat first.
+
+use serde::{Deserialize, Serialize};
+
+#[derive(Serialize, Deserialize, Clone, Copy, PartialEq, Eq, Hash, Debug)]
+#[serde(rename_all = "snake_case")]
+pub enum Feature {
+    /// `/plan` and `/rate`.
+    MealPlanner,
+    /// `/sharepage` and `/api`, the list on the web.
+    WebApp,
+    /// `/price` and `/compare`.
+    PriceTracking,
+}
+
+pub const ALL: [Feature; 3] = [Feature::MealPlanner, Feature::WebApp, Feature::PriceTracking];
+
+impl Feature {
+    pub fn name(self) -> &'static str {
+        match self {
+            Feature::MealPlanner => "meal_planner",
+            Feature::WebApp => "web_app",
+            Feature::PriceTracking => "price_tracking",
+        }
+    }
+
+    pub fn parse(name: &str) -> Option<Self> {
+        ALL.iter().copied().find(|feature| feature.name() == name)
+    }
+
+    fn commands(self) -> &'static [&'static str] {
+        match self {
+            Feature::MealPlanner => &["plan", "rate"],
+            Feature::WebApp => &["sharepage", "api"],
+            Feature::PriceTracking => &["price", "compare"],
+        }
+    }
+
+    /// The feature the command belongs to, if it is experimental.
+    pub fn for_command(command: &str) -> Option<Self> {
+        ALL.iter().copied().find(|feature| feature.commands().contains(&command))
+    }
+}
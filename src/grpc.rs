@@ -0,0 +1,141 @@
+//! Optional gRPC service for internal tooling, compiled in with the `grpc`
+//! feature and served on `GRPC_ADDR`. See `proto/shopping_list.proto`, changes
+//! go through the same operations as the batch API.
+
+use std::sync::Arc;
+
+use teloxide::prelude::*;
+
+use crate::Instance;
+
+#[cfg(feature = "grpc")]
+mod enabled {
+    use std::net::SocketAddr;
+    use std::sync::Arc;
+
+    use teloxide::prelude::*;
+    use tonic::{Request, Response, Status};
+
+    use crate::{batch, Instance};
+
+    mod proto {
+        tonic::include_proto!("shopping_list");
+    }
+
+    use proto::shopping_list_server::{ShoppingList, ShoppingListServer};
+
+    struct Service {
+        bots: Vec<(Bot, Arc<Instance>)>,
+    }
+
+    impl Service {
+        /// The bot of the list whose `/api` token the request carries.
+        async fn authorize<T>(&self, request: &Request<T>) -> Result<(Bot, Arc<Instance>), Status> {
+            let token = request.metadata()
+                .get("authorization")
+                .and_then(|value| value.to_str().ok())
+                .and_then(|value| value.strip_prefix("Bearer "))
+                .ok_or_else(|| Status::unauthenticated("Missing authorization: Bearer <token>"))?;
+            for (bot, instance) in self.bots.iter() {
+                if instance.snapshot.load().api_token.as_deref() == Some(token) {
+                    return Ok((bot.clone(), instance.clone()));
+                }
+            }
+            Err(Status::permission_denied("Unknown token"))
+        }
+
+        async fn change(&self, kind: batch::Kind, request: Request<proto::ItemRequest>) -> Result<Response<proto::Change>, Status> {
+            let (bot, instance) = self.authorize(&request).await?;
+            let request = request.into_inner();
+            let mut operation = batch::Operation::now(kind, &request.name);
+            if request.quantity > 0 {
+                operation.quantity = Some(request.quantity);
+            }
+            let (applied, rejected) = batch::apply(&bot, &instance, vec![operation]).await;
+            if let Some(reason) = rejected.into_iter().next() {
+                return Err(Status::invalid_argument(reason));
+            }
+            Ok(Response::new(proto::Change { applied: !applied.is_empty() }))
+        }
+    }
+
+    #[tonic::async_trait]
+    impl ShoppingList for Service {
+        async fn list_items(&self, request: Request<proto::ListItemsRequest>) -> Result<Response<proto::Items>, Status> {
+            let (_, instance) = self.authorize(&request).await?;
+            let data = instance.snapshot.load_full();
+            let mut section = String::new();
+            let mut items = Vec::new();
+            for item in data.list.items.iter() {
+                if item.header {
+                    section = item.name.clone();
+                    continue;
+                }
+                items.push(proto::Item { name: item.name.clone(), done: item.done, quantity: item.quantity, section: section.clone() });
+            }
+            Ok(Response::new(proto::Items { items }))
+        }
+
+        async fn add_item(&self, request: Request<proto::ItemRequest>) -> Result<Response<proto::Change>, Status> {
+            self.change(batch::Kind::Add, request).await
+        }
+
+        async fn check_item(&self, request: Request<proto::ItemRequest>) -> Result<Response<proto::Change>, Status> {
+            self.change(batch::Kind::Check, request).await
+        }
+
+        async fn uncheck_item(&self, request: Request<proto::ItemRequest>) -> Result<Response<proto::Change>, Status> {
+            self.change(batch::Kind::Uncheck, request).await
+        }
+
+        async fn remove_item(&self, request: Request<proto::ItemRequest>) -> Result<Response<proto::Change>, Status> {
+            self.change(batch::Kind::Delete, request).await
+        }
+
+        async fn list_recipes(&self, request: Request<proto::ListRecipesRequest>) -> Result<Response<proto::Recipes>, Status> {
+            let (_, instance) = self.authorize(&request).await?;
+            let data = instance.snapshot.load_full();
+            let mut recipes: Vec<proto::Recipe> = data.list.recipes.iter()
+                .map(|(name, recipe)| proto::Recipe {
+                    name: name.clone(),
+                    ingredients: recipe.ingredients.clone(),
+                    archived: data.list.archived_recipes.contains(name),
+                })
+                .collect();
+            recipes.sort_by_key(|recipe| recipe.name.to_lowercase());
+            Ok(Response::new(proto::Recipes { recipes }))
+        }
+
+        async fn list_pantry(&self, request: Request<proto::ListPantryRequest>) -> Result<Response<proto::Pantry>, Status> {
+            let (_, instance) = self.authorize(&request).await?;
+            let data = instance.snapshot.load_full();
+            let products = data.list.pantry.products().iter()
+                .map(|name| proto::Product { name: name.clone(), low: data.list.pantry.is_low(name) })
+                .collect();
+            Ok(Response::new(proto::Pantry { products }))
+        }
+    }
+
+    pub async fn serve(addr: SocketAddr, bots: Vec<(Bot, Arc<Instance>)>) {
+        log::info!("Serving gRPC on {}", addr);
+        let service = ShoppingListServer::new(Service { bots });
+        if let Err(error) = tonic::transport::Server::builder().add_service(service).serve(addr).await {
+            log::error!("gRPC server failed: {:?}", error);
+        }
+    }
+}
+
+/// Starts the gRPC server if `GRPC_ADDR` is set.
+pub fn spawn(bots: &[(Bot, Arc<Instance>)]) {
+    let addr = match std::env::var("GRPC_ADDR").ok().and_then(|addr| addr.parse().ok()) {
+        Some(addr) => addr,
+        None => return,
+    };
+    #[cfg(feature = "grpc")]
+    tokio::spawn(enabled::serve(addr, bots.to_vec()));
+    #[cfg(not(feature = "grpc"))]
+    {
+        let _: (std::net::SocketAddr, _) = (addr, bots);
+        log::warn!("GRPC_ADDR is set, but this build lacks the grpc feature");
+    }
+}
@@ -0,0 +1,62 @@
+use std::collections::HashMap;
+
+use chrono::{DateTime, Duration, Utc};
+use serde::{Deserialize, Serialize};
+
+use crate::deep_link;
+
+pub const READ_ONLY_TEXT: &str = "Du bist Gast und kannst die Liste nur ansehen.";
+pub const EXPIRED_TEXT: &str = "Der Gastlink ist abgelaufen.";
+
+const TOKEN_LENGTH: usize = 16;
+
+/// Time limited links that let other chats view, but not change, the list.
+#[derive(Serialize, Deserialize, Clone, Default)]
+pub struct GuestAccess {
+    /// Link tokens and when they expire. A token can be used by several chats.
+    tokens: HashMap<String, DateTime<Utc>>,
+    /// Chats that opened a guest link and until when they may view the list.
+    chats: HashMap<i64, DateTime<Utc>>,
+}
+
+#[derive(PartialEq)]
+pub enum Access {
+    Member,
+    Guest,
+    Expired,
+}
+
+impl GuestAccess {
+    /// Moves the access of a group that became a supergroup to its new ID.
+    pub fn migrate_chat(&mut self, from: i64, to: i64) {
+        if let Some(expires) = self.chats.remove(&from) {
+            self.chats.insert(to, expires);
+        }
+    }
+
+    pub fn create_token(&mut self, valid_for: Duration) -> String {
+        let now = Utc::now();
+        self.tokens.retain(|_, expires| *expires > now);
+        let token = deep_link::random_token(TOKEN_LENGTH);
+        self.tokens.insert(token.clone(), now + valid_for);
+        token
+    }
+
+    /// Makes `chat_id` a guest until the token expires.
+    pub fn redeem(&mut self, token: &str, chat_id: i64) -> Option<DateTime<Utc>> {
+        let expires = *self.tokens.get(token)?;
+        if expires <= Utc::now() {
+            return None;
+        }
+        self.chats.insert(chat_id, expires);
+        Some(expires)
+    }
+
+    pub fn access(&self, chat_id: i64) -> Access {
+        match self.chats.get(&chat_id) {
+            None => Access::Member,
+            Some(expires) if *expires > Utc::now() => Access::Guest,
+            Some(_) => Access::Expired,
+        }
+    }
+}
@@ -0,0 +1,90 @@
+use std::sync::{Arc, Mutex};
+use std::time::Duration;
+
+use chrono::{DateTime, Utc};
+use lazy_static::lazy_static;
+use sd_notify::NotifyState;
+use serde_json::json;
+use teloxide::prelude::*;
+
+use crate::Instance;
+
+const TELEGRAM_CHECK_INTERVAL: Duration = Duration::from_secs(60);
+/// Telegram counts as unreachable if the last successful check is older than this.
+const TELEGRAM_STALE_SECONDS: i64 = 180;
+/// A handler holding the data lock for longer than this is considered hung.
+const DISPATCHER_TIMEOUT: Duration = Duration::from_secs(10);
+
+#[derive(Default)]
+struct HealthState {
+    last_persist: Option<DateTime<Utc>>,
+    last_telegram_check: Option<DateTime<Utc>>,
+    last_telegram_ok: Option<DateTime<Utc>>,
+}
+
+lazy_static! {
+    static ref STATE: Mutex<HealthState> = Mutex::new(HealthState::default());
+}
+
+pub fn record_persist() {
+    STATE.lock().unwrap().last_persist = Some(Utc::now());
+}
+
+/// Whether the bot is healthy, and the details for `/healthz`.
+pub fn report() -> (bool, serde_json::Value) {
+    let state = STATE.lock().unwrap();
+    let telegram_ok = state.last_telegram_ok
+        .map(|at| Utc::now() - at < chrono::Duration::seconds(TELEGRAM_STALE_SECONDS))
+        .unwrap_or(false);
+    (telegram_ok, json!({
+        "healthy": telegram_ok,
+        "telegram_ok": telegram_ok,
+        "last_telegram_check": state.last_telegram_check,
+        "last_telegram_ok": state.last_telegram_ok,
+        "last_persist": state.last_persist,
+    }))
+}
+
+/// Periodically checks that the Telegram API is reachable.
+pub async fn monitor_telegram(bot: Bot) {
+    let mut interval = tokio::time::interval(TELEGRAM_CHECK_INTERVAL);
+    loop {
+        interval.tick().await;
+        let result = bot.get_me().send().await;
+        let mut state = STATE.lock().unwrap();
+        state.last_telegram_check = Some(Utc::now());
+        match result {
+            Ok(_) => state.last_telegram_ok = state.last_telegram_check,
+            Err(error) => log::warn!("Telegram health check failed: {:?}", error),
+        }
+    }
+}
+
+/// Tells systemd that startup is done and, if `WatchdogSec` is configured, keeps
+/// pinging the watchdog as long as no dispatcher is stuck holding its data lock.
+pub async fn notify_systemd(instances: Vec<Arc<Instance>>) {
+    if let Err(error) = sd_notify::notify(false, &[NotifyState::Ready]) {
+        log::warn!("Couldn't notify systemd: {:?}", error);
+    }
+    let mut usec = 0;
+    if !sd_notify::watchdog_enabled(false, &mut usec) {
+        return;
+    }
+    let mut interval = tokio::time::interval(Duration::from_micros(usec / 2));
+    loop {
+        interval.tick().await;
+        let mut hung = false;
+        for instance in instances.iter() {
+            if tokio::time::timeout(DISPATCHER_TIMEOUT, instance.data.lock()).await.is_err() {
+                log::error!("Dispatcher of {:?} seems to be hung, skipping watchdog ping", instance.namespace);
+                hung = true;
+            }
+        }
+        if hung {
+            continue;
+        }
+        if let Err(error) = sd_notify::notify(false, &[NotifyState::Watchdog]) {
+            log::warn!("Couldn't ping systemd watchdog: {:?}", error);
+        }
+    }
+}
@@ -0,0 +1,186 @@
+use std::collections::HashMap;
+
+use chrono::{DateTime, Datelike, NaiveDate, NaiveTime, TimeZone, Utc};
+use chrono_tz::Tz;
+use serde::{Deserialize, Serialize};
+
+use crate::domain::KnownUser;
+use crate::spending::Category;
+
+/// What happened to an item that left the list.
+#[derive(Serialize, Deserialize, Clone, Copy, PartialEq, Default)]
+pub enum Event {
+    #[default]
+    Bought,
+    /// Removed with 🗑 without being bought, left out of every statistic.
+    Deleted,
+}
+
+/// An item that was checked off and purged from the list, or deleted.
+#[derive(Serialize, Deserialize, Clone)]
+pub struct Purchase {
+    pub name: String,
+    pub bought_by: Option<i64>,
+    pub at: DateTime<Utc>,
+    /// In cents, if a price was recorded.
+    #[serde(default)]
+    pub price: Option<u64>,
+    #[serde(default)]
+    pub category: Option<Category>,
+    /// The recipe the item was added with.
+    #[serde(default)]
+    pub recipe: Option<String>,
+    #[serde(default)]
+    pub event: Event,
+    #[serde(default = "default_quantity")]
+    pub quantity: u32,
+}
+
+fn default_quantity() -> u32 {
+    1
+}
+
+/// Purchases of a month older than the retention, see `compact`.
+#[derive(Serialize, Deserialize, Clone)]
+pub struct Summary {
+    pub year: i32,
+    pub month: u32,
+    /// How often each item was bought, by the name it was bought with first.
+    pub bought: HashMap<String, u32>,
+    /// In cents, of the purchases with a price.
+    pub spent: u64,
+}
+
+/// Purchases of an item looked at for its usual quantity.
+const USUAL_QUANTITY_PURCHASES: usize = 5;
+
+impl Purchase {
+    pub fn bought(&self) -> bool {
+        self.event == Event::Bought
+    }
+}
+
+/// On how many days something from the recipe was bought, and the last time.
+pub fn recipe_usage(history: &[Purchase], recipe: &str) -> (usize, Option<DateTime<Utc>>) {
+    let mut days: Vec<_> = history.iter()
+        .filter(|purchase| purchase.bought() && purchase.recipe.as_deref() == Some(recipe))
+        .map(|purchase| purchase.at)
+        .collect();
+    days.sort();
+    let last = days.last().copied();
+    days.dedup_by_key(|at| at.naive_utc().date());
+    (days.len(), last)
+}
+
+const BADGES: [&str; 3] = ["MVP des Monats", "Einkaufsprofi", "Korbträger"];
+const MEDALS: [&str; 3] = ["🏆", "🥈", "🥉"];
+
+/// Number of purchases per user in the current month of `timezone`, most active first.
+pub fn monthly_ranking(history: &[Purchase], timezone: Tz) -> Vec<(i64, usize)> {
+    let now = Utc::now().with_timezone(&timezone);
+    let mut counts: HashMap<i64, usize> = HashMap::new();
+    for purchase in history.iter().filter(|purchase| purchase.bought()) {
+        let at = purchase.at.with_timezone(&timezone);
+        if at.year() != now.year() || at.month() != now.month() {
+            continue;
+        }
+        if let Some(user) = purchase.bought_by {
+            *counts.entry(user).or_insert(0) += 1;
+        }
+    }
+    let mut ranking: Vec<(i64, usize)> = counts.into_iter().collect();
+    ranking.sort_by(|(a_user, a_count), (b_user, b_count)| b_count.cmp(a_count).then(a_user.cmp(b_user)));
+    ranking
+}
+
+/// How many of the item are usually bought, if it is more than one and was bought that often at least twice recently.
+pub fn usual_quantity(history: &[Purchase], name: &str) -> Option<u32> {
+    let mut counts: HashMap<u32, usize> = HashMap::new();
+    for purchase in history.iter()
+        .rev()
+        .filter(|purchase| purchase.bought() && purchase.name.eq_ignore_ascii_case(name))
+        .take(USUAL_QUANTITY_PURCHASES) {
+        *counts.entry(purchase.quantity).or_insert(0) += 1;
+    }
+    let (quantity, count) = counts.into_iter().max_by_key(|(quantity, count)| (*count, *quantity))?;
+    Some(quantity).filter(|quantity| *quantity > 1 && count >= 2)
+}
+
+/// The first of the month `months` months ago, what is older is compacted.
+pub fn retention_cutoff(months: u32) -> Option<DateTime<Utc>> {
+    let now = Utc::now();
+    let months_since_year_zero = now.year() * 12 + now.month0() as i32 - months as i32;
+    let first = NaiveDate::from_ymd_opt(months_since_year_zero.div_euclid(12), months_since_year_zero.rem_euclid(12) as u32 + 1, 1)?;
+    Some(Utc.from_utc_datetime(&first.and_time(NaiveTime::MIN)))
+}
+
+/// Folds the purchases from before `cutoff` into monthly summaries and drops
+/// the deleted items, returns how many entries left the history.
+pub fn compact(history: &mut Vec<Purchase>, summaries: &mut Vec<Summary>, cutoff: DateTime<Utc>) -> usize {
+    let before = history.len();
+    for purchase in history.iter().filter(|purchase| purchase.at < cutoff && purchase.bought()) {
+        let (year, month) = (purchase.at.year(), purchase.at.month());
+        let index = match summaries.iter().position(|summary| summary.year == year && summary.month == month) {
+            Some(index) => index,
+            None => {
+                summaries.push(Summary { year, month, bought: HashMap::new(), spent: 0 });
+                summaries.len() - 1
+            }
+        };
+        let summary = &mut summaries[index];
+        let name = summary.bought.keys()
+            .find(|name| name.to_lowercase() == purchase.name.to_lowercase())
+            .cloned()
+            .unwrap_or_else(|| purchase.name.clone());
+        *summary.bought.entry(name).or_insert(0) += 1;
+        summary.spent += purchase.price.unwrap_or(0);
+    }
+    summaries.sort_by_key(|summary| (summary.year, summary.month));
+    history.retain(|purchase| purchase.at >= cutoff);
+    before - history.len()
+}
+
+/// The ten items bought most often, for `/stats`.
+pub fn most_bought_text(history: &[Purchase], summaries: &[Summary]) -> String {
+    let mut counts: HashMap<String, (String, usize)> = HashMap::new();
+    for purchase in history.iter().filter(|purchase| purchase.bought()) {
+        counts.entry(purchase.name.to_lowercase()).or_insert_with(|| (purchase.name.clone(), 0)).1 += 1;
+    }
+    for (name, count) in summaries.iter().flat_map(|summary| summary.bought.iter()) {
+        counts.entry(name.to_lowercase()).or_insert_with(|| (name.clone(), 0)).1 += *count as usize;
+    }
+    if counts.is_empty() {
+        return "Noch nichts gekauft.".to_string();
+    }
+    let mut counts: Vec<(String, usize)> = counts.into_values().collect();
+    counts.sort_by(|a, b| b.1.cmp(&a.1).then(a.0.cmp(&b.0)));
+    counts.iter()
+        .take(10)
+        .fold("Am häufigsten gekauft:".to_string(), |a, (name, count)| format!("{}\n - {}: {}×", a, name, count))
+}
+
+pub fn leaderboard_text(history: &[Purchase], users: &HashMap<i64, KnownUser>, timezone: Tz) -> String {
+    let ranking = monthly_ranking(history, timezone);
+    if ranking.is_empty() {
+        return "Diesen Monat wurde noch nichts abgehakt.".to_string();
+    }
+    ranking.iter()
+        .enumerate()
+        .fold(
+            format!("Bestenliste {}:\n", Utc::now().with_timezone(&timezone).format("%m/%Y")),
+            |a, (place, (user, count))| {
+                let name = users.get(user).map(KnownUser::name).unwrap_or_else(|| "?".to_string());
+                match (MEDALS.get(place), BADGES.get(place)) {
+                    (Some(medal), Some(badge)) => format!("{}\n{} {}: {} ({})", a, medal, name, count, badge),
+                    _ => format!("{}\n{}. {}: {}", a, place + 1, name, count),
+                }
+            },
+        )
+}
+
+/// Header line crowning this month's most active shopper, if there is one.
+pub fn mvp_header(history: &[Purchase], users: &HashMap<i64, KnownUser>, timezone: Tz) -> Option<String> {
+    let (user, _) = monthly_ranking(history, timezone).into_iter().next()?;
+    let user = users.get(&user)?;
+    Some(format!("{}: {} {}", BADGES[0], user.name(), MEDALS[0]))
+}
@@ -0,0 +1,318 @@
+use std::net::SocketAddr;
+use std::sync::Arc;
+
+use chrono::Utc;
+use serde_json::Value;
+use teloxide::prelude::*;
+use utoipa::OpenApi;
+use warp::Filter;
+use warp::http::{HeaderMap, StatusCode};
+use warp::hyper::body::Bytes;
+
+use crate::{batch, health, mail, meal_plan, push, share_page, store_data, voice, voice_auth, Instance};
+
+/// Upper bound of a voice assistant request, in bytes.
+const MAX_VOICE_BYTES: u64 = 64 * 1024;
+
+/// The description of the endpoints below at `/openapi.json`, shown by the page at `/docs`.
+#[derive(OpenApi)]
+#[openapi(
+    info(title = "Shopping list bot", description = "Endpoints of the embedded HTTP server. Tokens come from the bot's commands."),
+    paths(healthz, calendar, share_page, apply_batch, connect, voice, add_from_mail),
+    components(schemas(batch::Batch, batch::Operation, batch::Kind, batch::Outcome, batch::Rejected, mail::Mail)),
+)]
+struct ApiDoc;
+
+/// Swagger UI, loaded from a CDN so it doesn't have to be bundled.
+const DOCS_PAGE: &str = r##"<!DOCTYPE html>
+<html lang="en">
+<head>
+<meta charset="utf-8">
+<title>Shopping list bot API</title>
+<link rel="stylesheet" href="https://unpkg.com/swagger-ui-dist@5/swagger-ui.css">
+</head>
+<body>
+<div id="swagger-ui"></div>
+<script src="https://unpkg.com/swagger-ui-dist@5/swagger-ui-bundle.js"></script>
+<script>SwaggerUIBundle({ url: "openapi.json", dom_id: "#swagger-ui" });</script>
+</body>
+</html>
+"##;
+
+/// Embedded HTTP server, only started if `HTTP_ADDR` is set.
+pub async fn serve(addr: SocketAddr, bots: Vec<(Bot, Arc<Instance>)>) {
+    let healthz = warp::path("healthz")
+        .and(warp::path::end())
+        .and(warp::get())
+        .map(healthz);
+    let openapi = warp::path("openapi.json")
+        .and(warp::path::end())
+        .and(warp::get())
+        .map(|| warp::reply::json(&ApiDoc::openapi()));
+    let docs = warp::path("docs")
+        .and(warp::path::end())
+        .and(warp::get())
+        .map(|| warp::reply::html(DOCS_PAGE));
+
+    let bots = Arc::new(bots);
+    let calendar_bots = bots.clone();
+    let calendar = warp::path!("calendar" / String)
+        .and(warp::get())
+        .and_then(move |file: String| calendar(calendar_bots.clone(), file));
+    let page_bots = bots.clone();
+    let page = warp::path!("list" / String)
+        .and(warp::get())
+        .and_then(move |token: String| share_page(page_bots.clone(), token));
+    let push_bots = bots.clone();
+    let push = warp::path!("ws" / String)
+        .and(warp::ws())
+        .and_then(move |token: String, ws: warp::ws::Ws| connect(push_bots.clone(), token, ws));
+    let voice_bots = bots.clone();
+    let voice = warp::path!("voice" / String)
+        .and(warp::post())
+        .and(warp::header::headers_cloned())
+        .and(warp::body::content_length_limit(MAX_VOICE_BYTES))
+        .and(warp::body::bytes())
+        .and_then(move |assistant: String, headers: HeaderMap, body: Bytes| voice(voice_bots.clone(), assistant, headers, body));
+    let mail_bots = bots.clone();
+    let mail = warp::path!("mail" / String)
+        .and(warp::post())
+        .and(warp::body::content_length_limit(mail::MAX_BYTES))
+        .and(warp::body::json().or(warp::body::form()).unify())
+        .and_then(move |token: String, mail: mail::Mail| add_from_mail(mail_bots.clone(), token, mail));
+    let batch = warp::path!("api" / String / "batch")
+        .and(warp::post())
+        .and(warp::body::content_length_limit(batch::MAX_BYTES))
+        .and(warp::body::json())
+        .and_then(move |token: String, batch: batch::Batch| apply_batch(bots.clone(), token, batch));
+
+    log::info!("Serving HTTP on {}", addr);
+    warp::serve(healthz.or(openapi).or(docs).or(calendar).or(page).or(batch).or(push).or(voice).or(mail)).run(addr).await;
+}
+
+/// The meal plan of the bot whose `/calendar` token is in the file name.
+#[utoipa::path(
+    get, path = "/calendar/{file}", tag = "Feeds",
+    params(("file" = String, Path, description = "Token from /calendar followed by .ics")),
+    responses((status = 200, description = "iCalendar feed", content_type = "text/calendar"), (status = 404, description = "Unknown token")),
+)]
+async fn calendar(bots: Arc<Vec<(Bot, Arc<Instance>)>>, file: String) -> Result<Box<dyn warp::Reply>, warp::Rejection> {
+    let token = file.trim_end_matches(".ics");
+    for (_, instance) in bots.iter() {
+        let data = instance.snapshot.load_full();
+        if data.calendar_token.as_deref() == Some(token) {
+            let ical = meal_plan::to_ical(&data.meal_plan, &instance.namespace);
+            return Ok(Box::new(warp::reply::with_header(ical, "content-type", "text/calendar; charset=utf-8")));
+        }
+    }
+    Err(warp::reject::not_found())
+}
+
+/// Whether the bot is healthy, see `health`.
+#[utoipa::path(
+    get, path = "/healthz", tag = "Operations",
+    responses((status = 200, description = "Healthy", body = Object), (status = 503, description = "Unhealthy", body = Object)),
+)]
+fn healthz() -> impl warp::Reply {
+    let (healthy, report) = health::report();
+    let status = if healthy { StatusCode::OK } else { StatusCode::SERVICE_UNAVAILABLE };
+    warp::reply::with_status(warp::reply::json(&report), status)
+}
+
+/// Opens a WebSocket to the list whose `/api` token is in the path, see `push`.
+#[utoipa::path(
+    get, path = "/ws/{token}", tag = "API",
+    params(("token" = String, Path, description = "Token from /api")),
+    responses((status = 101, description = "WebSocket with the list and its changes, see the operations of a batch for changes sent by the client"), (status = 404, description = "Unknown token")),
+)]
+async fn connect(bots: Arc<Vec<(Bot, Arc<Instance>)>>, token: String, ws: warp::ws::Ws) -> Result<Box<dyn warp::Reply>, warp::Rejection> {
+    for (bot, instance) in bots.iter() {
+        if instance.snapshot.load().api_token.as_deref() == Some(token.as_str()) {
+            let (bot, instance) = (bot.clone(), instance.clone());
+            return Ok(Box::new(ws.on_upgrade(move |socket| push::serve(socket, bot, instance, token))));
+        }
+    }
+    Err(warp::reject::not_found())
+}
+
+/// The read-only page of the list whose `/sharepage` token is in the path.
+#[utoipa::path(
+    get, path = "/list/{token}", tag = "Feeds",
+    params(("token" = String, Path, description = "Token from /sharepage")),
+    responses((status = 200, description = "HTML page of the list", content_type = "text/html"), (status = 404, description = "Unknown token")),
+)]
+async fn share_page(bots: Arc<Vec<(Bot, Arc<Instance>)>>, token: String) -> Result<Box<dyn warp::Reply>, warp::Rejection> {
+    for (_, instance) in bots.iter() {
+        let data = instance.snapshot.load_full();
+        if data.share_page_token.as_deref() == Some(token.as_str()) {
+            return Ok(Box::new(warp::reply::html(share_page::to_html(&data))));
+        }
+    }
+    Err(warp::reject::not_found())
+}
+
+/// Applies the offline changes of an integration to the list whose `/api` token is in the path.
+#[utoipa::path(
+    post, path = "/api/{token}/batch", tag = "API",
+    params(("token" = String, Path, description = "Token from /api")),
+    request_body = batch::Batch,
+    responses((status = 200, description = "What became of the operations", body = batch::Outcome), (status = 404, description = "Unknown token")),
+)]
+async fn apply_batch(bots: Arc<Vec<(Bot, Arc<Instance>)>>, token: String, batch: batch::Batch) -> Result<Box<dyn warp::Reply>, warp::Rejection> {
+    for (bot, instance) in bots.iter() {
+        let outcome = {
+            let mut data = instance.data.lock().await;
+            if data.api_token.as_deref() != Some(token.as_str()) {
+                continue;
+            }
+            let outcome = data.apply_batch(batch);
+            if outcome.applied > 0 {
+                data.show_outside_change(bot, "a batch").await;
+            }
+            outcome
+        };
+        store_data(instance).await;
+        return Ok(Box::new(warp::reply::json(&outcome)));
+    }
+    Err(warp::reject::not_found())
+}
+
+/// Adds the items of a mail to the list whose `/mail` token is in the path.
+#[utoipa::path(
+    post, path = "/mail/{token}", tag = "Integrations",
+    params(("token" = String, Path, description = "Token from /mail")),
+    request_body(content = mail::Mail, content_type = "application/json", description = "Also accepted as a form"),
+    responses(
+        (status = 200, description = "Items added"),
+        (status = 422, description = "Items over the limits of the list, the others were added", body = String),
+        (status = 404, description = "Unknown token"),
+    ),
+)]
+async fn add_from_mail(bots: Arc<Vec<(Bot, Arc<Instance>)>>, token: String, mail: mail::Mail) -> Result<Box<dyn warp::Reply>, warp::Rejection> {
+    for (bot, instance) in bots.iter() {
+        let rejected = {
+            let mut data = instance.data.lock().await;
+            if data.mail_token.as_deref() != Some(token.as_str()) {
+                continue;
+            }
+            let (added, rejected) = data.add_from_mail(&mail);
+            if added > 0 {
+                data.show_outside_change(bot, "a mail").await;
+            }
+            rejected
+        };
+        store_data(instance).await;
+        if !rejected.is_empty() {
+            return Ok(Box::new(warp::reply::with_status(rejected.join("\n"), StatusCode::UNPROCESSABLE_ENTITY)));
+        }
+        return Ok(Box::new(StatusCode::OK));
+    }
+    Err(warp::reject::not_found())
+}
+
+/// Answers a request of an Alexa skill (`/voice/alexa`) or a Google Assistant action (`/voice/google`).
+#[utoipa::path(
+    post, path = "/voice/{assistant}", tag = "Integrations",
+    params(("assistant" = String, Path, description = "alexa or google")),
+    request_body(content = Object, description = "Alexa skill request or Dialogflow fulfillment request"),
+    responses(
+        (status = 200, description = "Response for the assistant", body = Object),
+        (status = 400, description = "Not a voice request"),
+        (status = 401, description = "Not signed by Alexa or without the Dialogflow credentials"),
+    ),
+)]
+async fn voice(bots: Arc<Vec<(Bot, Arc<Instance>)>>, assistant: String, headers: HeaderMap, body: Bytes) -> Result<Box<dyn warp::Reply>, warp::Rejection> {
+    let assistant = voice::Assistant::parse(&assistant).ok_or_else(warp::reject::not_found)?;
+    let request: Value = match serde_json::from_slice(&body) {
+        Ok(request) => request,
+        Err(_) => return Ok(Box::new(warp::reply::with_status("Not a voice request", StatusCode::BAD_REQUEST))),
+    };
+    if let Err(error) = voice_auth::verify(assistant, &headers, &body, &request).await {
+        log::warn!("Refusing voice request: {:?}", error);
+        return Ok(Box::new(StatusCode::UNAUTHORIZED));
+    }
+    let request = match assistant.request(&request) {
+        Some(request) => request,
+        None => return Ok(Box::new(warp::reply::with_status("Not a voice request", StatusCode::BAD_REQUEST))),
+    };
+    let (text, end_session) = answer_voice(&bots, request).await;
+    Ok(Box::new(warp::reply::json(&assistant.response(&text, end_session))))
+}
+
+async fn answer_voice(bots: &[(Bot, Arc<Instance>)], request: voice::Request) -> (String, bool) {
+    let code = match request.intent {
+        voice::Intent::Link(code) => code,
+        intent => {
+            for (bot, instance) in bots.iter() {
+                let answer = {
+                    let mut data = instance.data.lock().await;
+                    if !data.voice_users.contains(&request.user) {
+                        continue;
+                    }
+                    let answer = data.answer_voice(intent);
+                    data.show_outside_change(bot, "a voice assistant").await;
+                    answer
+                };
+                store_data(instance).await;
+                return answer;
+            }
+            return ("Noch keine Einkaufsliste verbunden. Schick /voice im Chat der Liste und sag dann: verbinde mit Code, und die Zahl.".to_string(), false);
+        }
+    };
+    if voice::is_throttled(&request.user) {
+        return ("Zu viele falsche Codes. Versuch es in ein paar Minuten noch einmal.".to_string(), true);
+    }
+    let now = Utc::now();
+    let mut linked = None;
+    for (index, (_, instance)) in bots.iter().enumerate() {
+        let data = instance.snapshot.load();
+        if data.voice_code.as_ref().map(|link| link.matches(&code, now)) == Some(true) {
+            linked = Some(index);
+            break;
+        }
+    }
+    let linked = match linked {
+        Some(linked) => linked,
+        None => {
+            voice::record_failed_attempt(&request.user);
+            // The codes are short, so each one only survives a few guesses
+            for (_, instance) in bots.iter() {
+                let dropped = {
+                    let mut data = instance.data.lock().await;
+                    let guessed = match data.voice_code.as_mut().filter(|link| now < link.expires) {
+                        Some(link) => {
+                            link.failed_attempts += 1;
+                            link.failed_attempts >= voice::MAX_FAILED_ATTEMPTS
+                        }
+                        None => continue,
+                    };
+                    if guessed {
+                        data.voice_code = None;
+                        log::warn!("Dropped the voice code of {:?} after {} wrong codes", instance.namespace, voice::MAX_FAILED_ATTEMPTS);
+                    }
+                    guessed
+                };
+                if dropped {
+                    store_data(instance).await;
+                }
+            }
+            return ("Der Code passt nicht oder ist abgelaufen.".to_string(), false);
+        }
+    };
+    // A user belongs to one list only
+    for (index, (_, instance)) in bots.iter().enumerate() {
+        let changed = {
+            let mut data = instance.data.lock().await;
+            if index == linked {
+                data.voice_code = None;
+                data.voice_users.insert(request.user.clone())
+            } else {
+                data.voice_users.remove(&request.user)
+            }
+        };
+        if changed {
+            store_data(instance).await;
+        }
+    }
+    ("Verbunden. Was soll auf die Einkaufsliste?".to_string(), false)
+}
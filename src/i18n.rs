@@ -0,0 +1,128 @@
+//! Texts in the languages a chat can choose during onboarding, and how numbers,
+//! dates and units are written in them.
+
+use serde::{Deserialize, Serialize};
+
+#[derive(Serialize, Deserialize, Clone, Copy, PartialEq, Eq, Hash, Debug, Default)]
+pub enum Language {
+    #[default]
+    German,
+    English,
+}
+
+pub const LANGUAGES: [Language; 2] = [Language::German, Language::English];
+
+impl Language {
+    pub fn name(self) -> &'static str {
+        match self {
+            Language::German => "🇩🇪 Deutsch",
+            Language::English => "🇬🇧 English",
+        }
+    }
+
+    /// `de` or `en`, for commands.
+    pub fn parse(code: &str) -> Option<Self> {
+        match code.to_lowercase().as_str() {
+            "de" | "deutsch" => Some(Language::German),
+            "en" | "english" => Some(Language::English),
+            _ => None,
+        }
+    }
+
+    pub fn code(self) -> &'static str {
+        match self {
+            Language::German => "de",
+            Language::English => "en",
+        }
+    }
+
+    /// `1,5` or `1.5`, with up to three decimals and without trailing zeros.
+    pub fn decimal(self, value: f64) -> String {
+        let value = format!("{:.3}", value);
+        let value = value.trim_end_matches('0').trim_end_matches('.');
+        match self {
+            Language::German => value.replace('.', ","),
+            Language::English => value.to_string(),
+        }
+    }
+
+    /// Day, month and time for `format`, like `24.12. 18:00` or `24/12 18:00`.
+    pub fn date_time_format(self) -> &'static str {
+        match self {
+            Language::German => "%d.%m. %H:%M",
+            Language::English => "%d/%m %H:%M",
+        }
+    }
+}
+
+/// Units of recipes and items that aren't weights or volumes, see `units`.
+#[derive(Clone, Copy, PartialEq, Debug)]
+pub enum Unit {
+    Tablespoon,
+    Teaspoon,
+    Pinch,
+    Piece,
+    Pack,
+}
+
+pub const UNITS: [Unit; 5] = [Unit::Tablespoon, Unit::Teaspoon, Unit::Pinch, Unit::Piece, Unit::Pack];
+
+pub fn unit(language: Language, unit: Unit) -> &'static str {
+    match (language, unit) {
+        (Language::German, Unit::Tablespoon) => "EL",
+        (Language::English, Unit::Tablespoon) => "tbsp",
+        (Language::German, Unit::Teaspoon) => "TL",
+        (Language::English, Unit::Teaspoon) => "tsp",
+        (Language::German, Unit::Pinch) => "Prise",
+        (Language::English, Unit::Pinch) => "pinch",
+        (Language::German, Unit::Piece) => "Stk.",
+        (Language::English, Unit::Piece) => "pcs",
+        (Language::German, Unit::Pack) => "Pck.",
+        (Language::English, Unit::Pack) => "pkg",
+    }
+}
+
+#[derive(Clone, Copy)]
+pub enum Text {
+    ShoppingList,
+    ShoppingListOpen,
+    Menu,
+    ChooseStore,
+    AnyStore,
+    DeleteMessages,
+    Yes,
+    No,
+    LoadDemo,
+    DemoLoaded,
+    ClearDemo,
+    DemoCleared,
+}
+
+pub fn text(language: Language, text: Text) -> &'static str {
+    match (language, text) {
+        (Language::German, Text::ShoppingList) => "Einkaufsliste",
+        (Language::English, Text::ShoppingList) => "Shopping list",
+        (Language::German, Text::ShoppingListOpen) => "nur offene",
+        (Language::English, Text::ShoppingListOpen) => "open only",
+        (Language::German, Text::Menu) => "Was möchtest du tun?",
+        (Language::English, Text::Menu) => "What would you like to do?",
+        (Language::German, Text::ChooseStore) => "Wo kauft ihr meistens ein?",
+        (Language::English, Text::ChooseStore) => "Where do you usually shop?",
+        (Language::German, Text::AnyStore) => "Egal",
+        (Language::English, Text::AnyStore) => "Anywhere",
+        (Language::German, Text::DeleteMessages) => "Sollen eure Nachrichten gelöscht werden, sobald sie auf der Liste stehen?",
+        (Language::English, Text::DeleteMessages) => "Should your messages be deleted once they are on the list?",
+        (Language::German, Text::Yes) => "Ja",
+        (Language::English, Text::Yes) => "Yes",
+        (Language::German, Text::No) => "Nein",
+        (Language::English, Text::No) => "No",
+        (Language::German, Text::LoadDemo) => "Wollt ihr mit einer Beispielliste und zwei Rezepten ausprobieren, wie alles funktioniert?",
+        (Language::English, Text::LoadDemo) => "Would you like to try things out with a sample list and two recipes?",
+        (Language::German, Text::DemoLoaded) => "Tippt auf einen Eintrag, um ihn abzuhaken, 💚 entfernt alles Erledigte und unter 📝🛒 findet ihr die Rezepte. Wenn ihr fertig seid:",
+        (Language::English, Text::DemoLoaded) => "Tap an item to check it off, 💚 removes everything checked and 📝🛒 has the recipes. When you're done:",
+        (Language::German, Text::ClearDemo) => "🗑 Beispiele entfernen",
+        (Language::English, Text::ClearDemo) => "🗑 Remove the samples",
+        (Language::German, Text::DemoCleared) => "Die Beispiele sind weg, viel Spaß mit eurer Liste!",
+        (Language::English, Text::DemoCleared) => "The samples are gone, enjoy your list!",
+    }
+}
@@ -0,0 +1,77 @@
+//! The shopping trips as an iCalendar file for `/export trips.ics`, so they
+//! show up in a calendar next to everything else, e.g. to go through what was
+//! spent.
+
+use chrono::{DateTime, Duration, Utc};
+
+use crate::history::Purchase;
+use crate::money::MoneyFormat;
+use crate::trips::Trip;
+
+/// Length of trips that weren't timed.
+const UNTIMED_MINUTES: i64 = 30;
+/// Longest line the format allows, in bytes, longer ones are folded.
+const MAX_LINE: usize = 75;
+
+/// A calendar with an event per trip.
+pub fn trips(trips: &[Trip], history: &[Purchase], money: &MoneyFormat) -> String {
+    let mut lines = vec![
+        "BEGIN:VCALENDAR".to_string(),
+        "VERSION:2.0".to_string(),
+        "PRODID:-//simple_shopping_list_bot//Einkäufe//DE".to_string(),
+    ];
+    let now = timestamp(Utc::now());
+    for trip in trips {
+        let start = trip.started.unwrap_or(trip.at - Duration::minutes(UNTIMED_MINUTES));
+        let summary = match &trip.store {
+            Some(store) => format!("Einkauf {}", store),
+            None => "Einkauf".to_string(),
+        };
+        let count: u32 = trip.items.iter().map(|item| item.quantity).sum();
+        let mut description = format!("{} Einträge", count);
+        if let Some(cents) = trip.spent(history) {
+            description = format!("{}, {}", description, money.format(cents));
+        }
+        let items: Vec<&str> = trip.items.iter().map(|item| item.name.as_str()).collect();
+        description = format!("{}\n{}", description, items.join(", "));
+        lines.extend(vec![
+            "BEGIN:VEVENT".to_string(),
+            format!("UID:trip-{}-{}@simple_shopping_list_bot", trip.id, trip.at.timestamp()),
+            format!("DTSTAMP:{}", now),
+            format!("DTSTART:{}", timestamp(start)),
+            format!("DTEND:{}", timestamp(trip.at)),
+            format!("SUMMARY:{}", escape(&summary)),
+            format!("DESCRIPTION:{}", escape(&description)),
+            "END:VEVENT".to_string(),
+        ]);
+    }
+    lines.push("END:VCALENDAR".to_string());
+    lines.iter().map(|line| fold(line)).collect::<Vec<_>>().join("")
+}
+
+fn timestamp(at: DateTime<Utc>) -> String {
+    at.format("%Y%m%dT%H%M%SZ").to_string()
+}
+
+pub fn escape(text: &str) -> String {
+    text.replace('\\', "\\\\")
+        .replace(';', "\\;")
+        .replace(',', "\\,")
+        .replace('\n', "\\n")
+}
+
+/// The line with CRLF, continued on lines starting with a space where it is too long.
+pub fn fold(line: &str) -> String {
+    let mut folded = String::new();
+    let mut length = 0;
+    for c in line.chars() {
+        if length + c.len_utf8() > MAX_LINE {
+            folded.push_str("\r\n ");
+            length = 1;
+        }
+        folded.push(c);
+        length += c.len_utf8();
+    }
+    folded.push_str("\r\n");
+    folded
+}
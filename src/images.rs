@@ -0,0 +1,69 @@
+use std::collections::{HashMap, VecDeque};
+use std::sync::Mutex;
+use std::time::Duration;
+
+use lazy_static::lazy_static;
+use serde_json::Value;
+
+const SEARCH_URL: &str = "https://world.openfoodfacts.org/cgi/search.pl";
+const TIMEOUT: Duration = Duration::from_secs(5);
+/// The most lookups kept, the oldest are dropped first.
+const MAX_CACHED: usize = 2000;
+
+#[derive(Default)]
+struct Cache {
+    /// Photo by item name, `None` if there was no match.
+    images: HashMap<String, Option<String>>,
+    /// Names in the order they were looked up.
+    order: VecDeque<String>,
+}
+
+lazy_static! {
+    static ref CACHE: Mutex<Cache> = Mutex::new(Cache::default());
+}
+
+/// The photo looked up for the item name before, `None` if it wasn't looked up yet.
+pub fn cached(name: &str) -> Option<Option<String>> {
+    CACHE.lock().unwrap().images.get(name).cloned()
+}
+
+/// Looks up a product photo for the item name on Open Food Facts and keeps it
+/// for `cached`.
+///
+/// The source can be swapped via `PRODUCT_IMAGE_SEARCH_URL`, as long as it
+/// answers in the same JSON format.
+pub async fn lookup(name: &str) -> Option<String> {
+    let image = search(name).await;
+    let mut cache = CACHE.lock().unwrap();
+    if cache.images.insert(name.to_string(), image.clone()).is_none() {
+        cache.order.push_back(name.to_string());
+        while cache.order.len() > MAX_CACHED {
+            if let Some(oldest) = cache.order.pop_front() {
+                cache.images.remove(&oldest);
+            }
+        }
+    }
+    image
+}
+
+async fn search(name: &str) -> Option<String> {
+    let url = std::env::var("PRODUCT_IMAGE_SEARCH_URL").unwrap_or_else(|_| SEARCH_URL.to_string());
+    let response = reqwest::Client::new()
+        .get(url.as_str())
+        .query(&[("search_terms", name), ("search_simple", "1"), ("json", "1"), ("page_size", "1")])
+        .timeout(TIMEOUT)
+        .send()
+        .await;
+    let json: Value = match response {
+        Ok(response) => response.json().await.ok()?,
+        Err(error) => {
+            log::warn!("Image lookup for {} failed: {:?}", name, error);
+            return None;
+        }
+    };
+    let product = json.get("products")?.get(0)?;
+    product.get("image_front_small_url")
+        .or_else(|| product.get("image_url"))?
+        .as_str()
+        .map(|url| url.to_string())
+}
@@ -0,0 +1,195 @@
+//! Converts exports of other shopping list apps for `/import`.
+//!
+//! - Bring!: the JSON of a list, with the items under `purchase`.
+//! - AnyList: a list shared as text, section names followed by `•` items.
+//! - Google Keep: notes from Google Takeout (JSON). Checklists become items and
+//!   text notes with a title become recipes.
+//!
+//! Lists pasted as text, with bullets or checkboxes like `[x]`, are taken over as well.
+
+use serde::{Deserialize, Serialize};
+use serde_json::Value;
+use teloxide::utils::html;
+
+use crate::normalize;
+
+#[derive(Serialize, Deserialize, Clone)]
+pub struct Entry {
+    pub name: String,
+    pub done: bool,
+    pub quantity: u32,
+    pub header: bool,
+}
+
+impl Entry {
+    fn item(name: &str, done: bool) -> Self {
+        let (name, quantity) = normalize::quantity(name);
+        Entry { name, done, quantity, header: false }
+    }
+}
+
+/// Items and recipes waiting to be confirmed.
+#[derive(Serialize, Deserialize, Clone, Default)]
+pub struct Import {
+    pub source: String,
+    pub items: Vec<Entry>,
+    pub recipes: Vec<(String, Vec<String>)>,
+}
+
+pub enum Format {
+    Bring,
+    AnyList,
+    Keep,
+}
+
+impl Format {
+    pub fn parse(name: &str) -> Option<Self> {
+        match name.to_lowercase().as_str() {
+            "bring" => Some(Format::Bring),
+            "anylist" => Some(Format::AnyList),
+            "keep" => Some(Format::Keep),
+            _ => None,
+        }
+    }
+
+    fn name(&self) -> &'static str {
+        match self {
+            Format::Bring => "Bring!",
+            Format::AnyList => "AnyList",
+            Format::Keep => "Google Keep",
+        }
+    }
+}
+
+pub fn convert(format: Format, content: &str) -> anyhow::Result<Import> {
+    let mut import = Import { source: format.name().to_string(), ..Import::default() };
+    match format {
+        Format::Bring => {
+            let json: Value = serde_json::from_str(content)?;
+            let purchase = json.get("purchase").and_then(|purchase| purchase.as_array())
+                .ok_or_else(|| anyhow::anyhow!("Das ist keine Liste aus Bring!"))?;
+            for entry in purchase {
+                let name = match entry.get("name").and_then(|name| name.as_str()) {
+                    Some(name) => name.trim(),
+                    None => continue,
+                };
+                let specification = entry.get("specification").and_then(|specification| specification.as_str()).unwrap_or_default().trim();
+                import.items.push(match specification.parse() {
+                    Ok(quantity) => Entry { name: name.to_string(), done: false, quantity, header: false },
+                    Err(_) if specification.is_empty() => Entry::item(name, false),
+                    Err(_) => Entry::item(&format!("{} ({})", name, specification), false),
+                });
+            }
+        }
+        Format::AnyList => {
+            for line in content.lines().map(|line| line.trim()).filter(|line| !line.is_empty()) {
+                match line.strip_prefix('•').or_else(|| line.strip_prefix('-')).or_else(|| line.strip_prefix('*')) {
+                    Some(name) => import.items.push(Entry::item(name.trim(), false)),
+                    None => {
+                        // The list's name and sections without items aren't worth a header
+                        if import.items.last().map(|entry| entry.header) == Some(true) {
+                            import.items.pop();
+                        }
+                        import.items.push(Entry { name: line.to_string(), done: false, quantity: 1, header: true });
+                    }
+                }
+            }
+            if import.items.last().map(|entry| entry.header) == Some(true) {
+                import.items.pop();
+            }
+        }
+        Format::Keep => {
+            let notes = match serde_json::from_str(content)? {
+                Value::Array(notes) => notes,
+                note => vec![note],
+            };
+            for note in notes {
+                let title = note.get("title").and_then(|title| title.as_str()).unwrap_or_default().trim().to_string();
+                if let Some(list) = note.get("listContent").and_then(|list| list.as_array()) {
+                    for entry in list {
+                        let name = entry.get("text").and_then(|text| text.as_str()).unwrap_or_default().trim();
+                        if !name.is_empty() {
+                            let done = entry.get("isChecked").and_then(|checked| checked.as_bool()).unwrap_or(false);
+                            import.items.push(Entry::item(name, done));
+                        }
+                    }
+                } else if let Some(text) = note.get("textContent").and_then(|text| text.as_str()) {
+                    let ingredients: Vec<String> = text.lines()
+                        .map(|line| line.trim().trim_start_matches(['-', '•', '*']).trim().to_string())
+                        .filter(|line| !line.is_empty())
+                        .collect();
+                    if !title.is_empty() && !ingredients.is_empty() {
+                        import.recipes.push((title, ingredients));
+                    }
+                }
+            }
+        }
+    }
+    if import.items.is_empty() && import.recipes.is_empty() {
+        anyhow::bail!("In der Datei war nichts zu finden, das ich übernehmen kann.");
+    }
+    Ok(import)
+}
+
+/// A pasted list like "- [x] Milch" and "- [ ] 2x Eier", `None` if the text
+/// doesn't look like one. Lines ending with `:` become section headers.
+pub fn pasted(text: &str) -> Option<Import> {
+    let lines: Vec<&str> = text.lines().map(|line| line.trim()).filter(|line| !line.is_empty()).collect();
+    if lines.len() < 2 {
+        return None;
+    }
+    let mut import = Import { source: "Text".to_string(), ..Import::default() };
+    for line in lines {
+        if let Some(name) = line.strip_suffix(':') {
+            import.items.push(Entry { name: name.trim().to_string(), done: false, quantity: 1, header: true });
+            continue;
+        }
+        let (rest, bullet) = match line.strip_prefix(['-', '*', '•']) {
+            Some(rest) => (rest.trim_start(), true),
+            None => (line, false),
+        };
+        let (name, done) = if let Some(name) = rest.strip_prefix("[x]").or_else(|| rest.strip_prefix("[X]")) {
+            (name, true)
+        } else if let Some(name) = rest.strip_prefix("[ ]").or_else(|| rest.strip_prefix("[]")) {
+            (name, false)
+        } else if let Some(name) = rest.strip_prefix(['☑', '✅', '✔', '✓']) {
+            (name, true)
+        } else if let Some(name) = rest.strip_prefix('☐') {
+            (name, false)
+        } else if bullet {
+            (rest, false)
+        } else {
+            // Anything else is more likely a message than a list
+            return None;
+        };
+        let name = name.trim_start_matches('\u{fe0f}').trim();
+        if !name.is_empty() {
+            import.items.push(Entry::item(name, done));
+        }
+    }
+    if import.items.iter().filter(|entry| !entry.header).count() < 2 {
+        return None;
+    }
+    Some(import)
+}
+
+impl Import {
+    pub fn preview(&self) -> String {
+        let mut text = format!("Import aus {}:", html::escape(&self.source));
+        for entry in &self.items {
+            if entry.header {
+                text.push_str(&format!("\n\n{}", html::bold(&html::escape(&entry.name))));
+            } else {
+                let quantity = if entry.quantity > 1 { format!("{}× ", entry.quantity) } else { String::new() };
+                text.push_str(&format!("\n {} {}{}", if entry.done { "✔️" } else { "-" }, quantity, html::escape(&entry.name)));
+            }
+        }
+        if !self.recipes.is_empty() {
+            text.push_str("\n\n<b>Rezepte:</b>");
+            for (name, ingredients) in &self.recipes {
+                text.push_str(&format!("\n - {} ({} Zutaten)", html::escape(name), ingredients.len()));
+            }
+        }
+        text
+    }
+}
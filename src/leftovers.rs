@@ -0,0 +1,36 @@
+//! Cooked leftovers and freezer contents, entered with `/leftover` and `/freeze`.
+
+use chrono::{Duration, NaiveDate};
+use serde::{Deserialize, Serialize};
+
+/// The longest leftovers can keep, in days.
+pub const MAX_DAYS: i64 = 3650;
+
+#[derive(Serialize, Deserialize, Clone)]
+pub struct Leftover {
+    pub name: String,
+    /// Day it was put into the fridge or the freezer.
+    pub stored: NaiveDate,
+    pub frozen: bool,
+    /// Whether the chat was reminded to use it.
+    #[serde(default)]
+    pub reminded: bool,
+}
+
+impl Leftover {
+    /// Last day to eat it, given the number of days leftovers keep in the fridge and in the freezer.
+    pub fn use_by(&self, fridge_days: i64, freezer_days: i64) -> NaiveDate {
+        let days = if self.frozen { freezer_days } else { fridge_days };
+        // Out of range only for dates nobody stores leftovers on
+        self.stored.checked_add_signed(Duration::days(days.clamp(0, MAX_DAYS))).unwrap_or(self.stored)
+    }
+
+    pub fn describe(&self, fridge_days: i64, freezer_days: i64) -> String {
+        format!(
+            "{} {} (bis {})",
+            if self.frozen { "🧊" } else { "🥡" },
+            self.name,
+            self.use_by(fridge_days, freezer_days).format("%d.%m."),
+        )
+    }
+}
@@ -0,0 +1,158 @@
+//! Recipes that chats publish for each other with `/publish` and copy from
+//! `/library`, a plugin compiled in with the `recipe-library` feature.
+//!
+//! The library is shared by all bots of this process and kept in
+//! `LIBRARY_FILE` (`./shopping_list_bot.library.json` by default).
+
+use std::env;
+use std::io::ErrorKind;
+
+use async_trait::async_trait;
+use lazy_static::lazy_static;
+use serde::{Deserialize, Serialize};
+use teloxide::prelude::*;
+use teloxide::types::{InlineKeyboardMarkup, User};
+use teloxide::utils::html;
+use tokio::sync::Mutex;
+
+use crate::domain::Recipe;
+use crate::{answer_with_toast, Data, ENVIRONMENT};
+use crate::outbox::SendQueued;
+use crate::plugins::{self, Plugin};
+
+#[derive(Serialize, Deserialize, Clone)]
+struct SharedRecipe {
+    pub id: u32,
+    pub name: String,
+    pub ingredients: Vec<String>,
+    /// First name of the user who published it.
+    pub author: String,
+    /// Only this chat can unpublish the recipe.
+    pub chat_id: i64,
+}
+
+lazy_static! {
+    static ref LIBRARY: Mutex<Option<Vec<SharedRecipe>>> = Mutex::new(None);
+}
+
+fn path() -> String {
+    env::var("LIBRARY_FILE").unwrap_or_else(|_| "./shopping_list_bot.library.json".to_string())
+}
+
+async fn load() -> anyhow::Result<Vec<SharedRecipe>> {
+    match tokio::fs::read_to_string(path()).await {
+        Ok(string) => Ok(serde_json::from_str(&string)?),
+        Err(error) if error.kind() == ErrorKind::NotFound => Ok(Vec::new()),
+        Err(error) => Err(error.into()),
+    }
+}
+
+/// Runs `f` on the library, reading it on first use and writing it back if `modify` is set.
+async fn with_library<R>(modify: bool, f: impl FnOnce(&mut Vec<SharedRecipe>) -> R) -> anyhow::Result<R> {
+    let mut guard = LIBRARY.lock().await;
+    if guard.is_none() {
+        *guard = Some(load().await?);
+    }
+    let recipes = guard.as_mut().unwrap();
+    let result = f(recipes);
+    if modify {
+        tokio::fs::write(path(), serde_json::to_string_pretty(recipes)?).await?;
+    }
+    Ok(result)
+}
+
+/// Publishes the recipe, replacing an earlier version from the same chat.
+async fn publish(chat_id: i64, author: &str, name: &str, ingredients: &[String]) -> anyhow::Result<()> {
+    with_library(true, |recipes| {
+        recipes.retain(|recipe| recipe.chat_id != chat_id || recipe.name != name);
+        let id = recipes.iter().map(|recipe| recipe.id + 1).max().unwrap_or(0);
+        recipes.push(SharedRecipe {
+            id,
+            name: name.to_string(),
+            ingredients: ingredients.to_vec(),
+            author: author.to_string(),
+            chat_id,
+        });
+    }).await
+}
+
+/// Whether the chat had published a recipe with that name.
+async fn unpublish(chat_id: i64, name: &str) -> anyhow::Result<bool> {
+    with_library(true, |recipes| {
+        let before = recipes.len();
+        recipes.retain(|recipe| recipe.chat_id != chat_id || recipe.name != name);
+        recipes.len() != before
+    }).await
+}
+
+async fn recipes() -> anyhow::Result<Vec<SharedRecipe>> {
+    with_library(false, |recipes| recipes.clone()).await
+}
+
+async fn get(id: u32) -> anyhow::Result<Option<SharedRecipe>> {
+    with_library(false, |recipes| recipes.iter().find(|recipe| recipe.id == id).cloned()).await
+}
+
+pub struct Library;
+
+#[async_trait]
+impl Plugin for Library {
+    fn name(&self) -> &'static str {
+        "library"
+    }
+
+    fn commands(&self) -> &'static [&'static str] {
+        &["publish", "unpublish", "library"]
+    }
+
+    async fn command(&self, data: &mut Data, ctx: &UpdateWithCx<Bot, Message>, user: &User, command: &str, args: &[&str]) -> anyhow::Result<()> {
+        let name = args.join(" ");
+        let text = match command {
+            "unpublish" => {
+                if !unpublish(ctx.update.chat_id(), &name).await? {
+                    anyhow::bail!("{} ist nicht veröffentlicht", name);
+                }
+                format!("{} ist nicht mehr in der Rezeptbibliothek", name)
+            }
+            "publish" => {
+                let recipe = data.list.recipes.get(&name).ok_or_else(|| anyhow::anyhow!("Unknown recipe {}", name))?;
+                publish(ctx.update.chat_id(), &data.user_name(user), &name, &recipe.ingredients).await?;
+                format!("{} ist jetzt in der Rezeptbibliothek", name)
+            }
+            _ => {
+                let recipes = recipes().await?;
+                let text = recipes.iter().fold("Rezeptbibliothek:".to_string(), |a, recipe| {
+                    format!("{}\n - {} (von {})", a, html::escape(&recipe.name), html::escape(&recipe.author))
+                });
+                let markup = recipes.iter().fold(InlineKeyboardMarkup::default(), |markup, recipe| {
+                    markup.append_row(vec![plugins::button(self, &recipe.id.to_string(), format!("📥 {}", recipe.name))])
+                });
+                return data.replace_menu(ctx, text, Some(markup)).await;
+            }
+        };
+        ctx.answer(ENVIRONMENT.decorate(text)).send_queued(&ctx.requester).await?;
+        Ok(())
+    }
+
+    /// Copies a recipe from the library, by its library ID.
+    async fn callback(&self, data: &mut Data, ctx: &UpdateWithCx<Bot, CallbackQuery>, payload: &str) -> anyhow::Result<()> {
+        let recipe = match payload.parse().ok() {
+            Some(id) => get(id).await,
+            None => Ok(None),
+        };
+        let recipe = match recipe {
+            Ok(Some(recipe)) => recipe,
+            Ok(None) => return data.refresh_stale_button(ctx).await,
+            Err(error) => return answer_with_toast(ctx, error.to_string()).await,
+        };
+        if data.list.recipes.contains_key(&recipe.name) {
+            return answer_with_toast(ctx, format!("Es gibt schon ein Rezept namens {}.", recipe.name)).await;
+        }
+        if let Err(error) = ENVIRONMENT.input_limits.check_ingredient_count(recipe.ingredients.len()) {
+            return answer_with_toast(ctx, error.to_string()).await;
+        }
+        data.names.register(&recipe.name);
+        data.list.recipes.insert(recipe.name.clone(), Recipe::new(recipe.ingredients));
+        answer_with_toast(ctx, format!("{} von {} übernommen", recipe.name, recipe.author)).await
+    }
+}
@@ -0,0 +1,60 @@
+//! Emails to the list, for those who don't use Telegram. A mail service that
+//! forwards incoming mails to a URL, like the routes of Mailgun, POSTs them to
+//! `/mail/<token>` with the token from `/mail`, as a form or as JSON:
+//!
+//! `{"subject": "Milch", "text": "Eier\nBrot"}`
+//!
+//! The subject and every line of the text become items, quoted replies and the
+//! signature are left out.
+
+use serde::Deserialize;
+use utoipa::ToSchema;
+
+/// Upper bound of a mail, in bytes.
+pub const MAX_BYTES: u64 = 1024 * 1024;
+/// Lines after these are ignored, a mail is no list import.
+const MAX_ITEMS: usize = 50;
+const REPLY_PREFIXES: [&str; 5] = ["re:", "aw:", "fwd:", "fw:", "wg:"];
+
+#[derive(Deserialize, ToSchema)]
+pub struct Mail {
+    #[serde(default, alias = "Subject")]
+    pub subject: String,
+    /// The plain text part.
+    #[serde(default, alias = "body-plain", alias = "plain")]
+    pub text: String,
+}
+
+impl Mail {
+    pub fn items(&self) -> Vec<String> {
+        let mut items = Vec::new();
+        let subject = strip_reply_prefixes(self.subject.trim());
+        if !subject.is_empty() {
+            items.push(subject.to_string());
+        }
+        for line in self.text.lines() {
+            // Signature
+            if line == "-- " || line == "--" {
+                break;
+            }
+            let line = line.trim();
+            // The quoted mail of a reply, and the line introducing it
+            if line.starts_with('>') || (line.starts_with("Am ") && line.ends_with("schrieb:")) || (line.starts_with("On ") && line.ends_with("wrote:")) {
+                continue;
+            }
+            let item = line.trim_start_matches(['-', '*', '•']).trim();
+            if !item.is_empty() {
+                items.push(item.to_string());
+            }
+        }
+        items.truncate(MAX_ITEMS);
+        items
+    }
+}
+
+fn strip_reply_prefixes(mut subject: &str) -> &str {
+    while let Some(prefix) = REPLY_PREFIXES.iter().find(|prefix| subject.get(..prefix.len()).map(|start| start.eq_ignore_ascii_case(prefix)) == Some(true)) {
+        subject = subject[prefix.len()..].trim_start();
+    }
+    subject
+}
@@ -1,341 +1,4982 @@
 extern crate serde_json;
 
-use std::collections::HashMap;
+use std::collections::{HashMap, HashSet, VecDeque};
+use std::fmt::Write;
+use std::sync::Arc;
 
+use arc_swap::ArcSwap;
+use clap::{App, AppSettings, Arg, SubCommand};
+use chrono::{DateTime, Datelike, NaiveDate, NaiveDateTime, NaiveTime, TimeZone, Timelike, Utc};
+use chrono_tz::Tz;
 use lazy_static::lazy_static;
+use rand::distributions::{Distribution, WeightedIndex};
 use serde::{Deserialize, Serialize};
+use serde_json::json;
 use teloxide::{ApiError, RequestError};
 use teloxide::prelude::*;
-use teloxide::types::{ChatId, InlineKeyboardButton, InlineKeyboardMarkup, MediaKind, MessageKind};
-use teloxide::types::InlineKeyboardButtonKind::CallbackData;
-use tokio::fs::{File, OpenOptions};
-use tokio::io::{self, AsyncReadExt, AsyncWriteExt};
+use teloxide::net::Download;
+use teloxide::types::{ChatId, ChatMemberStatus, ChatMemberUpdated, InlineKeyboardButton, InlineKeyboardMarkup, InlineQuery, InlineQueryResult, InlineQueryResultArticle, InputFile, InputMedia, InputMediaPhoto, InputMessageContent, InputMessageContentText, MediaDocument, MediaKind, MediaPhoto, MessageKind, ParseMode, ReplyMarkup, User};
+use teloxide::utils::command::parse_command;
+use teloxide::utils::html;
 use tokio::sync::Mutex;
 use tokio_stream::wrappers::UnboundedReceiverStream;
 
+use crate::callback::Action;
+use crate::domain::{Item, KnownUser, Recipe};
+use crate::outbox::SendQueued;
+
+mod automation;
+mod aliases;
+mod autocomplete;
+mod batch;
+mod broadcast;
+mod callback;
+mod changelog;
+mod cli;
+mod config;
+mod console;
+mod date_phrase;
+mod date_picker;
+mod debounce;
+mod dedup;
+mod deep_link;
+mod delta;
+mod discover;
+mod domain;
+mod duplicates;
+mod features;
+mod environment;
+mod grpc;
+mod guest;
+mod health;
+mod history;
+mod i18n;
+mod ics;
+mod http;
+mod images;
+mod import;
+mod leftovers;
+#[cfg(feature = "recipe-library")]
+mod library;
+mod mail;
+mod meal_plan;
+mod merge;
+mod membership;
+mod money;
+mod normalize;
+mod notifications;
+mod offers;
+mod onboarding;
+mod outbox;
+mod pause;
+mod push;
+mod quick_keyboard;
+mod quiet;
+mod personal;
+mod placeholders;
+mod plugins;
+mod postgres;
+mod profile;
+mod rate_limit;
+mod recap;
+mod recipe_info;
+mod recipe_text;
+mod reactions;
+mod receipt;
+mod redis;
+mod render;
+mod repl;
+mod reporting;
+mod reset;
+mod scheduler;
+mod search;
+mod seasons;
+mod shadow;
+mod share_page;
+mod sheets;
+mod shortcuts;
+mod signal;
+mod spending;
+mod stock_out;
+mod storage;
+mod synonyms;
+mod timeline;
+mod tour;
+mod transfer;
+mod trips;
+mod unfurl;
+mod units;
+mod usage;
+mod voice;
+mod voice_auth;
+mod webhooks;
+mod wishes;
+mod word_filter;
+
+/// An addition by a restricted user that waits for an admin.
 #[derive(Serialize, Deserialize, Clone)]
-struct Data {
-    items: Vec<(String, bool)>,
-    recipes: HashMap<String, Vec<String>>,
-    active_message: Option<(i64, i32)>,
-    current_recipe: Option<(Option<String>, Vec<String>)>,
+struct PendingItem {
+    text: String,
+    requested_by: i64,
+    /// For the approval buttons, so they still fit after another suggestion was handled.
+    #[serde(default)]
+    id: u64,
+}
+
+impl KnownUser {
+    /// Mention that notifies the user, even if they don't have a username.
+    fn mention(&self, id: i64) -> String {
+        render::mention(id, &self.name(), self.username.as_deref())
+    }
 }
 
-impl Default for Data {
+#[derive(Serialize, Deserialize, Clone)]
+#[serde(default)]
+struct Settings {
+    /// Replaced by `Data::notifications`, only read by the migration to schema version 3.
+    nudge_opt_out: HashSet<i64>,
+    /// Number of buttons per keyboard row (1 to 3).
+    columns: usize,
+    /// Only show items that haven't been checked off yet.
+    hide_done: bool,
+    /// Show a product photo of the newest open item above the list.
+    show_images: bool,
+    /// Messages starting with this are left alone, so the chat can still be used for talking.
+    note_prefix: String,
+    /// Party lists: items get a button to claim them instead of being checked off.
+    event_mode: bool,
+    /// Store shown in the title of the list of chats that haven't chosen one during onboarding.
+    store: Option<String>,
+    /// Whether messages are deleted once they are on the list, unless the chat chose otherwise during onboarding.
+    delete_messages: bool,
+    /// How many days leftovers keep in the fridge.
+    leftover_days: i64,
+    /// How many days frozen leftovers keep.
+    freezer_days: i64,
+    /// Fix typos of known products in new items.
+    autocorrect: bool,
+    /// Set with `/reset`, see `reset`.
+    weekly_reset: Option<reset::WeeklyReset>,
+    /// Items are wishes with a link and a priority, see `wishes`.
+    wish_list: bool,
+    /// Set with `/style`, see `render`.
+    style: render::Style,
+}
+
+impl Default for Settings {
     fn default() -> Self {
         Self {
-            items: Vec::new(),
-            recipes: HashMap::new(),
-            active_message: None,
-            current_recipe: None,
+            nudge_opt_out: HashSet::new(),
+            columns: 1,
+            hide_done: false,
+            show_images: false,
+            note_prefix: "//".to_string(),
+            event_mode: false,
+            store: None,
+            delete_messages: true,
+            leftover_days: 3,
+            freezer_days: 90,
+            autocorrect: false,
+            weekly_reset: None,
+            wish_list: false,
+            style: render::Style::default(),
+        }
+    }
+}
+
+/// Content of the active message as it was last sent.
+#[derive(Clone, PartialEq)]
+struct Rendered {
+    message: (i64, i32),
+    photo: Option<String>,
+    text: String,
+    markup: Option<InlineKeyboardMarkup>,
+}
+
+/// Sub-views of the menu that can be navigated back to.
+#[derive(Serialize, Deserialize, Clone, Copy, PartialEq)]
+enum View {
+    Edit,
+    Recipes,
+    RecipeManager,
+    Templates,
+    RecipeComposer,
+    /// Ingredients of the recipe being added, see `Data::adding_recipe`.
+    Ingredients,
+    /// Items to move or copy to another list.
+    Transfer,
+    /// Actions on every item at once.
+    Bulk,
+    Pantry,
+    /// Emoji that stand for an item, see `shortcuts`.
+    Shortcuts,
+}
+
+impl View {
+    fn label(&self) -> &'static str {
+        match self {
+            View::Edit => "Bearbeiten",
+            View::Recipes => "Rezepte",
+            View::RecipeManager => "Verwalten",
+            View::Templates => "Vorlagen",
+            View::RecipeComposer => "Neues Rezept",
+            View::Ingredients => "Zutaten",
+            View::Transfer => "Verschieben",
+            View::Bulk => "Alle Einträge",
+            View::Pantry => "Vorrat",
+            View::Shortcuts => "Kürzel",
         }
     }
 }
 
+#[derive(Serialize, Deserialize, Clone, Default)]
+struct Data {
+    /// Schema version, see `storage::migrate`.
+    #[serde(default)]
+    version: u32,
+    /// Stored with its fields next to the others, as before it had a type of its own.
+    #[serde(flatten)]
+    list: domain::ShoppingList,
+    active_message: Option<(i64, i32)>,
+    current_recipe: Option<(Option<String>, Vec<String>)>,
+    #[serde(default)]
+    users: HashMap<i64, KnownUser>,
+    #[serde(default)]
+    settings: Settings,
+    /// What was bought in the months that were compacted out of `history`.
+    #[serde(default)]
+    history_summaries: Vec<history::Summary>,
+    /// Every change of an item, for `/asof`, at most `timeline::MAX_CHANGES` of them.
+    #[serde(default)]
+    timeline: Vec<timeline::Change>,
+    #[serde(default)]
+    templates: HashMap<String, Vec<String>>,
+    #[serde(default)]
+    naming_template: bool,
+    /// Recipe being added from the recipe buttons and the indices of the ingredients left out.
+    #[serde(default)]
+    adding_recipe: Option<(String, HashSet<usize>)>,
+    /// Recipe to copy under the name sent next.
+    #[serde(default)]
+    duplicating_recipe: Option<String>,
+    /// Recipe whose details are asked for and the one asked next, see `recipe_info`.
+    #[serde(default)]
+    recipe_form: Option<(String, recipe_info::Field)>,
+    /// Whether the active message is a photo with the list as caption.
+    #[serde(default)]
+    active_photo: bool,
+    /// Last content sent for the active message, so unchanged lists aren't edited again.
+    #[serde(skip)]
+    rendered: Option<Rendered>,
+    /// Message below the list with the buttons and views like the recipe composer.
+    #[serde(default)]
+    menu_message: Option<(i64, i32)>,
+    /// Text and buttons of the menu message as last sent.
+    #[serde(skip)]
+    menu_rendered: Option<(String, Option<InlineKeyboardMarkup>)>,
+    /// Views opened from the main menu, the last one on top.
+    #[serde(default)]
+    navigation: Vec<View>,
+    /// View shown in the menu message, `None` for the main menu and one-off dialogues.
+    #[serde(default)]
+    menu_view: Option<View>,
+    /// Whether the edit view shows the checked items instead of the open ones.
+    #[serde(default)]
+    edit_done_tab: bool,
+    /// Whether the next message is a search, see `search`.
+    #[serde(default)]
+    searching: bool,
+    #[serde(default)]
+    stock_outs: stock_out::StockOuts,
+    #[serde(default)]
+    offers: Vec<offers::Offer>,
+    /// When the running trip was started, see `trips`.
+    #[serde(default)]
+    trip_started: Option<DateTime<Utc>>,
+    /// Whether the bulk view asks to confirm emptying the list.
+    #[serde(default)]
+    confirming_clear: bool,
+    /// Only items matching this are shown in the list and the edit view.
+    #[serde(default)]
+    filter: Option<String>,
+    /// Blocked words, see `word_filter`.
+    #[serde(default)]
+    word_filter: word_filter::WordFilter,
+    /// Users whose private chat shows their personal list, see `personal`.
+    #[serde(default)]
+    personal_lists: HashSet<i64>,
+    /// For a personal list, the user it belongs to.
+    #[serde(default)]
+    owner: Option<i64>,
+    /// ID of the item picked to move or copy to another list, see `transfer`.
+    #[serde(default)]
+    transfer_item: Option<u64>,
+    /// Items cleared after shopping, oldest first, see `trips`.
+    #[serde(default)]
+    trips: Vec<trips::Trip>,
+    /// Local day of the last weekly reset, see `reset`.
+    #[serde(default)]
+    last_reset: Option<NaiveDate>,
+    /// Day the messages of the list were last checked by the nightly maintenance, see `scheduler`.
+    #[serde(default)]
+    last_maintenance: Option<NaiveDate>,
+    /// A change from outside the chat couldn't be shown while Telegram was unreachable, see `show_outside_change`.
+    #[serde(default)]
+    list_outdated: bool,
+    /// First day of the last month posted as a recap, see `recap`.
+    #[serde(default)]
+    last_recap: Option<NaiveDate>,
+    /// Whether the chat was asked what happens to the open items on reset.
+    #[serde(default)]
+    reset_pending: bool,
+    /// Page of the active message shown, if it is too long for one message.
+    #[serde(default)]
+    page: usize,
+    /// While set, only admins can use the bot.
+    #[serde(default)]
+    maintenance: bool,
+    /// Every chat the bot has been used in.
+    #[serde(default)]
+    known_chats: HashSet<i64>,
+    #[serde(default)]
+    guests: guest::GuestAccess,
+    /// Users whose additions need an admin's approval.
+    #[serde(default)]
+    restricted_users: HashSet<i64>,
+    /// Users who left the chat of the list, they can't be assigned items.
+    #[serde(default)]
+    departed_users: HashSet<i64>,
+    #[serde(default)]
+    pending: Vec<PendingItem>,
+    /// Last ID given to a pending item.
+    #[serde(default)]
+    next_pending_id: u64,
+    #[serde(default)]
+    price_book: HashMap<String, spending::PriceEntry>,
+    #[serde(default)]
+    meal_plan: Vec<meal_plan::PlannedMeal>,
+    /// Secret part of the iCal feed URL, see `/calendar`.
+    #[serde(default)]
+    calendar_token: Option<String>,
+    /// Secret part of the `/api` URLs, see `batch`.
+    #[serde(default)]
+    api_token: Option<String>,
+    /// IDs of the latest operations applied through the API, oldest first.
+    #[serde(default)]
+    applied_operations: VecDeque<String>,
+    /// Secret part of the read-only page from `/sharepage`, see `share_page`.
+    #[serde(default)]
+    share_page_token: Option<String>,
+    /// Secret part of the `/mail` URL, see `mail`.
+    #[serde(default)]
+    mail_token: Option<String>,
+    /// Code from `/voice` that links a voice assistant to the list, see `voice`.
+    #[serde(default)]
+    voice_code: Option<voice::LinkCode>,
+    /// Assistant users linked to the list.
+    #[serde(default)]
+    voice_users: HashSet<String>,
+    /// Meal from `/plan` waiting for a day to be picked.
+    #[serde(default)]
+    pending_meal: Option<String>,
+    /// Week from `/plan auto` waiting to be accepted.
+    #[serde(default)]
+    meal_proposal: Option<meal_plan::Proposal>,
+    /// Items and recipes from `/import` or a pasted list waiting to be confirmed.
+    #[serde(default)]
+    pending_import: Option<import::Import>,
+    #[serde(default)]
+    leftovers: Vec<leftovers::Leftover>,
+    /// Diets, groups from `synonyms` or ingredients the chat can't eat, set with `/diet`.
+    #[serde(default)]
+    diet: Vec<String>,
+    /// Stars from 1 to 5 per recipe and user.
+    #[serde(default)]
+    ratings: HashMap<String, HashMap<i64, u8>>,
+    /// Servings, preparation time and source by recipe, see `recipe_info`.
+    #[serde(default)]
+    recipe_info: HashMap<String, recipe_info::RecipeInfo>,
+    /// IDs of recipe and template names used in buttons.
+    #[serde(default)]
+    names: callback::Registry,
+    /// Chosen during onboarding, German otherwise.
+    #[serde(default)]
+    languages: HashMap<i64, i18n::Language>,
+    /// Store and deleting messages as chosen during onboarding, see `chat_store`.
+    #[serde(default)]
+    onboarding_choices: HashMap<i64, onboarding::Choices>,
+    /// Set with `/timezone`, every time shown or parsed uses the zone of the list's chat.
+    #[serde(default)]
+    timezones: HashMap<i64, Tz>,
+    /// Chats the bot was removed from.
+    #[serde(default)]
+    archived_chats: HashMap<i64, membership::ArchivedChat>,
+    /// Chats without scheduled messages, see `pause`.
+    #[serde(default)]
+    paused_chats: HashMap<i64, pause::Pause>,
+    /// Set with `/currency`, see `money`.
+    #[serde(default)]
+    currencies: HashMap<i64, money::MoneyFormat>,
+    /// Set with `/quiet`, see `quiet`.
+    #[serde(default)]
+    quiet_hours: HashMap<i64, quiet::QuietHours>,
+    /// Scheduled messages waiting for the quiet hours of their chat to end.
+    #[serde(default)]
+    held_messages: Vec<quiet::HeldMessage>,
+    /// Set with `/birthday`, see `wishes`.
+    #[serde(default)]
+    birthdays: Vec<wishes::Birthday>,
+    /// The step of the `/tour` the chat of the list is expected to take next.
+    #[serde(default)]
+    tour: Option<tour::Step>,
+    /// What the demo of the onboarding added until it is cleared.
+    #[serde(default)]
+    demo: Option<onboarding::Demo>,
+    /// Chats that get texts and buttons for screen readers, see `render`.
+    #[serde(default)]
+    accessible_chats: HashSet<i64>,
+    /// Per user, set with `/notifications`.
+    #[serde(default)]
+    notifications: HashMap<i64, notifications::Preferences>,
+    /// Added by admins with `/script`, see `automation`.
+    #[serde(default)]
+    scripts: Vec<automation::Script>,
+    /// Set with `/webhook`, see `webhooks`.
+    #[serde(default)]
+    webhooks: HashMap<i64, webhooks::Webhook>,
+    /// Events waiting to be delivered to the webhooks.
+    #[serde(default)]
+    webhook_outbox: Vec<webhooks::Delivery>,
+    /// List and recipes the last webhook events were made from.
+    #[serde(skip)]
+    webhook_snapshot: Option<webhooks::Snapshot>,
+    /// Link-only items whose page titles are fetched once the update is handled, see `unfurl_titles`.
+    #[serde(skip)]
+    unfurling: Vec<unfurl::Pending>,
+    /// Turned on or off by admins with `/feature`, see `features`.
+    #[serde(default)]
+    features: HashMap<i64, HashMap<features::Feature, bool>>,
+    /// Reminders and other messages about a single item, newest last, see `reactions`.
+    #[serde(default)]
+    item_messages: Vec<reactions::ItemMessage>,
+    /// Updates handled lately, so they aren't handled again after a restart, see `dedup`.
+    #[serde(default)]
+    seen_updates: dedup::Seen,
+    /// Keys of the keyboard below the message field by chat, see `quick_keyboard`.
+    #[serde(default)]
+    quick_keyboards: HashMap<i64, Vec<quick_keyboard::QuickAction>>,
+    /// Commands set by admins with `/alias` by chat, see `aliases`.
+    #[serde(default)]
+    aliases: HashMap<i64, HashMap<String, aliases::Target>>,
+    /// Items by the emoji that stand for them, by chat, see `shortcuts`.
+    #[serde(default)]
+    shortcuts: HashMap<i64, HashMap<String, String>>,
+    /// Version each chat was last told about, see `changelog`.
+    #[serde(default)]
+    seen_versions: HashMap<i64, String>,
+    /// Names of recipes and items in other languages, set with `/translate`.
+    #[serde(default)]
+    translations: HashMap<String, HashMap<i18n::Language, String>>,
+}
+
 impl Data {
     fn get_shopping_list_message_text(&self) -> String {
-        format!(
-            "Einkaufsliste:\n{}",
-            self.items.iter()
-                .fold(
-                    String::new(),
-                    |a, (b, _)| {
-                        format!("{}\n - {}", a, b)
-                    },
-                )
-        )
+        let language = self.language();
+        let timezone = self.timezone();
+        let money = self.money();
+        let month = self.today().month();
+        let excluded: Vec<Vec<String>> = self.diet.iter().map(|restriction| synonyms::excluded_words(restriction)).collect();
+        // About one line per item, so long lists aren't reallocated over and over
+        let mut text = String::with_capacity(128 + 48 * (self.list.items.len() + self.pending.len()));
+        text.push_str(i18n::text(language, i18n::Text::ShoppingList));
+        if let Some(store) = self.store() {
+            write!(text, " {}", html::escape(store)).unwrap();
+        }
+        if self.settings.hide_done {
+            write!(text, " ({})", i18n::text(language, i18n::Text::ShoppingListOpen)).unwrap();
+        }
+        if let Some(query) = &self.filter {
+            write!(text, " 🔍 „{}“", html::escape(query)).unwrap();
+        }
+        text.push(':');
+        if let Some(mvp) = history::mvp_header(&self.list.history, &self.users, timezone) {
+            write!(text, "\n{}", html::escape(&mvp)).unwrap();
+        }
+        text.push('\n');
+        let style = if self.accessible() { render::Style::Accessible } else { self.settings.style };
+        let mut section = Vec::new();
+        for (_, item) in self.visible_items() {
+            if item.header {
+                text.push_str(&style.section(&std::mem::take(&mut section)));
+                write!(text, "\n\n{}", render::header(&item.name)).unwrap();
+                continue;
+            }
+            let mut line = render::ItemLine {
+                label: self.translated_label(item, language),
+                details: item.describe(&self.users, timezone, language, &money),
+                done: item.done,
+                warning: excluded.iter().any(|words| synonyms::matches(&item.name, words)),
+                in_season: seasons::hint(&item.name, month).map(|hint| hint == "🌱"),
+                notes: Vec::new(),
+                extra: Vec::new(),
+            };
+            let cheaper = self.store()
+                .and_then(|store| self.price_book.get(&item.name.to_lowercase())?.cheaper_elsewhere(store));
+            if let Some((store, cents)) = cheaper {
+                line.notes.push(("💡", format!("{} {}", store, money.format(cents))));
+            }
+            if let Some(offer) = self.offers.iter().find(|offer| offer.matches(&item.name)) {
+                line.notes.push(("💰", self.describe_offer(offer, &money)));
+            }
+            if let Some(recipe) = &item.recipe {
+                line.extra.push(format!("aus {}", self.translate(recipe, language)));
+            }
+            if let Some(category) = item.category {
+                line.extra.push(category.name().to_string());
+            }
+            if let Some(user) = item.checked_by.filter(|_| item.done).and_then(|id| self.users.get(&id)) {
+                line.extra.push(format!("abgehakt von {}", user.name()));
+            }
+            section.push(line);
+        }
+        text.push_str(&style.section(&section));
+        let snoozed: Vec<&str> = self.list.items.iter().filter(|item| item.snoozed).map(|item| item.name.as_str()).collect();
+        if !snoozed.is_empty() {
+            write!(text, "\n\n💤 {}", render::note(&format!("Beim nächsten Einkauf: {}", snoozed.join(", ")))).unwrap();
+        }
+        if !self.pending.is_empty() {
+            write!(text, "\n\n{}", render::header("Wartet auf Freigabe:")).unwrap();
+            for pending in &self.pending {
+                let user = self.users.get(&pending.requested_by).map(KnownUser::name).unwrap_or_else(|| "?".to_string());
+                write!(text, "\n - {} ({})", html::escape(&pending.text), html::escape(&user)).unwrap();
+            }
+        }
+        text
     }
 
-    fn get_recipe_text(&self) -> String {
-        if let Some((Some(name), ingredients)) = &self.current_recipe {
-            format!(
-                "{}:{}",
-                name,
-                ingredients.iter()
-                    .fold(String::new(), |a, b| { format!("{}\n - {}", a, b) })
-            )
-        } else {
-            String::new()
+    /// The current date in the list's timezone.
+    fn today(&self) -> NaiveDate {
+        Utc::now().with_timezone(&self.timezone()).naive_local().date()
+    }
+
+    /// Leftovers to eat first, soonest use-by date first.
+    fn leftovers_text(&self) -> String {
+        if self.leftovers.is_empty() {
+            return "Keine Reste. Eintragen mit /leftover oder /freeze.".to_string();
         }
+        let (fridge, freezer) = (self.settings.leftover_days, self.settings.freezer_days);
+        let mut leftovers: Vec<&leftovers::Leftover> = self.leftovers.iter().collect();
+        leftovers.sort_by_key(|leftover| leftover.use_by(fridge, freezer));
+        leftovers.into_iter().fold("Reste aufbrauchen:".to_string(), |a, leftover| {
+            format!("{}\n {}", a, leftover.describe(fridge, freezer))
+        })
     }
 
-    fn get_list_markup(&self) -> InlineKeyboardMarkup {
-        let mut markup = InlineKeyboardMarkup::default();
+    /// A button per leftover to mark it as eaten.
+    fn leftovers_markup(&self) -> InlineKeyboardMarkup {
+        let (fridge, freezer) = (self.settings.leftover_days, self.settings.freezer_days);
+        self.leftovers.iter().enumerate().fold(InlineKeyboardMarkup::default(), |markup, (i, leftover)| {
+            markup.append_row(vec![Action::UseLeftover(i).button(format!("✅ {}", leftover.describe(fridge, freezer)))])
+        })
+    }
 
-        for (i, (name, selected)) in self.items.iter().enumerate() {
-            markup = markup.append_row(vec![InlineKeyboardButton::new(format!("️{}{}", if *selected { "❤ " } else { "" }, name), CallbackData(format!("toggle {}", i)))]);
+    fn chat_language(&self, chat_id: i64) -> i18n::Language {
+        self.languages.get(&chat_id).copied().unwrap_or_default()
+    }
+
+    /// The store the chat chose during onboarding, the one of the settings otherwise.
+    fn chat_store(&self, chat_id: i64) -> Option<&str> {
+        match self.onboarding_choices.get(&chat_id) {
+            Some(choices) => choices.store.as_deref(),
+            None => self.settings.store.as_deref(),
         }
+    }
 
-        markup.append_row(
-            vec![
-                InlineKeyboardButton::new("💚", CallbackData("remove_done".to_string()))
-            ]
-        )
+    /// The store of the chat the list is shown in, like `language`.
+    fn store(&self) -> Option<&str> {
+        match self.active_message {
+            Some((chat_id, _)) => self.chat_store(chat_id),
+            None => self.settings.store.as_deref(),
+        }
     }
 
-    fn get_recipe_buttons(&self) -> InlineKeyboardMarkup {
-        let mut markup = InlineKeyboardMarkup::default();
+    /// Whether messages in the chat are deleted once they are on the list.
+    fn deletes_messages(&self, chat_id: i64) -> bool {
+        self.onboarding_choices.get(&chat_id).map_or(self.settings.delete_messages, |choices| choices.delete_messages)
+    }
+
+    fn chat_timezone(&self, chat_id: i64) -> Tz {
+        self.timezones.get(&chat_id).copied().unwrap_or(ENVIRONMENT.default_timezone)
+    }
 
-        for (name, _) in self.recipes.iter() {
-            markup = markup.append_row(vec![InlineKeyboardButton::new(name, CallbackData(format!("add {}", name)))]);
+    /// Whether scheduled messages to the chat are paused with `/pause`.
+    fn is_paused(&self, chat_id: i64) -> bool {
+        let today = Utc::now().with_timezone(&self.chat_timezone(chat_id)).naive_local().date();
+        self.paused_chats.get(&chat_id).map(|pause| pause.active(today)) == Some(true)
+    }
+
+    /// Whether it is within the quiet hours of the chat set with `/quiet`.
+    fn is_quiet(&self, chat_id: i64) -> bool {
+        let hour = Utc::now().with_timezone(&self.chat_timezone(chat_id)).hour();
+        self.quiet_hours.get(&chat_id).map(|quiet| quiet.contains(hour)) == Some(true)
+    }
+
+    /// Records the changes of items for `/asof`, starting with the list as it is.
+    fn record_timeline(&mut self, events: &[webhooks::Event]) {
+        let now = Utc::now();
+        if self.timeline.is_empty() {
+            let items: Vec<(String, bool)> = self.list.items.iter()
+                .filter(|item| !item.header)
+                .map(|item| (item.name.clone(), item.done))
+                .collect();
+            self.timeline = timeline::starting_with(&items, now);
+        } else {
+            timeline::record(&mut self.timeline, events, now);
         }
+    }
 
-        markup.append_row(
-            vec![
-                InlineKeyboardButton::new("💚", CallbackData("return_to_main_list".to_string()))
-            ]
-        )
+    /// Compacts the history and the timeline to the last `months` months, returns how many purchases were summarized.
+    fn compact_history(&mut self, months: u32) -> usize {
+        let cutoff = match history::retention_cutoff(months) {
+            Some(cutoff) => cutoff,
+            None => return 0,
+        };
+        timeline::compact(&mut self.timeline, cutoff);
+        history::compact(&mut self.list.history, &mut self.history_summaries, cutoff)
     }
 
-    fn get_action_buttons_markup(&self) -> InlineKeyboardMarkup {
-        InlineKeyboardMarkup::default().append_row(
-            vec![
-                InlineKeyboardButton::new("🛒", CallbackData("start_remove".to_string())),
-                InlineKeyboardButton::new("📝🛒", CallbackData("list_recipes".to_string()))
-            ]
-        )
-            .append_row(
-                vec![
-                    InlineKeyboardButton::new("📝➕", CallbackData("start_recipe".to_string()))
-                ]
-            )
+    /// The changes to the list and the recipes since the last call, see `webhooks`.
+    /// The first call only remembers the list, there is nothing to compare it with.
+    fn list_events(&mut self) -> Vec<webhooks::Event> {
+        let snapshot = webhooks::Snapshot {
+            items: self.list.items.iter()
+                .filter(|item| !item.header)
+                .map(|item| (item.name.to_lowercase(), (item.name.clone(), item.done)))
+                .collect(),
+            recipes: self.list.recipes.clone(),
+        };
+        match self.webhook_snapshot.replace(snapshot.clone()) {
+            Some(previous) => previous.events(&snapshot),
+            None => Vec::new(),
+        }
     }
 
-    async fn update_shopping_list<T: GetChatId>(&mut self, ctx: &UpdateWithCx<Bot, T>) -> anyhow::Result<()> {
-        self.replace_active_message(ctx, self.get_shopping_list_message_text(), Some(self.get_action_buttons_markup())).await?;
-        Ok(())
+    /// Applies what the scripts ask for on the events, returns whether the list changed.
+    fn run_scripts(&mut self, events: &[webhooks::Event]) -> bool {
+        if self.scripts.is_empty() || events.is_empty() {
+            return false;
+        }
+        let open = self.list.items.iter().filter(|item| !item.header && !item.done).count();
+        let mut changed = false;
+        for effect in automation::run(&self.scripts, events, open) {
+            let operation = match effect {
+                automation::Effect::Add(item) => batch::Operation::now(batch::Kind::Add, &item),
+                automation::Effect::Check(item) => batch::Operation::now(batch::Kind::Check, &item),
+                automation::Effect::Remove(item) => batch::Operation::now(batch::Kind::Delete, &item),
+            };
+            match self.apply_operation(&operation) {
+                Ok(applied) => changed |= applied,
+                Err(error) => log::warn!("A script couldn't add {}: {}", operation.item, error),
+            }
+        }
+        changed
     }
 
-    async fn replace_active_message<T: GetChatId>(&mut self, ctx: &UpdateWithCx<Bot, T>, text: String, markup: Option<InlineKeyboardMarkup>) -> anyhow::Result<()> {
-        if let Some((chat_id, message_id)) = &self.active_message {
-            let mut message = ctx.requester.edit_message_text(ChatId::Id(*chat_id), *message_id, text.clone());
-            if let Some(markup) = markup.clone() {
-                message = message.reply_markup(markup);
+    /// Queues the events for the webhook of the list's chat.
+    fn queue_webhook_events(&mut self, events: &[webhooks::Event]) {
+        let webhooks = &self.webhooks;
+        let webhook = match self.active_message.and_then(|(chat_id, _)| webhooks.get(&chat_id)) {
+            Some(webhook) => webhook,
+            None => return,
+        };
+        let now = Utc::now();
+        for event in events {
+            match webhooks::Delivery::new(webhook, event, now) {
+                Ok(delivery) => self.webhook_outbox.push(delivery),
+                Err(error) => log::error!("Couldn't queue {:?}: {:?}", event, error),
             }
-            match message.send().await {
-                Ok(message) => {
-                    self.active_message = Some((message.chat.id, message.id));
-                    return Ok(());
-                }
-                Err(RequestError::ApiError { kind: ApiError::MessageNotModified, .. }) => {
-                    log::warn!("Message has the same content!");
-                    return Ok(());
-                }
-                Err(_) => log::error!("Couldn't replace message!")
+        }
+    }
+
+    /// Moves everything stored for a group to the ID of the supergroup it was upgraded to.
+    /// Returns whether the list was shown in the group.
+    fn migrate_chat(&mut self, from: i64, to: i64) -> bool {
+        if self.known_chats.remove(&from) {
+            self.known_chats.insert(to);
+        }
+        if let Some(timezone) = self.timezones.remove(&from) {
+            self.timezones.insert(to, timezone);
+        }
+        if let Some(language) = self.languages.remove(&from) {
+            self.languages.insert(to, language);
+        }
+        if let Some(choices) = self.onboarding_choices.remove(&from) {
+            self.onboarding_choices.insert(to, choices);
+        }
+        if let Some(pause) = self.paused_chats.remove(&from) {
+            self.paused_chats.insert(to, pause);
+        }
+        if let Some(quiet) = self.quiet_hours.remove(&from) {
+            self.quiet_hours.insert(to, quiet);
+        }
+        if let Some(currency) = self.currencies.remove(&from) {
+            self.currencies.insert(to, currency);
+        }
+        if self.accessible_chats.remove(&from) {
+            self.accessible_chats.insert(to);
+        }
+        if let Some(webhook) = self.webhooks.remove(&from) {
+            self.webhooks.insert(to, webhook);
+        }
+        if let Some(features) = self.features.remove(&from) {
+            self.features.insert(to, features);
+        }
+        if let Some(actions) = self.quick_keyboards.remove(&from) {
+            self.quick_keyboards.insert(to, actions);
+        }
+        if let Some(aliases) = self.aliases.remove(&from) {
+            self.aliases.insert(to, aliases);
+        }
+        if let Some(shortcuts) = self.shortcuts.remove(&from) {
+            self.shortcuts.insert(to, shortcuts);
+        }
+        if let Some(version) = self.seen_versions.remove(&from) {
+            self.seen_versions.insert(to, version);
+        }
+        for held in self.held_messages.iter_mut().filter(|held| held.chat_id == from) {
+            held.chat_id = to;
+        }
+        self.guests.migrate_chat(from, to);
+        let active = self.active_message.map(|(chat_id, _)| chat_id) == Some(from);
+        if active {
+            // Messages of the old group can't be edited anymore
+            self.active_message = None;
+            self.active_photo = false;
+            self.menu_message = None;
+        }
+        active
+    }
+
+    /// Forgets a chat the bot was removed from, keeping its settings in case it comes back.
+    fn archive_chat(&mut self, chat_id: i64) {
+        if self.active_message.map(|(active_chat, _)| active_chat) == Some(chat_id) {
+            // The message can't be edited anymore
+            self.active_message = None;
+            self.active_photo = false;
+            self.menu_message = None;
+        }
+        self.known_chats.remove(&chat_id);
+        let archived = membership::ArchivedChat {
+            left_at: Utc::now(),
+            timezone: self.timezones.remove(&chat_id),
+            language: self.languages.remove(&chat_id),
+        };
+        self.archived_chats.insert(chat_id, archived);
+    }
+
+    /// Brings back the settings of an archived chat.
+    fn restore_chat(&mut self, chat_id: i64) {
+        if let Some(archived) = self.archived_chats.remove(&chat_id) {
+            if let Some(timezone) = archived.timezone {
+                self.timezones.insert(chat_id, timezone);
+            }
+            if let Some(language) = archived.language {
+                self.languages.insert(chat_id, language);
             }
         }
-        let mut message = ctx.requester.send_message(ctx.update.get_chat_id(), text);
-        if let Some(markup) = markup {
-            message = message.reply_markup(markup);
+        self.known_chats.insert(chat_id);
+    }
+
+    /// Forgets that the user is in the chat of the list, handing their open items back to everyone.
+    async fn member_left<T: GetChatId>(&mut self, ctx: &UpdateWithCx<Bot, T>, user_id: i64) -> anyhow::Result<()> {
+        self.departed_users.insert(user_id);
+        let mut orphaned = Vec::new();
+        for item in self.list.items.iter_mut().filter(|item| item.assignee == Some(user_id)) {
+            item.assignee = None;
+            item.nudged = false;
+            if !item.done {
+                orphaned.push(item.name.clone());
+            }
         }
-        let message = message.send().await?;
-        self.active_message = Some((message.chat.id, message.id));
+        if orphaned.is_empty() {
+            return Ok(());
+        }
+        let name = self.users.get(&user_id).map(KnownUser::name).unwrap_or_else(|| "Jemand".to_string());
+        let text = format!(
+            "{} ist nicht mehr in der Gruppe, das ist jetzt wieder frei: {}",
+            html::escape(&name),
+            html::escape(&orphaned.join(", ")),
+        );
+        ctx.requester.send_message(ctx.update.get_chat_id(), ENVIRONMENT.decorate(text))
+            .parse_mode(ParseMode::Html)
+            .send_queued(&ctx.requester)
+            .await?;
+        self.update_list_message(ctx).await
+    }
+
+    /// Language of the chat the list is shown in.
+    /// In a private chat the language from the user's profile, if they set one.
+    fn language(&self) -> i18n::Language {
+        let chat_id = match self.active_message {
+            Some((chat_id, _)) => chat_id,
+            None => return i18n::Language::default(),
+        };
+        // Private chats have the ID of the user
+        self.users.get(&chat_id)
+            .and_then(|user| user.profile.language)
+            .or_else(|| self.languages.get(&chat_id).copied())
+            .unwrap_or_default()
+    }
+
+    /// The name of a recipe or item in the language, as it is if there is no translation.
+    fn translate<'a>(&'a self, name: &'a str, language: i18n::Language) -> &'a str {
+        self.translations.get(name)
+            .and_then(|translations| translations.get(&language))
+            .map(String::as_str)
+            .unwrap_or(name)
+    }
 
+    fn translated_label(&self, item: &Item, language: i18n::Language) -> String {
+        item.label_as(self.translate(&item.name, language))
+    }
+
+    /// Moves the `/tour` on if it waits for `step`, explaining the next one.
+    async fn advance_tour<T: GetChatId>(&mut self, ctx: &UpdateWithCx<Bot, T>, step: tour::Step) -> anyhow::Result<()> {
+        if self.tour != Some(step) {
+            return Ok(());
+        }
+        self.tour = step.next();
+        let text = match self.tour {
+            Some(next) => next.instructions(),
+            None => tour::FINISHED_TEXT.to_string(),
+        };
+        ctx.requester.send_message(ctx.update.get_chat_id(), ENVIRONMENT.decorate(text)).send_queued(&ctx.requester).await?;
         Ok(())
     }
 
-    async fn handle_new_item<T: GetChatId>(&mut self, ctx: &UpdateWithCx<Bot, T>, text: String) -> anyhow::Result<()> {
-        if let Some(recipe) = self.recipes.get(&text) {
-            for ingredient in recipe {
-                self.items.push((ingredient.to_string(), false));
-            }
-        } else {
-            self.items.push((text, false));
+    /// Puts the sample items and recipes of the `onboarding` on the list.
+    fn load_demo(&mut self, language: i18n::Language) {
+        let items: Vec<String> = onboarding::demo_items(language).iter().map(|item| item.to_string()).collect();
+        self.list.items.extend(items.iter().cloned().map(Item::from_text));
+        let mut recipes = Vec::new();
+        for (name, ingredients) in onboarding::demo_recipes(language).iter() {
+            self.names.register(name);
+            self.list.recipes.insert(name.to_string(), Recipe::new(ingredients.iter().map(|ingredient| ingredient.to_string()).collect()));
+            recipes.push(name.to_string());
         }
+        self.demo = Some(onboarding::Demo { items, recipes });
+    }
 
-        self.update_shopping_list(&ctx).await
+    /// Removes what `load_demo` added and is still there, keeping everything else.
+    fn clear_demo(&mut self) {
+        let demo = match self.demo.take() {
+            Some(demo) => demo,
+            None => return,
+        };
+        self.list.items.retain(|item| !demo.items.contains(&item.to_text()));
+        for name in demo.recipes.iter() {
+            self.list.recipes.remove(name);
+        }
     }
-}
 
-lazy_static! {
-    static ref CONFIG: Mutex<Data> = Mutex::new(Data::default());
-}
+    /// Starts the `onboarding` dialogue in the chat of `ctx`, forgetting its earlier choices.
+    async fn start_onboarding<T: GetChatId>(&mut self, ctx: &UpdateWithCx<Bot, T>) -> anyhow::Result<()> {
+        if let ChatId::Id(chat_id) = ctx.update.get_chat_id() {
+            self.languages.remove(&chat_id);
+            self.onboarding_choices.remove(&chat_id);
+        }
+        self.replace_menu(ctx, onboarding::WELCOME_TEXT.to_string(), Some(onboarding::language_markup())).await
+    }
 
-const CONFIG_PATH: &'static str = "./shopping_list_bot.json";
+    /// Timezone of the chat the list is shown in.
+    fn timezone(&self) -> Tz {
+        match self.active_message {
+            Some((chat_id, _)) => self.chat_timezone(chat_id),
+            None => ENVIRONMENT.default_timezone,
+        }
+    }
 
-#[tokio::main]
-async fn main() {
-    {
-        let mut data = CONFIG.lock().await;
-        let read_data: io::Result<File> = OpenOptions::new()
-            .read(true)
-            .create(false)
-            .open(CONFIG_PATH).await;
-        if let Ok(mut read_data) = read_data {
-            let mut string = String::new();
-            read_data.read_to_string(&mut string).await.unwrap();
-            let read_data: Data = serde_json::from_str(string.as_str()).unwrap();
-            data.active_message = read_data.active_message;
-            data.items = read_data.items;
-            data.current_recipe = read_data.current_recipe;
-            data.recipes = read_data.recipes;
+    /// Whether the user wants to be mentioned in this kind of message.
+    fn wants_notification(&self, user_id: i64, kind: notifications::Kind) -> bool {
+        self.notifications.get(&user_id).cloned().unwrap_or_default().wants(kind)
+    }
+
+    /// Mentions of everyone in the chat who wants this kind of message, empty if nobody does.
+    fn mention_all(&self, kind: notifications::Kind) -> String {
+        let mut users: Vec<(&i64, &KnownUser)> = self.users.iter()
+            .filter(|(id, _)| !self.departed_users.contains(id) && self.wants_notification(**id, kind))
+            .collect();
+        users.sort_by_key(|(id, _)| **id);
+        users.iter().map(|(id, user)| user.mention(**id)).collect::<Vec<_>>().join(" ")
+    }
+
+    fn get_notification_markup(&self, user_id: i64) -> InlineKeyboardMarkup {
+        notifications::KINDS.iter().enumerate().fold(InlineKeyboardMarkup::default(), |markup, (i, kind)| {
+            let state = if self.wants_notification(user_id, *kind) { "🔔" } else { "🔕" };
+            markup.append_row(vec![Action::ToggleNotification(i).button(format!("{} {}", state, kind.name()))])
+        })
+    }
+
+    /// Whether the feature is on in the chat, see `features`.
+    fn has_feature(&self, chat_id: i64, feature: features::Feature) -> bool {
+        match self.features.get(&chat_id).and_then(|features| features.get(&feature)) {
+            Some(on) => *on,
+            None => config::current().default_features.contains(&feature),
+        }
+    }
+
+    /// Whether the chat of the list uses `/accessible`.
+    fn accessible(&self) -> bool {
+        self.active_message.map(|(chat_id, _)| self.accessible_chats.contains(&chat_id)) == Some(true)
+    }
+
+    /// Whether most items are written right-to-left, like in Hebrew or Arabic.
+    fn right_to_left(&self) -> bool {
+        let items = self.list.items.iter().filter(|item| !item.header);
+        let (rtl, total) = items.fold((0, 0), |(rtl, total), item| (rtl + render::is_rtl(&item.name) as usize, total + 1));
+        rtl * 2 > total
+    }
+
+    /// Text and buttons for screen readers if the chat uses `/accessible`, mirrored for a right-to-left list.
+    fn for_chat(&self, chat: ChatId, text: String, markup: Option<InlineKeyboardMarkup>) -> (String, Option<InlineKeyboardMarkup>) {
+        let (text, markup) = match chat {
+            ChatId::Id(chat_id) if self.accessible_chats.contains(&chat_id) => render::spell_out(&text, markup),
+            _ => (text, markup),
+        };
+        if self.right_to_left() {
+            render::right_to_left(&text, markup)
         } else {
-            log::warn!("Data file missing or damaged");
+            (text, markup)
         }
     }
-    run().await;
-}
 
-async fn run() {
-    teloxide::enable_logging!();
-    log::info!("Starting ShoppingWatcher...");
+    /// How amounts are shown in the chat of the list.
+    fn money(&self) -> money::MoneyFormat {
+        self.active_message
+            .and_then(|(chat_id, _)| self.currencies.get(&chat_id).cloned())
+            .unwrap_or_else(|| money::MoneyFormat { locale: self.language().into(), ..Default::default() })
+    }
 
-    let bot = Bot::from_env();
+    /// Items with their index, without the checked ones if those are hidden.
+    fn visible_items(&self) -> impl Iterator<Item = (usize, &Item)> {
+        let hide_done = self.settings.hide_done;
+        self.list.items.iter()
+            .enumerate()
+            .filter(|(_, item)| !item.snoozed)
+            .filter(move |(_, item)| !(hide_done && item.done) && self.matches_filter(item))
+    }
 
-    Dispatcher::new(bot)
-        .callback_queries_handler(|rx: DispatcherHandlerRx<Bot, CallbackQuery>| {
-            UnboundedReceiverStream::new(rx)
-                .for_each(|ctx| async move {
-                    handle_callback_query(ctx).await.expect("Error handling callback query");
-                    store_data().await
-                })
-        })
-        .messages_handler(|rx: DispatcherHandlerRx<Bot, Message>| {
-            UnboundedReceiverStream::new(rx)
-                .for_each(|ctx| async move {
-                    handle_message(ctx).await.expect("Error handling message");
-                    store_data().await
-                })
-        })
-        .dispatch()
-        .await;
-}
+    /// Whether the item is shown with the current search, headers only without one.
+    fn matches_filter(&self, item: &Item) -> bool {
+        match &self.filter {
+            Some(query) => !item.header && search::matches(query, &item.name),
+            None => true,
+        }
+    }
 
-async fn store_data() {
-    let data: Data = CONFIG.lock().await.clone();
-    let data_file = OpenOptions::new()
-        .create(true)
-        .write(true)
-        .truncate(true)
-        .open(CONFIG_PATH).await;
-    match data_file {
-        Ok(mut file) => {
-            match serde_json::to_string_pretty(&data) {
-                Ok(string) => {
-                    if let Err(error) = file.write_all(string.as_bytes()).await {
-                        log::error!("{:?}", error);
-                    }
+    /// Items of the edit view's current tab. The checked tab only keeps headers
+    /// of sections with checked items, empty ones can be removed from the open tab.
+    fn tab_items(&self) -> Vec<(usize, &Item)> {
+        let mut items: Vec<(usize, &Item)> = Vec::new();
+        let empty_header = |items: &Vec<(usize, &Item)>| self.edit_done_tab && items.last().map(|(_, last)| last.header) == Some(true);
+        for (i, item) in self.list.items.iter().enumerate().filter(|(_, item)| self.matches_filter(item)) {
+            if item.header {
+                if empty_header(&items) {
+                    items.pop();
                 }
-                Err(error) => log::error!("{:?}", error)
+                items.push((i, item));
+            } else if item.done == self.edit_done_tab && !item.snoozed {
+                items.push((i, item));
             }
         }
-        Err(error) => log::error!("{:?}", error)
+        if empty_header(&items) {
+            items.pop();
+        }
+        items
     }
-}
 
-async fn handle_message(ctx: UpdateWithCx<Bot, Message>) -> anyhow::Result<()> {
-    let mut guard = CONFIG.lock().await;
+    /// Notes are neither added to the list nor deleted.
+    fn is_note(&self, text: &str, bot_name: &str) -> bool {
+        let prefix = &self.settings.note_prefix;
+        (!prefix.is_empty() && text.starts_with(prefix.as_str()))
+            || matches!(parse_command(text, bot_name), Some(("note", _)))
+    }
 
-    if let MessageKind::Common(message) = ctx.update.kind.clone() {
-        if let MediaKind::Text(text) = message.media_kind {
-            let user = message.from.unwrap();
-            log::info!("{} ({}): {}", user.first_name, user.id, text.text);
-            match &mut guard.current_recipe {
-                Some((name, ingredients)) => {
-                    match name {
-                        None => {
-                            *name = Some(text.text);
-                        }
-                        Some(_) => {
-                            ingredients.push(text.text);
-                        }
-                    }
-                    let string = guard.get_recipe_text();
-                    guard.replace_active_message(&ctx, string, Some(get_recipe_markup())).await?;
-                }
-                None => {
-                    if text.text.starts_with("#") {
-                        return Ok(());
-                    }
-                    guard.handle_new_item(&ctx, text.text).await?;
+    fn remember_user(&mut self, user: &User) {
+        let profile = self.users.remove(&user.id).map(|known| known.profile).unwrap_or_default();
+        self.users.insert(user.id, KnownUser {
+            first_name: user.first_name.clone(),
+            username: user.username.clone(),
+            profile,
+        });
+    }
+
+    /// How the user appears in the chat, see `profile`.
+    fn user_name(&self, user: &User) -> String {
+        self.users.get(&user.id).map(KnownUser::name).unwrap_or_else(|| user.first_name.clone())
+    }
+
+    fn find_user_by_username(&self, username: &str) -> Option<i64> {
+        let username = username.trim_start_matches('@');
+        self.users.iter()
+            .find(|(_, user)| user.username.as_deref() == Some(username))
+            .map(|(id, _)| *id)
+    }
+
+    fn get_recipe_text(&self) -> String {
+        if let Some((Some(name), ingredients)) = &self.current_recipe {
+            let month = self.today().month();
+            let mut text = String::with_capacity(32 + 32 * ingredients.len());
+            write!(text, "{}:", html::escape(name)).unwrap();
+            for ingredient in ingredients {
+                write!(text, "\n - {}", html::escape(ingredient)).unwrap();
+                if let Some(hint) = seasons::hint(ingredient, month) {
+                    write!(text, " {}", hint).unwrap();
                 }
             }
-            ctx.delete_message().send().await?;
+            text
+        } else {
+            String::new()
         }
     }
-    Ok(())
-}
-
 
-async fn handle_callback_query(ctx: UpdateWithCx<Bot, CallbackQuery>) -> anyhow::Result<()> {
-    let mut guard = CONFIG.lock().await;
-    let user = ctx.update.from.clone();
-    log::info!("{} ({}): {:?}", user.first_name, user.id, ctx.update.data);
+    fn get_list_markup(&self) -> InlineKeyboardMarkup {
+        let columns = self.settings.columns;
+        let language = self.language();
+        let items: Vec<&Item> = self.list.items.iter().filter(|item| !item.header && !item.snoozed && self.matches_filter(item)).collect();
+        let done = items.iter().filter(|item| item.done).count();
+        let open = items.len() - done;
+        let tab = |active: bool, label: String| if active { format!("• {} •", label) } else { label };
+        let mut markup = InlineKeyboardMarkup::default().append_row(vec![
+            Action::EditTab { done: false }.button(tab(!self.edit_done_tab, format!("offen ({})", open))),
+            Action::EditTab { done: true }.button(tab(self.edit_done_tab, format!("erledigt ({})", done))),
+        ]);
 
-    if let Some(data) = ctx.update.data.clone() {
-        let mut split = data.split_whitespace();
-        match split.next() {
-            Some("start_recipe") => {
-                guard.current_recipe = Some((
-                    None,
-                    Vec::new()
-                ));
-                guard.replace_active_message(&ctx, "Neues Rezept:".to_string(), Some(get_recipe_markup())).await?;
-            }
-            Some("start_remove") => {
-                let markup = Some(guard.get_list_markup());
-                guard.replace_active_message(&ctx, "Einkaufsliste:".to_string(), markup).await?;
-            }
-            Some("recipe_done") => {
-                if let Some(recipe) = guard.current_recipe.clone() {
-                    if let Some(name) = recipe.0 {
-                        guard.recipes.insert(name, recipe.1);
-                    }
+        if columns == 1 {
+            for (_, item) in self.tab_items() {
+                if item.header {
+                    markup = markup.append_row(vec![get_header_button(item)]);
+                    continue;
                 }
-                let markup = Some(guard.get_action_buttons_markup());
-                guard.replace_active_message(&ctx, "👍".to_string(), markup).await?;
-
-                guard.current_recipe = None;
-            }
-            Some("toggle") => {
-                let toggle_value: &mut (String, bool) = guard.items.get_mut(split.next().unwrap().parse::<usize>()?).unwrap();
-                toggle_value.1 = !toggle_value.1;
-                let markup = Some(guard.get_list_markup());
-                guard.replace_active_message(&ctx, "Einkaufsliste:".to_string(), markup).await?;
-            }
-            Some("remove_done") => {
-                let to_remove: Vec<usize> = guard.items.iter()
-                    .enumerate()
-                    .rev()
-                    .filter(|(_, (_, gotten))| { *gotten })
-                    .map(|(i, _)| { i })
-                    .collect();
-                for i in to_remove {
-                    println!("Removing: {}", i);
-                    guard.items.remove(i);
+                let mut row = vec![
+                    Action::ToggleItem(item.id).button(format!("️{}{}", if item.done { "❤ " } else { "" }, self.translated_label(item, language))),
+                    Action::Decrement(item.id).button("➖"),
+                    Action::Increment(item.id).button("➕"),
+                ];
+                if !item.done {
+                    row.push(Action::Snooze(item.id).button("💤"));
+                    row.push(Action::NotAvailable(item.id).button("❌"));
                 }
-                let markup = Some(guard.get_action_buttons_markup());
-                let text = guard.get_shopping_list_message_text();
-                guard.replace_active_message(&ctx, text, markup).await?;
-            }
-            Some("list_recipes") => {
-                let markup = Some(guard.get_recipe_buttons());
-                guard.replace_active_message(&ctx, "Click the recipe to add:".to_string(), markup).await?;
+                if let Some(url) = &item.url {
+                    row.push(InlineKeyboardButton::url("🔗".to_string(), url.clone()));
+                }
+                row.push(Action::DeleteItem(item.id).button("🗑"));
+                markup = markup.append_row(row);
             }
-            Some("add") => {
-                let name = split.fold(String::new(), |a, b| format!("{} {}", a, b)).trim().to_string();
-                guard.handle_new_item(&ctx, name).await?;
+        } else {
+            // Quantity buttons don't fit next to several items, so the grid only toggles.
+            let mut buttons = Vec::new();
+            for (_, item) in self.tab_items() {
+                if item.header {
+                    markup = append_grid(markup, std::mem::take(&mut buttons), columns);
+                    markup = markup.append_row(vec![get_header_button(item)]);
+                    continue;
+                }
+                buttons.push(Action::ToggleItem(item.id).button(
+                    format!("️{}{}", if item.done { "❤ " } else { "" }, truncate_label(&self.translated_label(item, language), columns)),
+                ));
             }
-            Some("return_to_main_list") => {
-                guard.update_shopping_list(&ctx).await?;
+            markup = append_grid(markup, buttons, columns);
+        }
+
+        self.append_filter_row(markup).append_row(
+            vec![
+                Action::RemoveDone.button("💚"),
+                Action::ToggleView { list: true }.button("👁"),
+                Action::Search.button("🔍"),
+                Action::ShowMovable.button("📤"),
+                Action::ShowBulk.button("☑️")
+            ]
+        )
+    }
+
+    /// A button per pantry product to put it on the list.
+    fn get_pantry_markup(&self) -> InlineKeyboardMarkup {
+        self.list.pantry.products().iter()
+            .enumerate()
+            .fold(InlineKeyboardMarkup::default(), |markup, (i, product)| {
+                let label = if self.list.pantry.is_low(product) {
+                    format!("🛒 {} (auf der Liste)", product)
+                } else {
+                    format!("🪫 {} fast leer", product)
+                };
+                markup.append_row(vec![Action::RunningLow(i).button(label)])
+            })
+    }
+
+    /// A button per item to pick it for moving to another list.
+    fn get_transfer_markup(&self) -> InlineKeyboardMarkup {
+        self.list.items.iter()
+            .filter(|item| !item.header)
+            .fold(InlineKeyboardMarkup::default(), |markup, item| {
+                markup.append_row(vec![Action::TransferItem(item.id).button(format!("📤 {}", item.label()))])
+            })
+    }
+
+    /// A button to show everything again while searching.
+    fn append_filter_row(&self, markup: InlineKeyboardMarkup) -> InlineKeyboardMarkup {
+        match &self.filter {
+            Some(query) => markup.append_row(vec![Action::ClearFilter.button(format!("❌ Suche „{}“ aufheben", query))]),
+            None => markup,
+        }
+    }
+
+    fn get_recipe_buttons(&self) -> InlineKeyboardMarkup {
+        let columns = self.settings.columns;
+        let language = self.language();
+        let mut names: Vec<&String> = self.list.recipes.keys()
+            .filter(|name| !self.list.archived_recipes.contains(*name))
+            .collect();
+        // Best rated first, variants sorted by the rating of their base recipe
+        let rating = |name: &str| self.recipe_rating(recipe_base(name)).or_else(|| self.recipe_rating(name)).unwrap_or(0.0);
+        names.sort_by(|a, b| {
+            rating(b).partial_cmp(&rating(a)).unwrap_or(std::cmp::Ordering::Equal)
+                .then(recipe_base(a).cmp(recipe_base(b)))
+                .then(a.cmp(b))
+        });
+        let mut markup = InlineKeyboardMarkup::default();
+        let mut groups = names.into_iter().peekable();
+        while let Some(base) = groups.next() {
+            let mut group = vec![base];
+            while let Some(variant) = groups.next_if(|name| recipe_base(name) == recipe_base(base)) {
+                group.push(variant);
             }
-            _ => println!("Unknown callback query data: {}", data)
+            let buttons = group.into_iter()
+                .filter_map(|name| {
+                    let mut label = if name == base {
+                        self.translate(name, language).to_string()
+                    } else {
+                        format!("↳ {}", name[recipe_base(name).len()..].trim().trim_start_matches('(').trim_end_matches(')'))
+                    };
+                    if let Some(prep_time) = self.recipe_info.get(name).and_then(recipe_info::RecipeInfo::prep_time) {
+                        label = format!("{} {}", label, prep_time);
+                    }
+                    Some(Action::AddRecipe(self.names.id(name)?).button(truncate_label(&label, columns)))
+                })
+                .collect();
+            markup = append_grid(markup, buttons, columns);
         }
+
+        markup.append_row(vec![Action::ManageRecipes.button("⚙")])
     }
-    Ok(())
-}
 
-fn get_recipe_markup() -> InlineKeyboardMarkup {
-    InlineKeyboardMarkup::default().append_row(
-        vec![
-            InlineKeyboardButton::new("💚", CallbackData("recipe_done".to_string()))
-        ]
-    )
-}
+    fn get_ingredients_text(&self) -> String {
+        match &self.adding_recipe {
+            Some((name, _)) => format!("{}: Was habt ihr schon da?", html::bold(&html::escape(name))),
+            None => "Kein Rezept ausgewählt".to_string(),
+        }
+    }
 
-trait GetChatId {
-    fn get_chat_id(&self) -> ChatId;
-}
+    /// The ingredients of the recipe being added, ☑️ for those that go on the list.
+    fn get_ingredients_markup(&self) -> InlineKeyboardMarkup {
+        let (name, excluded) = match &self.adding_recipe {
+            Some(adding) => adding,
+            None => return InlineKeyboardMarkup::default(),
+        };
+        let ingredients = self.list.recipes.get(name).map(|recipe| recipe.ingredients.clone()).unwrap_or_default();
+        let buttons = ingredients.iter()
+            .enumerate()
+            .map(|(index, ingredient)| {
+                let check = if excluded.contains(&index) { "⬜" } else { "☑️" };
+                Action::ExcludeIngredient(index).button(truncate_label(&format!("{} {}", check, ingredient), self.settings.columns))
+            })
+            .collect();
+        // The recipe can have fewer ingredients by now than when some were left out
+        let added = (0..ingredients.len()).filter(|index| !excluded.contains(index)).count();
+        append_grid(InlineKeyboardMarkup::default(), buttons, self.settings.columns)
+            .append_row(vec![Action::AddIngredients.button(format!("➕ {} hinzufügen", added))])
+    }
+
+    /// Every recipe with how often and when it was last used.
+    fn get_recipe_manager_text(&self) -> String {
+        let timezone = self.timezone();
+        let mut names: Vec<&String> = self.list.recipes.keys().collect();
+        names.sort();
+        names.into_iter().fold("Rezepte:".to_string(), |a, name| {
+            let (uses, last) = history::recipe_usage(&self.list.history, name);
+            let last = match last {
+                Some(at) => format!("zuletzt {}", at.with_timezone(&timezone).format("%d.%m.%Y")),
+                None => "nie benutzt".to_string(),
+            };
+            let archived = if self.list.archived_recipes.contains(name) { " (archiviert)" } else { "" };
+            let info = match self.recipe_info.get(name) {
+                Some(info) => format!(" · {}", info.describe()),
+                None => String::new(),
+            };
+            format!("{}\n - {}{}: {}×, {}{}", a, html::escape(name), archived, uses, last, info)
+        })
+    }
+
+    fn get_recipe_manager_markup(&self) -> InlineKeyboardMarkup {
+        let mut names: Vec<&String> = self.list.recipes.keys().collect();
+        names.sort();
+        let mut markup = InlineKeyboardMarkup::default();
+        for (name, id) in names.into_iter().filter_map(|name| Some((name, self.names.id(name)?))) {
+            let archive = if self.list.archived_recipes.contains(name) { "📤" } else { "📦" };
+            markup = markup.append_row(vec![
+                Action::ArchiveRecipe(id).button(format!("{} {}", archive, name)),
+                Action::EditRecipeInfo(id).button("ℹ️"),
+                Action::DuplicateRecipe(id).button("⧉"),
+                Action::DeleteRecipe(id).button("🗑"),
+            ]);
+        }
+        markup.append_row(vec![Action::ListRecipes.button("📝🛒")])
+    }
+
+    fn get_template_markup(&self) -> InlineKeyboardMarkup {
+        let mut markup = InlineKeyboardMarkup::default();
+
+        for (name, id) in self.templates.keys().filter_map(|name| Some((name, self.names.id(name)?))) {
+            markup = markup.append_row(vec![
+                Action::ApplyTemplate(id).button(format!("📋 {}", name)),
+                Action::MergeTemplate(id).button("➕"),
+                Action::DeleteTemplate(id).button("🗑"),
+            ]);
+        }
+
+        markup.append_row(vec![Action::SaveTemplate.button("💾")])
+    }
+
+    /// Puts the template's items on the list, either replacing it or adding what is missing.
+    fn apply_template(&mut self, name: &str, merge: bool) -> anyhow::Result<()> {
+        let today = self.today();
+        if let Some(template) = self.templates.get(name) {
+            let missing: Vec<String> = template.iter()
+                .map(|entry| placeholders::expand(entry, today))
+                .filter(|entry| !merge || !self.list.items.iter().any(|item| &item.to_text() == entry))
+                .collect();
+            let existing = if merge { self.list.items.len() } else { 0 };
+            ENVIRONMENT.input_limits.check_item_count(existing + missing.len())?;
+            let new_items: Vec<Item> = missing.into_iter().map(Item::from_text).collect();
+            if !merge {
+                self.list.items.clear();
+            }
+            self.list.items.extend(new_items);
+        }
+        Ok(())
+    }
+
+    /// The first restriction from `/diet` that `name` violates.
+    fn diet_conflict(&self, name: &str) -> Option<&String> {
+        self.diet.iter().find(|restriction| synonyms::matches(name, &synonyms::excluded_words(restriction)))
+    }
+
+    /// Warning about items that `text` would add against the chat's diet, recipes included.
+    fn diet_warning(&self, text: &str) -> Option<String> {
+        let names = match self.list.recipes.get(text) {
+            Some(recipe) => recipe.ingredients.iter().map(|ingredient| ingredient.as_str()).collect(),
+            None => vec![text],
+        };
+        let conflicts: Vec<String> = names.into_iter()
+            .filter_map(|name| Some(format!("{} ({})", name, self.diet_conflict(name)?)))
+            .collect();
+        if conflicts.is_empty() {
+            None
+        } else {
+            Some(format!("⚠️ Passt nicht zur Ernährung: {}", conflicts.join(", ")))
+        }
+    }
+
+    /// Average stars of the recipe, `None` if nobody rated it yet.
+    fn recipe_rating(&self, name: &str) -> Option<f32> {
+        let ratings = self.ratings.get(name).filter(|ratings| !ratings.is_empty())?;
+        Some(ratings.values().map(|stars| *stars as f32).sum::<f32>() / ratings.len() as f32)
+    }
+
+    /// Star buttons to rate the recipe, `None` if there is no recipe with that name.
+    fn rating_markup(&self, name: &str) -> Option<InlineKeyboardMarkup> {
+        let id = self.list.recipes.get(name).and(self.names.id(name))?;
+        Some(InlineKeyboardMarkup::default().append_row((1..=5).map(|stars| Action::Rate(id, stars).button("⭐".repeat(stars as usize)))))
+    }
+
+    /// A random recipe that isn't archived, better rated ones are picked more often.
+    fn surprise_recipe(&self) -> Option<&String> {
+        let candidates: Vec<&String> = self.list.recipes.keys().filter(|name| !self.list.archived_recipes.contains(*name)).collect();
+        // Unrated recipes count like three stars
+        let weights = candidates.iter().map(|name| self.recipe_rating(name).unwrap_or(3.0));
+        let index = WeightedIndex::new(weights).ok()?.sample(&mut rand::thread_rng());
+        candidates.get(index).copied()
+    }
+
+    /// Copies the ingredients of `original` into a new recipe called `name`.
+    fn duplicate_recipe(&mut self, original: &str, name: &str) -> anyhow::Result<()> {
+        ENVIRONMENT.input_limits.check_text(name)?;
+        if self.list.recipes.contains_key(name) {
+            anyhow::bail!("Es gibt schon ein Rezept namens {}.", name);
+        }
+        self.duplicating_recipe = None;
+        if let Some(ingredients) = self.list.recipes.get(original).cloned() {
+            self.names.register(name);
+            self.list.recipes.insert(name.to_string(), ingredients);
+            if let Some(info) = self.recipe_info.get(original).cloned() {
+                self.recipe_info.insert(name.to_string(), info);
+            }
+        }
+        Ok(())
+    }
+
+    /// Fails with a user facing message if adding `text` would exceed the configured limits.
+    fn check_new_item(&self, text: &str) -> anyhow::Result<()> {
+        let added = match self.list.recipes.get(text) {
+            Some(recipe) => recipe.ingredients.len(),
+            None => {
+                ENVIRONMENT.input_limits.check_text(text)?;
+                1
+            }
+        };
+        ENVIRONMENT.input_limits.check_item_count(self.list.items.len() + added)
+    }
+
+    /// Cleans up a typed item, see `normalize`. Recipe names are taken as they are.
+    fn normalize_item(&self, text: &str) -> String {
+        if self.list.recipes.contains_key(text) {
+            return text.to_string();
+        }
+        // Links are case-sensitive, only the text around them is cleaned up
+        if let (rest, Some(url)) = unfurl::split_url(text) {
+            if rest.is_empty() {
+                return url;
+            }
+            return format!("{} {}", self.normalize_item(&rest), url);
+        }
+        let name = normalize::item(text, self.language());
+        if !self.settings.autocorrect || name.starts_with('#') {
+            return name;
+        }
+        let mut known: Vec<&str> = self.list.history.iter()
+            .filter(|purchase| purchase.bought())
+            .map(|purchase| purchase.name.as_str())
+            .collect();
+        for word in synonyms::words().chain(seasons::produce_names()) {
+            known.push(word);
+        }
+        normalize::correct(&name, known.into_iter(), self.language()).unwrap_or(name)
+    }
+
+    /// Cleans up an ingredient like a typed item, with the quantity in front: "eier (3)" becomes "3× Eier".
+    fn normalize_ingredient(&self, text: &str) -> String {
+        let (name, quantity) = normalize::quantity(text);
+        let name = self.normalize_item(&name);
+        if quantity > 1 {
+            format!("{}× {}", quantity, name)
+        } else {
+            name
+        }
+    }
+
+    /// Normalizes the ingredients of every recipe, returns how many changed.
+    fn normalize_recipes(&mut self) -> usize {
+        let mut changed = 0;
+        let mut recipes = self.list.recipes.clone();
+        for recipe in recipes.values_mut() {
+            for ingredient in recipe.ingredients.iter_mut() {
+                let normalized = self.normalize_ingredient(ingredient);
+                if normalized != *ingredient {
+                    *ingredient = normalized;
+                    changed += 1;
+                }
+            }
+        }
+        self.list.recipes = recipes;
+        changed
+    }
+
+    fn get_action_buttons_markup(&self) -> InlineKeyboardMarkup {
+        if self.settings.wish_list {
+            return self.get_wish_markup();
+        }
+        let mut markup = InlineKeyboardMarkup::default();
+        if self.settings.event_mode {
+            for (_, item) in self.visible_items().filter(|(_, item)| !item.header) {
+                let label = match item.claimed_by.and_then(|id| self.users.get(&id)) {
+                    Some(user) => format!("↩️ {} ({})", item.label(), user.name()),
+                    None => format!("🙋 Bring ich mit: {}", item.label()),
+                };
+                markup = markup.append_row(vec![Action::Claim(item.id).button(label)]);
+            }
+        }
+        for pending in self.pending.iter() {
+            markup = markup.append_row(vec![
+                Action::Approve(pending.id).button(format!("✅ {}", pending.text)),
+                Action::Reject(pending.id).button("❌"),
+            ]);
+        }
+        self.append_filter_row(markup).append_row(
+            vec![
+                Action::StartRemove.button("🛒"),
+                Action::ListRecipes.button("📝🛒"),
+                Action::Search.button("🔍")
+            ]
+        )
+            .append_row(
+                vec![
+                    Action::StartRecipe.button("📝➕"),
+                    Action::ListTemplates.button("📋"),
+                    Action::ToggleView { list: false }.button("👁")
+                ]
+            )
+            .append_row(vec![
+                Action::ShareText.button("📋 Als Text teilen"),
+                Action::TripTimer.button(if self.trip_started.is_some() { "⏹ Einkauf beenden" } else { "⏱ Einkauf starten" }),
+            ])
+    }
+
+    /// Wishes aren't checked off: a row per wish to change its stars, open its link or remove it.
+    fn get_wish_markup(&self) -> InlineKeyboardMarkup {
+        let mut markup = InlineKeyboardMarkup::default();
+        for (_, item) in self.visible_items().filter(|(_, item)| !item.header) {
+            let stars = if item.priority > 0 { wishes::stars(item.priority) } else { "☆".to_string() };
+            let mut row = vec![Action::WishPriority(item.id).button(format!("{} {}", stars, item.label()))];
+            if let Some(url) = &item.url {
+                row.push(InlineKeyboardButton::url("🔗".to_string(), url.clone()));
+            }
+            row.push(Action::DeleteItem(item.id).button("🗑"));
+            markup = markup.append_row(row);
+        }
+        self.append_filter_row(markup)
+            .append_row(vec![Action::Search.button("🔍"), Action::ShareText.button("📋 Als Text teilen")])
+    }
+
+    /// The list without markup and buttons, to forward it or copy it into other apps.
+    fn get_plain_text(&self) -> String {
+        let mut text = String::with_capacity(32 + 32 * self.list.items.len());
+        text.push_str(i18n::text(self.language(), i18n::Text::ShoppingList));
+        if let Some(store) = self.store() {
+            write!(text, " {}", store).unwrap();
+        }
+        text.push(':');
+        for item in &self.list.items {
+            if item.header {
+                write!(text, "\n\n{}:", item.name).unwrap();
+            } else {
+                write!(text, "\n{} {}", if item.done { "☑" } else { "☐" }, item.label()).unwrap();
+            }
+        }
+        text
+    }
+
+    /// Runs a command set with `/alias`, shortcuts add their item the same way.
+    async fn run_alias(&mut self, ctx: &UpdateWithCx<Bot, Message>, user: &User, target: aliases::Target) -> anyhow::Result<()> {
+        match target {
+            aliases::Target::Template(name) => {
+                if self.restricted_users.contains(&user.id) {
+                    anyhow::bail!("Vorlagen brauchen die Freigabe eines Admins.");
+                }
+                if !self.templates.contains_key(&name) {
+                    anyhow::bail!("Die Vorlage {} gibt es nicht mehr", name);
+                }
+                self.apply_template(&name, true)?;
+                self.update_shopping_list(ctx).await
+            }
+            aliases::Target::Add(item) => {
+                let name = self.normalize_item(&placeholders::expand(&item, self.today()));
+                self.check_new_item(&name)?;
+                self.handle_new_item(ctx, user.id, name).await
+            }
+        }
+    }
+
+    /// The action of a key of the chat's keyboard, if that is what was sent, see `quick_keyboard`.
+    fn quick_action(&self, chat_id: i64, text: &str) -> Option<quick_keyboard::QuickAction> {
+        quick_keyboard::find(self.quick_keyboards.get(&chat_id)?, text)
+    }
+
+    /// The item the emoji stands for in the chat, if that is what was sent, see `shortcuts`.
+    fn shortcut(&self, chat_id: i64, text: &str) -> Option<String> {
+        shortcuts::find(self.shortcuts.get(&chat_id)?, text).cloned()
+    }
+
+    async fn run_quick_action(&mut self, ctx: &UpdateWithCx<Bot, Message>, action: quick_keyboard::QuickAction) -> anyhow::Result<()> {
+        match action {
+            quick_keyboard::QuickAction::ShowList => {}
+            quick_keyboard::QuickAction::StartTrip if self.trip_started.is_none() => {
+                self.trip_started = Some(Utc::now());
+                ctx.answer(ENVIRONMENT.decorate("⏱ Viel Erfolg beim Einkaufen!".to_string())).send_queued(&ctx.requester).await?;
+            }
+            quick_keyboard::QuickAction::StartTrip => {
+                let text = self.finish_trip();
+                ctx.answer(ENVIRONMENT.decorate(text)).send_queued(&ctx.requester).await?;
+            }
+            quick_keyboard::QuickAction::Recipes => return self.open_view(ctx, View::Recipes).await,
+            quick_keyboard::QuickAction::RemoveDone => {
+                self.clear_done();
+                self.update_shopping_list(ctx).await?;
+                return self.advance_tour(ctx, tour::Step::RemoveDone).await;
+            }
+        }
+        self.update_shopping_list(ctx).await
+    }
+
+    /// Gives suggestions from before they had IDs one.
+    fn assign_pending_ids(&mut self) {
+        for pending in self.pending.iter_mut().filter(|pending| pending.id == 0) {
+            self.next_pending_id += 1;
+            pending.id = self.next_pending_id;
+        }
+    }
+
+    /// Shows the list with the main menu below it.
+    async fn update_shopping_list<T: GetChatId>(&mut self, ctx: &UpdateWithCx<Bot, T>) -> anyhow::Result<()> {
+        // The buttons of wishes and suggestions refer to them by ID
+        self.list.assign_item_ids();
+        self.assign_pending_ids();
+        let text = i18n::text(self.language(), i18n::Text::Menu).to_string();
+        let markup = Some(self.get_action_buttons_markup());
+        self.navigation.clear();
+        self.render_menu(ctx, text, markup).await
+    }
+
+    /// Opens a view on top of the current one, or goes back to it if it is open already.
+    async fn open_view<T: GetChatId>(&mut self, ctx: &UpdateWithCx<Bot, T>, view: View) -> anyhow::Result<()> {
+        match self.navigation.iter().position(|open| *open == view) {
+            Some(position) => self.navigation.truncate(position + 1),
+            None => self.navigation.push(view),
+        }
+        self.render_view(ctx, view).await
+    }
+
+    /// Shows the view with a breadcrumb like "Liste › Rezepte › Verwalten" above it.
+    async fn render_view<T: GetChatId>(&mut self, ctx: &UpdateWithCx<Bot, T>, view: View) -> anyhow::Result<()> {
+        // The buttons of the items refer to them by ID
+        self.list.assign_item_ids();
+        let (text, markup) = match view {
+            View::Edit => ("Einkaufsliste:".to_string(), self.get_list_markup()),
+            View::Recipes => ("Click the recipe to add:".to_string(), self.get_recipe_buttons()),
+            View::RecipeManager => (self.get_recipe_manager_text(), self.get_recipe_manager_markup()),
+            View::Templates => ("Vorlagen:".to_string(), self.get_template_markup()),
+            View::RecipeComposer => match self.current_recipe {
+                Some((Some(_), _)) => (self.get_recipe_text(), get_recipe_markup()),
+                _ => ("Neues Rezept:".to_string(), get_recipe_markup()),
+            },
+            View::Ingredients => (self.get_ingredients_text(), self.get_ingredients_markup()),
+            View::Transfer => ("Was soll auf eine andere Liste?".to_string(), self.get_transfer_markup()),
+            View::Bulk if self.confirming_clear => ("Wirklich alle Einträge löschen?".to_string(), get_clear_confirmation_markup()),
+            View::Bulk => ("Mit allen Einträgen:".to_string(), get_bulk_markup()),
+            View::Pantry => ("Vorrat, was geht zur Neige?".to_string(), self.get_pantry_markup()),
+            View::Shortcuts => {
+                let shortcuts = match ctx.update.get_chat_id() {
+                    ChatId::Id(chat_id) => self.shortcuts.get(&chat_id),
+                    ChatId::ChannelUsername(_) => None,
+                };
+                let text = match shortcuts {
+                    Some(_) => "Kürzel, ein Emoji allein setzt den Eintrag auf die Liste:",
+                    None => "Noch keine Kürzel, z.B. /shortcut 🍺 Bier Kasten",
+                };
+                (text.to_string(), get_shortcut_markup(shortcuts))
+            }
+        };
+        let breadcrumb = self.navigation.iter().fold("Liste".to_string(), |a, open| format!("{} › {}", a, open.label()));
+        self.replace_menu(ctx, format!("{}\n\n{}", html::italic(&breadcrumb), text), Some(markup)).await?;
+        self.menu_view = Some(view);
+        Ok(())
+    }
+
+    /// Leaves the recipe composer after the recipe was saved or dropped.
+    async fn close_recipe_composer(&mut self, ctx: &UpdateWithCx<Bot, CallbackQuery>, saved: bool) -> anyhow::Result<()> {
+        self.current_recipe = None;
+        self.navigation.retain(|view| *view != View::RecipeComposer);
+        answer_with_toast(ctx, "👍".to_string()).await?;
+        self.go_back(ctx).await?;
+        if saved {
+            self.advance_tour(ctx, tour::Step::CreateRecipe).await?;
+        }
+        Ok(())
+    }
+
+    /// Leaves the view shown in the menu, dropping whatever was being entered there.
+    async fn go_back<T: GetChatId>(&mut self, ctx: &UpdateWithCx<Bot, T>) -> anyhow::Result<()> {
+        if self.menu_view.is_some() && self.menu_view == self.navigation.last().copied() {
+            self.navigation.pop();
+        }
+        if self.navigation.last() != Some(&View::RecipeComposer) {
+            self.current_recipe = None;
+        }
+        self.pending_import = None;
+        self.pending_meal = None;
+        self.meal_proposal = None;
+        self.adding_recipe = None;
+        self.duplicating_recipe = None;
+        self.recipe_form = None;
+        self.naming_template = false;
+        self.searching = false;
+        self.confirming_clear = false;
+        match self.navigation.last().copied() {
+            Some(view) => self.render_view(ctx, view).await,
+            None => self.update_shopping_list(ctx).await,
+        }
+    }
+
+    /// Shows a change made through the API, a voice assistant or another list.
+    /// If Telegram can't be reached, the change stays and the list is edited
+    /// once it can be reached again, see `scheduler`.
+    async fn show_outside_change(&mut self, bot: &Bot, source: &str) {
+        let chat_id = match self.active_message {
+            Some((chat_id, _)) => chat_id,
+            // The list shows the changes whenever it is sent next
+            None => {
+                self.list_outdated = false;
+                return;
+            }
+        };
+        let ctx = UpdateWithCx { requester: bot.clone(), update: ChatId::Id(chat_id) };
+        match self.update_list_message(&ctx).await {
+            Ok(()) => self.list_outdated = false,
+            Err(error) => {
+                log::warn!("Couldn't show the changes of {}, trying again later: {:?}", source, error);
+                self.list_outdated = true;
+            }
+        }
+    }
+
+    /// Renders the list into its own message, which only has buttons to switch pages.
+    async fn update_list_message<T: GetChatId>(&mut self, ctx: &UpdateWithCx<Bot, T>) -> anyhow::Result<()> {
+        let photo = if self.settings.show_images { self.newest_item_image().0 } else { None };
+        let previous = self.active_message;
+        self.replace_active_message_with_photo(ctx, photo, self.get_shopping_list_message_text(), None).await?;
+        if self.active_message != previous {
+            // The menu belongs below the list
+            if let Some((chat_id, message_id)) = self.menu_message.take() {
+                if let Err(error) = ctx.requester.delete_message(ChatId::Id(chat_id), message_id).send_queued(&ctx.requester).await {
+                    log::warn!("Couldn't delete old menu: {:?}", error);
+                }
+            }
+        }
+        Ok(())
+    }
+
+    /// Photo of the most recently added open item that has one, of those looked
+    /// up so far, and the newer items that weren't looked up yet, see `look_up_images`.
+    fn newest_item_image(&self) -> (Option<String>, Vec<String>) {
+        let mut missing = Vec::new();
+        let open = self.list.items.iter().rev().filter(|item| !item.done && !item.header);
+        for item in open {
+            match images::cached(&item.name) {
+                Some(Some(image)) => return (Some(image), missing),
+                Some(None) => {}
+                None => missing.push(item.name.clone()),
+            }
+        }
+        (None, missing)
+    }
+
+    /// Cuts texts longer than a Telegram message into pages at line breaks and
+    /// returns the current page with buttons to switch pages.
+    fn paginate(&mut self, text: String, markup: Option<InlineKeyboardMarkup>) -> (String, Option<InlineKeyboardMarkup>) {
+        if text.chars().count() <= MAX_MESSAGE_LENGTH {
+            self.page = 0;
+            return (text, markup);
+        }
+        let mut pages: Vec<String> = vec![String::new()];
+        for line in text.lines() {
+            let page = pages.last_mut().unwrap();
+            if !page.is_empty() && page.chars().count() + line.chars().count() + 1 > MAX_MESSAGE_LENGTH {
+                pages.push(String::new());
+            }
+            let page = pages.last_mut().unwrap();
+            page.push_str(line);
+            page.push('\n');
+        }
+        self.page = self.page.min(pages.len() - 1);
+        let mut navigation = Vec::new();
+        if self.page > 0 {
+            navigation.push(Action::Page(self.page - 1).button("◀"));
+        }
+        navigation.push(Action::Page(self.page).button(format!("{}/{}", self.page + 1, pages.len())));
+        if self.page + 1 < pages.len() {
+            navigation.push(Action::Page(self.page + 1).button("▶"));
+        }
+        let markup = markup.unwrap_or_default().append_row(navigation);
+        (pages.swap_remove(self.page), Some(markup))
+    }
+
+    /// Shows a view like the recipe composer in the menu message, with a button to go back.
+    async fn replace_menu<T: GetChatId>(&mut self, ctx: &UpdateWithCx<Bot, T>, text: String, markup: Option<InlineKeyboardMarkup>) -> anyhow::Result<()> {
+        let markup = markup.unwrap_or_default().append_row(vec![Action::Back.button("⬅️ Zurück")]);
+        self.render_menu(ctx, text, Some(markup)).await
+    }
+
+    /// Renders the menu message, keeping the list above it up to date.
+    async fn render_menu<T: GetChatId>(&mut self, ctx: &UpdateWithCx<Bot, T>, text: String, markup: Option<InlineKeyboardMarkup>) -> anyhow::Result<()> {
+        self.menu_view = None;
+        self.update_list_message(ctx).await?;
+        let (text, markup) = self.for_chat(ctx.update.get_chat_id(), text, markup);
+        let mut text = ENVIRONMENT.decorate(text);
+        if text.chars().count() > MAX_MESSAGE_LENGTH {
+            // Only the list is split into pages, menus are cut off
+            let end = text.char_indices().nth(MAX_MESSAGE_LENGTH - 1).map(|(end, _)| end).unwrap_or(text.len());
+            let end = text[..end].rfind('\n').unwrap_or(end);
+            text.truncate(end);
+            text.push('…');
+        }
+        let rendered = (text.clone(), markup.clone());
+        if let Some((chat_id, message_id)) = self.menu_message {
+            let failed = outbox::take_failed_edit(&ctx.requester, chat_id, message_id).await;
+            if failed.is_none() && self.menu_rendered.as_ref() == Some(&rendered) {
+                return Ok(());
+            }
+            let result = if let Some(error) = failed {
+                // An earlier edit that had to wait failed, the menu may be gone
+                Err(error)
+            } else if self.menu_rendered.as_ref().is_some_and(|(previous, _)| *previous == text) {
+                let mut message = ctx.requester.edit_message_reply_markup(ChatId::Id(chat_id), message_id);
+                if let Some(markup) = markup.clone() {
+                    message = message.reply_markup(markup);
+                }
+                message.edit_queued(&ctx.requester).await
+            } else {
+                let mut message = ctx.requester.edit_message_text(ChatId::Id(chat_id), message_id, text.clone())
+                    .parse_mode(ParseMode::Html);
+                if let Some(markup) = markup.clone() {
+                    message = message.reply_markup(markup);
+                }
+                message.edit_queued(&ctx.requester).await
+            };
+            match result {
+                Ok(Some(_)) | Err(RequestError::ApiError { kind: ApiError::MessageNotModified, .. }) => {
+                    self.menu_rendered = Some(rendered);
+                    return Ok(());
+                }
+                // Sent once there is a free slot, unless a newer menu overtakes it. Until
+                // then it isn't known what the menu shows, so the next one is sent whole.
+                Ok(None) => {
+                    self.menu_rendered = None;
+                    return Ok(());
+                }
+                Err(error) => log::warn!("Couldn't edit the menu, sending a new one: {:?}", error),
+            }
+        }
+        let mut message = ctx.requester.send_message(ctx.update.get_chat_id(), text)
+            .parse_mode(ParseMode::Html);
+        if let Some(markup) = markup {
+            message = message.reply_markup(markup);
+        }
+        let message = message.send_queued(&ctx.requester).await?;
+        self.menu_message = Some((message.chat.id, message.id));
+        self.menu_rendered = Some(rendered);
+        Ok(())
+    }
+
+    async fn replace_active_message_with_photo<T: GetChatId>(&mut self, ctx: &UpdateWithCx<Bot, T>, photo: Option<String>, text: String, markup: Option<InlineKeyboardMarkup>) -> anyhow::Result<()> {
+        if let ChatId::Id(chat_id) = ctx.update.get_chat_id() {
+            debounce::supersede(chat_id);
+        }
+        let (text, markup) = self.for_chat(ctx.update.get_chat_id(), text, markup);
+        let (text, markup) = self.paginate(ENVIRONMENT.decorate(text), markup);
+        // Captions are limited to 1024 characters, longer lists stay plain text
+        let photo = photo.filter(|_| text.chars().count() <= 1024);
+        let mut rendered = Rendered { message: (0, 0), photo: photo.clone(), text: text.clone(), markup: markup.clone() };
+        if let Some((chat_id, message_id)) = self.active_message {
+            rendered.message = (chat_id, message_id);
+            let failed = outbox::take_failed_edit(&ctx.requester, chat_id, message_id).await;
+            if failed.is_none() && self.rendered.as_ref() == Some(&rendered) {
+                log::debug!("Active message is unchanged, skipping the edit");
+                return Ok(());
+            }
+            // Ticking off an item often only changes the buttons, sending the whole text again is a waste
+            let keyboard_only = self.rendered.as_ref().is_some_and(|previous| {
+                previous.message == rendered.message && previous.photo == rendered.photo && previous.text == rendered.text
+            });
+            if photo.is_some() == self.active_photo {
+                let result = match (failed, &photo) {
+                    // An earlier edit that had to wait failed, it is recovered from like this one
+                    (Some(error), _) => Err(error),
+                    (None, _) if keyboard_only => {
+                        let mut message = ctx.requester.edit_message_reply_markup(ChatId::Id(chat_id), message_id);
+                        if let Some(markup) = markup.clone() {
+                            message = message.reply_markup(markup);
+                        }
+                        message.edit_queued(&ctx.requester).await
+                    }
+                    (None, Some(url)) => {
+                        let media = InputMedia::Photo(
+                            InputMediaPhoto::new(InputFile::url(url))
+                                .caption(text.clone())
+                                .parse_mode(ParseMode::Html)
+                        );
+                        let mut message = ctx.requester.edit_message_media(ChatId::Id(chat_id), message_id, media);
+                        if let Some(markup) = markup.clone() {
+                            message = message.reply_markup(markup);
+                        }
+                        message.edit_queued(&ctx.requester).await
+                    }
+                    (None, None) => {
+                        let mut message = ctx.requester.edit_message_text(ChatId::Id(chat_id), message_id, text.clone())
+                            .parse_mode(ParseMode::Html);
+                        if let Some(markup) = markup.clone() {
+                            message = message.reply_markup(markup);
+                        }
+                        message.edit_queued(&ctx.requester).await
+                    }
+                };
+                match result {
+                    Ok(Some(message)) => {
+                        self.active_message = Some((message.chat.id, message.id));
+                        self.rendered = Some(rendered);
+                        return Ok(());
+                    }
+                    // Sent once there is a free slot, unless a newer render of the list overtakes
+                    // it. Until then it isn't known what the message shows, so the next one is sent whole.
+                    Ok(None) => {
+                        self.rendered = None;
+                        return Ok(());
+                    }
+                    Err(RequestError::ApiError { kind: ApiError::MessageNotModified, .. }) => {
+                        log::warn!("Message has the same content!");
+                        self.rendered = Some(rendered);
+                        return Ok(());
+                    }
+                    Err(RequestError::ApiError { kind: ApiError::MessageToEditNotFound, .. })
+                    | Err(RequestError::ApiError { kind: ApiError::MessageIdInvalid, .. }) => {
+                        // Someone deleted the list, so a new one is sent below
+                        log::info!("Active message {} in chat {} is gone, sending a new one", message_id, chat_id);
+                        self.active_message = None;
+                    }
+                    Err(RequestError::ApiError { kind: ApiError::BotBlocked, .. })
+                    | Err(RequestError::ApiError { kind: ApiError::BotKicked, .. })
+                    | Err(RequestError::ApiError { kind: ApiError::ChatNotFound, .. })
+                    | Err(RequestError::ApiError { kind: ApiError::GroupDeactivated, .. }) => {
+                        log::info!("Chat {} can't be reached anymore, archiving it", chat_id);
+                        self.archive_chat(chat_id);
+                        if ctx.update.get_chat_id() == ChatId::Id(chat_id) {
+                            return Ok(());
+                        }
+                    }
+                    Err(RequestError::ApiError { kind: ApiError::MessageIsTooLong, .. }) => {
+                        anyhow::bail!("The list is still too long after splitting it into pages");
+                    }
+                    Err(error) => log::error!("Couldn't replace message! {:?}", error)
+                }
+            }
+            if self.active_message.is_some() && photo.is_some() != self.active_photo {
+                if let Err(error) = ctx.requester.delete_message(ChatId::Id(chat_id), message_id).send_queued(&ctx.requester).await {
+                    // Text messages can't be turned into photos and vice versa
+                    log::warn!("Couldn't delete old message: {:?}", error);
+                }
+            }
+        }
+        let message = match photo {
+            Some(url) => {
+                let mut message = ctx.requester.send_photo(ctx.update.get_chat_id(), InputFile::url(url))
+                    .caption(text)
+                    .parse_mode(ParseMode::Html);
+                if let Some(markup) = markup {
+                    message = message.reply_markup(markup);
+                }
+                message.send_queued(&ctx.requester).await?
+            }
+            None => {
+                let mut message = ctx.requester.send_message(ctx.update.get_chat_id(), text)
+                    .parse_mode(ParseMode::Html);
+                if let Some(markup) = markup {
+                    message = message.reply_markup(markup);
+                }
+                message.send_queued(&ctx.requester).await?
+            }
+        };
+        self.active_message = Some((message.chat.id, message.id));
+        self.active_photo = message.photo().is_some();
+        rendered.message = (message.chat.id, message.id);
+        self.rendered = Some(rendered);
+
+        Ok(())
+    }
+
+    /// Deletes the list and the menu if they are in the chat, so their buttons can't be used anymore.
+    async fn leave_chat(&mut self, requester: &Bot, chat_id: i64) {
+        let mut messages = Vec::new();
+        if self.active_message.map(|(active_chat, _)| active_chat) == Some(chat_id) {
+            messages.extend(self.active_message.take());
+            self.active_photo = false;
+            self.rendered = None;
+        }
+        if self.menu_message.map(|(menu_chat, _)| menu_chat) == Some(chat_id) {
+            messages.extend(self.menu_message.take());
+            self.menu_rendered = None;
+        }
+        for (chat_id, message_id) in messages {
+            if let Err(error) = requester.delete_message(ChatId::Id(chat_id), message_id).send_queued(requester).await {
+                log::warn!("Couldn't delete message of the other list: {:?}", error);
+            }
+        }
+    }
+
+    /// Checks or unchecks an item from its button in the list.
+    async fn toggle_from_button(&mut self, instance: &Arc<Instance>, ctx: &UpdateWithCx<Bot, CallbackQuery>, user_id: i64, id: u64) -> anyhow::Result<()> {
+        let done = match self.list.toggle_item(id, user_id) {
+            Some(item) => item.done,
+            None => return self.refresh_stale_button(ctx).await,
+        };
+        render_list_markup_later(instance, ctx).await?;
+        if done {
+            self.advance_tour(ctx, tour::Step::CheckItem).await?;
+        }
+        Ok(())
+    }
+
+    /// Tells the user that the button refers to something that is gone and shows the current list.
+    async fn refresh_stale_button(&mut self, ctx: &UpdateWithCx<Bot, CallbackQuery>) -> anyhow::Result<()> {
+        answer_with_toast(ctx, callback::STALE_TEXT.to_string()).await?;
+        self.update_shopping_list(ctx).await
+    }
+
+    /// Sends a read-only copy of the list to a guest chat.
+    async fn send_guest_view(&mut self, ctx: &UpdateWithCx<Bot, Message>) -> anyhow::Result<()> {
+        // The claim and approval buttons refer to the items by ID
+        self.list.assign_item_ids();
+        self.assign_pending_ids();
+        ctx.answer(ENVIRONMENT.decorate(self.get_shopping_list_message_text()))
+            .parse_mode(ParseMode::Html)
+            .reply_markup(self.get_action_buttons_markup())
+            .send_queued(&ctx.requester)
+            .await?;
+        Ok(())
+    }
+
+    async fn redeem_guest_link(&mut self, ctx: &UpdateWithCx<Bot, Message>, token: &str) -> anyhow::Result<()> {
+        let chat_id = ctx.update.chat_id();
+        if self.active_message.map(|(active_chat, _)| active_chat) == Some(chat_id) {
+            ctx.reply_to(ENVIRONMENT.decorate("Dieser Chat verwaltet die Liste schon.".to_string())).send_queued(&ctx.requester).await?;
+            return Ok(());
+        }
+        match self.guests.redeem(token, chat_id) {
+            Some(expires) => {
+                let text = format!("Du kannst die Liste bis {} ansehen.", expires.with_timezone(&self.timezone()).format("%d.%m. %H:%M"));
+                ctx.answer(ENVIRONMENT.decorate(text)).send_queued(&ctx.requester).await?;
+                self.send_guest_view(ctx).await
+            }
+            None => {
+                ctx.reply_to(ENVIRONMENT.decorate("Der Gastlink ist ungültig oder abgelaufen.".to_string())).send_queued(&ctx.requester).await?;
+                Ok(())
+            }
+        }
+    }
+
+    /// Puts the prices of receipt lines on recently checked items that don't have one yet.
+    ///
+    /// Returns the matched items and the lines that matched nothing.
+    fn reconcile_receipt(&mut self, lines: Vec<receipt::Line>) -> (Vec<receipt::Line>, Vec<receipt::Line>) {
+        let since = Utc::now() - chrono::Duration::days(1);
+        let mut matched = Vec::new();
+        let mut unmatched = Vec::new();
+        for (line, cents) in lines {
+            let name = if let Some(item) = self.list.items.iter_mut()
+                .find(|item| item.done && item.price.is_none() && receipt::matches(&item.name, &line)) {
+                item.price = Some(cents);
+                item.name.clone()
+            } else if let Some(purchase) = self.list.history.iter_mut()
+                .rev()
+                .take_while(|purchase| purchase.at > since)
+                .find(|purchase| purchase.bought() && purchase.price.is_none() && receipt::matches(&purchase.name, &line)) {
+                purchase.price = Some(cents);
+                purchase.name.clone()
+            } else {
+                unmatched.push((line, cents));
+                continue;
+            };
+            let key = name.to_lowercase();
+            let category = self.price_book.get(&key).map(|entry| entry.category).unwrap_or(spending::Category::Sonstiges);
+            self.record_price(&name, cents, category);
+            for item in self.list.items.iter_mut().filter(|item| item.name == name) {
+                item.category = Some(category);
+            }
+            for purchase in self.list.history.iter_mut().rev().take_while(|purchase| purchase.at > since).filter(|purchase| purchase.name == name) {
+                purchase.category = Some(category);
+            }
+            matched.push((name, cents));
+        }
+        (matched, unmatched)
+    }
+
+    /// "4,99 € statt 6,49 € bis 18.10.", the regular price from the price book if it is higher.
+    fn describe_offer(&self, offer: &offers::Offer, money: &money::MoneyFormat) -> String {
+        let mut text = money.format(offer.cents);
+        if let Some(entry) = self.price_book.get(&offer.name.to_lowercase()).filter(|entry| entry.cents > offer.cents) {
+            write!(text, " statt {}", money.format(entry.cents)).unwrap();
+        }
+        write!(text, " bis {}", offer.until.format("%d.%m.")).unwrap();
+        text
+    }
+
+    /// Remembers the price at the list's store, see `spending::PriceEntry`.
+    fn record_price(&mut self, name: &str, cents: u64, category: spending::Category) {
+        let store = self.store().unwrap_or_default().to_string();
+        let entry = self.price_book.entry(name.to_lowercase())
+            .or_insert_with(|| spending::PriceEntry { cents, category, stores: HashMap::new() });
+        entry.cents = cents;
+        entry.category = category;
+        entry.stores.insert(store, cents);
+    }
+
+    /// Replaces the meal planned for `date`, an empty `meal` just removes it.
+    fn plan_meal(&mut self, date: NaiveDate, meal: String) {
+        self.meal_plan.retain(|planned| planned.date != date);
+        if !meal.is_empty() {
+            self.meal_plan.push(meal_plan::PlannedMeal { date, meal, rating_asked: false });
+            self.meal_plan.sort_by_key(|planned| planned.date);
+        }
+    }
+
+    /// Recipes for a generated plan with their weights: not archived, fitting the
+    /// diet, not eaten in the last `RECENT_MEAL_DAYS` and not in `exclude`.
+    fn plan_candidates(&self, exclude: &[String]) -> Vec<(String, f32)> {
+        let today = self.today();
+        let recent: HashSet<&String> = self.meal_plan.iter()
+            .filter(|planned| planned.date < today && planned.date >= today - chrono::Duration::days(RECENT_MEAL_DAYS))
+            .map(|planned| &planned.meal)
+            .collect();
+        self.list.recipes.iter()
+            .filter(|(name, _)| !self.list.archived_recipes.contains(*name) && !recent.contains(name) && !exclude.contains(name))
+            .filter(|(_, recipe)| recipe.ingredients.iter().all(|ingredient| self.diet_conflict(ingredient).is_none()))
+            // Unrated recipes count like three stars
+            .map(|(name, _)| (name.clone(), self.recipe_rating(name).unwrap_or(3.0)))
+            .collect()
+    }
+
+    /// Proposes dinners for the next seven days, leftovers that have to go first.
+    fn propose_week(&mut self) -> meal_plan::Proposal {
+        let today = self.today();
+        let (fridge, freezer) = (self.settings.leftover_days, self.settings.freezer_days);
+        let mut leftovers: Vec<&leftovers::Leftover> = self.leftovers.iter()
+            .filter(|leftover| leftover.use_by(fridge, freezer) >= today)
+            .collect();
+        leftovers.sort_by_key(|leftover| leftover.use_by(fridge, freezer));
+        let mut meals: Vec<String> = leftovers.into_iter()
+            .take(7)
+            .map(|leftover| format!("Reste: {}", leftover.name))
+            .collect();
+        meals.extend(meal_plan::pick(&self.plan_candidates(&[]), 7 - meals.len()));
+        meal_plan::Proposal { start: today, meals }
+    }
+
+    fn get_proposal_markup(&self, proposal: &meal_plan::Proposal) -> InlineKeyboardMarkup {
+        let markup = proposal.meals.iter().enumerate().fold(InlineKeyboardMarkup::default(), |markup, (day, meal)| {
+            let prep_time = self.recipe_info.get(meal).and_then(recipe_info::RecipeInfo::prep_time).map(|time| format!(" {}", time));
+            markup.append_row(vec![Action::SwapMeal(day).button(format!("🔄 {}: {}{}", proposal.day_label(day), meal, prep_time.unwrap_or_default()))])
+        });
+        markup.append_row(vec![Action::AcceptPlan.button("✅ Übernehmen"), Action::DiscardPlan.button("❌")])
+    }
+
+    /// Plans the proposed meals and puts the ingredients that aren't in the pantry on the list,
+    /// counting ingredients used by several meals only once with a higher quantity.
+    fn accept_proposal(&mut self, proposal: meal_plan::Proposal) -> anyhow::Result<()> {
+        let mut needed: Vec<(String, u32)> = Vec::new();
+        for meal in proposal.meals.iter() {
+            if let Some(recipe) = self.list.recipes.get(meal) {
+                for ingredient in recipe.missing(&self.list.pantry) {
+                    match needed.iter_mut().find(|(name, _)| name.eq_ignore_ascii_case(ingredient)) {
+                        Some((_, quantity)) => *quantity += 1,
+                        None => needed.push((ingredient.clone(), 1)),
+                    }
+                }
+            }
+        }
+        let new_items = needed.iter()
+            .filter(|(name, _)| !self.list.items.iter().any(|item| !item.done && item.name.eq_ignore_ascii_case(name)))
+            .count();
+        ENVIRONMENT.input_limits.check_item_count(self.list.items.len() + new_items)?;
+        for (name, quantity) in needed {
+            match self.list.items.iter_mut().find(|item| !item.done && item.name.eq_ignore_ascii_case(&name)) {
+                Some(item) => item.quantity += quantity,
+                None => self.list.items.push(Item { quantity, ..Item::new(name) }),
+            }
+        }
+        for (day, meal) in proposal.meals.into_iter().enumerate() {
+            self.plan_meal(proposal.start + chrono::Duration::days(day as i64), meal);
+        }
+        Ok(())
+    }
+
+    /// Applies a date picked with `date_picker` and returns a confirmation.
+    fn apply_picked_date(&mut self, purpose: &str, picked: NaiveDateTime) -> Option<String> {
+        if purpose == "p" {
+            let meal = self.pending_meal.take()?;
+            let text = format!("{} am {} geplant", meal, picked.format("%d.%m."));
+            self.plan_meal(picked.date(), meal);
+            return Some(text);
+        }
+        // Pickers of before were `u` with the index of the item, those are stale now
+        let id: u64 = purpose.strip_prefix('i')?.parse().ok()?;
+        let due = self.timezone().from_local_datetime(&picked).earliest()?.with_timezone(&Utc);
+        let item = self.list.items.iter_mut().find(|item| id != 0 && item.id == id && !item.header)?;
+        item.due = Some(due);
+        item.nudged = false;
+        Some(format!("{} fällig am {}", item.name, picked.format("%d.%m. %H:%M")))
+    }
+
+    async fn preview_import<T: GetChatId>(&mut self, ctx: &UpdateWithCx<Bot, T>, import: import::Import) -> anyhow::Result<()> {
+        let text = import.preview();
+        let markup = InlineKeyboardMarkup::default()
+            .append_row(vec![Action::ConfirmImport.button("✅ Übernehmen"), Action::DiscardImport.button("❌")]);
+        self.pending_import = Some(import);
+        self.replace_menu(ctx, text, Some(markup)).await
+    }
+
+    /// Lines for the recipe composer, checked against the limits and filtered,
+    /// `ingredients` is how many the recipe has with them.
+    fn check_recipe_lines(&self, user_id: i64, lines: &[String], ingredients: usize) -> anyhow::Result<Vec<String>> {
+        ENVIRONMENT.input_limits.check_ingredient_count(ingredients)?;
+        lines.iter()
+            .map(|line| {
+                ENVIRONMENT.input_limits.check_text(line)?;
+                self.filter_text(user_id, line)
+            })
+            .collect()
+    }
+
+    /// The text with blocked words masked, or an error if it is rejected. Admins aren't filtered.
+    fn filter_text(&self, user_id: i64, text: &str) -> anyhow::Result<String> {
+        if ENVIRONMENT.is_admin(user_id) {
+            return Ok(text.to_string());
+        }
+        self.word_filter.apply(text)
+    }
+
+    async fn handle_new_item<T: GetChatId>(&mut self, ctx: &UpdateWithCx<Bot, T>, user_id: i64, text: String) -> anyhow::Result<()> {
+        // Links and the stars of a wish would not survive normalizing
+        let (text, url) = unfurl::split_url(&text);
+        let (text, priority) = match self.settings.wish_list && !self.list.recipes.contains_key(&text) {
+            true => wishes::parse(&text),
+            false => (text, 0),
+        };
+        // The title of the page is fetched later, without holding the data
+        let unfurl = text.is_empty();
+        let text = match &url {
+            Some(url) if unfurl => unfurl::host(url),
+            _ => text,
+        };
+        let text = if self.list.recipes.contains_key(&text) { text } else { self.filter_text(user_id, &text)? };
+        let mut usual = None;
+        let restricted = self.restricted_users.contains(&user_id);
+        if restricted {
+            self.next_pending_id += 1;
+            self.pending.push(PendingItem { text, requested_by: user_id, id: self.next_pending_id });
+        } else if url.is_some() || (self.settings.wish_list && !self.list.recipes.contains_key(&text)) {
+            let mut item = Item { url: url.clone(), priority, ..Item::from_text(text) };
+            if let Some(url) = url.filter(|_| unfurl) {
+                let item_id = item.ensure_id(&mut self.list.next_item_id);
+                self.unfurling.push(unfurl::Pending { item_id, user_id, url });
+            }
+            self.list.items.push(item);
+        } else {
+            let single = !self.list.recipes.contains_key(&text);
+            self.list.add_text(text);
+            if single {
+                usual = self.list.apply_usual_quantity(self.list.items.len() - 1);
+            }
+        }
+
+        self.update_shopping_list(ctx).await?;
+        if let Some((index, quantity)) = usual {
+            let list = &mut self.list;
+            let id = list.items[index].ensure_id(&mut list.next_item_id);
+            let text = format!("{} wie üblich {}× eingetragen.", html::escape(&list.items[index].name), quantity);
+            let markup = InlineKeyboardMarkup::default()
+                .append_row(vec![Action::Decrement(id).button("➖"), Action::Increment(id).button("➕")]);
+            self.replace_menu(ctx, text, Some(markup)).await?;
+        }
+        if !restricted {
+            self.advance_tour(ctx, tour::Step::AddItem).await?;
+        }
+        Ok(())
+    }
+
+    /// Fails if the command is switched off, for the bot or in the chat.
+    fn check_command(&self, chat_id: i64, command: &str) -> anyhow::Result<()> {
+        if config::current().is_disabled(command) {
+            anyhow::bail!("/{} ist abgeschaltet", command);
+        }
+        match features::Feature::for_command(command) {
+            Some(feature) if !self.has_feature(chat_id, feature) => {
+                anyhow::bail!("/{} ist in diesem Chat nicht freigeschaltet", command);
+            }
+            _ => Ok(()),
+        }
+    }
+
+    async fn handle_command(&mut self, ctx: &UpdateWithCx<Bot, Message>, user: &User, bot_name: &str, command: &str, args: Vec<&str>) -> anyhow::Result<()> {
+        self.check_command(ctx.update.chat_id(), command)?;
+        match command {
+            "assign" => {
+                let index = parse_item_number(args.first())?;
+                let assignee = match args.get(1) {
+                    Some(username) => self.find_user_by_username(username)
+                        .ok_or_else(|| anyhow::anyhow!("Unknown user {}", username))?,
+                    None => user.id,
+                };
+                if self.departed_users.contains(&assignee) {
+                    anyhow::bail!("{} ist nicht mehr in der Gruppe", args[1]);
+                }
+                let item = self.list.items.get_mut(index).ok_or_else(|| anyhow::anyhow!("No item {}", index + 1))?;
+                item.assignee = Some(assignee);
+                item.nudged = false;
+                let name = item.name.clone();
+                let mention = self.users.get(&assignee)
+                    .filter(|_| assignee != user.id && self.wants_notification(assignee, notifications::Kind::Assignments))
+                    .map(|known| known.mention(assignee));
+                if let Some(mention) = mention {
+                    let text = format!("{}: {} übernimmst du, sagt {}.", mention, html::bold(&html::escape(&name)), html::escape(&self.user_name(user)));
+                    ctx.answer(ENVIRONMENT.decorate(text)).parse_mode(ParseMode::Html).send_queued(&ctx.requester).await?;
+                }
+            }
+            "due" => {
+                let index = parse_item_number(args.first())?;
+                let phrase = args.get(1..).map(|rest| rest.join(" ")).unwrap_or_default();
+                let now = Utc::now().with_timezone(&self.timezone());
+                let list = &mut self.list;
+                let item = list.items.get_mut(index).ok_or_else(|| anyhow::anyhow!("No item {}", index + 1))?;
+                let due = match date_phrase::parse(&phrase, now) {
+                    Some(due) => due,
+                    None => {
+                        let text = format!("Wann ist {} fällig?", html::escape(&item.name));
+                        // By ID, the index changes when the list does while the picker is open
+                        let id = item.ensure_id(&mut list.next_item_id);
+                        let markup = date_picker::Picker::new(&format!("i{}", id), true).month_markup(now.year(), now.month());
+                        return self.replace_menu(ctx, text, Some(markup)).await;
+                    }
+                };
+                let item = &mut self.list.items[index];
+                item.due = Some(due);
+                item.nudged = false;
+            }
+            "nudges" => {
+                let on = match args.first().copied() {
+                    Some("off") => false,
+                    Some("on") => true,
+                    _ => anyhow::bail!("Usage: /nudges on|off"),
+                };
+                self.notifications.entry(user.id).or_default().set(notifications::Kind::Reminders, on);
+                return Ok(());
+            }
+            "notifications" => {
+                if !ctx.update.chat.is_private() {
+                    anyhow::bail!("Das geht im privaten Chat mit dem Bot.");
+                }
+                let text = self.notifications.get(&user.id).cloned().unwrap_or_default().describe();
+                let markup = self.get_notification_markup(user.id);
+                return self.replace_menu(ctx, text, Some(markup)).await;
+            }
+            "columns" => {
+                match args.first().map(|arg| arg.parse::<usize>()) {
+                    Some(Ok(columns)) if (1..=3).contains(&columns) => self.settings.columns = columns,
+                    _ => anyhow::bail!("Usage: /columns 1|2|3"),
+                }
+            }
+            "style" => {
+                let style = args.first().and_then(|style| render::Style::parse(style))
+                    .ok_or_else(|| anyhow::anyhow!("Usage: /style normal|compact|verbose|plain"))?;
+                self.settings.style = style;
+                ctx.answer(ENVIRONMENT.decorate(style.describe().to_string())).send_queued(&ctx.requester).await?;
+            }
+            "autocorrect" => {
+                match args.first().copied() {
+                    Some("on") => self.settings.autocorrect = true,
+                    Some("off") => self.settings.autocorrect = false,
+                    _ => anyhow::bail!("Usage: /autocorrect on|off"),
+                }
+            }
+            "images" => {
+                match args.first().copied() {
+                    Some("on") => self.settings.show_images = true,
+                    Some("off") => self.settings.show_images = false,
+                    _ => anyhow::bail!("Usage: /images on|off"),
+                }
+            }
+            "price" => {
+                let index = parse_item_number(args.first())?;
+                let usage = || anyhow::anyhow!("Usage: /price <number> <amount> [Lebensmittel|Drogerie|Sonstiges]");
+                let cents = args.get(1).and_then(|amount| spending::parse_price(amount)).ok_or_else(usage)?;
+                let item = self.list.items.get(index).ok_or_else(|| anyhow::anyhow!("No item {}", index + 1))?;
+                let key = item.name.to_lowercase();
+                let category = match args.get(2) {
+                    Some(category) => spending::Category::parse(category).ok_or_else(usage)?,
+                    None => self.price_book.get(&key).map(|entry| entry.category).unwrap_or(spending::Category::Sonstiges),
+                };
+                let name = item.name.clone();
+                self.record_price(&name, cents, category);
+                let item = &mut self.list.items[index];
+                item.price = Some(cents);
+                item.category = Some(category);
+            }
+            "compare" => {
+                let name = args.join(" ");
+                if name.is_empty() {
+                    anyhow::bail!("Usage: /compare <item>");
+                }
+                let text = match self.price_book.get(&name.to_lowercase()) {
+                    Some(entry) if !entry.stores.is_empty() => format!("Preise für {}:\n{}", name, entry.comparison(&self.money())),
+                    _ => format!("Für {} ist kein Preis bekannt, eintragen mit /price.", name),
+                };
+                ctx.answer(ENVIRONMENT.decorate(text)).send_queued(&ctx.requester).await?;
+                return Ok(());
+            }
+            "report" => {
+                let now = Utc::now().with_timezone(&self.timezone());
+                let (month, year) = match args.first() {
+                    Some(month) => {
+                        let (month, year) = month.split_once('.')
+                            .and_then(|(month, year)| Some((month.parse().ok()?, year.parse().ok()?)))
+                            .ok_or_else(|| anyhow::anyhow!("Usage: /report [MM.YYYY]"))?;
+                        (month, year)
+                    }
+                    None => (now.month(), now.year()),
+                };
+                let text = spending::monthly_report(&self.list.history, year, month, self.timezone(), &self.money());
+                ctx.answer(ENVIRONMENT.decorate(text)).parse_mode(ParseMode::Html).send_queued(&ctx.requester).await?;
+                return Ok(());
+            }
+            "event" => {
+                match args.first().copied() {
+                    Some("on") => self.settings.event_mode = true,
+                    Some("off") => self.settings.event_mode = false,
+                    _ => anyhow::bail!("Usage: /event on|off"),
+                }
+            }
+            "wishes" => {
+                match args.first().copied() {
+                    Some("on") => self.settings.wish_list = true,
+                    Some("off") => self.settings.wish_list = false,
+                    _ => anyhow::bail!("Usage: /wishes on|off"),
+                }
+            }
+            "birthday" => {
+                let text = match args.last().copied() {
+                    None => {
+                        let lines: Vec<String> = self.birthdays.iter().map(|birthday| format!(" - {}", birthday.describe())).collect();
+                        if lines.is_empty() {
+                            "Keine Geburtstage. Eintragen mit /birthday <Name> <Tag.Monat.>".to_string()
+                        } else {
+                            format!("Geburtstage:\n{}", lines.join("\n"))
+                        }
+                    }
+                    Some("off") => {
+                        let name = args[..args.len() - 1].join(" ");
+                        self.birthdays.retain(|birthday| !birthday.name.eq_ignore_ascii_case(&name));
+                        format!("{} wird nicht mehr erinnert.", name)
+                    }
+                    Some(_) => {
+                        let birthday = wishes::Birthday::parse(&args).ok_or_else(|| anyhow::anyhow!("Usage: /birthday [<name> <day.month.>|<name> off]"))?;
+                        let text = format!("Eine Woche vorher kommt eine Erinnerung: {}", birthday.describe());
+                        self.birthdays.retain(|known| !known.name.eq_ignore_ascii_case(&birthday.name));
+                        self.birthdays.push(birthday);
+                        text
+                    }
+                };
+                ctx.answer(ENVIRONMENT.decorate(text)).send_queued(&ctx.requester).await?;
+                return Ok(());
+            }
+            "noteprefix" => {
+                // Without an argument only /note keeps messages
+                self.settings.note_prefix = args.first().map(|prefix| prefix.to_string()).unwrap_or_default();
+                return Ok(());
+            }
+            action @ "restrict" | action @ "unrestrict" => {
+                if !ENVIRONMENT.is_admin(user.id) {
+                    anyhow::bail!("Nur für Admins");
+                }
+                let username = args.first().ok_or_else(|| anyhow::anyhow!("Usage: /{} @user", action))?;
+                let id = self.find_user_by_username(username)
+                    .ok_or_else(|| anyhow::anyhow!("Unknown user {}", username))?;
+                if action == "restrict" {
+                    self.restricted_users.insert(id);
+                } else {
+                    self.restricted_users.remove(&id);
+                }
+            }
+            "maintenance" => {
+                if !ENVIRONMENT.is_admin(user.id) {
+                    anyhow::bail!("Nur für Admins");
+                }
+                match args.first().copied() {
+                    Some("on") => self.maintenance = true,
+                    Some("off") => self.maintenance = false,
+                    _ => anyhow::bail!("Usage: /maintenance on|off"),
+                }
+                let state = if self.maintenance { "an" } else { "aus" };
+                ctx.answer(ENVIRONMENT.decorate(format!("Wartungsmodus {}", state))).send_queued(&ctx.requester).await?;
+                return Ok(());
+            }
+            "script" => {
+                if !ENVIRONMENT.is_admin(user.id) {
+                    anyhow::bail!("Nur für Admins");
+                }
+                let text = match args.as_slice() {
+                    [] | ["list"] => self.scripts.iter().fold("Skripte:".to_string(), |text, script| {
+                        format!("{}\n\n{}:\n{}", text, script.name, script.source)
+                    }),
+                    ["add", name, ..] => {
+                        // The source as sent, with its line breaks
+                        let source = ctx.update.text()
+                            .and_then(|text| text.trim_start().split_once(char::is_whitespace))
+                            .and_then(|(_, rest)| rest.trim_start().strip_prefix("add"))
+                            .and_then(|rest| rest.trim_start().strip_prefix(*name))
+                            .map(|source| source.trim().to_string())
+                            .unwrap_or_default();
+                        if source.is_empty() {
+                            anyhow::bail!("Usage: /script add <name> <source>");
+                        }
+                        if let Err(error) = automation::compile(&source) {
+                            anyhow::bail!("Fehler im Skript: {}", error);
+                        }
+                        self.scripts.retain(|script| script.name != *name);
+                        self.scripts.push(automation::Script { name: name.to_string(), source });
+                        format!("Skript {} gespeichert", name)
+                    }
+                    ["remove", name] => {
+                        let before = self.scripts.len();
+                        self.scripts.retain(|script| script.name != *name);
+                        if self.scripts.len() == before {
+                            anyhow::bail!("Kein Skript {}", name);
+                        }
+                        format!("Skript {} entfernt", name)
+                    }
+                    _ => anyhow::bail!("Usage: /script [list|add <name> <source>|remove <name>]"),
+                };
+                ctx.answer(ENVIRONMENT.decorate(text)).send_queued(&ctx.requester).await?;
+                return Ok(());
+            }
+            "feature" => {
+                if !ENVIRONMENT.is_admin(user.id) {
+                    anyhow::bail!("Nur für Admins");
+                }
+                let chat_id = ctx.update.chat_id();
+                match args.as_slice() {
+                    [] => {}
+                    [name, state] => {
+                        let feature = features::Feature::parse(name).ok_or_else(|| anyhow::anyhow!("Unknown feature {}", name))?;
+                        let features = self.features.entry(chat_id).or_default();
+                        match *state {
+                            "on" => features.insert(feature, true),
+                            "off" => features.insert(feature, false),
+                            "default" => features.remove(&feature),
+                            _ => anyhow::bail!("Usage: /feature [<name> on|off|default]"),
+                        };
+                        if features.is_empty() {
+                            self.features.remove(&chat_id);
+                        }
+                    }
+                    _ => anyhow::bail!("Usage: /feature [<name> on|off|default]"),
+                }
+                let text = features::ALL.iter().fold("Funktionen in diesem Chat:".to_string(), |text, feature| {
+                    let state = if self.has_feature(chat_id, *feature) { "an" } else { "aus" };
+                    format!("{}\n{}: {}", text, feature.name(), state)
+                });
+                ctx.answer(ENVIRONMENT.decorate(text)).send_queued(&ctx.requester).await?;
+                return Ok(());
+            }
+            "usage" => {
+                if !ENVIRONMENT.is_admin(user.id) {
+                    anyhow::bail!("Nur für Admins");
+                }
+                // Archived chats are no longer among the known ones
+                let archived = self.archived_chats.len();
+                let items = self.list.items.iter().filter(|item| !item.header).count();
+                let done = self.list.items.iter().filter(|item| !item.header && item.done).count();
+                let (requests, failed, started) = usage::requests();
+                let error_rate = if requests > 0 { failed as f64 * 100.0 / requests as f64 } else { 0.0 };
+                let storage = match usage::storage_size().await {
+                    Some(size) => usage::describe_size(size),
+                    None => "in Redis".to_string(),
+                };
+                let text = format!(
+                    "Nutzung dieses Bots:\n\
+                     Chats: {} ({} archiviert)\n\
+                     Nutzer: {}\n\
+                     Einträge: {} ({} erledigt)\n\
+                     Rezepte: {}, Vorlagen: {}\n\
+                     Einkäufe im Verlauf: {}\n\n\
+                     Alle Bots:\n\
+                     Anfragen an Telegram seit {}: {}, davon fehlgeschlagen: {} ({:.1} %)\n\
+                     Speicher: {}",
+                    self.known_chats.len() + archived, archived,
+                    self.users.len(),
+                    items, done,
+                    self.list.recipes.len(), self.templates.len(),
+                    self.list.history.len(),
+                    started.with_timezone(&self.timezone()).format("%d.%m.%Y %H:%M"), requests, failed, error_rate,
+                    storage,
+                );
+                ctx.answer(ENVIRONMENT.decorate(text)).send_queued(&ctx.requester).await?;
+                return Ok(());
+            }
+            "broadcast" => {
+                if !ENVIRONMENT.is_admin(user.id) {
+                    anyhow::bail!("Nur für Admins");
+                }
+                if args.is_empty() {
+                    anyhow::bail!("Usage: /broadcast <text>");
+                }
+                let chats = self.known_chats.iter().copied().collect();
+                tokio::spawn(broadcast::send(ctx.requester.clone(), chats, args.join(" "), ctx.update.chat_id()));
+                return Ok(());
+            }
+            "start" => {
+                if let Some(name) = args.first().and_then(|payload| deep_link::parse_add_payload(payload)) {
+                    self.check_new_item(&name)?;
+                    let warning = self.diet_warning(&name).map(|warning| format!("\n{}", warning)).unwrap_or_default();
+                    ctx.answer(ENVIRONMENT.decorate(format!("✅ {} hinzugefügt{}", name, warning))).send_queued(&ctx.requester).await?;
+                    return self.handle_new_item(ctx, user.id, name).await;
+                }
+                return self.start_onboarding(ctx).await;
+            }
+            "guestlink" => {
+                let hours = match args.first() {
+                    Some(hours) => hours.parse::<i64>().ok().filter(|hours| *hours > 0)
+                        .ok_or_else(|| anyhow::anyhow!("Usage: /guestlink [hours]"))?,
+                    None => 24,
+                };
+                let token = self.guests.create_token(chrono::Duration::hours(hours));
+                let text = format!(
+                    "Gastlink, {} Stunden gültig (nur ansehen):\n{}",
+                    hours,
+                    deep_link::guest_link(bot_name, &token),
+                );
+                ctx.answer(ENVIRONMENT.decorate(text)).send_queued(&ctx.requester).await?;
+                return Ok(());
+            }
+            "alias" => {
+                if !ENVIRONMENT.is_admin(user.id) {
+                    anyhow::bail!("Nur für Admins");
+                }
+                let chat_id = ctx.update.chat_id();
+                let usage = || anyhow::anyhow!("Usage: /alias [<name> template <template>|<name> add <item>|<name> off]");
+                match args.as_slice() {
+                    [] => {}
+                    [name, "off"] => {
+                        let aliases = self.aliases.entry(chat_id).or_default();
+                        if aliases.remove(&name.to_lowercase()).is_none() {
+                            anyhow::bail!("Es gibt keinen Befehl /{}", name);
+                        }
+                        if aliases.is_empty() {
+                            self.aliases.remove(&chat_id);
+                        }
+                    }
+                    [name, kind, value @ ..] if !value.is_empty() => {
+                        let name = name.to_lowercase();
+                        if !aliases::valid_name(&name) {
+                            anyhow::bail!("Befehle bestehen aus bis zu 32 Kleinbuchstaben, Ziffern und _");
+                        }
+                        let value = value.join(" ");
+                        let target = aliases::Target::parse(kind, value.clone()).ok_or_else(usage)?;
+                        match &target {
+                            aliases::Target::Template(template) if !self.templates.contains_key(template) => {
+                                anyhow::bail!("Es gibt keine Vorlage {}", template);
+                            }
+                            aliases::Target::Add(item) => ENVIRONMENT.input_limits.check_text(item)?,
+                            aliases::Target::Template(_) => {}
+                        }
+                        self.aliases.entry(chat_id).or_default().insert(name, target);
+                    }
+                    _ => return Err(usage()),
+                }
+                if !args.is_empty() {
+                    if let Err(error) = ctx.requester.set_my_commands(aliases::menu(&self.aliases)).send().await {
+                        log::warn!("Couldn't update the command menu: {:?}", error);
+                    }
+                }
+                ctx.answer(ENVIRONMENT.decorate(aliases::describe(self.aliases.get(&chat_id)))).send_queued(&ctx.requester).await?;
+                return Ok(());
+            }
+            "shortcut" => {
+                let chat_id = ctx.update.chat_id();
+                match args.as_slice() {
+                    [] => return self.open_view(ctx, View::Shortcuts).await,
+                    [emoji] => {
+                        let shortcuts = self.shortcuts.entry(chat_id).or_default();
+                        let removed = shortcuts.remove(&shortcuts::key(emoji));
+                        if shortcuts.is_empty() {
+                            self.shortcuts.remove(&chat_id);
+                        }
+                        if removed.is_none() {
+                            anyhow::bail!("Es gibt kein Kürzel {}", emoji);
+                        }
+                        ctx.answer(ENVIRONMENT.decorate(format!("Kürzel {} gelöscht.", emoji))).send_queued(&ctx.requester).await?;
+                    }
+                    [emoji, item @ ..] => {
+                        if !shortcuts::valid_key(&shortcuts::key(emoji)) {
+                            anyhow::bail!("Usage: /shortcut [<emoji> [<item>]]");
+                        }
+                        let item = item.join(" ");
+                        ENVIRONMENT.input_limits.check_text(&item)?;
+                        self.shortcuts.entry(chat_id).or_default().insert(shortcuts::key(emoji), item.clone());
+                        ctx.answer(ENVIRONMENT.decorate(format!("{} setzt jetzt {} auf die Liste.", emoji, item))).send_queued(&ctx.requester).await?;
+                    }
+                }
+                return Ok(());
+            }
+            "keyboard" => {
+                let chat_id = ctx.update.chat_id();
+                let names = quick_keyboard::ALL.iter().map(|action| action.name()).collect::<Vec<_>>().join("|");
+                let actions = match args.first().copied() {
+                    Some("on") => quick_keyboard::DEFAULT.to_vec(),
+                    Some("off") => Vec::new(),
+                    Some(_) => args.iter()
+                        .map(|name| quick_keyboard::QuickAction::parse(name).ok_or_else(|| anyhow::anyhow!("Usage: /keyboard on|off|<{}>…", names)))
+                        .collect::<anyhow::Result<_>>()?,
+                    None => anyhow::bail!("Usage: /keyboard on|off|<{}>…", names),
+                };
+                let (text, markup) = if actions.is_empty() {
+                    self.quick_keyboards.remove(&chat_id);
+                    ("Tastatur ausgeblendet.", ReplyMarkup::kb_remove())
+                } else {
+                    let markup = ReplyMarkup::Keyboard(quick_keyboard::markup(&actions));
+                    self.quick_keyboards.insert(chat_id, actions);
+                    ("Die wichtigsten Aktionen sind jetzt unten auf der Tastatur.", markup)
+                };
+                ctx.answer(ENVIRONMENT.decorate(text.to_string())).reply_markup(markup).send_queued(&ctx.requester).await?;
+                return Ok(());
+            }
+            "currency" => {
+                let chat_id = ctx.update.chat_id();
+                if !args.is_empty() {
+                    let format = money::MoneyFormat::parse(&args).ok_or_else(|| anyhow::anyhow!("Usage: /currency <€|$|£|CHF…> [de|en]"))?;
+                    self.currencies.insert(chat_id, format);
+                }
+                let text = self.currencies.get(&chat_id).cloned().unwrap_or_default().describe();
+                ctx.answer(ENVIRONMENT.decorate(text)).send_queued(&ctx.requester).await?;
+                return Ok(());
+            }
+            "timezone" => {
+                let chat_id = ctx.update.chat_id();
+                if let Some(zone) = args.first() {
+                    let timezone = zone.parse::<Tz>().map_err(|_| anyhow::anyhow!("Unbekannte Zeitzone {}, z.B. Europe/Berlin", zone))?;
+                    self.timezones.insert(chat_id, timezone);
+                }
+                let text = format!("Zeitzone: {}", self.chat_timezone(chat_id).name());
+                ctx.answer(ENVIRONMENT.decorate(text)).send_queued(&ctx.requester).await?;
+                return Ok(());
+            }
+            "plan" => {
+                let today = Utc::now().with_timezone(&self.timezone()).naive_local().date();
+                if args.first() == Some(&"auto") {
+                    if self.list.recipes.is_empty() {
+                        anyhow::bail!("Noch keine Rezepte");
+                    }
+                    let proposal = self.propose_week();
+                    let markup = Some(self.get_proposal_markup(&proposal));
+                    self.meal_proposal = Some(proposal);
+                    return self.replace_menu(ctx, "Vorschlag für die Woche, tippe einen Tag zum Tauschen:".to_string(), markup).await;
+                }
+                if let Some(day) = args.first() {
+                    match date_phrase::parse_day(day, today) {
+                        Some(date) => {
+                            let meal = args.get(1..).map(|rest| rest.join(" ")).unwrap_or_default();
+                            ENVIRONMENT.input_limits.check_text(&meal)?;
+                            self.plan_meal(date, meal);
+                        }
+                        None => {
+                            // No day given, ask for one
+                            let meal = args.join(" ");
+                            ENVIRONMENT.input_limits.check_text(&meal)?;
+                            let text = format!("Wann gibt es {}?", html::escape(&meal));
+                            self.pending_meal = Some(meal);
+                            let markup = date_picker::Picker::new("p", false).month_markup(today.year(), today.month());
+                            return self.replace_menu(ctx, text, Some(markup)).await;
+                        }
+                    }
+                }
+                let mut text = meal_plan::week_text(&self.meal_plan, self.today(), &self.recipe_info);
+                if !self.leftovers.is_empty() {
+                    text = format!("{}\n\n{}", text, self.leftovers_text());
+                }
+                ctx.answer(ENVIRONMENT.decorate(text)).send_queued(&ctx.requester).await?;
+                return Ok(());
+            }
+            "calendar" => {
+                if self.calendar_token.is_none() || args.first() == Some(&"new") {
+                    self.calendar_token = Some(deep_link::random_token(24));
+                }
+                let path = format!("/calendar/{}.ics", self.calendar_token.as_deref().unwrap_or_default());
+                let text = match (&ENVIRONMENT.public_url, ENVIRONMENT.http_addr) {
+                    (Some(url), _) => format!("Essensplan als Kalender-Abo:\n{}{}", url, path),
+                    (None, Some(_)) => format!("Essensplan als Kalender-Abo unter {} (PUBLIC_URL ist nicht gesetzt)", path),
+                    (None, None) => anyhow::bail!("Der HTTP-Server ist nicht eingerichtet (HTTP_ADDR)"),
+                };
+                ctx.answer(ENVIRONMENT.decorate(text)).send_queued(&ctx.requester).await?;
+                return Ok(());
+            }
+            "api" => {
+                if self.api_token.is_none() || args.first() == Some(&"new") {
+                    self.api_token = Some(deep_link::random_token(24));
+                }
+                let path = format!("/api/{}/batch", self.api_token.as_deref().unwrap_or_default());
+                let text = match (&ENVIRONMENT.public_url, ENVIRONMENT.http_addr) {
+                    (Some(url), _) => format!(
+                        "Änderungen per POST an:\n{}{}\n\nLive per WebSocket:\n{}/ws/{}",
+                        url, path, url.replacen("http", "ws", 1), self.api_token.as_deref().unwrap_or_default(),
+                    ),
+                    (None, Some(_)) => format!("Änderungen per POST an {} (PUBLIC_URL ist nicht gesetzt)", path),
+                    (None, None) => anyhow::bail!("Der HTTP-Server ist nicht eingerichtet (HTTP_ADDR)"),
+                };
+                ctx.answer(ENVIRONMENT.decorate(text)).send_queued(&ctx.requester).await?;
+                return Ok(());
+            }
+            "sharepage" => {
+                let text = match args.as_slice() {
+                    ["off"] => {
+                        self.share_page_token = None;
+                        "Die Seite ist nicht mehr erreichbar".to_string()
+                    }
+                    [] | ["new"] => {
+                        if self.share_page_token.is_none() || !args.is_empty() {
+                            self.share_page_token = Some(deep_link::random_token(24));
+                        }
+                        let path = format!("/list/{}", self.share_page_token.as_deref().unwrap_or_default());
+                        match (&ENVIRONMENT.public_url, ENVIRONMENT.http_addr) {
+                            (Some(url), _) => format!("Die Liste zum Ansehen, ohne Änderungen:\n{}{}", url, path),
+                            (None, Some(_)) => format!("Die Liste zum Ansehen unter {} (PUBLIC_URL ist nicht gesetzt)", path),
+                            (None, None) => anyhow::bail!("Der HTTP-Server ist nicht eingerichtet (HTTP_ADDR)"),
+                        }
+                    }
+                    _ => anyhow::bail!("Usage: /sharepage [new|off]"),
+                };
+                ctx.answer(ENVIRONMENT.decorate(text)).send_queued(&ctx.requester).await?;
+                return Ok(());
+            }
+            "mail" => {
+                let text = match args.as_slice() {
+                    ["off"] => {
+                        self.mail_token = None;
+                        "Keine E-Mails mehr an die Liste".to_string()
+                    }
+                    [] | ["new"] => {
+                        if self.mail_token.is_none() || !args.is_empty() {
+                            self.mail_token = Some(deep_link::random_token(24));
+                        }
+                        let path = format!("/mail/{}", self.mail_token.as_deref().unwrap_or_default());
+                        match (&ENVIRONMENT.public_url, ENVIRONMENT.http_addr) {
+                            (Some(url), _) => format!("E-Mails, die an diese Adresse weitergeleitet werden, kommen auf die Liste:\n{}{}", url, path),
+                            (None, Some(_)) => format!("E-Mails an {} kommen auf die Liste (PUBLIC_URL ist nicht gesetzt)", path),
+                            (None, None) => anyhow::bail!("Der HTTP-Server ist nicht eingerichtet (HTTP_ADDR)"),
+                        }
+                    }
+                    _ => anyhow::bail!("Usage: /mail [new|off]"),
+                };
+                ctx.answer(ENVIRONMENT.decorate(text)).send_queued(&ctx.requester).await?;
+                return Ok(());
+            }
+            "voice" => {
+                if ENVIRONMENT.http_addr.is_none() {
+                    anyhow::bail!("Der HTTP-Server ist nicht eingerichtet (HTTP_ADDR)");
+                }
+                let text = match args.as_slice() {
+                    [] => {
+                        let code = voice::LinkCode::new(Utc::now());
+                        let text = format!(
+                            "Sag deinem Sprachassistenten in den nächsten {} Minuten: „verbinde mit Code {}“\nVerbunden: {}",
+                            voice::CODE_MINUTES, code.code, self.voice_users.len(),
+                        );
+                        self.voice_code = Some(code);
+                        text
+                    }
+                    ["off"] => {
+                        self.voice_code = None;
+                        self.voice_users.clear();
+                        "Alle Sprachassistenten getrennt".to_string()
+                    }
+                    _ => anyhow::bail!("Usage: /voice [off]"),
+                };
+                ctx.answer(ENVIRONMENT.decorate(text)).send_queued(&ctx.requester).await?;
+                return Ok(());
+            }
+            "qrcode" => {
+                let link = if args.is_empty() {
+                    deep_link::share_link(bot_name)
+                } else {
+                    deep_link::quick_add_link(bot_name, &args.join(" "))?
+                };
+                let png = deep_link::qr_code_png(&link)?;
+                ctx.answer_photo(InputFile::memory("qrcode.png", png))
+                    .caption(ENVIRONMENT.decorate(link))
+                    .send_queued(&ctx.requester)
+                    .await?;
+                return Ok(());
+            }
+            "filter" => {
+                if !ENVIRONMENT.is_admin(user.id) {
+                    anyhow::bail!("Nur für Admins");
+                }
+                for arg in args {
+                    match arg {
+                        "mask" => self.word_filter.mask = true,
+                        "reject" => self.word_filter.mask = false,
+                        word => {
+                            ENVIRONMENT.input_limits.check_text(word)?;
+                            self.word_filter.toggle(word);
+                        }
+                    }
+                }
+                ctx.answer(ENVIRONMENT.decorate(self.word_filter.describe())).send_queued(&ctx.requester).await?;
+            }
+            "diet" => {
+                for restriction in args {
+                    let restriction = restriction.to_lowercase();
+                    match self.diet.iter().position(|existing| *existing == restriction) {
+                        Some(index) => {
+                            self.diet.remove(index);
+                        }
+                        None => {
+                            ENVIRONMENT.input_limits.check_text(&restriction)?;
+                            self.diet.push(restriction);
+                        }
+                    }
+                }
+                let text = if self.diet.is_empty() {
+                    "Keine Einschränkungen. Setzen mit /diet vegetarisch laktose …".to_string()
+                } else {
+                    format!("Einschränkungen: {}", self.diet.join(", "))
+                };
+                ctx.answer(ENVIRONMENT.decorate(text)).send_queued(&ctx.requester).await?;
+            }
+            "leftover" | "freeze" => {
+                let name = args.join(" ");
+                if name.is_empty() {
+                    anyhow::bail!("Usage: /{} <meal>", command);
+                }
+                ENVIRONMENT.input_limits.check_text(&name)?;
+                self.leftovers.push(leftovers::Leftover { name, stored: self.today(), frozen: command == "freeze", reminded: false });
+                let text = self.leftovers_text();
+                ctx.answer(ENVIRONMENT.decorate(text)).send_queued(&ctx.requester).await?;
+                return Ok(());
+            }
+            "leftovers" => {
+                if args.first() == Some(&"window") {
+                    let days = |arg: Option<&&str>| -> anyhow::Result<i64> {
+                        let days = arg.ok_or_else(|| anyhow::anyhow!("Usage: /leftovers window <fridge days> [freezer days]"))?.parse::<i64>()?;
+                        if days < 1 {
+                            anyhow::bail!("Mindestens ein Tag");
+                        }
+                        if days > leftovers::MAX_DAYS {
+                            anyhow::bail!("Höchstens {} Tage", leftovers::MAX_DAYS);
+                        }
+                        Ok(days)
+                    };
+                    self.settings.leftover_days = days(args.get(1))?;
+                    if args.len() > 2 {
+                        self.settings.freezer_days = days(args.get(2))?;
+                    }
+                }
+                ctx.answer(ENVIRONMENT.decorate(self.leftovers_text())).reply_markup(self.leftovers_markup()).send_queued(&ctx.requester).await?;
+                return Ok(());
+            }
+            "pause" => {
+                let today = Utc::now().with_timezone(&self.chat_timezone(ctx.update.chat_id())).naive_local().date();
+                let phrase = args.iter().filter(|arg| **arg != "bis").copied().collect::<Vec<_>>().join(" ");
+                let until = if phrase.is_empty() {
+                    None
+                } else {
+                    let until = date_phrase::parse_day(&phrase, today)
+                        .ok_or_else(|| anyhow::anyhow!("Usage: /pause [bis <day>]"))?;
+                    if until <= today {
+                        anyhow::bail!("Der Tag muss in der Zukunft liegen");
+                    }
+                    Some(until)
+                };
+                let pause = pause::Pause { until };
+                ctx.answer(ENVIRONMENT.decorate(pause.describe())).send_queued(&ctx.requester).await?;
+                self.paused_chats.insert(ctx.update.chat_id(), pause);
+                return Ok(());
+            }
+            "accessible" => {
+                let chat_id = ctx.update.chat_id();
+                let text = match args.first().copied() {
+                    Some("on") => {
+                        self.accessible_chats.insert(chat_id);
+                        "Texte und Knöpfe kommen jetzt ohne Emoji, für Screenreader."
+                    }
+                    Some("off") => {
+                        self.accessible_chats.remove(&chat_id);
+                        "Texte und Knöpfe kommen wieder mit Emoji."
+                    }
+                    _ => anyhow::bail!("Usage: /accessible on|off"),
+                };
+                ctx.answer(ENVIRONMENT.decorate(text.to_string())).send_queued(&ctx.requester).await?;
+            }
+            "quiet" => {
+                let chat_id = ctx.update.chat_id();
+                let text = match args.first() {
+                    None => match self.quiet_hours.get(&chat_id) {
+                        Some(quiet) => quiet.describe(),
+                        None => "Keine Ruhezeit. Setzen mit /quiet 22-7.".to_string(),
+                    },
+                    Some(&"off") => {
+                        self.quiet_hours.remove(&chat_id);
+                        "Keine Ruhezeit mehr.".to_string()
+                    }
+                    Some(_) => {
+                        let quiet = quiet::QuietHours::parse(&args).ok_or_else(|| anyhow::anyhow!("Usage: /quiet off|<from>-<to>"))?;
+                        self.quiet_hours.insert(chat_id, quiet);
+                        quiet.describe()
+                    }
+                };
+                ctx.answer(ENVIRONMENT.decorate(text)).send_queued(&ctx.requester).await?;
+                return Ok(());
+            }
+            "resume" => {
+                let text = match self.paused_chats.remove(&ctx.update.chat_id()) {
+                    Some(_) => "Weiter geht's, Erinnerungen sind wieder an.",
+                    None => "Hier ist nichts pausiert.",
+                };
+                ctx.answer(ENVIRONMENT.decorate(text.to_string())).send_queued(&ctx.requester).await?;
+                return Ok(());
+            }
+            "reset" => {
+                self.settings.weekly_reset = match args.first().copied() {
+                    Some("off") => None,
+                    _ => Some(reset::WeeklyReset::parse(&args).ok_or_else(|| anyhow::anyhow!("Usage: /reset off|<weekday> [hour]"))?),
+                };
+                let text = match &self.settings.weekly_reset {
+                    Some(weekly_reset) => weekly_reset.describe(),
+                    None => "Die Liste wird nicht mehr automatisch zurückgesetzt.".to_string(),
+                };
+                ctx.answer(ENVIRONMENT.decorate(text)).send_queued(&ctx.requester).await?;
+                return Ok(());
+            }
+            "trips" => {
+                if self.trips.is_empty() {
+                    anyhow::bail!("Noch keine Einkäufe. Sie werden gespeichert, wenn du abgehakte Einträge entfernst.");
+                }
+                let timezone = self.timezone();
+                let shown: Vec<&trips::Trip> = self.trips.iter().rev().take(trips::SHOWN_TRIPS).collect();
+                let text = shown.iter().fold("Letzte Einkäufe:".to_string(), |a, trip| {
+                    format!("{}\n\n{}", a, trip.describe(&self.users, timezone))
+                });
+                let buttons = shown.iter()
+                    .map(|trip| Action::RepeatTrip(trip.id).button(format!("🔁 {}", trip.name(timezone))))
+                    .collect();
+                let markup = append_grid(InlineKeyboardMarkup::default(), buttons, 2);
+                return self.replace_menu(ctx, text, Some(markup)).await;
+            }
+            "export" => {
+                if args.as_slice() != ["trips.ics"] {
+                    anyhow::bail!("Usage: /export trips.ics");
+                }
+                if self.trips.is_empty() {
+                    anyhow::bail!("Noch keine Einkäufe. Sie werden gespeichert, wenn du abgehakte Einträge entfernst.");
+                }
+                let calendar = ics::trips(&self.trips, &self.list.history, &self.money());
+                ctx.answer_document(InputFile::memory("trips.ics", calendar.into_bytes()))
+                    .send_queued(&ctx.requester)
+                    .await?;
+                return Ok(());
+            }
+            "seasonal" => {
+                let month = self.today().month();
+                let buttons = seasons::in_season_now(month).into_iter()
+                    .map(|(index, name)| Action::AddSeasonal(index).button(format!("🌱 {}", name)))
+                    .collect();
+                let markup = append_grid(InlineKeyboardMarkup::default(), buttons, 3);
+                ctx.answer(ENVIRONMENT.decorate("Jetzt in Saison, zum Hinzufügen tippen:".to_string()))
+                    .reply_markup(markup)
+                    .send_queued(&ctx.requester)
+                    .await?;
+                return Ok(());
+            }
+            "pantry" => {
+                let products = args.get(1..).map(|rest| rest.join(" ")).unwrap_or_default();
+                let products = products.split(',').map(|product| product.trim()).filter(|product| !product.is_empty());
+                match args.first() {
+                    Some(&"add") => {
+                        for product in products {
+                            ENVIRONMENT.input_limits.check_text(product)?;
+                            self.list.pantry.add(product);
+                        }
+                    }
+                    Some(&"remove") => {
+                        for product in products {
+                            if !self.list.pantry.remove(product) {
+                                anyhow::bail!("{} ist nicht im Vorrat", product);
+                            }
+                        }
+                    }
+                    Some(_) => anyhow::bail!("Usage: /pantry [add|remove <product>, ...]"),
+                    None if !self.list.pantry.products().is_empty() => return self.open_view(ctx, View::Pantry).await,
+                    None => {}
+                }
+                let text = if self.list.pantry.products().is_empty() {
+                    "Der Vorrat ist leer.".to_string()
+                } else {
+                    format!("Vorrat: {}", self.list.pantry.products().join(", "))
+                };
+                ctx.answer(ENVIRONMENT.decorate(text)).send_queued(&ctx.requester).await?;
+                return Ok(());
+            }
+            "cookable" => {
+                let max_missing = match args.first() {
+                    Some(number) => number.parse::<usize>()?,
+                    None => 0,
+                };
+                let mut cookable: Vec<(&String, Vec<&String>)> = self.list.recipes.iter()
+                    .filter(|(name, _)| !self.list.archived_recipes.contains(*name))
+                    .map(|(name, recipe)| (name, recipe.missing(&self.list.pantry)))
+                    .filter(|(_, missing)| missing.len() <= max_missing)
+                    .collect();
+                cookable.sort_by(|(a_name, a_missing), (b_name, b_missing)| a_missing.len().cmp(&b_missing.len()).then(a_name.cmp(b_name)));
+                if cookable.is_empty() {
+                    anyhow::bail!("Mit dem Vorrat lässt sich kein Rezept kochen");
+                }
+                let mut markup = InlineKeyboardMarkup::default();
+                let mut text = "Das geht mit dem Vorrat:".to_string();
+                for (name, missing) in cookable {
+                    if missing.is_empty() {
+                        text.push_str(&format!("\n ✅ {}", html::escape(name)));
+                        continue;
+                    }
+                    let missing: Vec<&str> = missing.iter().map(|ingredient| ingredient.as_str()).collect();
+                    text.push_str(&format!("\n 🛒 {}: es fehlt {}", html::escape(name), html::escape(&missing.join(", "))));
+                    if let Some(id) = self.names.id(name) {
+                        markup = markup.append_row(vec![Action::AddMissing(id).button(format!("➕ Fehlendes für {}", name))]);
+                    }
+                }
+                ctx.answer(ENVIRONMENT.decorate(text))
+                    .parse_mode(ParseMode::Html)
+                    .reply_markup(markup)
+                    .send_queued(&ctx.requester)
+                    .await?;
+                return Ok(());
+            }
+            "rate" => {
+                let name = if args.is_empty() {
+                    let today = Utc::now().with_timezone(&self.timezone()).naive_local().date();
+                    self.meal_plan.iter().rev()
+                        .find(|planned| planned.date <= today && self.list.recipes.contains_key(&planned.meal))
+                        .map(|planned| planned.meal.clone())
+                        .ok_or_else(|| anyhow::anyhow!("Kein gekochtes Rezept im Essensplan"))?
+                } else {
+                    args.join(" ")
+                };
+                let markup = self.rating_markup(&name).ok_or_else(|| anyhow::anyhow!("Unknown recipe {}", name))?;
+                ctx.answer(ENVIRONMENT.decorate(format!("Wie war {}?", html::escape(&name))))
+                    .parse_mode(ParseMode::Html)
+                    .reply_markup(markup)
+                    .send_queued(&ctx.requester)
+                    .await?;
+                return Ok(());
+            }
+            "surprise" => {
+                let name = self.surprise_recipe().ok_or_else(|| anyhow::anyhow!("Noch keine Rezepte"))?;
+                let id = self.names.id(name).ok_or_else(|| anyhow::anyhow!("Unknown recipe {}", name))?;
+                let stars = self.recipe_rating(name).map(|rating| format!(" ({:.1} ⭐)", rating)).unwrap_or_default();
+                ctx.answer(ENVIRONMENT.decorate(format!("Wie wäre es mit {}{}?", html::escape(name), stars)))
+                    .parse_mode(ParseMode::Html)
+                    .reply_markup(InlineKeyboardMarkup::default().append_row(vec![Action::AddRecipe(id).button(format!("➕ {}", name))]))
+                    .send_queued(&ctx.requester)
+                    .await?;
+                return Ok(());
+            }
+            "import" => {
+                let format = args.first().and_then(|name| import::Format::parse(name))
+                    .ok_or_else(|| anyhow::anyhow!("Usage: /import bring|anylist|keep"))?;
+                // Text pasted below the command, e.g. a list shared from AnyList
+                let content = ctx.update.text().and_then(|text| text.split_once('\n')).map(|(_, rest)| rest).unwrap_or_default();
+                if content.trim().is_empty() {
+                    anyhow::bail!("Schick die Exportdatei mit /import {} als Beschriftung oder füge den Text unter dem Befehl ein.", args[0]);
+                }
+                return self.preview_import(ctx, import::convert(format, content)?).await;
+            }
+            "addrecipe" => {
+                // The recipe starts right after the command or on the next line
+                let text = ctx.update.text().and_then(|text| text.trim_start().split_once(char::is_whitespace)).map(|(_, rest)| rest).unwrap_or_default();
+                let lines = recipe_text::lines(text);
+                let lines = self.check_recipe_lines(user.id, &lines, lines.len().saturating_sub(1))?;
+                let mut lines = lines.into_iter();
+                self.current_recipe = Some((lines.next().map(|line| recipe_text::name(&line)), lines.collect()));
+                return self.open_view(ctx, View::RecipeComposer).await;
+            }
+            "normalize_recipes" => {
+                if !ENVIRONMENT.is_admin(user.id) {
+                    anyhow::bail!("Nur für Admins");
+                }
+                let text = match self.normalize_recipes() {
+                    0 => "Alle Zutaten sind schon einheitlich.".to_string(),
+                    changed => format!("{} Zutaten vereinheitlicht.", changed),
+                };
+                ctx.answer(ENVIRONMENT.decorate(text)).send_queued(&ctx.requester).await?;
+                return Ok(());
+            }
+            "prune" => {
+                if !ENVIRONMENT.is_admin(user.id) {
+                    anyhow::bail!("Nur für Admins");
+                }
+                let months = match args.first() {
+                    Some(months) => months.parse().ok().filter(|months| (1..=config::MAX_HISTORY_RETENTION_MONTHS).contains(months)).ok_or_else(|| anyhow::anyhow!("Usage: /prune [<months to keep>]"))?,
+                    None => config::current().history_retention_months,
+                };
+                let text = match self.compact_history(months) {
+                    0 => format!("Im Verlauf ist nichts älter als {} Monate.", months),
+                    pruned => format!("{} Einträge älter als {} Monate wurden zu Monatssummen zusammengefasst.", pruned, months),
+                };
+                ctx.answer(ENVIRONMENT.decorate(text)).send_queued(&ctx.requester).await?;
+                return Ok(());
+            }
+            "asof" => {
+                let usage = || anyhow::anyhow!("Usage: /asof <YYYY-MM-DD>");
+                let date = args.first()
+                    .and_then(|date| NaiveDate::parse_from_str(date, "%Y-%m-%d").or_else(|_| NaiveDate::parse_from_str(date, "%d.%m.%Y")).ok())
+                    .ok_or_else(usage)?;
+                let timezone = self.timezone();
+                // The end of the day, so the list shows what was added that day
+                let at = date.succ_opt()
+                    .and_then(|next| timezone.from_local_datetime(&next.and_time(NaiveTime::MIN)).earliest())
+                    .ok_or_else(usage)?
+                    .with_timezone(&Utc);
+                let day = date.format("%d.%m.%Y");
+                let items = match timeline::list_at(&self.timeline, at) {
+                    Some(items) => items,
+                    None => {
+                        let text = match timeline::since(&self.timeline) {
+                            Some(since) => format!("Die Liste wird erst seit dem {} aufgezeichnet.", since.with_timezone(&timezone).format("%d.%m.%Y")),
+                            None => "Die Liste wird ab der nächsten Änderung aufgezeichnet.".to_string(),
+                        };
+                        ctx.answer(ENVIRONMENT.decorate(text)).send_queued(&ctx.requester).await?;
+                        return Ok(());
+                    }
+                };
+                if items.is_empty() {
+                    ctx.answer(ENVIRONMENT.decorate(format!("Am {} war die Liste leer.", day))).send_queued(&ctx.requester).await?;
+                    return Ok(());
+                }
+                let text = items.iter().fold(format!("Die Liste am {}:", day), |a, (name, done)| {
+                    format!("{}\n{} {}", a, if *done { "☑" } else { "☐" }, name)
+                });
+                let markup = InlineKeyboardMarkup::default()
+                    .append_row(vec![Action::CopyAsOf(at.timestamp()).button("📋 In die Liste übernehmen")]);
+                return self.replace_menu(ctx, text, Some(markup)).await;
+            }
+            "stats" => {
+                let mut text = history::most_bought_text(&self.list.history, &self.history_summaries);
+                if let Some(stock_outs) = self.stock_outs.describe() {
+                    write!(text, "\n\n{}", stock_outs).unwrap();
+                }
+                ctx.answer(ENVIRONMENT.decorate(text)).send_queued(&ctx.requester).await?;
+                return Ok(());
+            }
+            "tour" => {
+                let text = match args.first().copied() {
+                    None => {
+                        self.tour = Some(tour::Step::first());
+                        tour::Step::first().instructions()
+                    }
+                    Some("off") => {
+                        self.tour = None;
+                        tour::ENDED_TEXT.to_string()
+                    }
+                    _ => anyhow::bail!("Usage: /tour [off]"),
+                };
+                ctx.answer(ENVIRONMENT.decorate(text)).send_queued(&ctx.requester).await?;
+                return Ok(());
+            }
+            "profile" => {
+                let known = self.users.get_mut(&user.id).ok_or_else(|| anyhow::anyhow!("Unknown user {}", user.id))?;
+                let rest = args.get(1..).unwrap_or_default().join(" ");
+                match args.first().copied() {
+                    None => {}
+                    Some("name") => known.profile.set_name(&rest)?,
+                    Some("emoji") => known.profile.set_emoji(&rest)?,
+                    Some("language") => {
+                        let language = i18n::Language::parse(&rest).ok_or_else(|| anyhow::anyhow!("Usage: /profile language de|en"))?;
+                        known.profile.language = Some(language);
+                    }
+                    Some("reset") => known.profile = profile::Profile::default(),
+                    _ => anyhow::bail!("Usage: /profile [name <name>|emoji <emoji>|language <de|en>|reset]"),
+                }
+                let text = format!("Du erscheinst als {}", known.name());
+                ctx.answer(ENVIRONMENT.decorate(text)).send_queued(&ctx.requester).await?;
+                return Ok(());
+            }
+            "translate" => {
+                let text = args.join(" ");
+                if !text.is_empty() {
+                    let usage = || anyhow::anyhow!("Usage: /translate de|en <name> = [<translation>]");
+                    let (language, rest) = text.split_once(' ').ok_or_else(usage)?;
+                    let language = i18n::Language::parse(language).ok_or_else(usage)?;
+                    let (name, translation) = rest.split_once('=').ok_or_else(usage)?;
+                    let (name, translation) = (name.trim().to_string(), translation.trim());
+                    if name.is_empty() {
+                        return Err(usage());
+                    }
+                    if translation.is_empty() {
+                        if let Some(translations) = self.translations.get_mut(&name) {
+                            translations.remove(&language);
+                            if translations.is_empty() {
+                                self.translations.remove(&name);
+                            }
+                        }
+                    } else {
+                        ENVIRONMENT.input_limits.check_text(translation)?;
+                        self.translations.entry(name).or_default().insert(language, translation.to_string());
+                    }
+                }
+                let mut names: Vec<&String> = self.translations.keys().collect();
+                names.sort();
+                let text = if names.is_empty() {
+                    "Noch keine Übersetzungen, z.B. /translate en Pfannkuchen = Pancakes".to_string()
+                } else {
+                    names.into_iter().fold("Übersetzungen:".to_string(), |a, name| {
+                        let translations: Vec<String> = i18n::LANGUAGES.iter()
+                            .filter_map(|language| Some(format!("{} {}", language.code(), self.translations[name].get(language)?)))
+                            .collect();
+                        format!("{}\n{}: {}", a, name, translations.join(", "))
+                    })
+                };
+                ctx.answer(ENVIRONMENT.decorate(text)).send_queued(&ctx.requester).await?;
+                if args.is_empty() {
+                    return Ok(());
+                }
+            }
+            "leaderboard" => {
+                ctx.answer(ENVIRONMENT.decorate(history::leaderboard_text(&self.list.history, &self.users, self.timezone()))).send_queued(&ctx.requester).await?;
+                return Ok(());
+            }
+            _ => {
+                let alias = self.aliases.get(&ctx.update.chat_id()).and_then(|aliases| aliases.get(command)).cloned();
+                match (alias, plugins::for_command(command)) {
+                    (Some(target), _) => return self.run_alias(ctx, user, target).await,
+                    (None, Some(plugin)) => return plugin.command(self, ctx, user, command, &args).await,
+                    (None, None) => anyhow::bail!("Unknown command: {}", command),
+                }
+            }
+        }
+        self.update_shopping_list(ctx).await
+    }
+}
+
+/// Parses the 1-based item number shown in the list into an index.
+fn parse_item_number(arg: Option<&&str>) -> anyhow::Result<usize> {
+    match arg.map(|arg| arg.parse::<usize>()) {
+        Some(Ok(number)) if number > 0 => Ok(number - 1),
+        _ => anyhow::bail!("Expected an item number"),
+    }
+}
+
+/// Telegram's limit for the text of a message.
+const MAX_MESSAGE_LENGTH: usize = 4096;
+const MAINTENANCE_TEXT: &str = "Bin kurz in Wartung 🛠 Bitte versuch es gleich nochmal.";
+/// Recipes planned this many days ago aren't proposed by `/plan auto` again.
+const RECENT_MEAL_DAYS: i64 = 14;
+/// Larger files sent to `/import` aren't downloaded, in bytes.
+const MAX_IMPORT_SIZE: u32 = 1024 * 1024;
+/// How many recipes `/discover` shows.
+const DISCOVER_RESULTS: usize = 3;
+/// Up to this many removed items are named in the toast, more are counted.
+const REMOVED_NAMES: usize = 3;
+/// Product photos looked up after an update at most, newest items first.
+const MAX_IMAGE_LOOKUPS: usize = 3;
+
+/// A bot with its own list, sharing the process with the other configured bots.
+pub(crate) struct Instance {
+    pub namespace: String,
+    pub data_path: String,
+    pub data: Mutex<Data>,
+    /// The data as of the last `store_data`, for reads that shouldn't wait for `data`.
+    pub snapshot: ArcSwap<Data>,
+    pub bot_name: Mutex<String>,
+    /// Personal lists loaded so far, by user, see `personal`.
+    pub personal: Mutex<HashMap<i64, personal::Loaded>>,
+}
+
+lazy_static! {
+    static ref ENVIRONMENT: environment::Environment = environment::Environment::from_env();
+}
+
+#[tokio::main]
+async fn main() {
+    let matches = App::new("shopping_bot")
+        .about("Telegram bot for a shared shopping list")
+        .subcommand(SubCommand::with_name("serve")
+            .about("Runs the bot (default)")
+            .arg(Arg::with_name("local-repl")
+                .long("local-repl")
+                .help("Reads updates from stdin and prints the answers instead of talking to Telegram")))
+        .subcommand(SubCommand::with_name("export")
+            .about("Writes the data file to FILE or stdout")
+            .arg(Arg::with_name("FILE")))
+        .subcommand(SubCommand::with_name("import")
+            .about("Replaces the data file with FILE")
+            .arg(Arg::with_name("FILE").required(true)))
+        .subcommand(SubCommand::with_name("migrate")
+            .about("Upgrades the data file to the current schema version")
+            .arg(Arg::with_name("cutover")
+                .long("cutover")
+                .help("Checks that the storage in STORAGE_SHADOW holds every list, so it can take over")))
+        .subcommand(SubCommand::with_name("validate")
+            .about("Checks the data file for integrity problems"))
+        .subcommand(SubCommand::with_name("admin")
+            .about("Sends COMMAND to the running bot on ADMIN_SOCKET, `admin help` lists them")
+            // Group chats have negative IDs
+            .setting(AppSettings::AllowNegativeNumbers)
+            .arg(Arg::with_name("COMMAND").multiple(true)))
+        .arg(Arg::with_name("bot")
+            .long("bot")
+            .takes_value(true)
+            .global(true)
+            .help("Namespace of the bot whose data file to use"))
+        .get_matches();
+
+    let path = ENVIRONMENT.data_path(matches.value_of("bot").unwrap_or_default());
+    let result = match matches.subcommand() {
+        ("export", Some(args)) => cli::export(&path, args.value_of("FILE")).await,
+        ("import", Some(args)) => cli::import(&path, args.value_of("FILE").unwrap()).await,
+        ("migrate", Some(args)) if args.is_present("cutover") => shadow::cutover(&path, matches.value_of("bot").unwrap_or_default()).await,
+        ("migrate", _) => cli::migrate(&path).await,
+        ("validate", _) => cli::validate(&path).await,
+        ("admin", Some(args)) => {
+            let command: Vec<&str> = args.values_of("COMMAND").map(Iterator::collect).unwrap_or_default();
+            cli::admin(matches.value_of("bot").unwrap_or_default(), &command).await
+        }
+        ("serve", Some(args)) if args.is_present("local-repl") => {
+            repl::serve().await;
+            Ok(())
+        }
+        _ => {
+            serve().await;
+            Ok(())
+        }
+    };
+    if let Err(error) = result {
+        eprintln!("{}", error);
+        std::process::exit(1);
+    }
+}
+
+async fn serve() {
+    teloxide::enable_logging!();
+    let _reporting = reporting::init();
+    config::spawn();
+    usage::start();
+    log::info!("Starting ShoppingWatcher...");
+    if ENVIRONMENT.staging {
+        log::warn!("Running in staging mode, only acting in chats {:?}", ENVIRONMENT.test_chats);
+    }
+
+    let mut bots = Vec::new();
+    for config in ENVIRONMENT.bots.iter() {
+        if config.token.is_empty() {
+            panic!("No token for bot {:?}, set TELOXIDE_TOKEN or BOT_TOKENS", config.namespace);
+        }
+        let instance = Instance {
+            namespace: config.namespace.clone(),
+            data_path: ENVIRONMENT.data_path(&config.namespace),
+            data: Mutex::new(Data::default()),
+            snapshot: ArcSwap::from_pointee(Data::default()),
+            bot_name: Mutex::new(String::new()),
+            personal: Mutex::new(HashMap::new()),
+        };
+        {
+            let mut data = instance.data.lock().await;
+            match storage::load_repairing(&instance.data_path).await {
+                Ok((Some(read_data), report)) => {
+                    if !report.is_empty() {
+                        log::warn!("Repaired the data of {:?} on load:\n{}", instance.namespace, report.join("\n"));
+                    }
+                    *data = read_data;
+                }
+                Ok((None, _)) => log::warn!("Data file {} missing, starting with an empty list", instance.data_path),
+                // Nothing could be set aside, don't overwrite the data with an empty list
+                Err(error) => panic!("Data file {} damaged: {:?}", instance.data_path, error),
+            }
+            for step in storage::migrate(&mut data) {
+                log::info!("Migrated data of {:?}: {}", instance.namespace, step);
+            }
+            instance.snapshot.store(Arc::new(data.clone()));
+        }
+        if let Err(error) = shadow::start(&instance).await {
+            log::error!("Couldn't copy the data of {:?} to the shadow storage: {:?}", instance.namespace, error);
+        }
+        let bot = Bot::new(config.token.clone());
+        let instance = Arc::new(instance);
+        transfer::register(&bot, &instance);
+        bots.push((bot, instance));
+    }
+
+    tokio::spawn(scheduler::run(bots.clone()));
+    for (bot, instance) in bots.iter() {
+        tokio::spawn(changelog::announce(bot.clone(), instance.clone()));
+    }
+    let instances: Vec<Arc<Instance>> = bots.iter().map(|(_, instance)| instance.clone()).collect();
+    sheets::spawn(&instances);
+    signal::spawn(&bots);
+    console::spawn(&bots);
+    grpc::spawn(&bots);
+    if let Some(addr) = ENVIRONMENT.http_addr {
+        tokio::spawn(http::serve(addr, bots.clone()));
+    }
+    tokio::spawn(health::notify_systemd(instances));
+
+    let dispatchers: Vec<_> = bots.into_iter()
+        .map(|(bot, instance)| tokio::spawn(run(bot, instance)))
+        .collect();
+    for dispatcher in dispatchers {
+        if let Err(error) = dispatcher.await {
+            log::error!("Dispatcher failed: {:?}", error);
+        }
+    }
+}
+
+async fn run(bot: Bot, instance: Arc<Instance>) {
+    match bot.get_me().send().await {
+        Ok(me) => *instance.bot_name.lock().await = me.user.username.unwrap_or_default(),
+        Err(error) => log::error!("Couldn't fetch bot info: {:?}", error),
+    }
+
+    tokio::spawn(health::monitor_telegram(bot.clone()));
+
+    let callback_instance = instance.clone();
+    let member_instance = instance.clone();
+    let chat_member_instance = instance.clone();
+    let inline_instance = instance.clone();
+    let listener = reactions::polling(bot.clone(), instance.clone());
+    Dispatcher::new(bot)
+        .callback_queries_handler(move |rx: DispatcherHandlerRx<Bot, CallbackQuery>| {
+            UnboundedReceiverStream::new(rx)
+                .for_each(move |ctx| {
+                    let instance = callback_instance.clone();
+                    async move {
+                        if rate_limit::throttle_callback_query(&ctx).await {
+                            return;
+                        }
+                        let context = json!({
+                            "bot": instance.namespace,
+                            "callback_query": ctx.update.data,
+                            "from": ctx.update.from.id,
+                            "chat_id": ctx.update.message.as_ref().map(|message| message.chat_id()),
+                        });
+                        refresh_data(&instance).await;
+                        let list = match &ctx.update.message {
+                            Some(message) => personal::list_for_chat(&instance, message.chat_id()).await,
+                            None => instance.clone(),
+                        };
+                        if !Arc::ptr_eq(&list, &instance) {
+                            refresh_data(&list).await;
+                        }
+                        let bot = ctx.requester.clone();
+                        if let Err(error) = handle_callback_query(&list, ctx).await {
+                            reporting::report(error, context).await;
+                        }
+                        store_data(&list).await;
+                        tokio::spawn(look_up_images(list, bot));
+                    }
+                })
+        })
+        .my_chat_members_handler(move |rx: DispatcherHandlerRx<Bot, ChatMemberUpdated>| {
+            UnboundedReceiverStream::new(rx)
+                .for_each(move |ctx| {
+                    let instance = member_instance.clone();
+                    async move {
+                        refresh_data(&instance).await;
+                        handle_my_chat_member(&instance, ctx).await;
+                        store_data(&instance).await
+                    }
+                })
+        })
+        .chat_members_handler(move |rx: DispatcherHandlerRx<Bot, ChatMemberUpdated>| {
+            UnboundedReceiverStream::new(rx)
+                .for_each(move |ctx| {
+                    let instance = chat_member_instance.clone();
+                    async move {
+                        refresh_data(&instance).await;
+                        if let Err(error) = handle_chat_member(&instance, ctx).await {
+                            log::error!("Couldn't handle a member change: {:?}", error);
+                        }
+                        store_data(&instance).await
+                    }
+                })
+        })
+        .inline_queries_handler(move |rx: DispatcherHandlerRx<Bot, InlineQuery>| {
+            UnboundedReceiverStream::new(rx)
+                .for_each(move |ctx| {
+                    let instance = inline_instance.clone();
+                    async move {
+                        if let Err(error) = handle_inline_query(&instance, ctx).await {
+                            log::warn!("Couldn't answer inline query: {:?}", error);
+                        }
+                    }
+                })
+        })
+        .messages_handler(move |rx: DispatcherHandlerRx<Bot, Message>| {
+            UnboundedReceiverStream::new(rx)
+                .for_each(move |ctx| {
+                    let instance = instance.clone();
+                    async move {
+                        if rate_limit::throttle_message(&ctx).await {
+                            return;
+                        }
+                        let context = json!({
+                            "bot": instance.namespace,
+                            "message": ctx.update.text(),
+                            "from": ctx.update.from().map(|user| user.id),
+                            "chat_id": ctx.update.chat_id(),
+                        });
+                        refresh_data(&instance).await;
+                        if personal::is_toggle(&instance, &ctx.update).await {
+                            if let Err(error) = personal::toggle(&instance, &ctx).await {
+                                reporting::report(error, context).await;
+                            }
+                            return;
+                        }
+                        let list = personal::list_for_chat(&instance, ctx.update.chat_id()).await;
+                        if !Arc::ptr_eq(&list, &instance) {
+                            refresh_data(&list).await;
+                        }
+                        let (bot, chat_id) = (ctx.requester.clone(), ctx.update.chat_id());
+                        if let Err(error) = handle_message(&list, ctx).await {
+                            reporting::report(error, context).await;
+                        }
+                        store_data(&list).await;
+                        tokio::spawn(unfurl_titles(list.clone(), bot.clone(), chat_id));
+                        tokio::spawn(look_up_images(list, bot));
+                    }
+                })
+        })
+        .dispatch_with_listener(listener, LoggingErrorHandler::with_custom_text("An error from the update listener"))
+        .await;
+}
+
+/// Names the link-only items added by an update after the titles of their pages.
+async fn unfurl_titles(instance: Arc<Instance>, bot: Bot, chat_id: i64) {
+    let pending = std::mem::take(&mut instance.data.lock().await.unfurling);
+    let mut titles = Vec::new();
+    for pending in pending {
+        if let Some(title) = unfurl::title(&pending.url).await {
+            titles.push((pending, title));
+        }
+    }
+    if titles.is_empty() {
+        return;
+    }
+    {
+        let mut data = instance.data.lock().await;
+        let mut renamed = false;
+        for (pending, title) in titles {
+            let title = match data.filter_text(pending.user_id, &title) {
+                Ok(title) => title,
+                Err(_) => continue,
+            };
+            // Unless it was renamed or removed meanwhile
+            let host = unfurl::host(&pending.url);
+            if let Some(item) = data.list.items.iter_mut().find(|item| item.id == pending.item_id && item.name == host) {
+                item.name = title;
+                renamed = true;
+            }
+        }
+        if !renamed {
+            return;
+        }
+        let ctx = UpdateWithCx { requester: bot, update: ChatId::Id(chat_id) };
+        if let Err(error) = data.update_shopping_list(&ctx).await {
+            log::error!("Couldn't show the titles of links: {:?}", error);
+        }
+    }
+    store_data(&instance).await
+}
+
+/// Looks up the photos of the newest items without holding the data, and shows
+/// the list again if one was found.
+async fn look_up_images(instance: Arc<Instance>, bot: Bot) {
+    let missing = {
+        let data = instance.data.lock().await;
+        if !data.settings.show_images {
+            return;
+        }
+        data.newest_item_image().1
+    };
+    let mut found = false;
+    for name in missing.iter().take(MAX_IMAGE_LOOKUPS) {
+        if images::lookup(name).await.is_some() {
+            found = true;
+            break;
+        }
+    }
+    if !found {
+        return;
+    }
+    instance.data.lock().await.show_outside_change(&bot, "product photos").await;
+    store_data(&instance).await
+}
+
+/// Picks up changes of other replicas sharing the data, see `redis`.
+async fn refresh_data(instance: &Instance) {
+    let mut data = instance.data.lock().await;
+    match storage::outdated(&instance.data_path).await {
+        Ok(false) => {}
+        Ok(true) => match storage::load(&instance.data_path).await {
+            Ok(Some(read_data)) => {
+                *data = read_data;
+                // Readers of the snapshot would go on seeing the state before the reload
+                instance.snapshot.store(Arc::new(data.clone()));
+            }
+            Ok(None) => log::warn!("Data of {:?} vanished, keeping the current state", instance.namespace),
+            Err(error) => log::error!("Couldn't reload data: {:?}", error),
+        },
+        Err(error) => log::error!("Couldn't check for changes: {:?}", error),
+    }
+}
+
+pub(crate) async fn store_data(instance: &Instance) {
+    // Logged while holding the lock, so the entries are in the order of the changes
+    let mut data = instance.data.lock().await;
+    let mut events = data.list_events();
+    // Changes of the scripts don't trigger them again
+    if data.run_scripts(&events) {
+        events.extend(data.list_events());
+    }
+    data.record_timeline(&events);
+    data.queue_webhook_events(&events);
+    match storage::log(&instance.data_path, &mut data).await {
+        Ok(()) => health::record_persist(),
+        Err(error) => log::error!("{:?}", error),
+    }
+    // Before the events are pushed, so a client connecting now gets a list that includes them
+    instance.snapshot.store(Arc::new(data.clone()));
+    push::publish(&instance.data_path, &events);
+}
+
+/// Archives the chat when the bot is removed from it and restores it when the bot comes back.
+async fn handle_my_chat_member(instance: &Instance, ctx: UpdateWithCx<Bot, ChatMemberUpdated>) {
+    let chat_id = ctx.update.chat.id;
+    let mut guard = instance.data.lock().await;
+    match ctx.update.new_chat_member.status() {
+        ChatMemberStatus::Left | ChatMemberStatus::Kicked => {
+            log::info!("Removed from chat {}, archiving it", chat_id);
+            guard.archive_chat(chat_id);
+        }
+        _ if guard.archived_chats.contains_key(&chat_id) => {
+            log::info!("Added to chat {} again, restoring it", chat_id);
+            guard.restore_chat(chat_id);
+        }
+        _ => {}
+    }
+}
+
+/// Keeps track of who is in the chat of the list, for assignments.
+async fn handle_chat_member(instance: &Instance, ctx: UpdateWithCx<Bot, ChatMemberUpdated>) -> anyhow::Result<()> {
+    let mut guard = instance.data.lock().await;
+    if guard.active_message.map(|(chat_id, _)| chat_id) != Some(ctx.update.chat.id) {
+        return Ok(());
+    }
+    let user_id = ctx.update.new_chat_member.user.id;
+    match ctx.update.new_chat_member.status() {
+        ChatMemberStatus::Left | ChatMemberStatus::Kicked => guard.member_left(&ctx, user_id).await,
+        _ => {
+            guard.departed_users.remove(&user_id);
+            Ok(())
+        }
+    }
+}
+
+/// Suggests item names for `@bot …`, see `autocomplete`.
+async fn handle_inline_query(instance: &Instance, ctx: UpdateWithCx<Bot, InlineQuery>) -> anyhow::Result<()> {
+    let suggestions = {
+        let guard = instance.data.lock().await;
+        // The history is only for people who use the list
+        match guard.users.get(&ctx.update.from.id) {
+            Some(user) => {
+                let language = user.profile.language.unwrap_or_else(|| guard.language());
+                autocomplete::suggestions(&ctx.update.query, &guard.list.history).into_iter()
+                    .map(|(name, count)| (guard.translate(&name, language).to_string(), name, count))
+                    .collect()
+            }
+            None => Vec::new(),
+        }
+    };
+    let results: Vec<InlineQueryResult> = suggestions.into_iter()
+        .enumerate()
+        .map(|(i, (title, name, count))| {
+            // The name the list knows is sent, whatever the user sees
+            let content = InputMessageContent::Text(InputMessageContentText::new(name));
+            let article = InlineQueryResultArticle::new(i.to_string(), title, content);
+            let article = if count > 0 { article.description(format!("{}× gekauft", count)) } else { article };
+            InlineQueryResult::Article(article)
+        })
+        .collect();
+    ctx.requester.answer_inline_query(ctx.update.id.clone(), results)
+        .is_personal(true)
+        .cache_time(10)
+        .send()
+        .await?;
+    Ok(())
+}
+
+async fn handle_message(instance: &Instance, ctx: UpdateWithCx<Bot, Message>) -> anyhow::Result<()> {
+    if !ENVIRONMENT.allows_chat(ctx.update.chat_id()) {
+        log::info!("Ignoring message in chat {}", ctx.update.chat_id());
+        return Ok(());
+    }
+    if let MessageKind::Common(message) = &ctx.update.kind {
+        if let MediaKind::Photo(photo) = &message.media_kind {
+            let marked = photo.caption.as_deref().map(|caption| caption.starts_with("/receipt")) == Some(true);
+            if marked || ctx.update.chat.is_private() {
+                return handle_receipt(instance, &ctx, photo).await;
+            }
+        }
+    }
+    if let MessageKind::Common(message) = &ctx.update.kind {
+        if let MediaKind::Document(document) = &message.media_kind {
+            if document.caption.as_deref().map(|caption| caption.starts_with("/import")) == Some(true) {
+                return handle_import_file(instance, &ctx, document).await;
+            }
+        }
+    }
+    let mut guard = instance.data.lock().await;
+
+    if let MessageKind::Migrate(migrate) = &ctx.update.kind {
+        let (from, to) = (ctx.update.chat_id(), migrate.migrate_to_chat_id);
+        log::info!("Chat {} was migrated to {}", from, to);
+        if guard.migrate_chat(from, to) {
+            let ctx = UpdateWithCx { requester: ctx.requester.clone(), update: ChatId::Id(to) };
+            guard.update_shopping_list(&ctx).await?;
+        }
+        return Ok(());
+    }
+    // Without admin rights there are no `chat_member` updates, only these messages
+    let in_list_chat = guard.active_message.map(|(chat_id, _)| chat_id) == Some(ctx.update.chat_id());
+    if let MessageKind::LeftChatMember(left) = &ctx.update.kind {
+        if in_list_chat {
+            guard.member_left(&ctx, left.left_chat_member.id).await?;
+        }
+        return Ok(());
+    }
+    if let MessageKind::NewChatMembers(members) = &ctx.update.kind {
+        if in_list_chat {
+            for member in members.new_chat_members.iter() {
+                guard.departed_users.remove(&member.id);
+            }
+        }
+        let bot_name = instance.bot_name.lock().await.clone();
+        if members.new_chat_members.iter().any(|member| member.username.as_deref() == Some(bot_name.as_str())) {
+            guard.known_chats.insert(ctx.update.chat_id());
+            return guard.start_onboarding(&ctx).await;
+        }
+    }
+    if let MessageKind::Common(message) = ctx.update.kind.clone() {
+        if let MediaKind::Text(text) = message.media_kind {
+            let user = message.from.unwrap();
+            log::info!("{} ({}): {}", user.first_name, user.id, text.text);
+            guard.remember_user(&user);
+            guard.known_chats.insert(ctx.update.chat_id());
+            let bot_name = instance.bot_name.lock().await.clone();
+            if guard.is_note(&text.text, &bot_name) {
+                return Ok(());
+            }
+            let guest_token = match parse_command(&text.text, &bot_name) {
+                Some(("start", args)) => args.first().and_then(|payload| deep_link::parse_guest_payload(payload)),
+                _ => None,
+            };
+            if let Some(token) = guest_token {
+                return guard.redeem_guest_link(&ctx, token).await;
+            }
+            match guard.guests.access(ctx.update.chat_id()) {
+                guest::Access::Member => {}
+                guest::Access::Guest => return guard.send_guest_view(&ctx).await,
+                guest::Access::Expired => {
+                    ctx.reply_to(ENVIRONMENT.decorate(guest::EXPIRED_TEXT.to_string())).send_queued(&ctx.requester).await?;
+                    return Ok(());
+                }
+            }
+            if guard.maintenance && !ENVIRONMENT.is_admin(user.id) {
+                ctx.reply_to(ENVIRONMENT.decorate(MAINTENANCE_TEXT.to_string())).send_queued(&ctx.requester).await?;
+                return Ok(());
+            }
+            if guard.searching {
+                if let Err(error) = ENVIRONMENT.input_limits.check_text(&text.text) {
+                    ctx.reply_to(ENVIRONMENT.decorate(error.to_string())).send_queued(&ctx.requester).await?;
+                    return Ok(());
+                }
+                guard.searching = false;
+                guard.filter = Some(text.text.trim().to_string());
+                guard.open_view(&ctx, View::Edit).await?;
+                if guard.deletes_messages(ctx.update.chat_id()) {
+                    ctx.delete_message().send_later(&ctx.requester);
+                }
+                return Ok(());
+            }
+            if guard.naming_template {
+                if let Err(error) = ENVIRONMENT.input_limits.check_text(&text.text) {
+                    ctx.reply_to(ENVIRONMENT.decorate(error.to_string())).send_queued(&ctx.requester).await?;
+                    return Ok(());
+                }
+                guard.naming_template = false;
+                let names = guard.list.items.iter().map(|item| item.to_text()).collect();
+                guard.names.register(&text.text);
+                guard.templates.insert(text.text, names);
+                guard.render_view(&ctx, View::Templates).await?;
+                if guard.deletes_messages(ctx.update.chat_id()) {
+                    ctx.delete_message().send_later(&ctx.requester);
+                }
+                return Ok(());
+            }
+            if let Some(original) = guard.duplicating_recipe.clone() {
+                let duplicated = guard.filter_text(user.id, &text.text)
+                    .and_then(|name| guard.duplicate_recipe(&original, &name));
+                if let Err(error) = duplicated {
+                    ctx.reply_to(ENVIRONMENT.decorate(error.to_string())).send_queued(&ctx.requester).await?;
+                    return Ok(());
+                }
+                guard.render_view(&ctx, View::RecipeManager).await?;
+                if guard.deletes_messages(ctx.update.chat_id()) {
+                    ctx.delete_message().send_later(&ctx.requester);
+                }
+                return Ok(());
+            }
+            if let Some((name, field)) = guard.recipe_form.clone() {
+                let mut info = guard.recipe_info.get(&name).cloned().unwrap_or_default();
+                if let Err(error) = field.answer(&mut info, &text.text) {
+                    ctx.reply_to(ENVIRONMENT.decorate(error.to_string())).send_queued(&ctx.requester).await?;
+                    return Ok(());
+                }
+                match field.next() {
+                    Some(next) => {
+                        let question = next.question(&name, &info);
+                        guard.recipe_form = Some((name.clone(), next));
+                        guard.replace_menu(&ctx, question, None).await?;
+                    }
+                    None => guard.recipe_form = None,
+                }
+                if info.is_empty() {
+                    guard.recipe_info.remove(&name);
+                } else {
+                    guard.recipe_info.insert(name, info);
+                }
+                if guard.recipe_form.is_none() {
+                    guard.render_view(&ctx, View::RecipeManager).await?;
+                }
+                if guard.deletes_messages(ctx.update.chat_id()) {
+                    ctx.delete_message().send_later(&ctx.requester);
+                }
+                return Ok(());
+            }
+            let mut recipe_lines = Vec::new();
+            if let Some((name, ingredients)) = &guard.current_recipe {
+                // A whole recipe can be pasted at once, with a line per ingredient
+                let lines = recipe_text::lines(&text.text);
+                let count = (ingredients.len() + lines.len()).saturating_sub(usize::from(name.is_none()));
+                match guard.check_recipe_lines(user.id, &lines, count) {
+                    Ok(lines) => recipe_lines = lines,
+                    Err(error) => {
+                        ctx.reply_to(ENVIRONMENT.decorate(error.to_string())).send_queued(&ctx.requester).await?;
+                        return Ok(());
+                    }
+                }
+            }
+            match &mut guard.current_recipe {
+                Some((name, ingredients)) => {
+                    let mut lines = recipe_lines.into_iter();
+                    if name.is_none() {
+                        *name = lines.next().map(|line| recipe_text::name(&line));
+                    }
+                    ingredients.extend(lines);
+                    guard.render_view(&ctx, View::RecipeComposer).await?;
+                }
+                None => {
+                    if text.text.trim() == "#" {
+                        return Ok(());
+                    }
+                    if let Some((command, args)) = parse_command(&text.text, &bot_name) {
+                        let result = match command {
+                            "discover" | "webhook" => guard.check_command(ctx.update.chat_id(), command),
+                            _ => guard.handle_command(&ctx, &user, &bot_name, command, args.clone()).await,
+                        };
+                        if let Err(error) = result {
+                            log::warn!("{}", error);
+                            ctx.reply_to(ENVIRONMENT.decorate(error.to_string())).send_queued(&ctx.requester).await?;
+                            return Ok(());
+                        }
+                        if command == "discover" || command == "webhook" {
+                            let delete_message = guard.deletes_messages(ctx.update.chat_id());
+                            // Don't hold the lock while asking the recipe site or the target of the webhook
+                            drop(guard);
+                            let result = match command {
+                                "discover" => discover_recipes(&ctx, &args).await,
+                                _ => webhook_command(instance, &ctx, &user, &args).await,
+                            };
+                            if let Err(error) = result {
+                                log::warn!("{}", error);
+                                ctx.reply_to(ENVIRONMENT.decorate(error.to_string())).send_queued(&ctx.requester).await?;
+                            } else if delete_message {
+                                ctx.delete_message().send_later(&ctx.requester);
+                            }
+                            return Ok(());
+                        }
+                    } else if let Some(action) = guard.quick_action(ctx.update.chat_id(), &text.text) {
+                        guard.run_quick_action(&ctx, action).await?;
+                    } else if let Some(item) = guard.shortcut(ctx.update.chat_id(), &text.text) {
+                        if let Err(error) = guard.run_alias(&ctx, &user, aliases::Target::Add(item)).await {
+                            ctx.reply_to(ENVIRONMENT.decorate(error.to_string())).send_queued(&ctx.requester).await?;
+                            return Ok(());
+                        }
+                    } else if let Some(import) = import::pasted(&text.text) {
+                        guard.preview_import(&ctx, import).await?;
+                    } else if offers::is_offer(&text.text) {
+                        let offer = match offers::parse(&text.text, guard.today()) {
+                            Ok(offer) => offer,
+                            Err(error) => {
+                                ctx.reply_to(ENVIRONMENT.decorate(error.to_string())).send_queued(&ctx.requester).await?;
+                                return Ok(());
+                            }
+                        };
+                        let reply = format!("💰 {} für {}", offer.name, guard.describe_offer(&offer, &guard.money()));
+                        guard.offers.retain(|existing| !existing.name.eq_ignore_ascii_case(&offer.name));
+                        guard.offers.push(offer);
+                        ctx.answer(ENVIRONMENT.decorate(reply)).send_queued(&ctx.requester).await?;
+                        guard.update_shopping_list(&ctx).await?;
+                    } else {
+                        let name = guard.normalize_item(&text.text);
+                        if let Err(error) = guard.check_new_item(&name) {
+                            ctx.reply_to(ENVIRONMENT.decorate(error.to_string())).send_queued(&ctx.requester).await?;
+                            return Ok(());
+                        }
+                        if let Some(warning) = guard.diet_warning(&name) {
+                            ctx.answer(ENVIRONMENT.decorate(warning)).send_queued(&ctx.requester).await?;
+                        }
+                        if let Err(error) = guard.handle_new_item(&ctx, user.id, name).await {
+                            ctx.reply_to(ENVIRONMENT.decorate(error.to_string())).send_queued(&ctx.requester).await?;
+                            return Ok(());
+                        }
+                    }
+                }
+            }
+            if guard.deletes_messages(ctx.update.chat_id()) {
+                ctx.delete_message().send_later(&ctx.requester);
+            }
+        }
+    }
+    Ok(())
+}
+
+
+/// Sends the recipes found for `/discover`, each with a button to save it.
+async fn discover_recipes(ctx: &UpdateWithCx<Bot, Message>, args: &[&str]) -> anyhow::Result<()> {
+    if args.is_empty() {
+        anyhow::bail!("Usage: /discover <ingredient>");
+    }
+    let found = discover::search(&args.join(" "), DISCOVER_RESULTS).await?;
+    if found.is_empty() {
+        anyhow::bail!("Keine Rezepte mit {} gefunden", args.join(" "));
+    }
+    for recipe in found {
+        let markup = InlineKeyboardMarkup::default()
+            .append_row(vec![Action::SaveDiscovered(recipe.id).button("💾 Als Rezept speichern")]);
+        match recipe.photo {
+            Some(photo) => {
+                ctx.answer_photo(InputFile::url(photo))
+                    .caption(ENVIRONMENT.decorate(recipe.name))
+                    .reply_markup(markup)
+                    .send_queued(&ctx.requester)
+                    .await?;
+            }
+            None => {
+                ctx.answer(ENVIRONMENT.decorate(recipe.name)).reply_markup(markup).send_queued(&ctx.requester).await?;
+            }
+        }
+    }
+    Ok(())
+}
+
+/// `/webhook`, which only locks the data between the requests it makes.
+async fn webhook_command(instance: &Instance, ctx: &UpdateWithCx<Bot, Message>, user: &User, args: &[&str]) -> anyhow::Result<()> {
+    let chat_id = ctx.update.chat_id();
+    if !args.is_empty() && !is_chat_admin(ctx, user.id).await? {
+        anyhow::bail!("Nur für Admins des Chats");
+    }
+    let text = match args {
+        [] => match instance.data.lock().await.webhooks.get(&chat_id) {
+            Some(webhook) => format!("Änderungen gehen an:\n{}\nFormat: {}", webhook.url, webhook.format.describe()),
+            None => "Kein Webhook eingerichtet".to_string(),
+        },
+        [format @ ("raw" | "simple")] => {
+            let mut data = instance.data.lock().await;
+            let webhook = data.webhooks.get_mut(&chat_id).ok_or_else(|| anyhow::anyhow!("Kein Webhook eingerichtet"))?;
+            webhook.format = if *format == "simple" { webhooks::Format::Simple } else { webhooks::Format::Raw };
+            format!("Format: {}", webhook.format.describe())
+        }
+        ["test"] => {
+            let delivery = {
+                let data = instance.data.lock().await;
+                let webhook = data.webhooks.get(&chat_id).ok_or_else(|| anyhow::anyhow!("Kein Webhook eingerichtet"))?;
+                let event = webhooks::Event::Test { item: "Milch".to_string() };
+                webhooks::Delivery::new(webhook, &event, Utc::now())?
+            };
+            match delivery.send().await {
+                Ok(()) => format!("Test an {} gesendet:\n{}", delivery.url, delivery.body),
+                Err(error) => format!("Test an {} fehlgeschlagen: {}", delivery.url, error),
+            }
+        }
+        ["off"] => {
+            let mut data = instance.data.lock().await;
+            if let Some(webhook) = data.webhooks.remove(&chat_id) {
+                data.webhook_outbox.retain(|delivery| delivery.url != webhook.url);
+            }
+            "Webhook entfernt".to_string()
+        }
+        [url] if url.starts_with("http") => {
+            webhooks::check_url(url).await?;
+            let secret = deep_link::random_token(32);
+            let text = format!(
+                "Änderungen gehen jetzt per POST an:\n{}\n\nSignatur ({}, HMAC-SHA256) mit dem Schlüssel:\n{}",
+                url, webhooks::SIGNATURE_HEADER, secret,
+            );
+            let webhook = webhooks::Webhook { url: url.to_string(), secret, format: Default::default() };
+            instance.data.lock().await.webhooks.insert(chat_id, webhook);
+            text
+        }
+        _ => anyhow::bail!("Usage: /webhook [<url>|raw|simple|test|off]"),
+    };
+    ctx.answer(ENVIRONMENT.decorate(text)).send_queued(&ctx.requester).await?;
+    Ok(())
+}
+
+/// Whether the user may change what the chat sends elsewhere: anyone in a
+/// private chat, only the administrators of a group.
+async fn is_chat_admin(ctx: &UpdateWithCx<Bot, Message>, user_id: i64) -> anyhow::Result<bool> {
+    if ctx.update.chat.is_private() || ENVIRONMENT.is_admin(user_id) {
+        return Ok(true);
+    }
+    let member = ctx.requester.get_chat_member(ctx.update.chat_id(), user_id).send().await?;
+    Ok(matches!(member.status(), ChatMemberStatus::Creator | ChatMemberStatus::Administrator))
+}
+
+/// Reads a receipt photo and records the prices of the items bought on this trip.
+///
+/// Receipts are recognized in private chats, or with `/receipt` as the caption in groups.
+async fn handle_receipt(instance: &Instance, ctx: &UpdateWithCx<Bot, Message>, photo: &MediaPhoto) -> anyhow::Result<()> {
+    {
+        let guard = instance.data.lock().await;
+        let is_admin = ctx.update.from().map(|user| ENVIRONMENT.is_admin(user.id)) == Some(true);
+        if guard.guests.access(ctx.update.chat_id()) != guest::Access::Member || (guard.maintenance && !is_admin) {
+            return Ok(());
+        }
+    }
+    let largest = match photo.photo.iter().max_by_key(|size| size.width * size.height) {
+        Some(size) => size,
+        None => return Ok(()),
+    };
+    // Don't hold the lock while downloading and waiting for the OCR service
+    let file = ctx.requester.get_file(largest.file_id.clone()).send().await?;
+    let mut jpeg = Vec::new();
+    ctx.requester.download_file(&file.file_path, &mut jpeg).await?;
+    let text = match receipt::recognize(jpeg).await {
+        Ok(text) => text,
+        Err(error) => {
+            log::warn!("Receipt recognition failed: {:?}", error);
+            ctx.reply_to(ENVIRONMENT.decorate(error.to_string())).send_queued(&ctx.requester).await?;
+            return Ok(());
+        }
+    };
+
+    let mut guard = instance.data.lock().await;
+    let (matched, unmatched) = guard.reconcile_receipt(receipt::parse_lines(&text));
+    ctx.reply_to(ENVIRONMENT.decorate(receipt::summary(&matched, &unmatched, &guard.money()))).send_queued(&ctx.requester).await?;
+    if !matched.is_empty() {
+        guard.update_shopping_list(ctx).await?;
+    }
+    Ok(())
+}
+
+/// Reads an export of another app, sent with `/import <app>` as the caption.
+async fn handle_import_file(instance: &Instance, ctx: &UpdateWithCx<Bot, Message>, document: &MediaDocument) -> anyhow::Result<()> {
+    {
+        let guard = instance.data.lock().await;
+        let is_admin = ctx.update.from().map(|user| ENVIRONMENT.is_admin(user.id)) == Some(true);
+        if guard.guests.access(ctx.update.chat_id()) != guest::Access::Member || (guard.maintenance && !is_admin) {
+            return Ok(());
+        }
+    }
+    let caption = document.caption.as_deref().unwrap_or_default();
+    let format = match caption.split_whitespace().nth(1).and_then(import::Format::parse) {
+        Some(format) => format,
+        None => {
+            ctx.reply_to(ENVIRONMENT.decorate("Usage: /import bring|anylist|keep".to_string())).send_queued(&ctx.requester).await?;
+            return Ok(());
+        }
+    };
+    if document.document.file_size.unwrap_or(0) > MAX_IMPORT_SIZE {
+        ctx.reply_to(ENVIRONMENT.decorate("Die Datei ist zu groß.".to_string())).send_queued(&ctx.requester).await?;
+        return Ok(());
+    }
+    // Don't hold the lock while downloading
+    let file = ctx.requester.get_file(document.document.file_id.clone()).send().await?;
+    let mut content = Vec::new();
+    ctx.requester.download_file(&file.file_path, &mut content).await?;
+    let import = match import::convert(format, &String::from_utf8_lossy(&content)) {
+        Ok(import) => import,
+        Err(error) => {
+            ctx.reply_to(ENVIRONMENT.decorate(error.to_string())).send_queued(&ctx.requester).await?;
+            return Ok(());
+        }
+    };
+    instance.data.lock().await.preview_import(ctx, import).await
+}
+
+async fn handle_callback_query(instance: &Arc<Instance>, ctx: UpdateWithCx<Bot, CallbackQuery>) -> anyhow::Result<()> {
+    if let Some(message) = &ctx.update.message {
+        if !ENVIRONMENT.allows_chat(message.chat_id()) {
+            log::info!("Ignoring callback query in chat {}", message.chat_id());
+            return Ok(());
+        }
+    }
+    let mut guard = instance.data.lock().await;
+    let user = ctx.update.from.clone();
+    log::info!("{} ({}): {:?}", user.first_name, user.id, ctx.update.data);
+    guard.remember_user(&user);
+    if let Some(message) = &ctx.update.message {
+        guard.known_chats.insert(message.chat_id());
+        match guard.guests.access(message.chat_id()) {
+            guest::Access::Member => {}
+            guest::Access::Guest => return answer_with_toast(&ctx, guest::READ_ONLY_TEXT.to_string()).await,
+            guest::Access::Expired => return answer_with_toast(&ctx, guest::EXPIRED_TEXT.to_string()).await,
+        }
+    }
+    if guard.maintenance && !ENVIRONMENT.is_admin(user.id) {
+        ctx.requester.answer_callback_query(ctx.update.id.clone())
+            .text(MAINTENANCE_TEXT)
+            .send()
+            .await?;
+        return Ok(());
+    }
+
+    let data = match ctx.update.data.clone() {
+        Some(data) => data,
+        None => return Ok(()),
+    };
+    let action = match Action::decode(&data) {
+        Some(action) => action,
+        None => {
+            log::info!("Unknown callback query data: {}", data);
+            return guard.refresh_stale_button(&ctx).await;
+        }
+    };
+    match action {
+        Action::StartRecipe => {
+            guard.current_recipe = Some((
+                None,
+                Vec::new()
+            ));
+            guard.open_view(&ctx, View::RecipeComposer).await?;
+        }
+        Action::StartRemove => {
+            guard.edit_done_tab = false;
+            guard.open_view(&ctx, View::Edit).await?;
+        }
+        Action::Search => {
+            guard.searching = true;
+            guard.replace_menu(&ctx, "Wonach suchst du?".to_string(), None).await?;
+        }
+        Action::ClearFilter => {
+            guard.filter = None;
+            match guard.menu_view {
+                Some(view) => guard.render_view(&ctx, view).await?,
+                None => guard.update_shopping_list(&ctx).await?,
+            }
+        }
+        Action::EditTab { done } => {
+            guard.edit_done_tab = done;
+            guard.render_view(&ctx, View::Edit).await?;
+        }
+        Action::RecipeDone => {
+            let (name, ingredients) = match guard.current_recipe.clone() {
+                Some((Some(name), ingredients)) => (name, ingredients),
+                _ => return guard.close_recipe_composer(&ctx, false).await,
+            };
+            // `storage::validate` doesn't accept recipes without ingredients
+            if ingredients.is_empty() {
+                return answer_with_toast(&ctx, "Ein Rezept braucht mindestens eine Zutat.".to_string()).await;
+            }
+            let ingredients: Vec<String> = ingredients.iter().map(|ingredient| guard.normalize_ingredient(ingredient)).collect();
+            guard.current_recipe = Some((Some(name.clone()), ingredients.clone()));
+            if let Some(existing) = duplicates::find(&guard.list.recipes, &name, &ingredients).map(str::to_string) {
+                let id = guard.names.register(&existing);
+                let text = format!("Es gibt schon ein ähnliches Rezept, {}. Was soll mit {} passieren?", html::bold(&html::escape(&existing)), html::bold(&html::escape(&name)));
+                let markup = InlineKeyboardMarkup::default()
+                    .append_row(vec![Action::MergeRecipe(id).button("🔀 Zusammenführen")])
+                    .append_row(vec![Action::OverwriteRecipe(id).button(format!("♻️ {} ersetzen", existing))])
+                    .append_row(vec![Action::KeepBothRecipes.button("➕ Beide behalten")]);
+                return guard.replace_menu(&ctx, text, Some(markup)).await;
+            }
+            guard.names.register(&name);
+            guard.list.recipes.insert(name, Recipe::new(ingredients));
+            guard.close_recipe_composer(&ctx, true).await?;
+        }
+        Action::MergeRecipe(id) | Action::OverwriteRecipe(id) => {
+            let ingredients = match &guard.current_recipe {
+                Some((Some(_), ingredients)) => ingredients.clone(),
+                _ => return guard.refresh_stale_button(&ctx).await,
+            };
+            let existing = match guard.names.name(id).filter(|existing| guard.list.recipes.contains_key(*existing)) {
+                Some(existing) => existing.to_string(),
+                None => return guard.refresh_stale_button(&ctx).await,
+            };
+            let ingredients = if matches!(action, Action::MergeRecipe(_)) {
+                duplicates::merge(&guard.list.recipes[&existing].ingredients, &ingredients)
+            } else {
+                ingredients
+            };
+            guard.list.recipes.insert(existing, Recipe::new(ingredients));
+            guard.close_recipe_composer(&ctx, true).await?;
+        }
+        Action::KeepBothRecipes => {
+            let (name, ingredients) = match guard.current_recipe.clone() {
+                Some((Some(name), ingredients)) => (name, ingredients),
+                _ => return guard.refresh_stale_button(&ctx).await,
+            };
+            let name = duplicates::free_name(&guard.list.recipes, &name);
+            guard.names.register(&name);
+            guard.list.recipes.insert(name, Recipe::new(ingredients));
+            guard.close_recipe_composer(&ctx, true).await?;
+        }
+        Action::ToggleItem(id) => {
+            guard.toggle_from_button(instance, &ctx, user.id, id).await?;
+        }
+        Action::Increment(id) | Action::Decrement(id) => {
+            let increment = matches!(action, Action::Increment(_));
+            let item: &mut Item = match guard.list.items.iter_mut().find(|item| id != 0 && item.id == id && !item.header) {
+                Some(item) => item,
+                None => return guard.refresh_stale_button(&ctx).await,
+            };
+            item.quantity = if increment {
+                item.quantity + 1
+            } else {
+                item.quantity.saturating_sub(1).max(1)
+            };
+            render_list_markup_later(instance, &ctx).await?;
+        }
+        Action::DeleteItem(id) => {
+            let item = match guard.list.items.iter().position(|item| id != 0 && item.id == id && !item.header) {
+                Some(index) => guard.list.items.remove(index),
+                None => return guard.refresh_stale_button(&ctx).await,
+            };
+            answer_with_toast(&ctx, format!("🗑 {} gelöscht", item.name)).await?;
+            guard.list.history.push(history::Purchase {
+                name: item.name,
+                bought_by: None,
+                at: Utc::now(),
+                price: None,
+                category: item.category,
+                recipe: item.recipe,
+                event: history::Event::Deleted,
+                quantity: item.quantity,
+            });
+            if guard.settings.wish_list {
+                guard.update_shopping_list(&ctx).await?;
+            } else {
+                guard.render_view(&ctx, View::Edit).await?;
+            }
+        }
+        Action::WishPriority(id) => {
+            let item = match guard.list.item_position(id).map(|index| &mut guard.list.items[index]) {
+                Some(item) if !item.header => item,
+                _ => return guard.refresh_stale_button(&ctx).await,
+            };
+            item.priority = (item.priority + 1) % (wishes::MAX_PRIORITY + 1);
+            guard.update_shopping_list(&ctx).await?;
+        }
+        Action::Snooze(id) => {
+            let item = match guard.list.item_position(id).map(|index| &mut guard.list.items[index]) {
+                Some(item) if !item.header && !item.done => item,
+                _ => return guard.refresh_stale_button(&ctx).await,
+            };
+            item.snoozed = true;
+            let text = format!("💤 {} kommt beim nächsten Einkauf wieder", item.name);
+            answer_with_toast(&ctx, text).await?;
+            guard.render_view(&ctx, View::Edit).await?;
+        }
+        Action::NotAvailable(id) => {
+            let data = &mut *guard;
+            let item = match data.list.item_position(id).map(|index| &mut data.list.items[index]) {
+                Some(item) if !item.header && !item.done => item,
+                _ => return guard.refresh_stale_button(&ctx).await,
+            };
+            // Maybe there is some next time
+            item.snoozed = true;
+            let name = item.name.clone();
+            let store = data.store().map(str::to_string);
+            let count = data.stock_outs.record(&name, store.as_deref());
+            answer_with_toast(&ctx, format!("❌ {} gab es nicht, kommt beim nächsten Einkauf wieder", name)).await?;
+            if count >= stock_out::SUGGEST_AFTER {
+                let store = store.map(|store| format!(" bei {}", store)).unwrap_or_default();
+                let text = format!(
+                    "{} gab es{} schon {}× nicht. Auf die Liste eines anderen Ladens damit?",
+                    html::escape(&name),
+                    html::escape(&store),
+                    count,
+                );
+                let markup = InlineKeyboardMarkup::default()
+                    .append_row(vec![Action::TransferItem(id).button("📤 Anderer Laden")]);
+                guard.replace_menu(&ctx, text, Some(markup)).await?;
+            } else {
+                guard.render_view(&ctx, View::Edit).await?;
+            }
+        }
+        Action::RunningLow(index) => {
+            let product = match guard.list.pantry.products().get(index) {
+                Some(product) => product.clone(),
+                None => return guard.refresh_stale_button(&ctx).await,
+            };
+            if guard.list.pantry.is_low(&product) {
+                return answer_with_toast(&ctx, format!("{} steht schon auf der Liste", product)).await;
+            }
+            if !guard.list.items.iter().any(|item| !item.done && item.name.eq_ignore_ascii_case(&product)) {
+                if let Err(error) = ENVIRONMENT.input_limits.check_item_count(guard.list.items.len() + 1) {
+                    return answer_with_toast(&ctx, error.to_string()).await;
+                }
+                let quantity = history::usual_quantity(&guard.list.history, &product).unwrap_or(1);
+                guard.list.items.push(Item { quantity, ..Item::new(product.clone()) });
+            }
+            guard.list.pantry.mark_low(&product);
+            answer_with_toast(&ctx, format!("🛒 {} steht auf der Liste", product)).await?;
+            guard.render_view(&ctx, View::Pantry).await?;
+        }
+        Action::DeleteShortcut(index) => {
+            let chat_id = ctx.update.message.as_ref().map(|message| message.chat_id()).unwrap_or(user.id);
+            let emoji = match shortcuts::sorted(guard.shortcuts.get(&chat_id)).get(index) {
+                Some((emoji, _)) => emoji.to_string(),
+                None => return guard.refresh_stale_button(&ctx).await,
+            };
+            if let Some(shortcuts) = guard.shortcuts.get_mut(&chat_id) {
+                shortcuts.remove(&emoji);
+                if shortcuts.is_empty() {
+                    guard.shortcuts.remove(&chat_id);
+                }
+            }
+            guard.render_view(&ctx, View::Shortcuts).await?;
+        }
+        Action::ShowBulk => {
+            guard.confirming_clear = false;
+            guard.open_view(&ctx, View::Bulk).await?;
+        }
+        Action::CheckAll | Action::UncheckAll => {
+            let done = action == Action::CheckAll;
+            for item in guard.list.items.iter_mut().filter(|item| !item.header && item.done != done) {
+                item.done = done;
+                item.checked_by = if done { Some(user.id) } else { None };
+            }
+            answer_with_toast(&ctx, if done { "Alles abgehakt" } else { "Alles wieder offen" }.to_string()).await?;
+            // The menu stays as it is, only the list changes
+            guard.update_list_message(&ctx).await?;
+        }
+        Action::ClearList => {
+            guard.confirming_clear = true;
+            guard.render_view(&ctx, View::Bulk).await?;
+        }
+        Action::ConfirmClear => {
+            if !guard.confirming_clear {
+                return guard.refresh_stale_button(&ctx).await;
+            }
+            guard.confirming_clear = false;
+            guard.list.items.clear();
+            answer_with_toast(&ctx, "Liste geleert".to_string()).await?;
+            guard.render_view(&ctx, View::Bulk).await?;
+        }
+        Action::Approve(id) | Action::Reject(id) => {
+            if !ENVIRONMENT.is_admin(user.id) {
+                return answer_with_toast(&ctx, "Nur für Admins".to_string()).await;
+            }
+            let index = match guard.pending.iter().position(|pending| id != 0 && pending.id == id) {
+                Some(index) => index,
+                None => return guard.refresh_stale_button(&ctx).await,
+            };
+            if matches!(action, Action::Approve(_)) {
+                if let Err(error) = guard.check_new_item(&guard.pending[index].text) {
+                    return answer_with_toast(&ctx, error.to_string()).await;
+                }
+                let pending = guard.pending.remove(index);
+                guard.list.add_text(pending.text);
+            } else {
+                guard.pending.remove(index);
+            }
+            guard.update_shopping_list(&ctx).await?;
+        }
+        Action::Claim(id) => {
+            let users = guard.users.clone();
+            let item: &mut Item = match guard.list.item_position(id).map(|index| &mut guard.list.items[index]) {
+                Some(item) if !item.header => item,
+                _ => return guard.refresh_stale_button(&ctx).await,
+            };
+            match item.claimed_by {
+                None => item.claimed_by = Some(user.id),
+                Some(claimer) if claimer == user.id => item.claimed_by = None,
+                Some(claimer) => {
+                    let name = users.get(&claimer).map(KnownUser::name).unwrap_or_default();
+                    return answer_with_toast(&ctx, format!("{} bringt das schon mit.", name)).await;
+                }
+            }
+            guard.update_shopping_list(&ctx).await?;
+        }
+        Action::ToggleView { list } => {
+            guard.settings.hide_done = !guard.settings.hide_done;
+            if list {
+                guard.render_view(&ctx, View::Edit).await?;
+            } else {
+                guard.update_shopping_list(&ctx).await?;
+            }
+        }
+        Action::RemoveHeader(id) => {
+            match guard.list.item_position(id) {
+                Some(index) if guard.list.items[index].header => {
+                    guard.list.items.remove(index);
+                }
+                _ => return guard.refresh_stale_button(&ctx).await,
+            }
+            guard.render_view(&ctx, View::Edit).await?;
+        }
+        Action::RemoveDone => {
+            let removed = guard.clear_done();
+            let text = match removed.len() {
+                0 => "Nichts abgehakt".to_string(),
+                1..=REMOVED_NAMES => format!("💚 {} entfernt", removed.join(", ")),
+                count => format!("💚 {} Einträge entfernt", count),
+            };
+            answer_with_toast(&ctx, text).await?;
+            guard.update_shopping_list(&ctx).await?;
+            guard.advance_tour(&ctx, tour::Step::RemoveDone).await?;
+        }
+        Action::TripTimer => {
+            if guard.trip_started.is_none() {
+                guard.trip_started = Some(Utc::now());
+                answer_with_toast(&ctx, "⏱ Viel Erfolg beim Einkaufen!".to_string()).await?;
+            } else {
+                let text = guard.finish_trip();
+                ctx.requester.send_message(ctx.update.get_chat_id(), ENVIRONMENT.decorate(text)).send_queued(&ctx.requester).await?;
+            }
+            guard.update_shopping_list(&ctx).await?;
+        }
+        Action::ResetKeep | Action::ResetDrop => {
+            if !guard.reset_pending {
+                return guard.refresh_stale_button(&ctx).await;
+            }
+            let keep = action == Action::ResetKeep;
+            guard.reset_list(keep);
+            answer_with_toast(&ctx, if keep { "Offene Einträge übernommen" } else { "Neue, leere Liste" }.to_string()).await?;
+            guard.update_shopping_list(&ctx).await?;
+        }
+        Action::ToggleNotification(index) => {
+            let kind = match notifications::KINDS.get(index) {
+                Some(kind) => *kind,
+                None => return guard.refresh_stale_button(&ctx).await,
+            };
+            let user_id = ctx.update.from.id;
+            let on = !guard.wants_notification(user_id, kind);
+            guard.notifications.entry(user_id).or_default().set(kind, on);
+            answer_with_toast(&ctx, format!("{}: {}", kind.name(), if on { "an" } else { "aus" })).await?;
+            let text = guard.notifications[&user_id].describe();
+            let markup = guard.get_notification_markup(user_id);
+            guard.replace_menu(&ctx, text, Some(markup)).await?;
+        }
+        Action::RepeatTrip(id) => {
+            let trip = match guard.trips.iter().find(|trip| trip.id == id) {
+                Some(trip) => trip.clone(),
+                None => return guard.refresh_stale_button(&ctx).await,
+            };
+            let missing: Vec<&trips::TripItem> = trip.items.iter()
+                .filter(|entry| !guard.list.items.iter().any(|item| !item.done && item.name == entry.name))
+                .collect();
+            if let Err(error) = ENVIRONMENT.input_limits.check_item_count(guard.list.items.len() + missing.len()) {
+                return answer_with_toast(&ctx, error.to_string()).await;
+            }
+            let added = missing.len();
+            for entry in missing {
+                guard.list.items.push(Item { quantity: entry.quantity.max(1), ..Item::new(entry.name.clone()) });
+            }
+            answer_with_toast(&ctx, format!("🔁 {} Einträge wieder auf der Liste", added)).await?;
+            guard.update_shopping_list(&ctx).await?;
+        }
+        Action::CopyAsOf(timestamp) => {
+            let items = match Utc.timestamp_opt(timestamp, 0).single().and_then(|at| timeline::list_at(&guard.timeline, at)) {
+                Some(items) => items,
+                None => return guard.refresh_stale_button(&ctx).await,
+            };
+            let missing: Vec<String> = items.into_iter()
+                .map(|(name, _)| name)
+                .filter(|name| !guard.list.items.iter().any(|item| !item.done && item.name.to_lowercase() == name.to_lowercase()))
+                .collect();
+            if let Err(error) = ENVIRONMENT.input_limits.check_item_count(guard.list.items.len() + missing.len()) {
+                return answer_with_toast(&ctx, error.to_string()).await;
+            }
+            let added = missing.len();
+            for name in missing {
+                guard.list.items.push(Item::new(name));
+            }
+            answer_with_toast(&ctx, format!("📋 {} Einträge übernommen", added)).await?;
+            guard.update_shopping_list(&ctx).await?;
+        }
+        Action::ListRecipes => {
+            guard.open_view(&ctx, View::Recipes).await?;
+        }
+        Action::AddRecipe(id) => {
+            let name = match guard.names.name(id).filter(|name| guard.list.recipes.contains_key(*name)) {
+                Some(name) => name.to_string(),
+                None => return guard.refresh_stale_button(&ctx).await,
+            };
+            if let Err(error) = guard.check_new_item(&name) {
+                answer_with_toast(&ctx, error.to_string()).await?;
+                return Ok(());
+            }
+            if let Some(warning) = guard.diet_warning(&name) {
+                answer_with_toast(&ctx, warning).await?;
+            }
+            // Suggestions from restricted users are approved as a whole
+            if guard.restricted_users.contains(&user.id) {
+                return guard.handle_new_item(&ctx, user.id, name).await;
+            }
+            guard.adding_recipe = Some((name, HashSet::new()));
+            guard.open_view(&ctx, View::Ingredients).await?;
+        }
+        Action::ExcludeIngredient(index) => {
+            let excluded = match &mut guard.adding_recipe {
+                Some((_, excluded)) => excluded,
+                None => return guard.refresh_stale_button(&ctx).await,
+            };
+            if !excluded.remove(&index) {
+                excluded.insert(index);
+            }
+            guard.render_view(&ctx, View::Ingredients).await?;
+        }
+        Action::AddIngredients => {
+            let (name, excluded) = match guard.adding_recipe.take() {
+                Some(adding) => adding,
+                None => return guard.refresh_stale_button(&ctx).await,
+            };
+            let added = guard.list.add_recipe(&name, &excluded);
+            let text = match added {
+                1 => "➕ 1 Zutat hinzugefügt".to_string(),
+                added => format!("➕ {} Zutaten hinzugefügt", added),
+            };
+            answer_with_toast(&ctx, text).await?;
+            guard.go_back(&ctx).await?;
+            guard.advance_tour(&ctx, tour::Step::AddItem).await?;
+        }
+        Action::ManageRecipes => {
+            guard.open_view(&ctx, View::RecipeManager).await?;
+        }
+        Action::DuplicateRecipe(id) => {
+            let name = match guard.names.name(id).filter(|name| guard.list.recipes.contains_key(*name)) {
+                Some(name) => name.to_string(),
+                None => return guard.refresh_stale_button(&ctx).await,
+            };
+            let text = format!("Name der Kopie von {}, z.B. \"{} (vegetarisch)\":", html::escape(&name), html::escape(&name));
+            guard.duplicating_recipe = Some(name);
+            guard.replace_menu(&ctx, text, None).await?;
+        }
+        Action::EditRecipeInfo(id) => {
+            let name = match guard.names.name(id).filter(|name| guard.list.recipes.contains_key(*name)) {
+                Some(name) => name.to_string(),
+                None => return guard.refresh_stale_button(&ctx).await,
+            };
+            let field = recipe_info::Field::FIRST;
+            let text = field.question(&name, &guard.recipe_info.get(&name).cloned().unwrap_or_default());
+            guard.recipe_form = Some((name, field));
+            guard.replace_menu(&ctx, text, None).await?;
+        }
+        Action::Rate(id, stars) => {
+            let name = match guard.names.name(id).filter(|name| guard.list.recipes.contains_key(*name)) {
+                Some(name) => name.to_string(),
+                None => return guard.refresh_stale_button(&ctx).await,
+            };
+            guard.ratings.entry(name.clone()).or_default().insert(user.id, stars.clamp(1, 5));
+            answer_with_toast(&ctx, format!("{} für {} gespeichert", "⭐".repeat(stars.clamp(1, 5) as usize), name)).await?;
+        }
+        Action::Page(page) => {
+            guard.page = page;
+            guard.update_shopping_list(&ctx).await?;
+        }
+        Action::Onboarding(step, choice) => {
+            let chat_id = match &ctx.update.message {
+                Some(message) => message.chat_id(),
+                None => return Ok(()),
+            };
+            let language = guard.chat_language(chat_id);
+            match step {
+                onboarding::LANGUAGE => {
+                    let language = i18n::LANGUAGES.get(choice as usize).copied().unwrap_or_default();
+                    guard.languages.insert(chat_id, language);
+                    let text = i18n::text(language, i18n::Text::ChooseStore).to_string();
+                    guard.replace_menu(&ctx, text, Some(onboarding::store_markup(language))).await?;
+                }
+                onboarding::STORE => {
+                    let store = onboarding::STORES.get(choice as usize).map(|store| store.to_string());
+                    let delete_messages = guard.deletes_messages(chat_id);
+                    guard.onboarding_choices.insert(chat_id, onboarding::Choices { store, delete_messages });
+                    let text = i18n::text(language, i18n::Text::DeleteMessages).to_string();
+                    guard.replace_menu(&ctx, text, Some(onboarding::delete_messages_markup(language))).await?;
+                }
+                onboarding::DELETE_MESSAGES => {
+                    let store = guard.chat_store(chat_id).map(str::to_string);
+                    guard.onboarding_choices.insert(chat_id, onboarding::Choices { store, delete_messages: choice == 1 });
+                    if guard.list.items.is_empty() && guard.list.recipes.is_empty() {
+                        let text = i18n::text(language, i18n::Text::LoadDemo).to_string();
+                        guard.replace_menu(&ctx, text, Some(onboarding::demo_markup(language))).await?;
+                    } else {
+                        guard.update_shopping_list(&ctx).await?;
+                    }
+                }
+                _ if choice == 1 && guard.list.items.is_empty() && guard.list.recipes.is_empty() => {
+                    guard.load_demo(language);
+                    let text = i18n::text(language, i18n::Text::DemoLoaded).to_string();
+                    guard.render_menu(&ctx, text, Some(onboarding::clear_demo_markup(language))).await?;
+                }
+                _ => guard.update_shopping_list(&ctx).await?,
+            }
+        }
+        Action::ClearDemo => {
+            guard.clear_demo();
+            let language = guard.language();
+            answer_with_toast(&ctx, i18n::text(language, i18n::Text::DemoCleared).to_string()).await?;
+            guard.update_shopping_list(&ctx).await?;
+        }
+        Action::AddSeasonal(index) => {
+            let name = match seasons::produce(index) {
+                Some(name) => name.to_string(),
+                None => return answer_with_toast(&ctx, callback::STALE_TEXT.to_string()).await,
+            };
+            if let Err(error) = guard.check_new_item(&name) {
+                return answer_with_toast(&ctx, error.to_string()).await;
+            }
+            answer_with_toast(&ctx, format!("✅ {} hinzugefügt", name)).await?;
+            guard.handle_new_item(&ctx, user.id, name).await?;
+        }
+        Action::SwapMeal(day) => {
+            let mut proposal = match guard.meal_proposal.take() {
+                Some(proposal) if day < proposal.meals.len() => proposal,
+                proposal => {
+                    guard.meal_proposal = proposal;
+                    return guard.refresh_stale_button(&ctx).await;
+                }
+            };
+            match meal_plan::pick(&guard.plan_candidates(&proposal.meals), 1).pop() {
+                Some(meal) => proposal.meals[day] = meal,
+                None => answer_with_toast(&ctx, "Keine anderen Rezepte übrig".to_string()).await?,
+            }
+            let markup = Some(guard.get_proposal_markup(&proposal));
+            guard.meal_proposal = Some(proposal);
+            guard.replace_menu(&ctx, "Vorschlag für die Woche, tippe einen Tag zum Tauschen:".to_string(), markup).await?;
+        }
+        Action::AcceptPlan => {
+            let proposal = match guard.meal_proposal.take() {
+                Some(proposal) => proposal,
+                None => return guard.refresh_stale_button(&ctx).await,
+            };
+            if let Err(error) = guard.accept_proposal(proposal.clone()) {
+                guard.meal_proposal = Some(proposal);
+                return answer_with_toast(&ctx, error.to_string()).await;
+            }
+            guard.update_shopping_list(&ctx).await?;
+        }
+        Action::ConfirmImport => {
+            let import = match guard.pending_import.take() {
+                Some(import) => import,
+                None => return guard.refresh_stale_button(&ctx).await,
+            };
+            if let Err(error) = guard.apply_import(import.clone(), &ENVIRONMENT.input_limits, ENVIRONMENT.is_admin(user.id)) {
+                guard.pending_import = Some(import);
+                return answer_with_toast(&ctx, error.to_string()).await;
+            }
+            guard.update_shopping_list(&ctx).await?;
+        }
+        Action::DiscardImport => {
+            guard.pending_import = None;
+            guard.update_shopping_list(&ctx).await?;
+        }
+        Action::CheckItem(id) => {
+            let item = match guard.list.items.iter_mut().find(|item| id != 0 && item.id == id) {
+                Some(item) => item,
+                None => return answer_with_toast(&ctx, "Nicht mehr auf der Liste.".to_string()).await,
+            };
+            if item.done {
+                return answer_with_toast(&ctx, "Schon erledigt.".to_string()).await;
+            }
+            item.done = true;
+            item.checked_by = Some(user.id);
+            let name = item.name.clone();
+            answer_with_toast(&ctx, format!("{} erledigt", name)).await?;
+            guard.update_shopping_list(&ctx).await?;
+        }
+        Action::ShareText => {
+            ctx.requester.answer_callback_query(ctx.update.id.clone())
+                .send()
+                .await?;
+            // Long lists are split at line breaks, like `paginate` does
+            let mut messages = vec![String::new()];
+            for line in guard.get_plain_text().lines() {
+                if messages.last().map(|message| message.chars().count() + line.chars().count() + 1 > MAX_MESSAGE_LENGTH) == Some(true) {
+                    messages.push(String::new());
+                }
+                let message = messages.last_mut().unwrap();
+                message.push_str(line);
+                message.push('\n');
+            }
+            for message in messages {
+                ctx.requester.send_message(ctx.update.get_chat_id(), message)
+                    .send_queued(&ctx.requester)
+                    .await?;
+            }
+        }
+        Action::DiscardPlan => {
+            guard.meal_proposal = None;
+            guard.update_shopping_list(&ctx).await?;
+        }
+        Action::UseLeftover(index) => {
+            if index >= guard.leftovers.len() {
+                return answer_with_toast(&ctx, callback::STALE_TEXT.to_string()).await;
+            }
+            let leftover = guard.leftovers.remove(index);
+            answer_with_toast(&ctx, format!("{} aufgebraucht", leftover.name)).await?;
+            if let Some(message) = &ctx.update.message {
+                // The buttons point at indices, so they have to be rebuilt
+                ctx.requester.edit_message_text(message.chat_id(), message.id, ENVIRONMENT.decorate(guard.leftovers_text()))
+                    .reply_markup(guard.leftovers_markup())
+                    .send_queued(&ctx.requester)
+                    .await?;
+            }
+        }
+        Action::AddMissing(id) => {
+            let missing: Vec<String> = match guard.names.name(id).and_then(|name| guard.list.recipes.get(name)) {
+                Some(recipe) => recipe.missing(&guard.list.pantry).into_iter().cloned().collect(),
+                None => return guard.refresh_stale_button(&ctx).await,
+            };
+            if let Err(error) = ENVIRONMENT.input_limits.check_item_count(guard.list.items.len() + missing.len()) {
+                return answer_with_toast(&ctx, error.to_string()).await;
+            }
+            answer_with_toast(&ctx, format!("{} Zutaten hinzugefügt", missing.len())).await?;
+            if guard.restricted_users.contains(&user.id) {
+                // Given IDs when the list is shown below
+                guard.pending.extend(missing.into_iter().map(|text| PendingItem { text, requested_by: user.id, id: 0 }));
+            } else {
+                guard.list.items.extend(missing.iter().map(|ingredient| Item::from_ingredient(ingredient)));
+            }
+            guard.update_shopping_list(&ctx).await?;
+        }
+        Action::SaveDiscovered(id) => {
+            // Don't hold the lock while asking the recipe site
+            drop(guard);
+            let found = discover::lookup(id).await;
+            guard = instance.data.lock().await;
+            let (name, ingredients) = match found {
+                Ok(Some(found)) => found,
+                Ok(None) => return answer_with_toast(&ctx, callback::STALE_TEXT.to_string()).await,
+                Err(error) => return answer_with_toast(&ctx, error.to_string()).await,
+            };
+            if guard.list.recipes.contains_key(&name) {
+                return answer_with_toast(&ctx, format!("Es gibt schon ein Rezept namens {}.", name)).await;
+            }
+            if let Err(error) = ENVIRONMENT.input_limits.check_ingredient_count(ingredients.len()) {
+                return answer_with_toast(&ctx, error.to_string()).await;
+            }
+            guard.names.register(&name);
+            answer_with_toast(&ctx, format!("{} mit {} Zutaten gespeichert", name, ingredients.len())).await?;
+            guard.list.recipes.insert(name, Recipe::new(ingredients));
+        }
+        Action::Plugin(data) => match plugins::for_callback(&data) {
+            Some((plugin, payload)) => plugin.callback(&mut guard, &ctx, payload).await?,
+            None => return guard.refresh_stale_button(&ctx).await,
+        },
+        Action::ArchiveRecipe(id) | Action::DeleteRecipe(id) => {
+            let name = match guard.names.name(id).filter(|name| guard.list.recipes.contains_key(*name)) {
+                Some(name) => name.to_string(),
+                None => return guard.refresh_stale_button(&ctx).await,
+            };
+            if matches!(action, Action::DeleteRecipe(_)) {
+                guard.list.recipes.remove(&name);
+                guard.list.archived_recipes.remove(&name);
+                guard.ratings.remove(&name);
+                guard.recipe_info.remove(&name);
+                if !guard.templates.contains_key(&name) {
+                    guard.names.release(&name);
+                }
+            } else if !guard.list.archived_recipes.remove(&name) {
+                guard.list.archived_recipes.insert(name);
+            }
+            guard.render_view(&ctx, View::RecipeManager).await?;
+        }
+        Action::ListTemplates => {
+            guard.open_view(&ctx, View::Templates).await?;
+        }
+        Action::SaveTemplate => {
+            guard.naming_template = true;
+            guard.replace_menu(&ctx, "Name der Vorlage:".to_string(), None).await?;
+        }
+        Action::ApplyTemplate(id) | Action::MergeTemplate(id) => {
+            let name = match guard.names.name(id).filter(|name| guard.templates.contains_key(*name)) {
+                Some(name) => name.to_string(),
+                None => return guard.refresh_stale_button(&ctx).await,
+            };
+            if guard.restricted_users.contains(&user.id) {
+                return answer_with_toast(&ctx, "Vorlagen brauchen die Freigabe eines Admins.".to_string()).await;
+            }
+            if let Err(error) = guard.apply_template(&name, matches!(action, Action::MergeTemplate(_))) {
+                answer_with_toast(&ctx, error.to_string()).await?;
+                return Ok(());
+            }
+            guard.update_shopping_list(&ctx).await?;
+        }
+        Action::DeleteTemplate(id) => {
+            if let Some(name) = guard.names.name(id).map(|name| name.to_string()) {
+                guard.templates.remove(&name);
+                if !guard.list.recipes.contains_key(&name) {
+                    guard.names.release(&name);
+                }
+            }
+            guard.render_view(&ctx, View::Templates).await?;
+        }
+        Action::Back => {
+            guard.go_back(&ctx).await?;
+        }
+        Action::ShowMovable => {
+            guard.open_view(&ctx, View::Transfer).await?;
+        }
+        Action::TransferItem(id) => {
+            match guard.list.item_position(id) {
+                Some(index) if !guard.list.items[index].header => {}
+                _ => return guard.refresh_stale_button(&ctx).await,
+            }
+            guard.transfer_item = Some(id);
+            let chat_id = ctx.update.message.as_ref().map(|message| message.chat_id()).unwrap_or(user.id);
+            tokio::spawn(transfer::show_targets(ctx.requester.clone(), instance.clone(), chat_id, user.id));
+        }
+        Action::MoveTo(target) | Action::CopyTo(target) => {
+            let chat_id = ctx.update.message.as_ref().map(|message| message.chat_id()).unwrap_or(user.id);
+            let copy = matches!(action, Action::CopyTo(_));
+            tokio::spawn(transfer::transfer(ctx.requester.clone(), ctx.update.id.clone(), instance.clone(), chat_id, user.id, target, copy));
+        }
+        Action::ReturnToMainList => {
+            guard.update_shopping_list(&ctx).await?;
+        }
+        Action::DatePicker(picker, step) => {
+            let picked = match step {
+                date_picker::Step::Noop => return Ok(()),
+                date_picker::Step::Month(year, month) => {
+                    let text = "Datum wählen:".to_string();
+                    return guard.replace_menu(&ctx, text, Some(picker.month_markup(year, month))).await;
+                }
+                date_picker::Step::Day(date) if picker.with_time => {
+                    let text = "Uhrzeit wählen:".to_string();
+                    return guard.replace_menu(&ctx, text, Some(picker.hour_markup(date))).await;
+                }
+                date_picker::Step::Day(date) => date.and_time(NaiveTime::from_hms_opt(0, 0, 0).unwrap()),
+                date_picker::Step::Hour(picked) => picked,
+                date_picker::Step::Cancel => {
+                    guard.pending_meal = None;
+                    return guard.update_shopping_list(&ctx).await;
+                }
+            };
+            match guard.apply_picked_date(&picker.purpose, picked) {
+                Some(confirmation) => answer_with_toast(&ctx, confirmation).await?,
+                None => return guard.refresh_stale_button(&ctx).await,
+            }
+            guard.update_shopping_list(&ctx).await?;
+        }
+    }
+    Ok(())
+}
+
+/// Answers the button right away, but only edits the list once the chat stopped
+/// tapping for a moment, so checking off several items in a row is a single edit.
+async fn render_list_markup_later(instance: &Arc<Instance>, ctx: &UpdateWithCx<Bot, CallbackQuery>) -> anyhow::Result<()> {
+    ctx.requester.answer_callback_query(ctx.update.id.clone())
+        .send()
+        .await?;
+    let chat_id = match ctx.update.get_chat_id() {
+        ChatId::Id(chat_id) => chat_id,
+        ChatId::ChannelUsername(_) => return Ok(()),
+    };
+    let instance = instance.clone();
+    let ctx = UpdateWithCx { requester: ctx.requester.clone(), update: ChatId::Id(chat_id) };
+    tokio::spawn(async move {
+        if !debounce::settle(chat_id).await {
+            return;
+        }
+        {
+            let mut guard = instance.data.lock().await;
+            if let Err(error) = guard.render_view(&ctx, View::Edit).await {
+                log::error!("Couldn't render the list: {:?}", error);
+            }
+        }
+        store_data(&instance).await
+    });
+    Ok(())
+}
+
+async fn answer_with_toast(ctx: &UpdateWithCx<Bot, CallbackQuery>, text: String) -> anyhow::Result<()> {
+    ctx.requester.answer_callback_query(ctx.update.id.clone())
+        .text(text)
+        .send()
+        .await?;
+    Ok(())
+}
+
+fn get_header_button(item: &Item) -> InlineKeyboardButton {
+    Action::RemoveHeader(item.id).button(format!("🗑 {}", item.name))
+}
+
+/// Lays out the buttons in rows of `columns` buttons each.
+fn append_grid(mut markup: InlineKeyboardMarkup, buttons: Vec<InlineKeyboardButton>, columns: usize) -> InlineKeyboardMarkup {
+    for row in buttons.chunks(columns.max(1)) {
+        markup = markup.append_row(row.to_vec());
+    }
+    markup
+}
+
+/// Variants are named after their base recipe with a suffix in parentheses, like "Lasagne (vegetarisch)".
+fn recipe_base(name: &str) -> &str {
+    match name.find(" (") {
+        Some(start) if name.ends_with(')') => &name[..start],
+        _ => name,
+    }
+}
+
+/// Shortens labels so that `columns` buttons still fit next to each other on a phone.
+fn truncate_label(label: &str, columns: usize) -> String {
+    let max_chars = match columns {
+        0 | 1 => return label.to_string(),
+        2 => 18,
+        _ => 11,
+    };
+    if label.chars().count() <= max_chars {
+        label.to_string()
+    } else {
+        format!("{}…", label.chars().take(max_chars - 1).collect::<String>())
+    }
+}
+
+fn get_recipe_markup() -> InlineKeyboardMarkup {
+    InlineKeyboardMarkup::default().append_row(
+        vec![
+            Action::RecipeDone.button("💚")
+        ]
+    )
+}
+
+fn get_bulk_markup() -> InlineKeyboardMarkup {
+    InlineKeyboardMarkup::default()
+        .append_row(vec![Action::CheckAll.button("✅ alles abhaken")])
+        .append_row(vec![Action::UncheckAll.button("↩️ alles zurücksetzen")])
+        .append_row(vec![Action::ClearList.button("🗑 Liste leeren")])
+}
+
+fn get_clear_confirmation_markup() -> InlineKeyboardMarkup {
+    InlineKeyboardMarkup::default()
+        .append_row(vec![Action::ConfirmClear.button("🗑 Ja, alles löschen"), Action::ShowBulk.button("Abbrechen")])
+}
+
+/// A button per shortcut to delete it.
+fn get_shortcut_markup(shortcuts: Option<&HashMap<String, String>>) -> InlineKeyboardMarkup {
+    shortcuts::sorted(shortcuts).into_iter()
+        .enumerate()
+        .fold(InlineKeyboardMarkup::default(), |markup, (i, (emoji, item))| {
+            markup.append_row(vec![Action::DeleteShortcut(i).button(format!("🗑 {} {}", emoji, item))])
+        })
+}
+
+trait GetChatId {
+    fn get_chat_id(&self) -> ChatId;
+}
 
 impl GetChatId for CallbackQuery {
     fn get_chat_id(&self) -> ChatId {
@@ -343,6 +4984,18 @@ impl GetChatId for CallbackQuery {
     }
 }
 
+impl GetChatId for ChatId {
+    fn get_chat_id(&self) -> ChatId {
+        self.clone()
+    }
+}
+
+impl GetChatId for ChatMemberUpdated {
+    fn get_chat_id(&self) -> ChatId {
+        ChatId::Id(self.chat.id)
+    }
+}
+
 impl GetChatId for Message {
     fn get_chat_id(&self) -> ChatId {
         ChatId::Id(self.chat_id())
@@ -0,0 +1,85 @@
+use std::collections::HashMap;
+
+use chrono::{Datelike, Duration, NaiveDate, Utc};
+use rand::distributions::{Distribution, WeightedIndex};
+use serde::{Deserialize, Serialize};
+
+use crate::ics;
+use crate::recipe_info::RecipeInfo;
+
+#[derive(Serialize, Deserialize, Clone)]
+pub struct PlannedMeal {
+    pub date: NaiveDate,
+    pub meal: String,
+    /// Whether the chat was already asked to rate the meal.
+    #[serde(default)]
+    pub rating_asked: bool,
+}
+
+/// A generated week of dinners that wasn't accepted yet.
+#[derive(Serialize, Deserialize, Clone)]
+pub struct Proposal {
+    pub start: NaiveDate,
+    /// One meal per day, starting at `start`.
+    pub meals: Vec<String>,
+}
+
+impl Proposal {
+    pub fn day_label(&self, day: usize) -> String {
+        let date = self.start + Duration::days(day as i64);
+        format!("{} {}", DAY_NAMES[date.weekday().num_days_from_monday() as usize], date.format("%d.%m."))
+    }
+}
+
+const DAY_NAMES: [&str; 7] = ["Mo", "Di", "Mi", "Do", "Fr", "Sa", "So"];
+
+/// Picks up to `count` different meals, each with a chance proportional to its weight.
+pub fn pick(candidates: &[(String, f32)], count: usize) -> Vec<String> {
+    let mut candidates = candidates.to_vec();
+    let mut picked = Vec::new();
+    while picked.len() < count {
+        let index = match WeightedIndex::new(candidates.iter().map(|(_, weight)| *weight)) {
+            Ok(weights) => weights.sample(&mut rand::thread_rng()),
+            Err(_) => break,
+        };
+        picked.push(candidates.remove(index).0);
+    }
+    picked
+}
+
+/// The planned meals of the seven days starting `today`, with how long the recipes take.
+pub fn week_text(plan: &[PlannedMeal], today: NaiveDate, info: &HashMap<String, RecipeInfo>) -> String {
+    let mut text = "Essensplan:".to_string();
+    for offset in 0..7 {
+        let date = today + Duration::days(offset);
+        let meal = plan.iter().find(|meal| meal.date == date).map(|meal| meal.meal.as_str()).unwrap_or("–");
+        text.push_str(&format!("\n{} {}: {}", DAY_NAMES[date.weekday().num_days_from_monday() as usize], date.format("%d.%m."), meal));
+        if let Some(prep_time) = info.get(meal).and_then(RecipeInfo::prep_time) {
+            text.push_str(&format!(" ({})", prep_time));
+        }
+    }
+    text
+}
+
+/// Whole-day events for every planned meal of the list of `namespace`.
+pub fn to_ical(plan: &[PlannedMeal], namespace: &str) -> String {
+    let stamp = Utc::now().format("%Y%m%dT%H%M%SZ");
+    let mut lines = vec![
+        "BEGIN:VCALENDAR".to_string(),
+        "VERSION:2.0".to_string(),
+        "PRODID:-//shopping_bot//Essensplan//DE".to_string(),
+        "X-WR-CALNAME:Essensplan".to_string(),
+    ];
+    for meal in plan {
+        lines.push("BEGIN:VEVENT".to_string());
+        // There is one meal a day, the namespace tells apart the plans of several lists in one calendar
+        lines.push(format!("UID:meal-{}-{}@shopping_bot", namespace, meal.date.format("%Y%m%d")));
+        lines.push(format!("DTSTAMP:{}", stamp));
+        lines.push(format!("DTSTART;VALUE=DATE:{}", meal.date.format("%Y%m%d")));
+        lines.push(format!("DTEND;VALUE=DATE:{}", (meal.date + Duration::days(1)).format("%Y%m%d")));
+        lines.push(format!("SUMMARY:{}", ics::escape(&meal.meal)));
+        lines.push("END:VEVENT".to_string());
+    }
+    lines.push("END:VCALENDAR".to_string());
+    lines.iter().map(|line| ics::fold(line)).collect()
+}
@@ -0,0 +1,21 @@
+//! What is kept of a chat after the bot was removed from it, so it can be
+//! restored if the bot is added again within `ARCHIVE_RETENTION_DAYS`.
+
+use chrono::{DateTime, Utc};
+use chrono_tz::Tz;
+use serde::{Deserialize, Serialize};
+
+use crate::i18n::Language;
+
+#[derive(Serialize, Deserialize, Clone)]
+pub struct ArchivedChat {
+    pub left_at: DateTime<Utc>,
+    pub timezone: Option<Tz>,
+    pub language: Option<Language>,
+}
+
+impl ArchivedChat {
+    pub fn expired(&self, retention_days: i64) -> bool {
+        Utc::now() - self.left_at > chrono::Duration::days(retention_days)
+    }
+}
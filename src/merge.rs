@@ -0,0 +1,284 @@
+//! Three-way merge of the data when another replica wrote it meanwhile, see
+//! `redis`. Instead of dropping this replica's change, both versions are merged
+//! against the data they started from.
+//!
+//! Top-level fields changed on one side only take that side's value, fields
+//! changed on both sides take this replica's. Items are merged one by one,
+//! matched by ID or, for items without one, by name: additions of both sides
+//! are kept, removals of both sides applied, and an item changed on both sides
+//! takes this replica's change. An item removed on one side but changed on the
+//! other is kept with the change. Both replicas may give the same ID to
+//! different new items, this replica's item gets a new one then.
+//!
+//! Lists that only ever grow at the end, like the history, keep what both
+//! sides added, in the order of time.
+
+use std::collections::{HashMap, HashSet};
+
+use chrono::{DateTime, Utc};
+use serde_json::{Map, Value};
+
+/// Top-level fields whose entries are only appended, or dropped when they get old.
+const APPEND_ONLY: [&str; 2] = ["history", "timeline"];
+
+/// Identifies an item across versions of the list, the n-th item of a name if it has no ID.
+fn item_keys(items: &[Value]) -> Vec<String> {
+    let mut seen: HashMap<String, usize> = HashMap::new();
+    items.iter()
+        .map(|item| {
+            match item.get("id").and_then(|id| id.as_u64()).filter(|id| *id != 0) {
+                Some(id) => format!("id {}", id),
+                None => {
+                    let name = item.get("name").and_then(|name| name.as_str()).unwrap_or_default().to_lowercase();
+                    let header = item.get("header").and_then(|header| header.as_bool()).unwrap_or(false);
+                    let name = format!("{} {}", if header { "header" } else { "item" }, name);
+                    let count = seen.entry(name.clone()).or_insert(0);
+                    *count += 1;
+                    format!("{} {}", name, count)
+                }
+            }
+        })
+        .collect()
+}
+
+fn by_key(items: &[Value]) -> HashMap<String, &Value> {
+    item_keys(items).into_iter().zip(items.iter()).collect()
+}
+
+/// Gives an item the next free ID, unless it has none yet.
+fn with_new_id(item: &Value, next_id: &mut u64) -> Value {
+    let mut item = item.clone();
+    if let Some(id) = item.get_mut("id").filter(|id| id.as_u64().is_some_and(|id| id != 0)) {
+        *next_id += 1;
+        *id = (*next_id).into();
+    }
+    item
+}
+
+/// The items of `theirs` with the additions, removals and changes of `ours` since `base`.
+/// New IDs are taken from `next_id`.
+fn merge_items(base: &[Value], ours: &[Value], theirs: &[Value], next_id: &mut u64) -> Vec<Value> {
+    let base_items = by_key(base);
+    let our_items = by_key(ours);
+    let mut merged = Vec::with_capacity(theirs.len().max(ours.len()));
+    // Items of ours that are in the merged list already
+    let mut placed: HashSet<String> = HashSet::new();
+    let mut their_keys: HashSet<String> = HashSet::new();
+    for (key, their_item) in item_keys(theirs).into_iter().zip(theirs.iter()) {
+        match (base_items.get(&key), our_items.get(&key)) {
+            // Removed here, unless it was changed there
+            (Some(base_item), None) if *base_item == their_item => {}
+            (Some(_), None) => merged.push(their_item.clone()),
+            // Added on both sides with the same ID or name, both are kept unless they are the same
+            (None, Some(our_item)) => {
+                merged.push(their_item.clone());
+                if *our_item == their_item {
+                    placed.insert(key.clone());
+                }
+            }
+            // Changed here
+            (Some(base_item), Some(our_item)) if base_item != our_item => {
+                merged.push((*our_item).clone());
+                placed.insert(key.clone());
+            }
+            (_, our_item) => {
+                merged.push(their_item.clone());
+                if our_item.is_some() {
+                    placed.insert(key.clone());
+                }
+            }
+        }
+        their_keys.insert(key);
+    }
+    for (key, our_item) in item_keys(ours).into_iter().zip(ours.iter()) {
+        if placed.contains(&key) {
+            continue;
+        }
+        match base_items.get(&key) {
+            // Added here, under a new ID if it was given to another item there
+            None if their_keys.contains(&key) => merged.push(with_new_id(our_item, next_id)),
+            None => merged.push(our_item.clone()),
+            // Removed there, unless it was changed here
+            Some(base_item) if *base_item != our_item => merged.push(our_item.clone()),
+            Some(_) => {}
+        }
+    }
+    merged
+}
+
+/// The entries of `theirs` and those `ours` added since `base`, without those
+/// either side dropped, ordered by their time and ID.
+fn merge_appended(base: &[Value], ours: &[Value], theirs: &[Value]) -> Vec<Value> {
+    let keys = |entries: &[Value]| entries.iter().map(|entry| entry.to_string()).collect::<HashSet<String>>();
+    let (base_keys, our_keys, their_keys) = (keys(base), keys(ours), keys(theirs));
+    let mut merged: Vec<Value> = theirs.iter()
+        .filter(|entry| {
+            let key = entry.to_string();
+            our_keys.contains(&key) || !base_keys.contains(&key)
+        })
+        .cloned()
+        .collect();
+    merged.extend(ours.iter()
+        .filter(|entry| {
+            let key = entry.to_string();
+            !base_keys.contains(&key) && !their_keys.contains(&key)
+        })
+        .cloned());
+    // Stable, entries of the same moment stay in the order they were added
+    merged.sort_by_key(|entry| (
+        entry.get("at").and_then(|at| at.as_str()).and_then(|at| at.parse::<DateTime<Utc>>().ok()),
+        entry.get("id").and_then(|id| id.as_u64()),
+    ));
+    merged
+}
+
+/// Merges the top-level fields of the data, `ours` and `theirs` both started from `base`.
+pub fn merge(base: &Map<String, Value>, ours: &Map<String, Value>, theirs: &Map<String, Value>) -> Map<String, Value> {
+    let mut merged = theirs.clone();
+    // IDs given on either side stay taken
+    let mut next_id = [ours, theirs].iter()
+        .filter_map(|data| data.get("next_item_id").and_then(|id| id.as_u64()))
+        .max()
+        .unwrap_or(0);
+    for (field, our_value) in ours.iter() {
+        let base_value = base.get(field);
+        if base_value == Some(our_value) {
+            continue;
+        }
+        let their_value = theirs.get(field);
+        let value = match (field.as_str(), base_value, their_value) {
+            (_, base_value, their_value) if base_value == their_value => our_value.clone(),
+            ("items", Some(Value::Array(base_items)), Some(Value::Array(their_items))) => match our_value {
+                Value::Array(our_items) => Value::Array(merge_items(base_items, our_items, their_items, &mut next_id)),
+                _ => our_value.clone(),
+            },
+            (field, Some(Value::Array(base_entries)), Some(Value::Array(their_entries))) if APPEND_ONLY.contains(&field) => match our_value {
+                Value::Array(our_entries) => Value::Array(merge_appended(base_entries, our_entries, their_entries)),
+                _ => our_value.clone(),
+            },
+            _ => our_value.clone(),
+        };
+        merged.insert(field.clone(), value);
+    }
+    if next_id > 0 {
+        merged.insert("next_item_id".to_string(), next_id.into());
+    }
+    merged
+}
+
+#[cfg(test)]
+mod tests {
+    use serde_json::json;
+
+    use super::*;
+
+    fn data(items: Value, next_item_id: u64) -> Map<String, Value> {
+        json!({ "items": items, "next_item_id": next_item_id }).as_object().unwrap().clone()
+    }
+
+    fn names(data: &Map<String, Value>) -> Vec<&str> {
+        data["items"].as_array().unwrap().iter().map(|item| item["name"].as_str().unwrap()).collect()
+    }
+
+    #[test]
+    fn additions_of_both_sides_are_kept() {
+        let base = data(json!([{ "name": "Milch" }]), 0);
+        let ours = data(json!([{ "name": "Milch" }, { "name": "Brot" }]), 0);
+        let theirs = data(json!([{ "name": "Milch" }, { "name": "Eier" }]), 0);
+        assert_eq!(names(&merge(&base, &ours, &theirs)), vec!["Milch", "Eier", "Brot"]);
+    }
+
+    #[test]
+    fn the_same_addition_is_kept_once() {
+        let base = data(json!([]), 0);
+        let ours = data(json!([{ "name": "Brot" }]), 0);
+        let theirs = data(json!([{ "name": "Brot" }]), 0);
+        assert_eq!(names(&merge(&base, &ours, &theirs)), vec!["Brot"]);
+    }
+
+    #[test]
+    fn different_additions_of_the_same_name_are_both_kept() {
+        let base = data(json!([]), 0);
+        let ours = data(json!([{ "name": "Brot", "quantity": 2 }]), 0);
+        let theirs = data(json!([{ "name": "Brot", "quantity": 1 }]), 0);
+        assert_eq!(names(&merge(&base, &ours, &theirs)), vec!["Brot", "Brot"]);
+    }
+
+    #[test]
+    fn removals_of_both_sides_are_applied() {
+        let base = data(json!([{ "name": "Milch" }, { "name": "Brot" }, { "name": "Eier" }]), 0);
+        let ours = data(json!([{ "name": "Brot" }, { "name": "Eier" }]), 0);
+        let theirs = data(json!([{ "name": "Milch" }, { "name": "Eier" }]), 0);
+        assert_eq!(names(&merge(&base, &ours, &theirs)), vec!["Eier"]);
+    }
+
+    #[test]
+    fn removed_here_but_changed_there_is_kept() {
+        let base = data(json!([{ "id": 1, "name": "Milch", "done": false }, { "name": "Brot" }]), 1);
+        let ours = data(json!([{ "name": "Brot" }]), 1);
+        let theirs = data(json!([{ "id": 1, "name": "Milch", "done": true }]), 1);
+        let merged = merge(&base, &ours, &theirs);
+        assert_eq!(merged["items"], json!([{ "id": 1, "name": "Milch", "done": true }]));
+    }
+
+    #[test]
+    fn changed_here_but_removed_there_is_kept() {
+        let base = data(json!([{ "id": 1, "name": "Milch", "done": false }, { "name": "Brot" }]), 1);
+        let ours = data(json!([{ "id": 1, "name": "Milch", "done": true }]), 1);
+        let theirs = data(json!([{ "name": "Brot" }]), 1);
+        let merged = merge(&base, &ours, &theirs);
+        assert_eq!(merged["items"], json!([{ "id": 1, "name": "Milch", "done": true }]));
+    }
+
+    #[test]
+    fn changes_of_both_sides_take_ours() {
+        let base = data(json!([{ "id": 1, "name": "Milch", "quantity": 1 }]), 1);
+        let ours = data(json!([{ "id": 1, "name": "Milch", "quantity": 3 }]), 1);
+        let theirs = data(json!([{ "id": 1, "name": "Milch", "quantity": 2 }]), 1);
+        let merged = merge(&base, &ours, &theirs);
+        assert_eq!(merged["items"], json!([{ "id": 1, "name": "Milch", "quantity": 3 }]));
+    }
+
+    #[test]
+    fn colliding_ids_keep_both_items() {
+        let base = data(json!([{ "id": 1, "name": "Milch" }]), 1);
+        let ours = data(json!([{ "id": 1, "name": "Milch" }, { "id": 2, "name": "Brot" }]), 2);
+        let theirs = data(json!([{ "id": 1, "name": "Milch" }, { "id": 2, "name": "Eier" }, { "id": 3, "name": "Käse" }]), 3);
+        let merged = merge(&base, &ours, &theirs);
+        assert_eq!(merged["items"], json!([
+            { "id": 1, "name": "Milch" },
+            { "id": 2, "name": "Eier" },
+            { "id": 3, "name": "Käse" },
+            { "id": 4, "name": "Brot" },
+        ]));
+        assert_eq!(merged["next_item_id"], json!(4));
+    }
+
+    #[test]
+    fn history_appended_on_both_sides_is_kept() {
+        let purchase = |name: &str, at: &str| json!({ "name": name, "at": at });
+        let old = purchase("Milch", "2021-06-01T10:00:00Z");
+        let base = json!({ "history": [old.clone()] }).as_object().unwrap().clone();
+        let ours = json!({ "history": [old.clone(), purchase("Brot", "2021-06-02T10:00:00.500Z")] }).as_object().unwrap().clone();
+        let theirs = json!({ "history": [old.clone(), purchase("Eier", "2021-06-02T10:00:00Z"), purchase("Käse", "2021-06-03T10:00:00Z")] })
+            .as_object().unwrap().clone();
+        let merged = merge(&base, &ours, &theirs);
+        assert_eq!(merged["history"], json!([
+            old,
+            purchase("Eier", "2021-06-02T10:00:00Z"),
+            purchase("Brot", "2021-06-02T10:00:00.500Z"),
+            purchase("Käse", "2021-06-03T10:00:00Z"),
+        ]));
+    }
+
+    #[test]
+    fn history_dropped_on_one_side_stays_dropped() {
+        let purchase = |name: &str, at: &str| json!({ "name": name, "at": at });
+        let old = purchase("Milch", "2020-01-01T10:00:00Z");
+        let base = json!({ "history": [old.clone()] }).as_object().unwrap().clone();
+        let ours = json!({ "history": [] }).as_object().unwrap().clone();
+        let theirs = json!({ "history": [old, purchase("Brot", "2021-06-02T10:00:00Z")] }).as_object().unwrap().clone();
+        let merged = merge(&base, &ours, &theirs);
+        assert_eq!(merged["history"], json!([purchase("Brot", "2021-06-02T10:00:00Z")]));
+    }
+}
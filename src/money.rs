@@ -0,0 +1,84 @@
+//! How amounts are shown in a chat, set with `/currency`: the currency symbol
+//! and whether numbers are written the German way, 1.234,56 €, or the English
+//! way, $1,234.56. Prices are stored in cents of whatever currency the chat uses.
+
+use serde::{Deserialize, Serialize};
+
+use crate::i18n::Language;
+
+#[derive(Serialize, Deserialize, Clone, Copy, PartialEq, Default)]
+pub enum Locale {
+    /// Decimal comma, the symbol after the amount.
+    #[default]
+    German,
+    /// Decimal point, the symbol before the amount.
+    English,
+}
+
+#[derive(Serialize, Deserialize, Clone, PartialEq)]
+pub struct MoneyFormat {
+    pub symbol: String,
+    pub locale: Locale,
+}
+
+impl From<Language> for Locale {
+    fn from(language: Language) -> Self {
+        match language {
+            Language::German => Locale::German,
+            Language::English => Locale::English,
+        }
+    }
+}
+
+impl Default for MoneyFormat {
+    fn default() -> Self {
+        MoneyFormat { symbol: "€".to_string(), locale: Locale::German }
+    }
+}
+
+impl MoneyFormat {
+    /// `/currency` arguments like `$ en` or `CHF`, the locale defaults to the usual one of the currency.
+    pub fn parse(args: &[&str]) -> Option<Self> {
+        let symbol = match args.first()?.to_uppercase().as_str() {
+            "EUR" | "€" => "€".to_string(),
+            "USD" | "$" => "$".to_string(),
+            "GBP" | "£" => "£".to_string(),
+            other if other.chars().count() <= 3 && other.chars().all(|c| c.is_alphabetic()) => other.to_string(),
+            _ => return None,
+        };
+        let locale = match args.get(1).map(|locale| locale.to_lowercase()) {
+            Some(locale) if locale == "de" => Locale::German,
+            Some(locale) if locale == "en" => Locale::English,
+            Some(_) => return None,
+            None if symbol == "$" || symbol == "£" => Locale::English,
+            None => Locale::German,
+        };
+        Some(MoneyFormat { symbol, locale })
+    }
+
+    pub fn format(&self, cents: u64) -> String {
+        let (thousands, decimal) = match self.locale {
+            Locale::German => ('.', ','),
+            Locale::English => (',', '.'),
+        };
+        let units = (cents / 100).to_string();
+        let mut grouped = String::new();
+        for (i, digit) in units.chars().enumerate() {
+            if i > 0 && (units.len() - i).is_multiple_of(3) {
+                grouped.push(thousands);
+            }
+            grouped.push(digit);
+        }
+        let amount = format!("{}{}{:02}", grouped, decimal, cents % 100);
+        match self.locale {
+            Locale::German => format!("{} {}", amount, self.symbol),
+            // Letters need a space, "CHF 4.99" but "$4.99"
+            Locale::English if self.symbol.chars().all(|c| c.is_alphabetic()) => format!("{} {}", self.symbol, amount),
+            Locale::English => format!("{}{}", self.symbol, amount),
+        }
+    }
+
+    pub fn describe(&self) -> String {
+        format!("Preise werden so angezeigt: {}", self.format(123_456))
+    }
+}
@@ -0,0 +1,71 @@
+//! Tidies up new items however they were typed: "  milch  vollfett. " becomes
+//! "Milch vollfett", pack sizes like "3*500g" are summed up, see `units`. With
+//! `/autocorrect on`, typos of known products are fixed.
+
+use crate::{i18n, search, units};
+
+/// Punctuation that is left over at the end of a typed item.
+const TRAILING: &[char] = &['.', ',', ';', ':', '!', '?', '-'];
+
+/// Trims, collapses whitespace, totals pack sizes, strips trailing punctuation and capitalizes.
+/// Headers keep their `#`.
+pub fn item(text: &str, language: i18n::Language) -> String {
+    if let Some(name) = text.trim().strip_prefix('#') {
+        return format!("# {}", item(name, language));
+    }
+    let text = units::normalize(text, language);
+    let text = text.trim_end_matches(TRAILING).trim_end();
+    let mut chars = text.chars();
+    match chars.next() {
+        Some(first) => first.to_uppercase().chain(chars).collect(),
+        None => String::new(),
+    }
+}
+
+/// "Eier (3)" and "3x Eier" become "Eier" with a quantity of 3.
+pub fn quantity(name: &str) -> (String, u32) {
+    if let Some(rest) = name.strip_suffix(')') {
+        if let Some((name, quantity)) = rest.rsplit_once(" (") {
+            if let Ok(quantity) = quantity.trim().parse() {
+                return (name.trim().to_string(), quantity);
+            }
+        }
+    }
+    if let Some((quantity, name)) = name.split_once(['x', '×']) {
+        if let Ok(quantity) = quantity.trim().parse() {
+            return (name.trim().to_string(), quantity);
+        }
+    }
+    (name.to_string(), 1)
+}
+
+/// The known product `name` is most likely a typo of, if there is exactly one.
+pub fn correct<'a>(name: &str, known: impl Iterator<Item = &'a str>, language: i18n::Language) -> Option<String> {
+    let lowercase = name.to_lowercase();
+    let tolerance = search::tolerance(&lowercase);
+    if tolerance == 0 {
+        return None;
+    }
+    let mut best: Option<(usize, &str)> = None;
+    let mut tied = false;
+    for candidate in known {
+        let distance = search::distance(&lowercase, &candidate.to_lowercase());
+        if distance == 0 {
+            return None;
+        }
+        if distance > tolerance {
+            continue;
+        }
+        match best {
+            Some((best_distance, best_name)) if distance == best_distance => {
+                tied |= best_name.to_lowercase() != candidate.to_lowercase();
+            }
+            Some((best_distance, _)) if distance > best_distance => {}
+            _ => {
+                best = Some((distance, candidate));
+                tied = false;
+            }
+        }
+    }
+    best.filter(|_| !tied).map(|(_, candidate)| item(candidate, language))
+}
@@ -0,0 +1,60 @@
+//! Which proactive messages a user is mentioned in, set with `/notifications`
+//! in a private chat with the bot while it shows the shared list. Reminders
+//! and assignments mention everyone who didn't turn them off, digests like
+//! the monthly recap only those who turned them on.
+
+use std::collections::HashSet;
+
+use serde::{Deserialize, Serialize};
+
+#[derive(Serialize, Deserialize, Clone, Copy, PartialEq, Eq, Hash, Debug)]
+pub enum Kind {
+    /// Items due soon, see `scheduler`.
+    Reminders,
+    /// Someone else assigned an item with `/assign`.
+    Assignments,
+    /// The monthly recap and the weekly reset.
+    Digests,
+}
+
+pub const KINDS: [Kind; 3] = [Kind::Reminders, Kind::Assignments, Kind::Digests];
+
+impl Kind {
+    pub fn name(&self) -> &'static str {
+        match self {
+            Kind::Reminders => "Erinnerungen",
+            Kind::Assignments => "Zuteilungen",
+            Kind::Digests => "Rückblicke",
+        }
+    }
+
+    fn default_on(&self) -> bool {
+        *self != Kind::Digests
+    }
+}
+
+#[derive(Serialize, Deserialize, Clone, Default)]
+pub struct Preferences {
+    /// Kinds switched away from their default.
+    changed: HashSet<Kind>,
+}
+
+impl Preferences {
+    pub fn wants(&self, kind: Kind) -> bool {
+        kind.default_on() != self.changed.contains(&kind)
+    }
+
+    pub fn set(&mut self, kind: Kind, on: bool) {
+        if on == kind.default_on() {
+            self.changed.remove(&kind);
+        } else {
+            self.changed.insert(kind);
+        }
+    }
+
+    pub fn describe(&self) -> String {
+        KINDS.iter().fold("Erwähnen bei:".to_string(), |text, kind| {
+            format!("{}\n - {}: {}", text, kind.name(), if self.wants(*kind) { "an" } else { "aus" })
+        })
+    }
+}
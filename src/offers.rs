@@ -0,0 +1,44 @@
+//! Special offers, typed like "Angebot: Kaffee 4,99 bis Samstag". Matching
+//! items get a 💰 in the list until the offer expires, see `scheduler`.
+
+use chrono::NaiveDate;
+use serde::{Deserialize, Serialize};
+
+use crate::{date_phrase, search, spending};
+
+const PREFIX: &str = "angebot:";
+
+#[derive(Serialize, Deserialize, Clone)]
+pub struct Offer {
+    pub name: String,
+    pub cents: u64,
+    /// Last day of the offer.
+    pub until: NaiveDate,
+}
+
+impl Offer {
+    pub fn matches(&self, item_name: &str) -> bool {
+        search::matches(&self.name, item_name)
+    }
+}
+
+/// Whether the message announces an offer.
+pub fn is_offer(text: &str) -> bool {
+    text.trim_start().to_lowercase().starts_with(PREFIX)
+}
+
+/// Parses "Angebot: <item> <price> bis <day>".
+pub fn parse(text: &str, today: NaiveDate) -> anyhow::Result<Offer> {
+    let usage = || anyhow::anyhow!("Usage: Angebot: <item> <price> bis <day>");
+    let text = text.trim_start();
+    let words: Vec<&str> = text[PREFIX.len()..].split_whitespace().collect();
+    let until = words.iter().rposition(|word| word.eq_ignore_ascii_case("bis")).ok_or_else(usage)?;
+    let day = words[until + 1..].join(" ");
+    let until_day = date_phrase::parse_day(&day, today).ok_or_else(|| anyhow::anyhow!("Unbekannter Tag: {}", day))?;
+    let (price, name) = words[..until].split_last().ok_or_else(usage)?;
+    let cents = spending::parse_price(price).ok_or_else(usage)?;
+    if name.is_empty() {
+        return Err(usage());
+    }
+    Ok(Offer { name: name.join(" "), cents, until: until_day })
+}
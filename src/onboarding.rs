@@ -0,0 +1,87 @@
+//! Short setup dialogue for new chats: language, store and whether messages
+//! are deleted, ending with the first list. A chat without items or recipes
+//! can start with a demo list and two recipes to try things out, which go
+//! again with one tap.
+
+use serde::{Deserialize, Serialize};
+use teloxide::types::InlineKeyboardMarkup;
+
+use crate::callback::Action;
+use crate::i18n::{self, Language, Text};
+
+pub const WELCOME_TEXT: &str = "Hallo! Ich führe eure Einkaufsliste. Welche Sprache?\nHi! I keep your shopping list. Which language?";
+
+/// Stores offered as buttons, the choice after the last one means no preference.
+pub const STORES: [&str; 6] = ["Rewe", "Edeka", "Aldi", "Lidl", "Kaufland", "Netto"];
+
+pub const LANGUAGE: u8 = 0;
+pub const STORE: u8 = 1;
+pub const DELETE_MESSAGES: u8 = 2;
+pub const DEMO: u8 = 3;
+
+/// What a chat chose, chats that haven't been through the dialogue use the settings.
+#[derive(Serialize, Deserialize, Clone)]
+pub struct Choices {
+    pub store: Option<String>,
+    pub delete_messages: bool,
+}
+
+/// What the demo added, so only that is removed again.
+#[derive(Serialize, Deserialize, Clone, Default)]
+pub struct Demo {
+    pub items: Vec<String>,
+    pub recipes: Vec<String>,
+}
+
+pub fn demo_items(language: Language) -> &'static [&'static str] {
+    match language {
+        Language::German => &["# Obst & Gemüse", "Äpfel", "Tomaten", "# Kühlregal", "Milch", "Butter", "Brot"],
+        Language::English => &["# Fruit & vegetables", "Apples", "Tomatoes", "# Dairy", "Milk", "Butter", "Bread"],
+    }
+}
+
+pub fn demo_recipes(language: Language) -> [(&'static str, &'static [&'static str]); 2] {
+    match language {
+        Language::German => [
+            ("Spaghetti Bolognese", &["Spaghetti", "Hackfleisch", "Passierte Tomaten", "Zwiebel", "Parmesan"]),
+            ("Pfannkuchen", &["Mehl", "Eier", "Milch", "Zucker"]),
+        ],
+        Language::English => [
+            ("Spaghetti bolognese", &["Spaghetti", "Minced beef", "Tomato passata", "Onion", "Parmesan"]),
+            ("Pancakes", &["Flour", "Eggs", "Milk", "Sugar"]),
+        ],
+    }
+}
+
+pub fn language_markup() -> InlineKeyboardMarkup {
+    InlineKeyboardMarkup::default().append_row(
+        i18n::LANGUAGES.iter().enumerate().map(|(i, language)| Action::Onboarding(LANGUAGE, i as u8).button(language.name())),
+    )
+}
+
+pub fn store_markup(language: Language) -> InlineKeyboardMarkup {
+    let markup = STORES.chunks(3).enumerate().fold(InlineKeyboardMarkup::default(), |markup, (row, stores)| {
+        markup.append_row(
+            stores.iter().enumerate().map(|(i, store)| Action::Onboarding(STORE, (row * 3 + i) as u8).button(*store)),
+        )
+    });
+    markup.append_row(vec![Action::Onboarding(STORE, STORES.len() as u8).button(i18n::text(language, Text::AnyStore))])
+}
+
+pub fn delete_messages_markup(language: Language) -> InlineKeyboardMarkup {
+    InlineKeyboardMarkup::default().append_row(vec![
+        Action::Onboarding(DELETE_MESSAGES, 1).button(i18n::text(language, Text::Yes)),
+        Action::Onboarding(DELETE_MESSAGES, 0).button(i18n::text(language, Text::No)),
+    ])
+}
+
+pub fn demo_markup(language: Language) -> InlineKeyboardMarkup {
+    InlineKeyboardMarkup::default().append_row(vec![
+        Action::Onboarding(DEMO, 1).button(i18n::text(language, Text::Yes)),
+        Action::Onboarding(DEMO, 0).button(i18n::text(language, Text::No)),
+    ])
+}
+
+pub fn clear_demo_markup(language: Language) -> InlineKeyboardMarkup {
+    InlineKeyboardMarkup::default().append_row(vec![Action::ClearDemo.button(i18n::text(language, Text::ClearDemo))])
+}
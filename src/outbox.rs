@@ -0,0 +1,333 @@
+//! Every message and edit goes through here to stay within Telegram's limits:
+//! 30 messages per second per bot, one per second in a private chat and 20 per
+//! minute in a group. An edit that can't be sent right away waits in the
+//! background, so the list isn't kept locked meanwhile, and is dropped if a
+//! newer edit of the same message overtakes it. When Telegram asks to wait
+//! anyway, nothing more is sent to the chat until then, and edits are retried.
+
+use std::collections::{HashMap, VecDeque};
+use std::future::Future;
+use std::pin::Pin;
+use std::time::{Duration, Instant};
+
+use lazy_static::lazy_static;
+use teloxide::payloads::{DeleteMessage, EditMessageMedia, EditMessageReplyMarkup, EditMessageText, SendDocument, SendMessage, SendPhoto};
+use teloxide::requests::{Output, Request};
+use teloxide::{ApiError, RequestError};
+use teloxide::types::ChatId;
+use teloxide::Bot;
+use tokio::sync::Mutex;
+
+use crate::usage;
+
+const GLOBAL_PER_SECOND: usize = 30;
+const GROUP_PER_MINUTE: usize = 20;
+const PRIVATE_INTERVAL: Duration = Duration::from_secs(1);
+
+type Queued<T, E> = Pin<Box<dyn Future<Output = Result<T, E>> + Send>>;
+
+#[derive(Default)]
+struct State {
+    /// When the messages of the last second were sent.
+    sent: VecDeque<Instant>,
+    /// When the messages of the last minute were sent, per chat.
+    chats: HashMap<i64, VecDeque<Instant>>,
+    /// Newest edit requested per message.
+    edits: HashMap<(i64, i32), u64>,
+    /// Until when Telegram asked not to send to a chat, after too many requests.
+    flood_waits: HashMap<i64, Instant>,
+    /// Why the delayed edit of a message failed, until its owner recovers from it.
+    failed_edits: HashMap<(i64, i32), RequestError>,
+}
+
+lazy_static! {
+    /// The limits of each bot, by token.
+    static ref STATES: Mutex<HashMap<String, State>> = Mutex::new(HashMap::new());
+}
+
+/// Payloads that count against the limits of a chat.
+pub trait Outgoing {
+    fn chat(&self) -> i64;
+
+    /// The message changed by an edit.
+    fn message(&self) -> Option<i32> {
+        None
+    }
+}
+
+fn chat_key(chat_id: &ChatId) -> i64 {
+    match chat_id {
+        ChatId::Id(id) => *id,
+        // Channels are addressed by name, they share one limit
+        ChatId::ChannelUsername(_) => 0,
+    }
+}
+
+impl Outgoing for SendMessage {
+    fn chat(&self) -> i64 {
+        chat_key(&self.chat_id)
+    }
+}
+
+impl Outgoing for SendPhoto {
+    fn chat(&self) -> i64 {
+        chat_key(&self.chat_id)
+    }
+}
+
+impl Outgoing for SendDocument {
+    fn chat(&self) -> i64 {
+        chat_key(&self.chat_id)
+    }
+}
+
+impl Outgoing for DeleteMessage {
+    fn chat(&self) -> i64 {
+        chat_key(&self.chat_id)
+    }
+}
+
+impl Outgoing for EditMessageText {
+    fn chat(&self) -> i64 {
+        chat_key(&self.chat_id)
+    }
+
+    fn message(&self) -> Option<i32> {
+        Some(self.message_id)
+    }
+}
+
+impl Outgoing for EditMessageMedia {
+    fn chat(&self) -> i64 {
+        chat_key(&self.chat_id)
+    }
+
+    fn message(&self) -> Option<i32> {
+        Some(self.message_id)
+    }
+}
+
+impl Outgoing for EditMessageReplyMarkup {
+    fn chat(&self) -> i64 {
+        chat_key(&self.chat_id)
+    }
+
+    fn message(&self) -> Option<i32> {
+        Some(self.message_id)
+    }
+}
+
+pub trait SendQueued: Request {
+    /// Like `send`, but waits until the message can be sent without hitting a limit of `bot`.
+    fn send_queued(self, bot: &Bot) -> Queued<Output<Self>, Self::Err>;
+
+    /// Sends in the background once there is a free slot, for requests whose answer isn't needed.
+    fn send_later(self, bot: &Bot);
+
+    /// Like `send_queued`, but `None` if the edit has to wait for a free slot.
+    /// It is sent later, unless a newer edit of the same message overtakes it.
+    fn edit_queued(self, bot: &Bot) -> Queued<Option<Output<Self>>, Self::Err>;
+}
+
+impl<R> SendQueued for R
+where
+    R: Request<Err = RequestError> + Send + Sync + 'static,
+    R::Payload: Outgoing,
+    Output<R>: Send,
+{
+    fn send_queued(self, bot: &Bot) -> Queued<Output<Self>, Self::Err> {
+        let token = bot.token().to_string();
+        Box::pin(async move {
+            let chat = self.payload_ref().chat();
+            wait_for_slot(&token, chat).await;
+            let result = self.send().await;
+            record(&token, chat, result.as_ref().err()).await;
+            result
+        })
+    }
+
+    fn send_later(self, bot: &Bot) {
+        let token = bot.token().to_string();
+        tokio::spawn(async move {
+            let chat = self.payload_ref().chat();
+            wait_for_slot(&token, chat).await;
+            let result = self.send().await;
+            record(&token, chat, result.as_ref().err()).await;
+            if let Err(error) = result {
+                log::warn!("Couldn't send a request to chat {}: {:?}", chat, error);
+            }
+        });
+    }
+
+    fn edit_queued(self, bot: &Bot) -> Queued<Option<Output<Self>>, Self::Err> {
+        let token = bot.token().to_string();
+        Box::pin(async move {
+            let chat = self.payload_ref().chat();
+            let key = self.payload_ref().message().map(|message| (chat, message));
+            let generation = match key {
+                Some(key) => {
+                    let mut states = STATES.lock().await;
+                    let generation = states.entry(token.clone()).or_default().edits.entry(key).or_insert(0);
+                    *generation += 1;
+                    *generation
+                }
+                None => 0,
+            };
+            if reserve(&token, chat).await.is_some() {
+                // The caller usually holds the list, waiting here would keep newer edits from overtaking this one
+                send_edit_later(self, token, chat, key, generation);
+                return Ok(None);
+            }
+            if !is_newest(&token, key, generation).await {
+                return Ok(None);
+            }
+            let result = self.send_ref().await;
+            record(&token, chat, result.as_ref().err()).await;
+            match result {
+                Err(error) if flood_wait(&error).is_some() && requeue(&token, key, generation).await => {
+                    send_edit_later(self, token, chat, key, generation);
+                    Ok(None)
+                }
+                result => result.map(Some),
+            }
+        })
+    }
+}
+
+/// Sends the edit once there is a free slot, again after a flood wait, unless a newer edit overtakes it.
+fn send_edit_later<R>(request: R, token: String, chat: i64, key: Option<(i64, i32)>, generation: u64)
+where
+    R: Request<Err = RequestError> + Send + Sync + 'static,
+    Output<R>: Send,
+{
+    tokio::spawn(async move {
+        loop {
+            wait_for_slot(&token, chat).await;
+            if !is_newest(&token, key, generation).await {
+                return;
+            }
+            let result = request.send_ref().await;
+            record(&token, chat, result.as_ref().err()).await;
+            match result {
+                Err(error) if flood_wait(&error).is_some() && requeue(&token, key, generation).await => continue,
+                Err(error) => {
+                    log::warn!("Couldn't send a delayed edit to chat {}: {:?}", chat, error);
+                    if let Some(key) = key {
+                        STATES.lock().await.entry(token.clone()).or_default().failed_edits.insert(key, error);
+                    }
+                }
+                Ok(_) => {}
+            }
+            return;
+        }
+    });
+}
+
+/// Why the last delayed edit of the message failed, if it did, so the caller can recover
+/// from it as if the edit had just been sent, e.g. by sending a deleted message again.
+pub async fn take_failed_edit(bot: &Bot, chat: i64, message: i32) -> Option<RequestError> {
+    STATES.lock().await.get_mut(bot.token())?.failed_edits.remove(&(chat, message))
+}
+
+/// Whether a delayed edit of the message failed, without taking the error.
+pub async fn has_failed_edit(bot: &Bot, chat: i64, message: i32) -> bool {
+    STATES.lock().await.get(bot.token()).is_some_and(|state| state.failed_edits.contains_key(&(chat, message)))
+}
+
+/// Counts the request, and holds back the chat for as long as Telegram asks to.
+async fn record(token: &str, chat: i64, error: Option<&RequestError>) {
+    usage::record_request(error.is_none());
+    if let Some(seconds) = error.and_then(flood_wait) {
+        log::warn!("Flood wait of {}s for chat {}", seconds, chat);
+        let until = Instant::now() + Duration::from_secs(seconds);
+        STATES.lock().await.entry(token.to_string()).or_default().flood_waits.insert(chat, until);
+    }
+}
+
+/// How long Telegram asked to wait before sending again, if it did.
+fn flood_wait(error: &RequestError) -> Option<u64> {
+    match error {
+        RequestError::RetryAfter(seconds) => Some((*seconds).max(0) as u64),
+        // teloxide looks for the wait in `response_parameters`, Telegram calls them `parameters`,
+        // so it usually only shows up as "Too Many Requests: retry after 5"
+        RequestError::ApiError { status_code, kind } if status_code.as_u16() == 429 => {
+            let seconds = match kind {
+                ApiError::Unknown(description) => description.rsplit(' ').next().and_then(|seconds| seconds.parse().ok()),
+                _ => None,
+            };
+            Some(seconds.unwrap_or(1))
+        }
+        _ => None,
+    }
+}
+
+/// Queues an edit that was sent already once more, unless a newer one was queued since.
+async fn requeue(token: &str, key: Option<(i64, i32)>, generation: u64) -> bool {
+    let key = match key {
+        Some(key) => key,
+        None => return true,
+    };
+    let mut states = STATES.lock().await;
+    let edits = &mut states.entry(token.to_string()).or_default().edits;
+    if edits.contains_key(&key) {
+        return false;
+    }
+    edits.insert(key, generation);
+    true
+}
+
+/// Whether no newer edit of the message was queued, forgetting it if so.
+async fn is_newest(token: &str, key: Option<(i64, i32)>, generation: u64) -> bool {
+    let key = match key {
+        Some(key) => key,
+        None => return true,
+    };
+    let mut states = STATES.lock().await;
+    let edits = &mut states.entry(token.to_string()).or_default().edits;
+    if edits.get(&key) != Some(&generation) {
+        return false;
+    }
+    edits.remove(&key);
+    true
+}
+
+async fn wait_for_slot(token: &str, chat: i64) {
+    while let Some(wait) = reserve(token, chat).await {
+        tokio::time::sleep(wait).await;
+    }
+}
+
+/// Takes a slot for a message to the chat, or tells how long to wait for one.
+async fn reserve(token: &str, chat: i64) -> Option<Duration> {
+    let mut states = STATES.lock().await;
+    let state = states.entry(token.to_string()).or_default();
+    let now = Instant::now();
+    while state.sent.front().map(|sent| now - *sent > Duration::from_secs(1)) == Some(true) {
+        state.sent.pop_front();
+    }
+    let chat_sent = state.chats.entry(chat).or_default();
+    while chat_sent.front().map(|sent| now - *sent > Duration::from_secs(60)) == Some(true) {
+        chat_sent.pop_front();
+    }
+    // Group IDs are negative
+    let chat_wait = if chat < 0 {
+        chat_sent.get(chat_sent.len().wrapping_sub(GROUP_PER_MINUTE))
+            .filter(|_| chat_sent.len() >= GROUP_PER_MINUTE)
+            .map(|sent| *sent + Duration::from_secs(60))
+    } else {
+        chat_sent.back().map(|sent| *sent + PRIVATE_INTERVAL)
+    };
+    let global_wait = state.sent.front()
+        .filter(|_| state.sent.len() >= GLOBAL_PER_SECOND)
+        .map(|sent| *sent + Duration::from_secs(1));
+    state.flood_waits.retain(|_, until| *until > now);
+    let flood_wait = state.flood_waits.get(&chat).copied();
+    match chat_wait.into_iter().chain(global_wait).chain(flood_wait).max().filter(|until| *until > now) {
+        Some(until) => Some(until - now),
+        None => {
+            state.sent.push_back(now);
+            state.chats.entry(chat).or_default().push_back(now);
+            None
+        }
+    }
+}
@@ -0,0 +1,24 @@
+//! `/pause` for holidays: no reminders or other scheduled messages in the chat
+//! until `/resume` or the given day.
+
+use chrono::NaiveDate;
+use serde::{Deserialize, Serialize};
+
+#[derive(Serialize, Deserialize, Clone)]
+pub struct Pause {
+    /// First day scheduled messages are sent again, `None` until `/resume`.
+    pub until: Option<NaiveDate>,
+}
+
+impl Pause {
+    pub fn active(&self, today: NaiveDate) -> bool {
+        self.until.map(|until| today < until) != Some(false)
+    }
+
+    pub fn describe(&self) -> String {
+        match self.until {
+            Some(until) => format!("Pause bis {}, bis dahin gibt es keine Erinnerungen. Vorher weiter mit /resume.", until.format("%d.%m.%Y")),
+            None => "Pause, bis dahin gibt es keine Erinnerungen. Weiter mit /resume.".to_string(),
+        }
+    }
+}
@@ -0,0 +1,167 @@
+//! Personal lists: in a private chat, `/personal` switches between the shared
+//! list and a list of the user's own. Each personal list is an `Instance` with
+//! its own data file, so everything works on it like on the shared list, and its
+//! items can be moved to other lists, see `transfer`.
+//!
+//! Reminders, the sheet sync and the HTTP API only cover the shared list.
+//!
+//! A personal list is loaded with its first update after the start. Lists
+//! nobody used for a while are unloaded again, as are the least recently used
+//! ones when too many are loaded, so hundreds of users don't fill the memory.
+
+use std::collections::HashMap;
+use std::sync::Arc;
+use std::time::{Duration, Instant};
+
+use arc_swap::ArcSwap;
+use teloxide::prelude::*;
+use teloxide::types::ChatId;
+use teloxide::utils::command::parse_command;
+use tokio::sync::Mutex;
+
+use crate::outbox::SendQueued;
+use crate::{storage, store_data, Data, Instance, ENVIRONMENT};
+
+/// Personal lists kept in memory, the least recently used ones are unloaded beyond that.
+const MAX_LOADED: usize = 50;
+/// Personal lists unused for this long are unloaded.
+const IDLE: Duration = Duration::from_secs(30 * 60);
+
+/// A personal list in memory.
+pub struct Loaded {
+    list: Arc<Instance>,
+    used: Instant,
+}
+
+impl Loaded {
+    /// Whether no update is being handled on the list.
+    fn idle(&self) -> bool {
+        Arc::strong_count(&self.list) == 1
+    }
+}
+
+fn namespace(shared: &str, user_id: i64) -> String {
+    if shared.is_empty() {
+        format!("personal.{}", user_id)
+    } else {
+        format!("{}.personal.{}", shared, user_id)
+    }
+}
+
+/// The data file of a user's personal list.
+pub fn data_path(shared: &str, user_id: i64) -> String {
+    ENVIRONMENT.data_path(&namespace(shared, user_id))
+}
+
+/// The list a chat works on: the shared one, or in a private chat the user's
+/// personal list after switching to it.
+pub async fn list_for_chat(instance: &Arc<Instance>, chat_id: i64) -> Arc<Instance> {
+    // Only private chats, which have the ID of the user, can have a personal list
+    if !instance.data.lock().await.personal_lists.contains(&chat_id) {
+        return instance.clone();
+    }
+    let mut lists = instance.personal.lock().await;
+    if let Some(loaded) = lists.get_mut(&chat_id) {
+        loaded.used = Instant::now();
+        return loaded.list.clone();
+    }
+    let namespace = namespace(&instance.namespace, chat_id);
+    let data_path = ENVIRONMENT.data_path(&namespace);
+    let mut data = match storage::load(&data_path).await {
+        Ok(Some(data)) => data,
+        Ok(None) => Data::default(),
+        Err(error) => {
+            // Don't overwrite a damaged file with an empty list
+            log::error!("Personal list {} damaged, using the shared list: {:?}", data_path, error);
+            return instance.clone();
+        }
+    };
+    storage::migrate(&mut data);
+    data.owner = Some(chat_id);
+    let list = Arc::new(Instance {
+        namespace,
+        data_path,
+        snapshot: ArcSwap::from_pointee(data.clone()),
+        data: Mutex::new(data),
+        bot_name: Mutex::new(instance.bot_name.lock().await.clone()),
+        personal: Mutex::new(HashMap::new()),
+    });
+    lists.insert(chat_id, Loaded { list: list.clone(), used: Instant::now() });
+    while lists.len() > MAX_LOADED {
+        let oldest = lists.iter()
+            .filter(|(_, loaded)| loaded.idle())
+            .min_by_key(|(_, loaded)| loaded.used)
+            .map(|(user_id, _)| *user_id);
+        match oldest.and_then(|user_id| lists.remove(&user_id)) {
+            Some(loaded) => unload(loaded).await,
+            None => break,
+        }
+    }
+    list
+}
+
+/// Unloads the personal lists nobody used for a while, they are loaded again with their next update.
+pub async fn unload_idle(instance: &Instance) {
+    // Held while unloading, so a list isn't loaded again before it is written
+    let mut lists = instance.personal.lock().await;
+    let idle: Vec<i64> = lists.iter()
+        .filter(|(_, loaded)| loaded.idle() && loaded.used.elapsed() > IDLE)
+        .map(|(user_id, _)| *user_id)
+        .collect();
+    for user_id in idle {
+        if let Some(loaded) = lists.remove(&user_id) {
+            unload(loaded).await;
+        }
+    }
+}
+
+async fn unload(loaded: Loaded) {
+    store_data(&loaded.list).await;
+    {
+        // Written in full, so loading it again doesn't depend on the log
+        let data = loaded.list.data.lock().await;
+        if let Err(error) = storage::save(&loaded.list.data_path, &data).await {
+            log::error!("Couldn't write personal list {:?} before unloading it: {:?}", loaded.list.namespace, error);
+        }
+    }
+    storage::forget(&loaded.list.data_path);
+    log::info!("Unloaded personal list {:?}", loaded.list.namespace);
+}
+
+pub async fn is_toggle(instance: &Instance, message: &Message) -> bool {
+    let bot_name = instance.bot_name.lock().await.clone();
+    matches!(message.text().and_then(|text| parse_command(text, &bot_name)), Some(("personal", _)))
+}
+
+/// Switches a private chat between the shared list and the user's personal list.
+pub async fn toggle(instance: &Arc<Instance>, ctx: &UpdateWithCx<Bot, Message>) -> anyhow::Result<()> {
+    let chat_id = ctx.update.chat_id();
+    if !ctx.update.chat.is_private() {
+        ctx.reply_to(ENVIRONMENT.decorate("Persönliche Listen gibt es nur im privaten Chat mit mir.".to_string())).send_queued(&ctx.requester).await?;
+        return Ok(());
+    }
+    let left = list_for_chat(instance, chat_id).await;
+    let personal = {
+        let mut data = instance.data.lock().await;
+        if !data.personal_lists.remove(&chat_id) {
+            data.personal_lists.insert(chat_id);
+        }
+        data.personal_lists.contains(&chat_id)
+    };
+    store_data(instance).await;
+    // Buttons of the other list would act on the wrong items now
+    left.data.lock().await.leave_chat(&ctx.requester, chat_id).await;
+    store_data(&left).await;
+
+    let text = if personal {
+        "Du siehst jetzt deine persönliche Liste. Zurück zur gemeinsamen mit /personal."
+    } else {
+        "Du siehst jetzt die gemeinsame Liste."
+    };
+    ctx.answer(ENVIRONMENT.decorate(text.to_string())).send_queued(&ctx.requester).await?;
+    let joined = list_for_chat(instance, chat_id).await;
+    let ctx = UpdateWithCx { requester: ctx.requester.clone(), update: ChatId::Id(chat_id) };
+    joined.data.lock().await.update_shopping_list(&ctx).await?;
+    store_data(&joined).await;
+    Ok(())
+}
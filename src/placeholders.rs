@@ -0,0 +1,48 @@
+//! Placeholders in templates and in the items of `/alias … add`, filled in
+//! when they are put on the list: "Essensplan KW{week}" becomes
+//! "Essensplan KW42", "Brötchen {weekday_count}" orders one per school day
+//! left in the week. Anything else in braces stays as it is.
+
+use chrono::{Datelike, NaiveDate, Weekday};
+
+const WEEKDAYS: [&str; 7] = ["Montag", "Dienstag", "Mittwoch", "Donnerstag", "Freitag", "Samstag", "Sonntag"];
+const MONTHS: [&str; 12] = [
+    "Januar", "Februar", "März", "April", "Mai", "Juni",
+    "Juli", "August", "September", "Oktober", "November", "Dezember",
+];
+
+/// What the placeholder stands for on `today`, `None` if it isn't one.
+fn value(name: &str, today: NaiveDate) -> Option<String> {
+    Some(match name {
+        "date" => today.format("%d.%m.").to_string(),
+        "weekday" => WEEKDAYS[today.weekday().num_days_from_monday() as usize].to_string(),
+        // Monday to Friday from today on
+        "weekday_count" => Weekday::Fri.number_from_monday().saturating_sub(today.weekday().num_days_from_monday()).to_string(),
+        "week" => today.iso_week().week().to_string(),
+        "month" => MONTHS[today.month0() as usize].to_string(),
+        "year" => today.year().to_string(),
+        _ => return None,
+    })
+}
+
+/// The text with the placeholders filled in.
+pub fn expand(text: &str, today: NaiveDate) -> String {
+    let mut expanded = String::new();
+    let mut rest = text;
+    while let Some(start) = rest.find('{') {
+        expanded.push_str(&rest[..start]);
+        let placeholder = &rest[start..];
+        match placeholder.find('}').and_then(|end| Some((end, value(&placeholder[1..end], today)?))) {
+            Some((end, value)) => {
+                expanded.push_str(&value);
+                rest = &placeholder[end + 1..];
+            }
+            None => {
+                expanded.push('{');
+                rest = &placeholder[1..];
+            }
+        }
+    }
+    expanded.push_str(rest);
+    expanded
+}
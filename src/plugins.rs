@@ -0,0 +1,77 @@
+//! Extensions compiled in with a feature, like the recipe library, kept out of
+//! the main command and callback handling. A plugin names the commands it
+//! handles, gets the buttons it made with `button` back, and runs with every
+//! tick of the scheduler.
+//!
+//! Built-in commands take precedence, a plugin only gets commands the bot
+//! doesn't know itself.
+
+use async_trait::async_trait;
+use lazy_static::lazy_static;
+use teloxide::prelude::*;
+use teloxide::types::{InlineKeyboardButton, User};
+
+use crate::{Data, Instance};
+use crate::callback::Action;
+
+#[async_trait]
+pub(crate) trait Plugin: Send + Sync {
+    /// Unique, without spaces, it prefixes the callback data of the plugin's buttons.
+    fn name(&self) -> &'static str;
+
+    /// Commands the plugin handles, without the slash.
+    fn commands(&self) -> &'static [&'static str] {
+        &[]
+    }
+
+    async fn command(&self, _data: &mut Data, _ctx: &UpdateWithCx<Bot, Message>, _user: &User, _command: &str, _args: &[&str]) -> anyhow::Result<()> {
+        Ok(())
+    }
+
+    /// A button made with `button` was pressed, with the payload given there.
+    async fn callback(&self, _data: &mut Data, _ctx: &UpdateWithCx<Bot, CallbackQuery>, _payload: &str) -> anyhow::Result<()> {
+        Ok(())
+    }
+
+    /// About once a minute, see `scheduler`.
+    async fn tick(&self, _bot: &Bot, _instance: &Instance) -> anyhow::Result<()> {
+        Ok(())
+    }
+}
+
+fn registered() -> Vec<Box<dyn Plugin>> {
+    vec![
+        #[cfg(feature = "recipe-library")]
+        Box::new(crate::library::Library),
+    ]
+}
+
+lazy_static! {
+    static ref PLUGINS: Vec<Box<dyn Plugin>> = registered();
+}
+
+/// The plugin handling the command.
+pub(crate) fn for_command(command: &str) -> Option<&'static dyn Plugin> {
+    // Qualified, teloxide's prelude has a `commands` for every stream
+    PLUGINS.iter().map(|plugin| plugin.as_ref()).find(|plugin| Plugin::commands(*plugin).contains(&command))
+}
+
+/// The plugin that made the button, and its payload.
+pub(crate) fn for_callback(data: &str) -> Option<(&'static dyn Plugin, &str)> {
+    let (name, payload) = data.split_once(' ').unwrap_or((data, ""));
+    PLUGINS.iter().find(|plugin| plugin.name() == name).map(|plugin| (plugin.as_ref(), payload))
+}
+
+/// A button whose presses go to `callback` of the plugin.
+#[cfg_attr(not(feature = "recipe-library"), allow(dead_code))]
+pub(crate) fn button(plugin: &dyn Plugin, payload: &str, text: impl Into<String>) -> InlineKeyboardButton {
+    Action::Plugin(format!("{} {}", plugin.name(), payload)).button(text)
+}
+
+pub(crate) async fn tick(bot: &Bot, instance: &Instance) {
+    for plugin in PLUGINS.iter() {
+        if let Err(error) = plugin.tick(bot, instance).await {
+            log::error!("Plugin {} failed for {:?}: {:?}", plugin.name(), instance.namespace, error);
+        }
+    }
+}
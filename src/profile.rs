@@ -0,0 +1,53 @@
+//! How a user appears in the list, the leaderboard and on assignments, set
+//! with `/profile`: a short name instead of the Telegram first name and an
+//! emoji in front of it. The language set there is the one the user sees
+//! names in where the bot talks to them alone.
+
+use serde::{Deserialize, Serialize};
+
+use crate::i18n::Language;
+
+/// In characters.
+const MAX_NAME_LENGTH: usize = 20;
+/// In characters, an emoji can be several, like flags or skin tones.
+const MAX_EMOJI_LENGTH: usize = 8;
+
+#[derive(Serialize, Deserialize, Clone, Default)]
+pub struct Profile {
+    #[serde(default, skip_serializing_if = "Option::is_none")]
+    pub name: Option<String>,
+    #[serde(default, skip_serializing_if = "Option::is_none")]
+    pub emoji: Option<String>,
+    /// Language of the user's private chat with the bot and of their suggestions, see `Data::translations`.
+    #[serde(default, skip_serializing_if = "Option::is_none")]
+    pub language: Option<Language>,
+}
+
+impl Profile {
+    /// The name with the emoji, the first name if the user didn't set a name.
+    pub fn display(&self, first_name: &str) -> String {
+        let name = self.name.as_deref().unwrap_or(first_name);
+        match &self.emoji {
+            Some(emoji) => format!("{} {}", emoji, name),
+            None => name.to_string(),
+        }
+    }
+
+    pub fn set_name(&mut self, name: &str) -> anyhow::Result<()> {
+        let name = name.trim();
+        if name.is_empty() || name.chars().count() > MAX_NAME_LENGTH {
+            anyhow::bail!("Der Name muss 1 bis {} Zeichen lang sein", MAX_NAME_LENGTH);
+        }
+        self.name = Some(name.to_string());
+        Ok(())
+    }
+
+    pub fn set_emoji(&mut self, emoji: &str) -> anyhow::Result<()> {
+        let emoji = emoji.trim();
+        if emoji.is_empty() || emoji.chars().count() > MAX_EMOJI_LENGTH || emoji.chars().any(|c| c.is_alphanumeric() || c.is_whitespace()) {
+            anyhow::bail!("Das ist kein Emoji");
+        }
+        self.emoji = Some(emoji.to_string());
+        Ok(())
+    }
+}
@@ -0,0 +1,136 @@
+//! Live updates for clients like desktop widgets or e-ink displays: a
+//! WebSocket at `/ws/<token>`, with the token from `/api`, first gets the list
+//!
+//! `{"event": "list", "items": [{"item": "Milch", "done": false, "quantity": 1}]}`
+//!
+//! and then every change as the same events raw webhooks get, see `webhooks`.
+//! Clients change the list by sending operations like in a batch, each is
+//! answered with `{"event": "result", "id": "a1", "applied": true}`.
+
+use std::collections::HashMap;
+use std::sync::{Arc, Mutex};
+use std::time::Duration;
+
+use chrono::Utc;
+use futures_util::{SinkExt, StreamExt};
+use lazy_static::lazy_static;
+use serde_json::json;
+use teloxide::prelude::*;
+use tokio::sync::broadcast;
+use tokio::sync::broadcast::error::RecvError;
+use warp::ws::{Message, WebSocket};
+
+use crate::{batch, store_data, webhooks, Instance};
+
+/// Events kept for a slow client, one that falls further behind gets the whole list again.
+const CAPACITY: usize = 64;
+/// How often an idle client is checked for a changed `/api` token.
+const TOKEN_CHECK: Duration = Duration::from_secs(30);
+
+lazy_static! {
+    /// Events of each data file.
+    static ref CHANNELS: Mutex<HashMap<String, broadcast::Sender<String>>> = Mutex::new(HashMap::new());
+}
+
+fn channel(path: &str) -> broadcast::Sender<String> {
+    CHANNELS.lock().unwrap()
+        .entry(path.to_string())
+        .or_insert_with(|| broadcast::channel(CAPACITY).0)
+        .clone()
+}
+
+/// Sends the events to the clients connected to the list of the data file.
+pub fn publish(path: &str, events: &[webhooks::Event]) {
+    if events.is_empty() {
+        return;
+    }
+    let sender = channel(path);
+    let now = Utc::now();
+    for event in events {
+        match webhooks::to_json(event, now) {
+            // Fails only without clients
+            Ok(json) => drop(sender.send(json)),
+            Err(error) => log::error!("Couldn't serialize {:?}: {:?}", event, error),
+        }
+    }
+}
+
+async fn list(instance: &Instance) -> String {
+    let data = instance.snapshot.load_full();
+    let items: Vec<_> = data.list.items.iter()
+        .filter(|item| !item.header)
+        .map(|item| json!({ "item": item.name, "done": item.done, "quantity": item.quantity }))
+        .collect();
+    json!({ "event": "list", "items": items }).to_string()
+}
+
+/// Whether the `/api` token was changed since the client connected.
+fn revoked(instance: &Instance, token: &str) -> bool {
+    instance.snapshot.load().api_token.as_deref() != Some(token)
+}
+
+/// Applies an operation sent by a client, `None` if the `/api` token was changed meanwhile.
+async fn apply(bot: &Bot, instance: &Instance, token: &str, operation: batch::Operation) -> Option<String> {
+    let id = operation.id.clone();
+    let outcome = {
+        let mut data = instance.data.lock().await;
+        if data.api_token.as_deref() != Some(token) {
+            return None;
+        }
+        let outcome = data.apply_batch(batch::Batch { operations: vec![operation] });
+        if outcome.applied > 0 {
+            data.show_outside_change(bot, "a WebSocket client").await;
+        }
+        outcome
+    };
+    store_data(instance).await;
+    let mut result = json!({ "event": "result", "id": id, "applied": outcome.applied > 0 });
+    if let Some(rejected) = outcome.rejected.first() {
+        result["error"] = rejected.reason.clone().into();
+    }
+    Some(result.to_string())
+}
+
+/// Talks to a client until it disconnects.
+pub async fn serve(socket: WebSocket, bot: Bot, instance: Arc<Instance>, token: String) {
+    let (mut sink, mut stream) = socket.split();
+    let mut events = channel(&instance.data_path).subscribe();
+    if sink.send(Message::text(list(&instance).await)).await.is_err() {
+        return;
+    }
+    let mut token_check = tokio::time::interval(TOKEN_CHECK);
+    loop {
+        let reply = tokio::select! {
+            event = events.recv() => match event {
+                // The token changes with the data, so it is checked before anything more is sent
+                _ if revoked(&instance, &token) => break,
+                Ok(event) => event,
+                Err(RecvError::Lagged(_)) => list(&instance).await,
+                Err(RecvError::Closed) => break,
+            },
+            _ = token_check.tick() => {
+                if revoked(&instance, &token) {
+                    break;
+                }
+                continue;
+            }
+            message = stream.next() => match message {
+                Some(Ok(message)) if message.is_text() => {
+                    match serde_json::from_str::<batch::Operation>(message.to_str().unwrap_or_default()) {
+                        Ok(operation) => match apply(&bot, &instance, &token, operation).await {
+                            Some(reply) => reply,
+                            None => break,
+                        },
+                        Err(error) => json!({ "event": "error", "error": error.to_string() }).to_string(),
+                    }
+                }
+                Some(Ok(message)) if message.is_close() => break,
+                Some(Ok(_)) => continue,
+                Some(Err(_)) | None => break,
+            },
+        };
+        if sink.send(Message::text(reply)).await.is_err() {
+            break;
+        }
+    }
+}
@@ -0,0 +1,61 @@
+//! A keyboard below the message field with the most common actions in plain
+//! words, for those who find the emoji buttons of the list cryptic. It is
+//! turned on per chat with `/keyboard`, tapping a key sends its text, which is
+//! taken as the action instead of as a new item.
+
+use serde::{Deserialize, Serialize};
+use teloxide::types::{KeyboardButton, KeyboardMarkup};
+
+#[derive(Serialize, Deserialize, Clone, Copy, PartialEq, Eq, Debug)]
+#[serde(rename_all = "snake_case")]
+pub enum QuickAction {
+    ShowList,
+    /// Starts timing a trip, or ends it, like the ⏱ button.
+    StartTrip,
+    Recipes,
+    RemoveDone,
+}
+
+pub const ALL: [QuickAction; 4] = [QuickAction::ShowList, QuickAction::StartTrip, QuickAction::Recipes, QuickAction::RemoveDone];
+/// The keys of `/keyboard on`.
+pub const DEFAULT: [QuickAction; 3] = [QuickAction::ShowList, QuickAction::StartTrip, QuickAction::Recipes];
+
+/// Keys per row.
+const COLUMNS: usize = 2;
+
+impl QuickAction {
+    /// For `/keyboard`.
+    pub fn name(self) -> &'static str {
+        match self {
+            QuickAction::ShowList => "liste",
+            QuickAction::StartTrip => "einkauf",
+            QuickAction::Recipes => "rezepte",
+            QuickAction::RemoveDone => "erledigt",
+        }
+    }
+
+    pub fn parse(name: &str) -> Option<Self> {
+        ALL.iter().copied().find(|action| action.name() == name.to_lowercase())
+    }
+
+    pub fn label(self) -> &'static str {
+        match self {
+            QuickAction::ShowList => "📋 Liste zeigen",
+            QuickAction::StartTrip => "🛒 Einkauf starten",
+            QuickAction::Recipes => "📝 Rezepte",
+            QuickAction::RemoveDone => "💚 Erledigte entfernen",
+        }
+    }
+}
+
+/// The action of the key whose text was sent.
+pub fn find(actions: &[QuickAction], text: &str) -> Option<QuickAction> {
+    actions.iter().copied().find(|action| action.label() == text.trim())
+}
+
+pub fn markup(actions: &[QuickAction]) -> KeyboardMarkup {
+    let rows: Vec<Vec<KeyboardButton>> = actions.chunks(COLUMNS)
+        .map(|row| row.iter().map(|action| KeyboardButton::new(action.label())).collect())
+        .collect();
+    KeyboardMarkup::new(rows).resize_keyboard(true)
+}
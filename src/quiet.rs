@@ -0,0 +1,49 @@
+//! Quiet hours per chat, set with `/quiet 22-7`: scheduled messages created
+//! during the window are held and sent once it is over. Edits of the list
+//! don't notify anyone, so they still happen.
+
+use serde::{Deserialize, Serialize};
+use teloxide::types::InlineKeyboardMarkup;
+
+#[derive(Serialize, Deserialize, Clone, Copy)]
+pub struct QuietHours {
+    /// First quiet hour, local time of the chat.
+    pub start: u32,
+    /// First hour messages are sent again, may be before `start` to span midnight.
+    pub end: u32,
+}
+
+impl QuietHours {
+    /// `/quiet` arguments like `22-7` or `22 7`.
+    pub fn parse(args: &[&str]) -> Option<Self> {
+        let joined = args.join(" ");
+        let mut hours = joined.split(|c: char| c == '-' || c.is_whitespace()).filter(|part| !part.is_empty());
+        let mut hour = || -> Option<u32> { hours.next()?.trim_end_matches('h').parse().ok().filter(|hour| *hour < 24) };
+        let (start, end) = (hour()?, hour()?);
+        if start == end || hours.next().is_some() {
+            return None;
+        }
+        Some(QuietHours { start, end })
+    }
+
+    pub fn contains(&self, hour: u32) -> bool {
+        if self.start < self.end {
+            self.start <= hour && hour < self.end
+        } else {
+            hour >= self.start || hour < self.end
+        }
+    }
+
+    pub fn describe(&self) -> String {
+        format!("Ruhezeit von {} bis {} Uhr, Erinnerungen kommen danach.", self.start, self.end)
+    }
+}
+
+/// A scheduled message held back until the quiet hours of its chat are over.
+#[derive(Serialize, Deserialize, Clone)]
+pub struct HeldMessage {
+    pub chat_id: i64,
+    /// HTML, already decorated.
+    pub text: String,
+    pub markup: Option<InlineKeyboardMarkup>,
+}
@@ -0,0 +1,98 @@
+use std::collections::HashMap;
+use std::sync::Mutex;
+use std::time::{Duration, Instant};
+
+use lazy_static::lazy_static;
+use teloxide::prelude::*;
+
+use crate::ENVIRONMENT;
+use crate::outbox::SendQueued;
+
+/// Token bucket per user: `burst` interactions at once, refilled by `per_minute`.
+pub struct Limits {
+    pub burst: f64,
+    pub per_minute: f64,
+    pub mute: Duration,
+}
+
+struct Bucket {
+    tokens: f64,
+    last_refill: Instant,
+    muted_until: Option<Instant>,
+}
+
+enum Verdict {
+    Allow,
+    /// The user just ran out of tokens and gets muted.
+    Mute,
+    /// The user is still muted.
+    Drop,
+}
+
+lazy_static! {
+    static ref BUCKETS: Mutex<HashMap<i64, Bucket>> = Mutex::new(HashMap::new());
+}
+
+fn check(user_id: i64) -> Verdict {
+    let limits = &ENVIRONMENT.rate_limits;
+    let now = Instant::now();
+    let mut buckets = BUCKETS.lock().unwrap();
+    let bucket = buckets.entry(user_id).or_insert(Bucket {
+        tokens: limits.burst,
+        last_refill: now,
+        muted_until: None,
+    });
+    if let Some(until) = bucket.muted_until {
+        if now < until {
+            return Verdict::Drop;
+        }
+        bucket.muted_until = None;
+    }
+    let elapsed = now.duration_since(bucket.last_refill).as_secs_f64();
+    bucket.tokens = (bucket.tokens + elapsed * limits.per_minute / 60.0).min(limits.burst);
+    bucket.last_refill = now;
+    if bucket.tokens >= 1.0 {
+        bucket.tokens -= 1.0;
+        Verdict::Allow
+    } else {
+        bucket.muted_until = Some(now + limits.mute);
+        Verdict::Mute
+    }
+}
+
+fn warning() -> String {
+    format!("Nicht so schnell! Ich ignoriere dich für {} Sekunden.", ENVIRONMENT.rate_limits.mute.as_secs())
+}
+
+/// Returns `true` if the message should be ignored.
+pub async fn throttle_message(ctx: &UpdateWithCx<Bot, Message>) -> bool {
+    let user_id = match ctx.update.from() {
+        Some(user) => user.id,
+        None => return false,
+    };
+    match check(user_id) {
+        Verdict::Allow => false,
+        Verdict::Mute => {
+            log::warn!("Muting user {} for spamming", user_id);
+            if let Err(error) = ctx.reply_to(ENVIRONMENT.decorate(warning())).send_queued(&ctx.requester).await {
+                log::error!("Couldn't send rate limit warning: {:?}", error);
+            }
+            true
+        }
+        Verdict::Drop => true,
+    }
+}
+
+/// Returns `true` if the callback query should be ignored.
+pub async fn throttle_callback_query(ctx: &UpdateWithCx<Bot, CallbackQuery>) -> bool {
+    match check(ctx.update.from.id) {
+        Verdict::Allow => false,
+        Verdict::Mute | Verdict::Drop => {
+            let answer = ctx.requester.answer_callback_query(ctx.update.id.clone()).text(warning());
+            if let Err(error) = answer.send().await {
+                log::error!("Couldn't send rate limit warning: {:?}", error);
+            }
+            true
+        }
+    }
+}
@@ -0,0 +1,170 @@
+//! Checking items off by reacting 👍 to their reminder, taking the reaction
+//! back unchecks them. Every message the bot sends with a ✅ button for an item
+//! is remembered, so the reaction can be matched to the item.
+//!
+//! The teloxide version used here predates reactions, so `polling` fetches the
+//! updates itself: it asks Telegram for `message_reaction` updates as well,
+//! handles them and passes everything else on to the dispatcher, leaving out
+//! updates handled before, see `dedup`. In groups, Telegram only sends
+//! reactions to bots that are admins.
+
+use std::sync::Arc;
+use std::time::Duration;
+
+use futures_util::stream::{self, Stream, StreamExt};
+use serde::{Deserialize, Serialize};
+use serde_json::{json, Value};
+use teloxide::prelude::*;
+use teloxide::types::{ChatId, InlineKeyboardButtonKind, InlineKeyboardMarkup, Update};
+
+use crate::callback::Action;
+use crate::{personal, refresh_data, store_data, Instance};
+
+const THUMBS_UP: &str = "👍";
+/// Item messages remembered per list, older ones can't be reacted to anymore.
+const MAX_MESSAGES: usize = 100;
+/// Seconds Telegram holds a request for updates open.
+const POLLING_TIMEOUT: u64 = 10;
+const ERROR_DELAY: Duration = Duration::from_secs(5);
+/// Changes of other members are only sent when asked for, reactions as well.
+const ALLOWED_UPDATES: [&str; 6] = ["message", "callback_query", "inline_query", "my_chat_member", "chat_member", "message_reaction"];
+
+/// A message about a single item.
+#[derive(Serialize, Deserialize, Clone)]
+pub struct ItemMessage {
+    pub chat_id: i64,
+    pub message_id: i32,
+    pub item: u64,
+}
+
+pub fn remember(messages: &mut Vec<ItemMessage>, message: ItemMessage) {
+    messages.push(message);
+    if messages.len() > MAX_MESSAGES {
+        messages.drain(..messages.len() - MAX_MESSAGES);
+    }
+}
+
+/// The ID of the item a message with a ✅ button is about.
+pub fn item_of(markup: &InlineKeyboardMarkup) -> Option<u64> {
+    markup.inline_keyboard.iter().flatten().find_map(|button| match &button.kind {
+        InlineKeyboardButtonKind::CallbackData(data) => match Action::decode(data) {
+            Some(Action::CheckItem(id)) => Some(id),
+            _ => None,
+        },
+        _ => None,
+    })
+}
+
+#[derive(Deserialize)]
+struct Chat {
+    id: i64,
+}
+
+#[derive(Deserialize)]
+struct ReactingUser {
+    id: i64,
+}
+
+#[derive(Deserialize)]
+struct ReactionType {
+    /// Missing for custom emoji.
+    #[serde(default)]
+    emoji: Option<String>,
+}
+
+#[derive(Deserialize)]
+struct Reaction {
+    chat: Chat,
+    message_id: i32,
+    /// Missing for anonymous admins.
+    #[serde(default)]
+    user: Option<ReactingUser>,
+    old_reaction: Vec<ReactionType>,
+    new_reaction: Vec<ReactionType>,
+}
+
+fn has_thumbs_up(reactions: &[ReactionType]) -> bool {
+    reactions.iter().any(|reaction| reaction.emoji.as_deref() == Some(THUMBS_UP))
+}
+
+/// Updates for the dispatcher, like teloxide's polling, handling reactions on the way.
+pub fn polling(bot: Bot, instance: Arc<Instance>) -> impl Stream<Item = anyhow::Result<Update>> {
+    let client = reqwest::Client::new();
+    stream::unfold(0, move |offset| {
+        let (bot, instance, client) = (bot.clone(), instance.clone(), client.clone());
+        async move {
+            let (updates, offset) = match fetch(&client, &bot, offset).await {
+                Ok(values) => {
+                    let offset = values.last()
+                        .and_then(|value| value["update_id"].as_i64())
+                        .map(|id| id + 1)
+                        .unwrap_or(offset);
+                    let mut updates = Vec::new();
+                    for value in values {
+                        if !instance.data.lock().await.seen_updates.first_time(&value) {
+                            log::info!("Skipping update {} handled before", value["update_id"]);
+                            continue;
+                        }
+                        if let Some(reaction) = value.get("message_reaction") {
+                            if let Err(error) = handle(&bot, &instance, reaction.clone()).await {
+                                log::error!("Couldn't handle a reaction: {:?}", error);
+                            }
+                            continue;
+                        }
+                        match serde_json::from_value::<Update>(value.clone()) {
+                            Ok(update) => updates.push(Ok(update)),
+                            Err(error) => log::error!("Cannot parse an update: {:?}\n{}", error, value),
+                        }
+                    }
+                    (updates, offset)
+                }
+                Err(error) => {
+                    tokio::time::sleep(ERROR_DELAY).await;
+                    (vec![Err(error)], offset)
+                }
+            };
+            Some((stream::iter(updates), offset))
+        }
+    })
+    .flatten()
+}
+
+async fn fetch(client: &reqwest::Client, bot: &Bot, offset: i64) -> anyhow::Result<Vec<Value>> {
+    let url = bot.api_url().join(&format!("bot{}/getUpdates", bot.token()))?;
+    let response: Value = client.post(url)
+        .json(&json!({ "offset": offset, "timeout": POLLING_TIMEOUT, "allowed_updates": ALLOWED_UPDATES }))
+        .send().await?
+        .json().await?;
+    match response["result"].as_array() {
+        Some(updates) if response["ok"].as_bool() == Some(true) => Ok(updates.clone()),
+        _ => anyhow::bail!("getUpdates failed: {}", response),
+    }
+}
+
+async fn handle(bot: &Bot, instance: &Arc<Instance>, reaction: Value) -> anyhow::Result<()> {
+    let reaction: Reaction = serde_json::from_value(reaction)?;
+    let (before, after) = (has_thumbs_up(&reaction.old_reaction), has_thumbs_up(&reaction.new_reaction));
+    if before == after {
+        return Ok(());
+    }
+    let chat_id = reaction.chat.id;
+    let list = personal::list_for_chat(instance, chat_id).await;
+    refresh_data(&list).await;
+    {
+        let mut data = list.data.lock().await;
+        let id = match data.item_messages.iter().find(|message| message.chat_id == chat_id && message.message_id == reaction.message_id) {
+            Some(message) => message.item,
+            None => return Ok(()),
+        };
+        let item = match data.list.items.iter_mut().find(|item| id != 0 && item.id == id) {
+            Some(item) if item.done != after => item,
+            _ => return Ok(()),
+        };
+        item.done = after;
+        item.checked_by = if after { reaction.user.map(|user| user.id) } else { None };
+        let ctx = UpdateWithCx { requester: bot.clone(), update: ChatId::Id(chat_id) };
+        data.update_shopping_list(&ctx).await?;
+    }
+    store_data(&list).await;
+    Ok(())
+}
@@ -0,0 +1,133 @@
+//! Month in review, posted as a picture on the first day of the next month:
+//! spending per category and purchases per weekday as bar charts, with the
+//! numbers and the top products in the caption, see `scheduler`.
+
+use std::collections::HashMap;
+
+use chrono::Datelike;
+use chrono_tz::Tz;
+use image::{DynamicImage, ImageOutputFormat, Rgb, RgbImage};
+use teloxide::utils::html;
+
+use crate::history::Purchase;
+use crate::money::MoneyFormat;
+use crate::spending::{Category, CATEGORIES};
+
+const WIDTH: u32 = 800;
+const HEIGHT: u32 = 400;
+const MARGIN: u32 = 40;
+const BACKGROUND: Rgb<u8> = Rgb([250, 247, 240]);
+/// One color per category, in the order of `CATEGORIES`.
+const CATEGORY_COLORS: [Rgb<u8>; 3] = [Rgb([76, 175, 80]), Rgb([33, 150, 243]), Rgb([255, 152, 0])];
+const WEEKDAY_COLOR: Rgb<u8> = Rgb([120, 120, 120]);
+const BUSIEST_COLOR: Rgb<u8> = Rgb([233, 30, 99]);
+const TOP_PRODUCTS: usize = 5;
+const WEEKDAYS: [&str; 7] = ["Montag", "Dienstag", "Mittwoch", "Donnerstag", "Freitag", "Samstag", "Sonntag"];
+
+pub struct Recap {
+    year: i32,
+    month: u32,
+    bought: usize,
+    /// Most bought items with their count, most first.
+    top: Vec<(String, usize)>,
+    /// Spent cents per category, in the order of `CATEGORIES`.
+    spent: [u64; 3],
+    /// Purchases per weekday, Monday first.
+    weekdays: [usize; 7],
+}
+
+impl Recap {
+    /// The recap of the given month, `None` if nothing was bought.
+    pub fn of_month(history: &[Purchase], year: i32, month: u32, timezone: Tz) -> Option<Self> {
+        let mut recap = Recap { year, month, bought: 0, top: Vec::new(), spent: [0; 3], weekdays: [0; 7] };
+        let mut counts: HashMap<String, (String, usize)> = HashMap::new();
+        for purchase in history.iter().filter(|purchase| purchase.bought()) {
+            let at = purchase.at.with_timezone(&timezone);
+            if at.year() != year || at.month() != month {
+                continue;
+            }
+            recap.bought += 1;
+            recap.weekdays[at.weekday().num_days_from_monday() as usize] += 1;
+            counts.entry(purchase.name.to_lowercase()).or_insert_with(|| (purchase.name.clone(), 0)).1 += 1;
+            if let Some(cents) = purchase.price {
+                let category = purchase.category.unwrap_or(Category::Sonstiges);
+                let index = CATEGORIES.iter().position(|c| *c == category).unwrap();
+                recap.spent[index] += cents;
+            }
+        }
+        if recap.bought == 0 {
+            return None;
+        }
+        let mut top: Vec<(String, usize)> = counts.into_values().collect();
+        top.sort_by(|a, b| b.1.cmp(&a.1).then(a.0.cmp(&b.0)));
+        top.truncate(TOP_PRODUCTS);
+        recap.top = top;
+        Some(recap)
+    }
+
+    /// Index of the weekday with the most purchases, the earlier one on a tie.
+    fn busiest_day(&self) -> usize {
+        (0..7).max_by_key(|day| (self.weekdays[*day], 7 - day)).unwrap()
+    }
+
+    /// The numbers of the picture and the top products, as HTML.
+    pub fn caption(&self, money: &MoneyFormat) -> String {
+        let mut lines = vec![
+            html::bold(&format!("Rückblick {:02}/{}", self.month, self.year)),
+            format!("{} Einträge gekauft, meistens am {}.", self.bought, WEEKDAYS[self.busiest_day()]),
+        ];
+        if self.spent.iter().sum::<u64>() > 0 {
+            let colors = ["🟩", "🟦", "🟧"];
+            for ((category, cents), color) in CATEGORIES.iter().zip(self.spent.iter()).zip(colors.iter()) {
+                lines.push(format!("{} {}: {}", color, category.name(), money.format(*cents)));
+            }
+        }
+        lines.push("Am häufigsten:".to_string());
+        for (name, count) in self.top.iter() {
+            lines.push(format!(" - {}: {}×", html::escape(name), count));
+        }
+        lines.join("\n")
+    }
+
+    /// Spending per category on the left, purchases per weekday on the right, as a PNG.
+    pub fn render_png(&self) -> anyhow::Result<Vec<u8>> {
+        let mut image = RgbImage::from_pixel(WIDTH, HEIGHT, BACKGROUND);
+        let half = WIDTH / 2;
+        let max_spent = self.spent.iter().copied().max().unwrap_or(0);
+        if max_spent > 0 {
+            for (index, (cents, color)) in self.spent.iter().zip(CATEGORY_COLORS.iter()).enumerate() {
+                let height = bar_height(*cents, max_spent);
+                draw_bar(&mut image, index as u32, 3, 0, half, height, *color);
+            }
+        }
+        let busiest = self.busiest_day();
+        let max_day = *self.weekdays.iter().max().unwrap();
+        for (index, count) in self.weekdays.iter().enumerate() {
+            let color = if index == busiest { BUSIEST_COLOR } else { WEEKDAY_COLOR };
+            draw_bar(&mut image, index as u32, 7, half, half, bar_height(*count as u64, max_day as u64), color);
+        }
+        let mut png = Vec::new();
+        DynamicImage::ImageRgb8(image).write_to(&mut png, ImageOutputFormat::Png)?;
+        Ok(png)
+    }
+}
+
+/// Height in pixels of a bar for `value`, the highest bar filling the chart.
+fn bar_height(value: u64, max: u64) -> u32 {
+    if max == 0 {
+        return 0;
+    }
+    (value * (HEIGHT - 2 * MARGIN) as u64 / max) as u32
+}
+
+/// Draws bar `index` of `count` bars standing on the bottom margin of the chart starting at `left`.
+fn draw_bar(image: &mut RgbImage, index: u32, count: u32, left: u32, width: u32, height: u32, color: Rgb<u8>) {
+    let slot = (width - 2 * MARGIN) / count;
+    let x = left + MARGIN + index * slot + slot / 6;
+    let bottom = HEIGHT - MARGIN;
+    for px in x..x + slot * 2 / 3 {
+        for py in bottom - height..bottom {
+            image.put_pixel(px, py, color);
+        }
+    }
+}
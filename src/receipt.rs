@@ -0,0 +1,94 @@
+use std::time::Duration;
+
+use serde_json::Value;
+
+use crate::money::MoneyFormat;
+use crate::spending;
+
+const OCR_URL: &str = "https://api.ocr.space/parse/image";
+const TIMEOUT: Duration = Duration::from_secs(30);
+
+/// A receipt line or list item with its price in cents.
+pub type Line = (String, u64);
+
+/// Extracts the text of a receipt photo with the OCR.space API.
+///
+/// Needs `OCR_API_KEY`; the service can be swapped via `OCR_URL`, as long as
+/// it answers in the same JSON format.
+pub async fn recognize(jpeg: Vec<u8>) -> anyhow::Result<String> {
+    let api_key = std::env::var("OCR_API_KEY")
+        .map_err(|_| anyhow::anyhow!("Belegerkennung ist nicht eingerichtet"))?;
+    let url = std::env::var("OCR_URL").unwrap_or_else(|_| OCR_URL.to_string());
+    let image = format!("data:image/jpeg;base64,{}", base64::encode(jpeg));
+    let json: Value = reqwest::Client::new()
+        .post(url.as_str())
+        .form(&[
+            ("apikey", api_key.as_str()),
+            ("language", "ger"),
+            ("isTable", "true"),
+            ("base64Image", image.as_str()),
+        ])
+        .timeout(TIMEOUT)
+        .send()
+        .await?
+        .json()
+        .await?;
+    json.get("ParsedResults")
+        .and_then(|results| results.get(0))
+        .and_then(|result| result.get("ParsedText"))
+        .and_then(|text| text.as_str())
+        .map(|text| text.to_string())
+        .ok_or_else(|| anyhow::anyhow!("Auf dem Beleg wurde kein Text erkannt"))
+}
+
+/// Lines that end in a price like `MILCH 1,19 A`.
+pub fn parse_lines(text: &str) -> Vec<Line> {
+    text.lines()
+        .filter_map(|line| {
+            let mut words: Vec<&str> = line.split_whitespace().collect();
+            // Tax class letter after the price
+            if words.last().map(|word| word.len() == 1 && word.chars().all(char::is_alphabetic)) == Some(true) {
+                words.pop();
+            }
+            let price = words.pop()?;
+            if !price.contains(',') && !price.contains('.') {
+                return None;
+            }
+            let cents = spending::parse_price(price)?;
+            let name = words.join(" ");
+            if name.is_empty() || name.to_lowercase().starts_with("summe") {
+                return None;
+            }
+            Some((name, cents))
+        })
+        .collect()
+}
+
+/// Receipts abbreviate and shout, so this compares lowercase words by prefix.
+pub fn matches(item_name: &str, line: &str) -> bool {
+    let line = line.to_lowercase();
+    let item_name = item_name.to_lowercase();
+    if line.contains(&item_name) {
+        return true;
+    }
+    line.split_whitespace()
+        .filter(|word| word.chars().count() >= 4)
+        .any(|word| item_name.split_whitespace().any(|item_word| item_word.starts_with(word) || word.starts_with(item_word)))
+}
+
+pub fn summary(matched: &[Line], unmatched: &[Line], money: &MoneyFormat) -> String {
+    let mut text = String::from("Beleg ausgewertet.");
+    if !matched.is_empty() {
+        text.push_str("\n\nZugeordnet:");
+        for (name, cents) in matched {
+            text.push_str(&format!("\n - {}: {}", name, money.format(*cents)));
+        }
+    }
+    if !unmatched.is_empty() {
+        text.push_str("\n\nNicht zugeordnet:");
+        for (line, cents) in unmatched {
+            text.push_str(&format!("\n - {}: {}", line, money.format(*cents)));
+        }
+    }
+    text
+}
@@ -0,0 +1,124 @@
+//! Details of a recipe besides its ingredients: how many servings the
+//! ingredients make, how long it takes and where it is from. The ℹ️ button in
+//! the recipe manager asks for one detail after the other, `-` keeps what was
+//! there and `x` removes it.
+
+use serde::{Deserialize, Serialize};
+use teloxide::utils::html;
+
+/// More servings are likely a typo.
+const MAX_SERVINGS: u32 = 100;
+/// A day, longer is likely a typo.
+const MAX_MINUTES: u32 = 24 * 60;
+
+#[derive(Serialize, Deserialize, Clone, Default, PartialEq)]
+pub struct RecipeInfo {
+    #[serde(default)]
+    pub servings: Option<u32>,
+    #[serde(default)]
+    pub prep_minutes: Option<u32>,
+    /// Link to where the recipe is from.
+    #[serde(default)]
+    pub source: Option<String>,
+}
+
+impl RecipeInfo {
+    pub fn is_empty(&self) -> bool {
+        *self == RecipeInfo::default()
+    }
+
+    /// "⏱ 30 min", for meal plans.
+    pub fn prep_time(&self) -> Option<String> {
+        let minutes = self.prep_minutes?;
+        Some(match (minutes / 60, minutes % 60) {
+            (0, minutes) => format!("⏱ {} min", minutes),
+            (hours, 0) => format!("⏱ {} h", hours),
+            (hours, minutes) => format!("⏱ {}:{:02} h", hours, minutes),
+        })
+    }
+
+    /// "👥 4 · ⏱ 30 min · 🔗" with a link to the source, as HTML.
+    pub fn describe(&self) -> String {
+        let mut parts = Vec::new();
+        if let Some(servings) = self.servings {
+            parts.push(format!("👥 {}", servings));
+        }
+        parts.extend(self.prep_time());
+        if let Some(source) = &self.source {
+            parts.push(format!("<a href=\"{}\">🔗</a>", html::escape(source)));
+        }
+        parts.join(" · ")
+    }
+}
+
+#[derive(Serialize, Deserialize, Clone, Copy, PartialEq)]
+#[serde(rename_all = "snake_case")]
+pub enum Field {
+    Servings,
+    PrepTime,
+    Source,
+}
+
+impl Field {
+    pub const FIRST: Field = Field::Servings;
+
+    pub fn next(self) -> Option<Field> {
+        match self {
+            Field::Servings => Some(Field::PrepTime),
+            Field::PrepTime => Some(Field::Source),
+            Field::Source => None,
+        }
+    }
+
+    /// What is asked for the field, with the current value.
+    pub fn question(self, name: &str, info: &RecipeInfo) -> String {
+        let (question, current) = match self {
+            Field::Servings => ("Für wie viele Portionen sind die Zutaten?", info.servings.map(|servings| servings.to_string())),
+            Field::PrepTime => ("Wie lange dauert es in Minuten, z.B. 45 oder 1:30?", info.prep_minutes.map(|minutes| minutes.to_string())),
+            Field::Source => ("Link zum Rezept?", info.source.clone()),
+        };
+        let current = match current {
+            Some(current) => format!("Bisher: {}. - behält es, x entfernt es.", html::escape(&current)),
+            None => "- überspringt es.".to_string(),
+        };
+        format!("{}: {}\n{}", html::bold(&html::escape(name)), question, current)
+    }
+
+    /// Sets the field from the answer to its question.
+    pub fn answer(self, info: &mut RecipeInfo, text: &str) -> anyhow::Result<()> {
+        let text = text.trim();
+        if text == "-" {
+            return Ok(());
+        }
+        let remove = text.eq_ignore_ascii_case("x");
+        match self {
+            Field::Servings if remove => info.servings = None,
+            Field::Servings => match text.parse() {
+                Ok(servings) if (1..=MAX_SERVINGS).contains(&servings) => info.servings = Some(servings),
+                _ => anyhow::bail!("Bitte eine Zahl von 1 bis {}", MAX_SERVINGS),
+            },
+            Field::PrepTime if remove => info.prep_minutes = None,
+            Field::PrepTime => match parse_minutes(text) {
+                Some(minutes) if (1..=MAX_MINUTES).contains(&minutes) => info.prep_minutes = Some(minutes),
+                _ => anyhow::bail!("Bitte die Minuten, z.B. 45, oder Stunden und Minuten, z.B. 1:30"),
+            },
+            Field::Source if remove => info.source = None,
+            Field::Source if text.starts_with("https://") || text.starts_with("http://") => info.source = Some(text.to_string()),
+            Field::Source => anyhow::bail!("Bitte einen Link, der mit https:// beginnt"),
+        }
+        Ok(())
+    }
+}
+
+/// "45", "45 min" or "1:30".
+fn parse_minutes(text: &str) -> Option<u32> {
+    let text = text.trim_end_matches("min").trim();
+    match text.split_once(':') {
+        Some((hours, minutes)) if minutes.len() == 2 => {
+            let minutes: u32 = minutes.parse().ok().filter(|minutes| *minutes < 60)?;
+            Some(hours.parse::<u32>().ok()?.checked_mul(60)? + minutes)
+        }
+        Some(_) => None,
+        None => text.parse().ok(),
+    }
+}
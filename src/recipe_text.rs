@@ -0,0 +1,47 @@
+//! Recipes pasted as a whole, in the recipe composer or with `/addrecipe`:
+//!
+//! ```text
+//! Pfannkuchen:
+//! - 200 g Mehl
+//! - 3 Eier
+//! ```
+//!
+//! The first line is the name, unless the composer has one already, and every
+//! other line is an ingredient. Bullets and numbers in front of the lines are
+//! dropped.
+
+const BULLETS: &[char] = &['-', '*', '•', '–', '·'];
+const NUMBER_ENDS: &[char] = &['.', ')'];
+
+/// The lines that aren't empty, without their bullets.
+pub fn lines(text: &str) -> Vec<String> {
+    text.lines()
+        .map(strip_bullet)
+        .filter(|line| !line.is_empty())
+        .map(str::to_string)
+        .collect()
+}
+
+/// A line used as the name of the recipe, without the colon after it.
+pub fn name(line: &str) -> String {
+    line.trim_end_matches(':').trim().to_string()
+}
+
+/// "- Mehl" and "1. Mehl" become "Mehl", "1.5 l Milch" stays as it is.
+fn strip_bullet(line: &str) -> &str {
+    let line = line.trim();
+    let numbered = line.trim_start_matches(|c: char| c.is_ascii_digit());
+    let rest = match line.strip_prefix(BULLETS) {
+        Some(rest) => rest,
+        None if numbered.len() < line.len() => match numbered.strip_prefix(NUMBER_ENDS) {
+            Some(rest) => rest,
+            None => return line,
+        },
+        None => return line,
+    };
+    if rest.starts_with(char::is_whitespace) {
+        rest.trim()
+    } else {
+        line
+    }
+}
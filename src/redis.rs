@@ -0,0 +1,171 @@
+//! Optional Redis storage, so that several replicas of the bot share one list,
+//! compiled in with the `redis-storage` feature.
+//!
+//! With `REDIS_URL` (`redis://[[user]:password@]host[:port][/db]`) set, the data
+//! is kept under `shopping_bot:<data file name>` instead of the data file, and
+//! `<key>:version` is bumped with every write. A replica reloads the data before
+//! handling an update if another one wrote meanwhile, and a write only goes
+//! through if nobody else wrote since the data was loaded. Otherwise it fails
+//! with a `storage::Conflict` and the change is merged into the newer data, see `merge`.
+//!
+//! While moving to or from Redis, it can also hold a copy of the data next to
+//! the data file, see `shadow`.
+
+use lazy_static::lazy_static;
+
+use crate::shadow;
+
+#[cfg(feature = "redis-storage")]
+mod enabled {
+    use std::collections::HashMap;
+    use std::path::Path;
+    use std::sync::Mutex;
+
+    use lazy_static::lazy_static;
+    use redis::aio::Connection;
+    use redis::AsyncCommands;
+
+    lazy_static! {
+        /// Version of the data this replica loaded or wrote last, per key.
+        static ref VERSIONS: Mutex<HashMap<String, i64>> = Mutex::new(HashMap::new());
+    }
+
+    /// The bot talks to Redis about once per update, so every operation opens
+    /// its own connection. That also keeps a `WATCH` to the one transaction.
+    async fn connect(url: &str) -> anyhow::Result<Connection> {
+        Ok(redis::Client::open(url)?.get_async_connection().await?)
+    }
+
+    fn keys(path: &str) -> (String, String) {
+        let name = Path::new(path).file_stem().and_then(|name| name.to_str()).unwrap_or(path);
+        let key = format!("shopping_bot:{}", name);
+        let version_key = format!("{}:version", key);
+        (key, version_key)
+    }
+
+    fn seen(key: &str) -> i64 {
+        VERSIONS.lock().unwrap().get(key).copied().unwrap_or(0)
+    }
+
+    pub async fn load(url: &str, path: &str) -> anyhow::Result<Option<String>> {
+        let (key, version_key) = keys(path);
+        let mut connection = connect(url).await?;
+        // In one transaction, so the version belongs to the data
+        let (data, version): (Option<String>, Option<i64>) = redis::pipe().atomic()
+            .get(&key)
+            .get(&version_key)
+            .query_async(&mut connection).await?;
+        VERSIONS.lock().unwrap().insert(key, version.unwrap_or(0));
+        Ok(data)
+    }
+
+    pub async fn outdated(url: &str, path: &str) -> anyhow::Result<bool> {
+        let (key, version_key) = keys(path);
+        let current: Option<i64> = connect(url).await?.get(&version_key).await?;
+        Ok(current.unwrap_or(0) != seen(&key))
+    }
+
+    pub async fn save(url: &str, path: &str, json: &str) -> anyhow::Result<()> {
+        let (key, version_key) = keys(path);
+        let mut connection = connect(url).await?;
+        redis::cmd("WATCH").arg(&version_key).query_async::<_, ()>(&mut connection).await?;
+        let current: Option<i64> = connection.get(&version_key).await?;
+        if current.unwrap_or(0) != seen(&key) {
+            redis::cmd("UNWATCH").query_async::<_, ()>(&mut connection).await?;
+            return Err(crate::storage::Conflict(key).into());
+        }
+        // Nothing if another replica wrote since the WATCH
+        let written: Option<(i64,)> = redis::pipe().atomic()
+            .set(&key, json).ignore()
+            .incr(&version_key, 1)
+            .query_async(&mut connection).await?;
+        match written {
+            Some((version,)) => {
+                VERSIONS.lock().unwrap().insert(key, version);
+                Ok(())
+            }
+            None => Err(crate::storage::Conflict(key).into()),
+        }
+    }
+
+    pub async fn overwrite(url: &str, path: &str, json: &str) -> anyhow::Result<()> {
+        let (key, version_key) = keys(path);
+        let mut connection = connect(url).await?;
+        let (version,): (i64,) = redis::pipe().atomic()
+            .set(&key, json).ignore()
+            .incr(&version_key, 1)
+            .query_async(&mut connection).await?;
+        VERSIONS.lock().unwrap().insert(key, version);
+        Ok(())
+    }
+}
+
+lazy_static! {
+    static ref URL: Option<String> = {
+        let url = std::env::var("REDIS_URL").ok();
+        if url.is_some() && !cfg!(feature = "redis-storage") {
+            log::warn!("REDIS_URL is set, but this build lacks the redis-storage feature");
+            return None;
+        }
+        url
+    };
+}
+
+/// Whether the data is kept in Redis instead of the data file.
+pub fn configured() -> bool {
+    URL.is_some() && shadow::backend() != Some(shadow::Backend::Redis)
+}
+
+/// Whether Redis can be used, to keep the data or as the shadow storage.
+pub fn available() -> bool {
+    URL.is_some()
+}
+
+#[cfg(feature = "redis-storage")]
+fn url() -> anyhow::Result<&'static str> {
+    URL.as_deref().ok_or_else(|| anyhow::anyhow!("REDIS_URL isn't set"))
+}
+
+/// The data stored for the data file at `path`, `None` if there is none yet.
+pub async fn load(path: &str) -> anyhow::Result<Option<String>> {
+    #[cfg(feature = "redis-storage")]
+    return enabled::load(url()?, path).await;
+    #[cfg(not(feature = "redis-storage"))]
+    {
+        let _ = path;
+        anyhow::bail!("This build lacks the redis-storage feature")
+    }
+}
+
+/// Whether another replica wrote the data since this one loaded or wrote it.
+pub async fn outdated(path: &str) -> anyhow::Result<bool> {
+    #[cfg(feature = "redis-storage")]
+    return enabled::outdated(url()?, path).await;
+    #[cfg(not(feature = "redis-storage"))]
+    {
+        let _ = path;
+        anyhow::bail!("This build lacks the redis-storage feature")
+    }
+}
+
+/// Writes the data no matter who wrote it before, for the shadow storage.
+pub async fn overwrite(path: &str, json: &str) -> anyhow::Result<()> {
+    #[cfg(feature = "redis-storage")]
+    return enabled::overwrite(url()?, path, json).await;
+    #[cfg(not(feature = "redis-storage"))]
+    {
+        let _ = (path, json);
+        anyhow::bail!("This build lacks the redis-storage feature")
+    }
+}
+
+/// Fails without writing if another replica wrote the data meanwhile.
+pub async fn save(path: &str, json: &str) -> anyhow::Result<()> {
+    #[cfg(feature = "redis-storage")]
+    return enabled::save(url()?, path, json).await;
+    #[cfg(not(feature = "redis-storage"))]
+    {
+        let _ = (path, json);
+        anyhow::bail!("This build lacks the redis-storage feature")
+    }
+}
@@ -0,0 +1,264 @@
+//! HTML building blocks for the list message, which is sent with
+//! `ParseMode::Html`. Everything users typed is escaped here, so names like
+//! "<Kekse> & Milch" can't break the message. `*` and `_` need no escaping in HTML.
+//!
+//! Chats with `/accessible on` get everything without emoji and with buttons
+//! labeled in words, for screen readers.
+//!
+//! Names in Hebrew or Arabic are isolated, so quantities, emoji and the text
+//! around them stay in place. A list that is mostly right-to-left is shown
+//! right-aligned with its buttons mirrored, see `right_to_left`.
+
+use serde::{Deserialize, Serialize};
+use teloxide::types::{InlineKeyboardButtonKind, InlineKeyboardMarkup};
+use teloxide::utils::html;
+
+use crate::callback::Action;
+
+/// Hebrew, Arabic and the scripts written like them.
+fn is_rtl_char(c: char) -> bool {
+    matches!(c as u32, 0x0590..=0x08FF | 0xFB1D..=0xFDFF | 0xFE70..=0xFEFF)
+}
+
+/// Whether the text starts right-to-left, judged by its first letter.
+pub fn is_rtl(text: &str) -> bool {
+    text.chars().find(|c| c.is_alphabetic()).map(is_rtl_char) == Some(true)
+}
+
+/// Text with right-to-left letters in an isolate, so it can't reorder what is around it.
+fn isolate(text: String) -> String {
+    if text.chars().any(is_rtl_char) {
+        format!("\u{2068}{}\u{2069}", text)
+    } else {
+        text
+    }
+}
+
+/// A section header.
+pub fn header(name: &str) -> String {
+    html::bold(&isolate(html::escape(name)))
+}
+
+/// An item line, struck through once it is checked off.
+pub fn item(text: &str, done: bool) -> String {
+    let text = isolate(html::escape(text));
+    if done {
+        html::strike(&text)
+    } else {
+        text
+    }
+}
+
+/// Additional information next to an item or below the list.
+pub fn note(text: &str) -> String {
+    html::italic(&isolate(html::escape(text)))
+}
+
+/// Mention that notifies the user, even if they don't have a username.
+pub fn mention(id: i64, first_name: &str, username: Option<&str>) -> String {
+    match username {
+        Some(username) => format!("@{}", html::escape(username)),
+        None => html::link(&format!("tg://user?id={}", id), first_name),
+    }
+}
+
+/// How the items of the list are shown, set with `/style`: one line per item,
+/// a single line per section for small phones, everything known about an item
+/// for a tablet in the kitchen, or without the emoji.
+#[derive(Serialize, Deserialize, Clone, Copy, PartialEq, Default)]
+pub enum Style {
+    #[default]
+    Normal,
+    Compact,
+    Verbose,
+    Plain,
+    /// For screen readers, used in chats with `/accessible on` whatever the style is.
+    Accessible,
+}
+
+/// What is known about an item, rendered according to the `Style`.
+pub struct ItemLine {
+    /// Quantity and name.
+    pub label: String,
+    /// The label with assignee, price and due date.
+    pub details: String,
+    pub done: bool,
+    /// Doesn't fit the diet of the chat.
+    pub warning: bool,
+    /// Whether seasonal produce is in season.
+    pub in_season: Option<bool>,
+    /// Hints with their emoji, like a cheaper store or an offer.
+    pub notes: Vec<(&'static str, String)>,
+    /// Only shown verbosely, like the recipe the item came from.
+    pub extra: Vec<String>,
+}
+
+impl Style {
+    pub fn parse(text: &str) -> Option<Self> {
+        Some(match text.to_lowercase().as_str() {
+            "normal" => Style::Normal,
+            "compact" => Style::Compact,
+            "verbose" => Style::Verbose,
+            "plain" => Style::Plain,
+            _ => return None,
+        })
+    }
+
+    pub fn describe(&self) -> &'static str {
+        match self {
+            Style::Normal => "Ein Eintrag pro Zeile.",
+            Style::Compact => "Alle Einträge eines Abschnitts in einer Zeile.",
+            Style::Verbose => "Ein Eintrag pro Zeile mit allen Hinweisen darunter.",
+            Style::Plain => "Ein Eintrag pro Zeile, ohne Emoji.",
+            Style::Accessible => "Ein Eintrag pro Zeile, ohne Emoji und mit offen oder erledigt davor.",
+        }
+    }
+
+    /// The items of one section, each line starting with a line break.
+    pub fn section(&self, lines: &[ItemLine]) -> String {
+        if lines.is_empty() {
+            return String::new();
+        }
+        if *self == Style::Compact {
+            let items: Vec<String> = lines.iter()
+                .map(|line| format!("{}{}", if line.warning { "⚠️ " } else { "" }, item(&line.label, line.done)))
+                .collect();
+            return format!("\n{}", items.join(", "));
+        }
+        let mut text = String::new();
+        for line in lines {
+            text.push_str(&self.line(line));
+        }
+        text
+    }
+
+    fn line(&self, line: &ItemLine) -> String {
+        let plain = matches!(self, Style::Plain | Style::Accessible);
+        let warning = match (line.warning, plain) {
+            (false, _) => "",
+            (true, false) => "⚠️ ",
+            (true, true) => "! ",
+        };
+        let mut text = match self {
+            // Strikethrough isn't read out
+            Style::Accessible => format!(
+                "\n - {}: {}{}",
+                if line.done { "erledigt" } else { "offen" },
+                if line.warning { "passt nicht zur Ernährung, " } else { "" },
+                html::escape(&line.details),
+            ),
+            _ => format!("\n - {}{}", warning, item(&line.details, line.done)),
+        };
+        match (line.in_season, plain) {
+            (Some(true), false) => text.push_str(" 🌱"),
+            (Some(false), false) => text.push_str(" ❄️"),
+            (Some(true), true) => text.push_str(" (Saison)"),
+            (Some(false), true) => text.push_str(" (keine Saison)"),
+            (None, _) => {}
+        }
+        for (emoji, hint) in line.notes.iter() {
+            match self {
+                Style::Verbose => text.push_str(&format!("\n    {} {}", emoji, note(hint))),
+                Style::Plain | Style::Accessible => text.push_str(&format!(" {}", note(&format!("({})", hint)))),
+                _ => text.push_str(&format!(" {} {}", emoji, note(hint))),
+            }
+        }
+        if *self == Style::Verbose {
+            for hint in line.extra.iter() {
+                text.push_str(&format!("\n    {}", note(hint)));
+            }
+        }
+        text
+    }
+}
+
+/// Every line right-aligned, and the buttons of each row in reverse, the arrows of the pages too.
+pub fn right_to_left(text: &str, markup: Option<InlineKeyboardMarkup>) -> (String, Option<InlineKeyboardMarkup>) {
+    let text = text.split('\n').map(|line| format!("\u{200f}{}", line)).collect::<Vec<_>>().join("\n");
+    let markup = markup.map(|mut markup| {
+        for row in markup.inline_keyboard.iter_mut() {
+            row.reverse();
+            for button in row.iter_mut() {
+                match button.text.as_str() {
+                    "◀" => button.text = "▶".to_string(),
+                    "▶" => button.text = "◀".to_string(),
+                    _ => {}
+                }
+            }
+        }
+        markup
+    });
+    (text, markup)
+}
+
+/// Whether the character is an emoji or joins one.
+pub fn is_emoji(c: char) -> bool {
+    matches!(c as u32, 0x1F000..=0x1FAFF | 0x2190..=0x21FF | 0x2300..=0x23FF | 0x2600..=0x27BF | 0x2B00..=0x2BFF | 0xFE0F | 0x200D | 0x20E3)
+}
+
+/// The text without emoji and the spaces they leave behind.
+pub fn strip_emoji(text: &str) -> String {
+    let lines: Vec<String> = text.split('\n')
+        .map(|line| {
+            let line: String = line.chars().filter(|c| !is_emoji(*c)).collect();
+            let indent = line.len() - line.trim_start().len();
+            format!("{}{}", &line[..indent], line.split(' ').filter(|word| !word.is_empty()).collect::<Vec<_>>().join(" "))
+        })
+        .collect();
+    lines.join("\n")
+}
+
+/// What a button that is nothing but an emoji does, in words.
+fn spoken_label(action: &Action, emoji: &str) -> Option<&'static str> {
+    Some(match action {
+        Action::StartRemove => "Bearbeiten",
+        Action::ListRecipes => "Rezepte",
+        Action::StartRecipe => "Neues Rezept",
+        Action::ManageRecipes => "Rezepte verwalten",
+        Action::ListTemplates => "Vorlagen",
+        Action::SaveTemplate => "Als Vorlage speichern",
+        Action::Search => "Suchen",
+        Action::ToggleView { .. } => "Erledigte zeigen oder verbergen",
+        Action::RemoveDone => "Erledigte entfernen",
+        Action::ShowMovable => "Verschieben",
+        Action::ShowBulk => "Alle Einträge",
+        Action::Increment(_) => "mehr",
+        Action::Decrement(_) => "weniger",
+        Action::Snooze(_) => "später",
+        Action::NotAvailable(_) => "gab es nicht",
+        Action::MergeTemplate(_) => "hinzufügen",
+        Action::EditRecipeInfo(_) => "Details",
+        Action::DuplicateRecipe(_) | Action::CopyTo(_) => "kopieren",
+        Action::DeleteItem(_) | Action::DeleteRecipe(_) | Action::DeleteTemplate(_) => "löschen",
+        Action::Reject(_) => "ablehnen",
+        Action::DiscardPlan | Action::DiscardImport => "verwerfen",
+        Action::Page(_) if emoji == "◀" => "vorige Seite",
+        Action::Page(_) if emoji == "▶" => "nächste Seite",
+        _ => return None,
+    })
+}
+
+/// Text and buttons for screen readers: no emoji, and buttons that were only an emoji say what they do.
+pub fn spell_out(text: &str, markup: Option<InlineKeyboardMarkup>) -> (String, Option<InlineKeyboardMarkup>) {
+    let markup = markup.map(|mut markup| {
+        for button in markup.inline_keyboard.iter_mut().flatten() {
+            let original = button.text.trim_start_matches('\u{fe0f}');
+            let label = match &button.kind {
+                InlineKeyboardButtonKind::Url(_) => "Link".to_string(),
+                _ if original.starts_with("❤ ") => format!("erledigt: {}", &original["❤ ".len()..]),
+                InlineKeyboardButtonKind::CallbackData(data) => {
+                    let stripped = strip_emoji(original).trim().to_string();
+                    match Action::decode(data).and_then(|action| spoken_label(&action, original)) {
+                        Some(spoken) if stripped.is_empty() => spoken.to_string(),
+                        _ if stripped.is_empty() => original.to_string(),
+                        _ => stripped,
+                    }
+                }
+                _ => strip_emoji(original).trim().to_string(),
+            };
+            button.text = label;
+        }
+        markup
+    });
+    (strip_emoji(text), markup)
+}
@@ -0,0 +1,344 @@
+//! `serve --local-repl`: runs the bot against a stand-in for the Bot API, so
+//! the handlers can be tried and demoed without a token. Lines on stdin become
+//! updates of a group chat and whatever the bot sends is printed:
+//!
+//! ```text
+//! msg alice Milch
+//! msg bob /recipes
+//! cb alice toggle 3
+//! ```
+//!
+//! `cb` presses the button with the callback data (`cb alice ti3`) on the
+//! newest message that has it. `toggle`, `inc`, `dec` and `delete` with the
+//! index of an item are understood as well. The data is stored under the
+//! `repl` namespace, apart from the data of the real bots.
+
+use std::collections::{BTreeMap, HashMap, VecDeque};
+use std::net::SocketAddr;
+use std::sync::Arc;
+use std::time::Duration;
+
+use arc_swap::ArcSwap;
+use chrono::Utc;
+use serde_json::{json, Map, Value};
+use teloxide::prelude::*;
+use tokio::io::AsyncBufReadExt;
+use tokio::sync::{Mutex, Notify};
+use warp::hyper::body::Bytes;
+use warp::Filter;
+
+use crate::callback::Action;
+use crate::{storage, Data, ENVIRONMENT, Instance};
+
+const NAMESPACE: &str = "repl";
+const CHAT_ID: i64 = -1000;
+const BOT_ID: i64 = 1;
+/// Longest a request for updates is held open, like Telegram does.
+const MAX_POLLING: Duration = Duration::from_secs(10);
+
+const HELP: &str = "msg <user> <text>   sends a message, e.g. msg alice Milch
+cb <user> <data>    presses a button, e.g. cb alice ti3 or cb alice toggle 3
+help                shows this
+quit                ends the session";
+
+/// What the stand-in knows of the chat.
+#[derive(Default)]
+struct Api {
+    updates: Mutex<VecDeque<Value>>,
+    arrived: Notify,
+    /// The messages in the chat by ID, as last sent or edited.
+    messages: Mutex<BTreeMap<i32, Value>>,
+    /// Names of the users in order of their first line, their ID is the position plus 100.
+    users: Mutex<Vec<String>>,
+    next_id: Mutex<i32>,
+}
+
+impl Api {
+    async fn next_id(&self) -> i32 {
+        let mut id = self.next_id.lock().await;
+        *id += 1;
+        *id
+    }
+
+    async fn user(&self, name: &str) -> Value {
+        let mut users = self.users.lock().await;
+        let position = match users.iter().position(|user| user == name) {
+            Some(position) => position,
+            None => {
+                users.push(name.to_string());
+                users.len() - 1
+            }
+        };
+        json!({ "id": 100 + position as i64, "is_bot": false, "first_name": name, "username": name })
+    }
+
+    async fn push(&self, update: Value) {
+        self.updates.lock().await.push_back(update);
+        self.arrived.notify_one();
+    }
+}
+
+pub async fn serve() {
+    teloxide::enable_logging!();
+    let api = Arc::new(Api::default());
+    let addr = start_api(api.clone());
+    let bot = Bot::new(NAMESPACE).set_api_url(format!("http://{}/", addr).parse().expect("Invalid API address"));
+    let instance = Instance {
+        namespace: NAMESPACE.to_string(),
+        data_path: ENVIRONMENT.data_path(NAMESPACE),
+        data: Mutex::new(Data::default()),
+        snapshot: ArcSwap::from_pointee(Data::default()),
+        bot_name: Mutex::new(String::new()),
+        personal: Mutex::new(HashMap::new()),
+    };
+    {
+        let mut data = instance.data.lock().await;
+        match storage::load(&instance.data_path).await {
+            Ok(Some(read_data)) => *data = read_data,
+            Ok(None) => {}
+            Err(error) => log::error!("Couldn't read {}, starting with an empty list: {:?}", instance.data_path, error),
+        }
+        storage::migrate(&mut data);
+        // Update IDs of earlier sessions would be taken as handled already
+        *api.next_id.lock().await = data.seen_updates.last_update_id().unwrap_or_default() as i32;
+        instance.snapshot.store(Arc::new(data.clone()));
+    }
+    let instance = Arc::new(instance);
+    tokio::spawn(crate::run(bot, instance.clone()));
+
+    println!("{}", HELP);
+    let mut lines = tokio::io::BufReader::new(tokio::io::stdin()).lines();
+    while let Ok(Some(line)) = lines.next_line().await {
+        match read_line(&api, &instance, line.trim()).await {
+            Ok(true) => {}
+            Ok(false) => break,
+            Err(error) => println!("! {}", error),
+        }
+    }
+}
+
+/// Turns a line into an update, `false` to end the session.
+async fn read_line(api: &Api, instance: &Instance, line: &str) -> anyhow::Result<bool> {
+    let mut words = line.splitn(3, ' ');
+    let (command, user, rest) = (words.next().unwrap_or_default(), words.next(), words.next().unwrap_or_default().trim());
+    let user = match (command, user) {
+        ("", _) => return Ok(true),
+        ("quit", _) | ("exit", _) => return Ok(false),
+        ("msg", Some(user)) | ("cb", Some(user)) if !rest.is_empty() => api.user(user).await,
+        ("help", _) => {
+            println!("{}", HELP);
+            return Ok(true);
+        }
+        _ => anyhow::bail!("Unknown line, see help"),
+    };
+    let update_id = api.next_id().await;
+    match command {
+        "msg" => {
+            let message = json!({
+                "message_id": api.next_id().await,
+                "date": Utc::now().timestamp(),
+                "chat": chat(),
+                "from": user,
+                "text": rest,
+            });
+            api.push(json!({ "update_id": update_id, "message": message })).await;
+        }
+        _ => {
+            let data = callback_data(&instance.snapshot.load(), rest)?;
+            let message = api.messages.lock().await.values().rev()
+                .find(|message| buttons(message).iter().flatten().any(|(_, button)| button == &data))
+                .cloned()
+                .ok_or_else(|| anyhow::anyhow!("No message has a button {}", data))?;
+            let query = json!({
+                "id": update_id.to_string(),
+                "from": user,
+                "message": message,
+                "chat_instance": NAMESPACE,
+                "data": data,
+            });
+            api.push(json!({ "update_id": update_id, "callback_query": query })).await;
+        }
+    }
+    Ok(true)
+}
+
+/// `toggle 3` as the callback data of the button of the item at index 3,
+/// anything else as it is.
+fn callback_data(data: &Data, text: &str) -> anyhow::Result<String> {
+    let mut words = text.split_whitespace();
+    let (name, index) = (words.next().unwrap_or_default(), words.next().and_then(|index| index.parse::<usize>().ok()));
+    let index = match (name, index) {
+        ("toggle", Some(index)) | ("inc", Some(index)) | ("dec", Some(index)) | ("delete", Some(index)) => index,
+        _ => return Ok(text.to_string()),
+    };
+    // The buttons refer to the items by ID
+    let id = data.list.items.get(index)
+        .map(|item| item.id)
+        .filter(|id| *id != 0)
+        .ok_or_else(|| anyhow::anyhow!("No item {} with a button", index))?;
+    let action = match name {
+        "toggle" => Action::ToggleItem(id),
+        "inc" => Action::Increment(id),
+        "dec" => Action::Decrement(id),
+        _ => Action::DeleteItem(id),
+    };
+    Ok(action.encode())
+}
+
+fn chat() -> Value {
+    json!({ "id": CHAT_ID, "type": "group", "title": "REPL" })
+}
+
+/// Labels and callback data of the inline buttons of a message, by row.
+fn buttons(message: &Value) -> Vec<Vec<(String, String)>> {
+    let rows = message["reply_markup"]["inline_keyboard"].as_array().cloned().unwrap_or_default();
+    rows.iter()
+        .map(|row| {
+            row.as_array().into_iter().flatten()
+                .map(|button| {
+                    let label = button["text"].as_str().unwrap_or_default().to_string();
+                    let data = button["callback_data"].as_str().or_else(|| button["url"].as_str()).unwrap_or_default();
+                    (label, data.to_string())
+                })
+                .collect()
+        })
+        .collect()
+}
+
+fn start_api(api: Arc<Api>) -> SocketAddr {
+    let api = warp::any().map(move || api.clone());
+    let route = warp::post()
+        .and(warp::path!(String / String))
+        .and(warp::header::optional::<String>("content-type"))
+        .and(warp::body::bytes())
+        .and(api)
+        .then(|_token: String, method: String, content_type: Option<String>, body: Bytes, api: Arc<Api>| async move {
+            let params = parse_params(content_type.as_deref().unwrap_or_default(), &body);
+            let result = answer(&api, &method, params).await;
+            warp::reply::json(&json!({ "ok": true, "result": result }))
+        });
+    let (addr, server) = warp::serve(route).bind_ephemeral(([127, 0, 0, 1], 0));
+    tokio::spawn(server);
+    addr
+}
+
+/// The parameters of a request, sent as JSON or, with files, as a form.
+fn parse_params(content_type: &str, body: &[u8]) -> Map<String, Value> {
+    let body = String::from_utf8_lossy(body);
+    let boundary = match content_type.split("boundary=").nth(1) {
+        Some(boundary) => format!("--{}", boundary.trim_matches('"')),
+        None => return serde_json::from_str(&body).unwrap_or_default(),
+    };
+    let mut params = Map::new();
+    for part in body.split(boundary.as_str()) {
+        let (headers, value) = match part.split_once("\r\n\r\n") {
+            Some(split) => split,
+            None => continue,
+        };
+        let name = match headers.split("name=\"").nth(1).and_then(|rest| rest.split('"').next()) {
+            Some(name) => name.to_string(),
+            None => continue,
+        };
+        let value = value.trim_end_matches("\r\n");
+        let value = if headers.contains("filename=") {
+            Value::String("(Datei)".to_string())
+        } else {
+            serde_json::from_str(value).unwrap_or_else(|_| Value::String(value.to_string()))
+        };
+        params.insert(name, value);
+    }
+    params
+}
+
+/// teloxide names the methods `SendMessage`, Telegram `sendMessage`.
+async fn answer(api: &Api, method: &str, params: Map<String, Value>) -> Value {
+    let name = method.to_lowercase();
+    match name.as_str() {
+        "getme" => json!({
+            "id": BOT_ID, "is_bot": true, "first_name": "Einkaufsliste", "username": "repl_bot",
+            "can_join_groups": true, "can_read_all_group_messages": true, "supports_inline_queries": true,
+        }),
+        "getupdates" => {
+            let timeout = params.get("timeout").and_then(Value::as_u64).map(Duration::from_secs).unwrap_or_default();
+            if api.updates.lock().await.is_empty() {
+                let _ = tokio::time::timeout(timeout.min(MAX_POLLING), api.arrived.notified()).await;
+            }
+            Value::Array(api.updates.lock().await.drain(..).collect())
+        }
+        name if name.starts_with("send") || name.starts_with("edit") => {
+            let message_id = match params.get("message_id").and_then(Value::as_i64) {
+                Some(id) => id as i32,
+                None => api.next_id().await,
+            };
+            let message = match api.messages.lock().await.get(&message_id) {
+                // Only the buttons change, the text stays
+                Some(previous) if name == "editmessagereplymarkup" => {
+                    let mut message = previous.clone();
+                    message["reply_markup"] = params.get("reply_markup").cloned().unwrap_or(Value::Null);
+                    message
+                }
+                _ => message(name, message_id, &params),
+            };
+            print_message(method, &message, &params);
+            api.messages.lock().await.insert(message_id, message.clone());
+            message
+        }
+        _ => {
+            if name == "deletemessage" {
+                if let Some(id) = params.get("message_id").and_then(Value::as_i64) {
+                    api.messages.lock().await.remove(&(id as i32));
+                }
+            }
+            if name != "answercallbackquery" || params.contains_key("text") {
+                println!("← {} {}", method, Value::Object(params));
+            }
+            Value::Bool(true)
+        }
+    }
+}
+
+/// The message Telegram would answer a request to send or edit it with.
+fn message(name: &str, message_id: i32, params: &Map<String, Value>) -> Value {
+    let mut message = json!({
+        "message_id": message_id,
+        "date": Utc::now().timestamp(),
+        "chat": chat(),
+        "from": { "id": BOT_ID, "is_bot": true, "first_name": "Einkaufsliste" },
+    });
+    let media = params.get("media").filter(|media| media.is_object());
+    let text = params.get("text")
+        .or_else(|| params.get("caption"))
+        .or_else(|| media.and_then(|media| media.get("caption")))
+        .cloned()
+        .unwrap_or_else(|| Value::String(String::new()));
+    // teloxide can't read a document without a MIME type
+    let file = json!({ "file_id": NAMESPACE, "file_unique_id": NAMESPACE, "mime_type": "application/octet-stream" });
+    if name == "sendphoto" || media.is_some() {
+        message["photo"] = json!([{ "file_id": NAMESPACE, "file_unique_id": NAMESPACE, "width": 1, "height": 1 }]);
+        message["caption"] = text;
+    } else if name == "senddocument" {
+        message["document"] = file;
+        message["caption"] = text;
+    } else {
+        message["text"] = text;
+    }
+    if let Some(markup) = params.get("reply_markup").filter(|markup| markup.get("inline_keyboard").is_some()) {
+        message["reply_markup"] = markup.clone();
+    }
+    message
+}
+
+fn print_message(method: &str, message: &Value, params: &Map<String, Value>) {
+    let text = message["text"].as_str().or_else(|| message["caption"].as_str()).unwrap_or_default();
+    println!("← {} #{}\n{}", method, message["message_id"], text);
+    for row in buttons(message) {
+        let row: Vec<String> = row.iter().map(|(label, data)| format!("[{} | {}]", label, data)).collect();
+        println!("  {}", row.join(" "));
+    }
+    // The keyboard below the message field, see `quick_keyboard`
+    let keys = params.get("reply_markup").and_then(|markup| markup["keyboard"].as_array()).cloned().unwrap_or_default();
+    for row in keys {
+        let row: Vec<&str> = row.as_array().into_iter().flatten().filter_map(|key| key["text"].as_str()).collect();
+        println!("  ⌨ {}", row.join(" | "));
+    }
+}
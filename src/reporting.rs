@@ -0,0 +1,70 @@
+//! Optional error reporting to Sentry (`SENTRY_DSN`) or a generic webhook
+//! (`ERROR_WEBHOOK_URL`), compiled in with the `error-reporting` feature.
+
+use serde_json::Value;
+
+#[cfg(feature = "error-reporting")]
+mod enabled {
+    use serde_json::{json, Value};
+
+    pub struct Guard(#[allow(dead_code)] Option<sentry::ClientInitGuard>);
+
+    pub fn init() -> Guard {
+        let sentry = std::env::var("SENTRY_DSN").ok().map(|dsn| {
+            sentry::init((dsn, sentry::ClientOptions {
+                release: sentry::release_name!(),
+                ..Default::default()
+            }))
+        });
+        if std::env::var("ERROR_WEBHOOK_URL").is_ok() {
+            let previous = std::panic::take_hook();
+            std::panic::set_hook(Box::new(move |info| {
+                let payload = json!({ "panic": info.to_string() });
+                if let Ok(handle) = tokio::runtime::Handle::try_current() {
+                    handle.spawn(send_webhook(payload));
+                }
+                previous(info);
+            }));
+        }
+        Guard(sentry)
+    }
+
+    pub async fn report(error: &anyhow::Error, context: Value) {
+        if sentry::Hub::current().client().is_some() {
+            sentry::with_scope(
+                |scope| scope.set_extra("update", context.clone()),
+                || sentry::capture_message(&format!("{:?}", error), sentry::Level::Error),
+            );
+        }
+        send_webhook(json!({ "error": format!("{:?}", error), "update": context })).await;
+    }
+
+    async fn send_webhook(mut payload: Value) {
+        let url = match std::env::var("ERROR_WEBHOOK_URL") {
+            Ok(url) => url,
+            Err(_) => return,
+        };
+        payload["version"] = json!(env!("CARGO_PKG_VERSION"));
+        if let Err(error) = reqwest::Client::new().post(url.as_str()).json(&payload).send().await {
+            log::error!("Couldn't send error report: {:?}", error);
+        }
+    }
+}
+
+#[cfg(feature = "error-reporting")]
+pub use enabled::init;
+
+#[cfg(not(feature = "error-reporting"))]
+pub struct Guard;
+
+#[cfg(not(feature = "error-reporting"))]
+pub fn init() -> Guard {
+    Guard
+}
+
+/// Logs a handler error and forwards it with the update it happened on.
+pub async fn report(error: anyhow::Error, context: Value) {
+    log::error!("{:?} while handling {}", error, context);
+    #[cfg(feature = "error-reporting")]
+    enabled::report(&error, context).await;
+}
@@ -0,0 +1,39 @@
+//! Optional weekly reset of the list, set with `/reset`. When it is due, checked
+//! items are archived as a trip and the chat is asked whether the open items
+//! are carried over to the fresh list or dropped.
+
+use chrono::{Datelike, NaiveDate, NaiveDateTime, Timelike, Weekday};
+use serde::{Deserialize, Serialize};
+
+use crate::date_phrase;
+
+#[derive(Serialize, Deserialize, Clone, Copy)]
+pub struct WeeklyReset {
+    pub weekday: Weekday,
+    /// Local hour of the list's timezone.
+    pub hour: u32,
+}
+
+impl WeeklyReset {
+    /// `/reset` arguments like `sonntag 22`, the hour defaulting to the evening.
+    pub fn parse(args: &[&str]) -> Option<Self> {
+        let weekday = date_phrase::parse_weekday(args.first()?)?;
+        let hour = match args.get(1) {
+            Some(hour) => hour.trim_end_matches('h').parse().ok().filter(|hour| *hour < 24)?,
+            None => 22,
+        };
+        Some(WeeklyReset { weekday, hour })
+    }
+
+    /// Whether the reset is due at `now`, local time, and didn't happen today yet.
+    pub fn is_due(&self, now: NaiveDateTime, last: Option<NaiveDate>) -> bool {
+        now.weekday() == self.weekday && now.hour() >= self.hour && last != Some(now.date())
+    }
+
+    pub fn describe(&self) -> String {
+        let name = date_phrase::weekday_name(self.weekday);
+        let mut chars = name.chars();
+        let name: String = chars.next().map(|first| first.to_uppercase().chain(chars).collect()).unwrap_or_default();
+        format!("Die Liste wird jeden {} um {} Uhr zurückgesetzt.", name, self.hour)
+    }
+}
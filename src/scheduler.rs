@@ -0,0 +1,538 @@
+use std::sync::Arc;
+use std::time::Duration;
+
+use chrono::{Datelike, Timelike, Utc};
+use teloxide::prelude::*;
+use teloxide::types::{ChatId, InlineKeyboardMarkup, InputFile, ParseMode};
+use teloxide::utils::html;
+
+use crate::{config, ENVIRONMENT, Instance, notifications, outbox, personal, plugins, reactions, recap, shadow, store_data, webhooks, wishes};
+use crate::callback::Action;
+use crate::outbox::SendQueued;
+use crate::quiet::HeldMessage;
+
+const TICK: Duration = Duration::from_secs(60);
+/// Hour of the night from which the messages of the list are checked, in the list's time zone.
+const MAINTENANCE_HOUR: u32 = 4;
+/// Webhook events sent per tick at most, the others wait for the next one.
+const MAX_DELIVERIES: usize = 20;
+/// Including the lookup of the target, well below a tick so deliveries don't overlap.
+const DELIVERY_TIMEOUT: Duration = Duration::from_secs(15);
+
+pub async fn run(bots: Vec<(Bot, Arc<Instance>)>) {
+    let mut interval = tokio::time::interval(TICK);
+    loop {
+        interval.tick().await;
+        for (bot, instance) in bots.iter() {
+            if let Err(error) = send_nudges(bot, instance).await {
+                log::error!("Couldn't send nudges for {:?}: {:?}", instance.namespace, error);
+            }
+            if let Err(error) = ask_for_ratings(bot, instance).await {
+                log::error!("Couldn't ask for ratings for {:?}: {:?}", instance.namespace, error);
+            }
+            if let Err(error) = remind_leftovers(bot, instance).await {
+                log::error!("Couldn't remind of leftovers for {:?}: {:?}", instance.namespace, error);
+            }
+            if let Err(error) = weekly_reset(bot, instance).await {
+                log::error!("Couldn't reset the list of {:?}: {:?}", instance.namespace, error);
+            }
+            if let Err(error) = deliver_held(bot, instance).await {
+                log::error!("Couldn't send held messages for {:?}: {:?}", instance.namespace, error);
+            }
+            if let Err(error) = expire_offers(bot, instance).await {
+                log::error!("Couldn't expire offers for {:?}: {:?}", instance.namespace, error);
+            }
+            if let Err(error) = post_recap(bot, instance).await {
+                log::error!("Couldn't post the monthly recap for {:?}: {:?}", instance.namespace, error);
+            }
+            if let Err(error) = remind_birthdays(bot, instance).await {
+                log::error!("Couldn't remind of birthdays for {:?}: {:?}", instance.namespace, error);
+            }
+            show_outdated_list(bot, instance).await;
+            tokio::spawn(deliver_webhooks(instance.clone()));
+            plugins::tick(bot, instance).await;
+            purge_archived_chats(instance).await;
+            personal::unload_idle(instance).await;
+            shadow::verify(instance).await;
+            compact_history(instance).await;
+            if let Err(error) = heal_messages(bot, instance).await {
+                log::error!("Couldn't check the messages of {:?}: {:?}", instance.namespace, error);
+            }
+        }
+    }
+}
+
+/// Edits the list once Telegram is reachable again, if changes from outside
+/// the chat couldn't be shown before, or sends it again if an edit that had
+/// to wait failed, e.g. because the message was deleted meanwhile.
+async fn show_outdated_list(bot: &Bot, instance: &Instance) {
+    {
+        let mut data = instance.data.lock().await;
+        let failed = match data.active_message {
+            Some((chat_id, message_id)) => outbox::has_failed_edit(bot, chat_id, message_id).await,
+            None => false,
+        };
+        if !data.list_outdated && !failed {
+            return;
+        }
+        data.show_outside_change(bot, if failed { "a failed delayed edit" } else { "an earlier outage" }).await;
+        if data.list_outdated {
+            return;
+        }
+        if failed {
+            log::info!("Showed the list again after a delayed edit failed for {:?}", instance.namespace);
+        } else {
+            log::info!("Showed the changes made while Telegram was unreachable for {:?}", instance.namespace);
+        }
+    }
+    store_data(instance).await;
+}
+
+/// POSTs the webhook events that are due, failed ones are tried again later.
+///
+/// Runs on its own task, so slow targets don't hold up the rest of the tick.
+/// They are all sent at once, and each is given up for this tick after
+/// `DELIVERY_TIMEOUT`.
+async fn deliver_webhooks(instance: Arc<Instance>) {
+    let now = Utc::now();
+    let due: Vec<webhooks::Delivery> = {
+        let mut data = instance.data.lock().await;
+        let mut due = Vec::new();
+        let mut later = Vec::new();
+        for delivery in data.webhook_outbox.drain(..) {
+            if delivery.next_try <= now && due.len() < MAX_DELIVERIES {
+                due.push(delivery);
+            } else {
+                later.push(delivery);
+            }
+        }
+        data.webhook_outbox = later;
+        due
+    };
+    if due.is_empty() {
+        return;
+    }
+    let sending: Vec<_> = due.into_iter()
+        .map(|delivery| tokio::spawn(async move {
+            let result = match tokio::time::timeout(DELIVERY_TIMEOUT, delivery.send()).await {
+                Ok(result) => result,
+                Err(_) => Err(anyhow::anyhow!("No answer in time")),
+            };
+            (delivery, result)
+        }))
+        .collect();
+    let mut failed = Vec::new();
+    for sent in sending {
+        let (mut delivery, result) = match sent.await {
+            Ok(sent) => sent,
+            Err(error) => {
+                log::error!("A webhook delivery failed: {:?}", error);
+                continue;
+            }
+        };
+        if let Err(error) = result {
+            if delivery.retry_later(now) {
+                log::warn!("Couldn't deliver a webhook event to {}, trying again: {:?}", delivery.url, error);
+                failed.push(delivery);
+            } else {
+                log::error!("Couldn't deliver a webhook event to {}, giving up: {:?}", delivery.url, error);
+            }
+        }
+    }
+    instance.data.lock().await.webhook_outbox.extend(failed);
+    store_data(&instance).await;
+}
+
+/// Mentions the assignee of every open item that is due soon, once per item.
+async fn send_nudges(bot: &Bot, instance: &Instance) -> anyhow::Result<()> {
+    let mut nudges = Vec::new();
+    {
+        let mut guard = instance.data.lock().await;
+        let data = &mut *guard;
+        let chat_id = match data.active_message {
+            Some((chat_id, _)) if !data.is_paused(chat_id) => chat_id,
+            _ => return Ok(()),
+        };
+        let deadline = Utc::now() + chrono::Duration::minutes(config::current().nudge_lead_minutes);
+        let users = data.users.clone();
+        let timezone = data.timezone();
+        let preferences = data.notifications.clone();
+        for item in data.list.items.iter_mut() {
+            if item.done || item.nudged || item.snoozed {
+                continue;
+            }
+            if let (Some(assignee), Some(due)) = (item.assignee, item.due) {
+                if due > deadline || !preferences.get(&assignee).cloned().unwrap_or_default().wants(notifications::Kind::Reminders) {
+                    continue;
+                }
+                if let Some(user) = users.get(&assignee) {
+                    let id = item.ensure_id(&mut data.list.next_item_id);
+                    nudges.push((chat_id, format!(
+                        "{}: {} ist bis {} fällig und noch offen.",
+                        user.mention(assignee),
+                        html::bold(&html::escape(&item.name)),
+                        due.with_timezone(&timezone).format("%H:%M"),
+                    ), id));
+                }
+                item.nudged = true;
+            }
+        }
+    }
+    if nudges.is_empty() {
+        return Ok(());
+    }
+    store_data(instance).await;
+
+    let messages = nudges.into_iter()
+        .map(|(chat_id, text, id)| HeldMessage {
+            chat_id,
+            text: ENVIRONMENT.decorate(text),
+            markup: Some(InlineKeyboardMarkup::default().append_row(vec![Action::CheckItem(id).button("✅ erledigt")])),
+        })
+        .collect();
+    send_or_hold(bot, instance, messages).await
+}
+
+/// Resets the list when the weekly reset is due, asking first whether open items are carried over.
+async fn weekly_reset(bot: &Bot, instance: &Instance) -> anyhow::Result<()> {
+    let prompt = {
+        let mut data = instance.data.lock().await;
+        let (weekly_reset, chat_id) = match (data.settings.weekly_reset, data.active_message) {
+            (Some(weekly_reset), Some((chat_id, _))) if !data.is_paused(chat_id) => (weekly_reset, chat_id),
+            _ => return Ok(()),
+        };
+        let now = Utc::now().with_timezone(&data.timezone()).naive_local();
+        if !weekly_reset.is_due(now, data.last_reset) {
+            return Ok(());
+        }
+        data.last_reset = Some(now.date());
+        if data.list.items.iter().any(|item| !item.header && !item.done) {
+            data.reset_pending = true;
+            Some((chat_id, data.mention_all(notifications::Kind::Digests)))
+        } else {
+            data.reset_list(false);
+            let ctx = UpdateWithCx { requester: bot.clone(), update: ChatId::Id(chat_id) };
+            if data.is_quiet(chat_id) {
+                // Editing the list doesn't wake anyone, sending it anew would
+                data.update_list_message(&ctx).await?;
+            } else {
+                data.update_shopping_list(&ctx).await?;
+            }
+            None
+        }
+    };
+    store_data(instance).await;
+
+    if let Some((chat_id, mentions)) = prompt {
+        let markup = InlineKeyboardMarkup::default()
+            .append_row(vec![Action::ResetKeep.button("↪️ Übernehmen"), Action::ResetDrop.button("🗑 Verwerfen")]);
+        let mut text = "Neue Woche, neue Liste. Was passiert mit den offenen Einträgen?".to_string();
+        if !mentions.is_empty() {
+            text = format!("{}\n{}", text, mentions);
+        }
+        let text = ENVIRONMENT.decorate(text);
+        send_or_hold(bot, instance, vec![HeldMessage { chat_id, text, markup: Some(markup) }]).await?;
+    }
+    Ok(())
+}
+
+/// Drops offers after their last day, with a note if an item on the list had one.
+async fn expire_offers(bot: &Bot, instance: &Instance) -> anyhow::Result<()> {
+    let mut notes = Vec::new();
+    {
+        let mut data = instance.data.lock().await;
+        let today = data.today();
+        if !data.offers.iter().any(|offer| offer.until < today) {
+            return Ok(());
+        }
+        let (expired, current): (Vec<_>, Vec<_>) = std::mem::take(&mut data.offers)
+            .into_iter()
+            .partition(|offer| offer.until < today);
+        data.offers = current;
+        if let Some((chat_id, _)) = data.active_message {
+            let tagged: Vec<String> = expired.iter()
+                .filter(|offer| data.list.items.iter().any(|item| !item.done && offer.matches(&item.name)))
+                .map(|offer| offer.name.clone())
+                .collect();
+            if !tagged.is_empty() {
+                // Only the 💰 goes away, editing the list doesn't notify anyone
+                let ctx = UpdateWithCx { requester: bot.clone(), update: ChatId::Id(chat_id) };
+                data.update_list_message(&ctx).await?;
+                if !data.is_paused(chat_id) {
+                    let text = format!("Angebot abgelaufen: {}", html::escape(&tagged.join(", ")));
+                    notes.push(HeldMessage { chat_id, text: ENVIRONMENT.decorate(text), markup: None });
+                }
+            }
+        }
+    }
+    store_data(instance).await;
+    send_or_hold(bot, instance, notes).await
+}
+
+/// Posts the recap of the previous month on the first day of a month, after quiet hours.
+async fn post_recap(bot: &Bot, instance: &Instance) -> anyhow::Result<()> {
+    let recap = {
+        let mut data = instance.data.lock().await;
+        let chat_id = match data.active_message {
+            Some((chat_id, _)) if !data.is_paused(chat_id) && !data.is_quiet(chat_id) => chat_id,
+            _ => return Ok(()),
+        };
+        let today = data.today();
+        let month = (today - chrono::Duration::days(1)).with_day(1).unwrap();
+        if today.day() != 1 || data.last_recap == Some(month) {
+            return Ok(());
+        }
+        data.last_recap = Some(month);
+        let mentions = data.mention_all(notifications::Kind::Digests);
+        recap::Recap::of_month(&data.list.history, month.year(), month.month(), data.timezone())
+            .map(|recap| {
+                let mut caption = recap.caption(&data.money());
+                if !mentions.is_empty() {
+                    caption = format!("{}\n\n{}", caption, mentions);
+                }
+                (chat_id, caption, recap)
+            })
+    };
+    store_data(instance).await;
+
+    if let Some((chat_id, caption, recap)) = recap {
+        let png = recap.render_png()?;
+        bot.send_photo(ChatId::Id(chat_id), InputFile::memory("rueckblick.png", png))
+            .caption(ENVIRONMENT.decorate(caption))
+            .parse_mode(ParseMode::Html)
+            .send_queued(bot)
+            .await?;
+    }
+    Ok(())
+}
+
+/// Announces birthdays a week ahead, with the most wanted wishes of a wish list.
+async fn remind_birthdays(bot: &Bot, instance: &Instance) -> anyhow::Result<()> {
+    let mut reminders = Vec::new();
+    {
+        let mut guard = instance.data.lock().await;
+        let data = &mut *guard;
+        let chat_id = match data.active_message {
+            Some((chat_id, _)) if !data.is_paused(chat_id) => chat_id,
+            _ => return Ok(()),
+        };
+        let today = data.today();
+        let ideas = match data.settings.wish_list {
+            true => wishes::suggestions(data.list.items.iter().filter(|item| !item.header).map(|item| (item.name.as_str(), item.priority))),
+            false => Vec::new(),
+        };
+        for birthday in data.birthdays.iter_mut() {
+            let next = birthday.next(today);
+            if next - today > chrono::Duration::days(wishes::REMIND_DAYS) || birthday.reminded == Some(next.year()) {
+                continue;
+            }
+            birthday.reminded = Some(next.year());
+            let mut text = format!("{} hat am {} Geburtstag.", html::bold(&html::escape(&birthday.name)), next.format("%d.%m."));
+            if !ideas.is_empty() {
+                text = format!("{}\nIdeen von der Wunschliste: {}", text, html::escape(&ideas.join(", ")));
+            }
+            reminders.push(HeldMessage { chat_id, text: ENVIRONMENT.decorate(text), markup: None });
+        }
+    }
+    if reminders.is_empty() {
+        return Ok(());
+    }
+    store_data(instance).await;
+    send_or_hold(bot, instance, reminders).await
+}
+
+/// Drops archived chats once their retention is over, and pauses that ended.
+async fn purge_archived_chats(instance: &Instance) {
+    let purged = {
+        let mut data = instance.data.lock().await;
+        let before = (data.archived_chats.len(), data.paused_chats.len());
+        data.archived_chats.retain(|_, archived| !archived.expired(ENVIRONMENT.archive_retention_days));
+        let today = data.today();
+        data.paused_chats.retain(|_, pause| pause.active(today));
+        (data.archived_chats.len(), data.paused_chats.len()) != before
+    };
+    if purged {
+        store_data(instance).await;
+    }
+}
+
+/// Folds purchases older than the configured retention into monthly summaries.
+async fn compact_history(instance: &Instance) {
+    let months = config::current().history_retention_months;
+    if months == 0 {
+        return;
+    }
+    let compacted = instance.data.lock().await.compact_history(months);
+    if compacted > 0 {
+        log::info!("Compacted {} history entries of {:?}", compacted, instance.namespace);
+        store_data(instance).await;
+    }
+}
+
+/// Once a night, edits the list and the menu again to what they should show.
+/// Edits that got lost are made up for, and deleted messages are sent anew.
+async fn heal_messages(bot: &Bot, instance: &Instance) -> anyhow::Result<()> {
+    {
+        let mut data = instance.data.lock().await;
+        let chat_id = match data.active_message {
+            Some((chat_id, _)) if !data.is_paused(chat_id) && !data.archived_chats.contains_key(&chat_id) => chat_id,
+            _ => return Ok(()),
+        };
+        let now = Utc::now().with_timezone(&data.timezone()).naive_local();
+        if now.hour() < MAINTENANCE_HOUR || data.last_maintenance == Some(now.date()) {
+            return Ok(());
+        }
+        data.last_maintenance = Some(now.date());
+        // Without the last rendering, nothing is skipped as unchanged
+        data.rendered = None;
+        data.menu_rendered = None;
+        let ctx = UpdateWithCx { requester: bot.clone(), update: ChatId::Id(chat_id) };
+        data.update_list_message(&ctx).await?;
+        match data.menu_view {
+            Some(view) => data.render_view(&ctx, view).await?,
+            None => data.update_shopping_list(&ctx).await?,
+        }
+        log::debug!("Checked the messages of {:?}", instance.namespace);
+    }
+    store_data(instance).await;
+    Ok(())
+}
+
+/// Reminds the chat once of every leftover on the day before it should be eaten.
+async fn remind_leftovers(bot: &Bot, instance: &Instance) -> anyhow::Result<()> {
+    let mut reminders = Vec::new();
+    {
+        let mut data = instance.data.lock().await;
+        let chat_id = match data.active_message {
+            Some((chat_id, _)) if !data.is_paused(chat_id) => chat_id,
+            _ => return Ok(()),
+        };
+        let tomorrow = data.today() + chrono::Duration::days(1);
+        let (fridge, freezer) = (data.settings.leftover_days, data.settings.freezer_days);
+        for leftover in data.leftovers.iter_mut().filter(|leftover| !leftover.reminded) {
+            let use_by = leftover.use_by(fridge, freezer);
+            if use_by <= tomorrow {
+                reminders.push((chat_id, format!(
+                    "{} sollte bis {} gegessen werden.",
+                    html::bold(&html::escape(&leftover.name)),
+                    use_by.format("%d.%m."),
+                )));
+                leftover.reminded = true;
+            }
+        }
+    }
+    if reminders.is_empty() {
+        return Ok(());
+    }
+    store_data(instance).await;
+
+    let messages = reminders.into_iter()
+        .map(|(chat_id, text)| HeldMessage { chat_id, text: ENVIRONMENT.decorate(text), markup: None })
+        .collect();
+    send_or_hold(bot, instance, messages).await
+}
+
+/// Asks the chat to rate planned recipes once their day is over.
+async fn ask_for_ratings(bot: &Bot, instance: &Instance) -> anyhow::Result<()> {
+    let mut questions: Vec<(i64, String, InlineKeyboardMarkup)> = Vec::new();
+    {
+        let mut data = instance.data.lock().await;
+        let chat_id = match data.active_message {
+            Some((chat_id, _)) if !data.is_paused(chat_id) => chat_id,
+            _ => return Ok(()),
+        };
+        let today = Utc::now().with_timezone(&data.timezone()).naive_local().date();
+        let mut plan = std::mem::take(&mut data.meal_plan);
+        // Older meals are skipped, so an upgrade doesn't ask about the whole plan at once
+        let oldest = today - chrono::Duration::days(config::current().rating_days);
+        for planned in plan.iter_mut().filter(|planned| planned.date < today && planned.date >= oldest && !planned.rating_asked) {
+            planned.rating_asked = true;
+            if let Some(markup) = data.rating_markup(&planned.meal) {
+                questions.push((chat_id, format!("Wie war {}?", html::escape(&planned.meal)), markup));
+            }
+        }
+        data.meal_plan = plan;
+    }
+    if questions.is_empty() {
+        return Ok(());
+    }
+    store_data(instance).await;
+
+    let messages = questions.into_iter()
+        .map(|(chat_id, text, markup)| HeldMessage { chat_id, text: ENVIRONMENT.decorate(text), markup: Some(markup) })
+        .collect();
+    send_or_hold(bot, instance, messages).await
+}
+
+/// Sends the messages, except those to chats in their quiet hours, which are held for `deliver_held`.
+async fn send_or_hold(bot: &Bot, instance: &Instance, messages: Vec<HeldMessage>) -> anyhow::Result<()> {
+    let (held, messages): (Vec<HeldMessage>, Vec<HeldMessage>) = {
+        let data = instance.data.lock().await;
+        messages.into_iter().partition(|message| data.is_quiet(message.chat_id))
+    };
+    if !held.is_empty() {
+        instance.data.lock().await.held_messages.extend(held);
+        store_data(instance).await;
+    }
+    send(bot, instance, messages).await
+}
+
+/// Sends the held messages of chats whose quiet hours are over.
+async fn deliver_held(bot: &Bot, instance: &Instance) -> anyhow::Result<()> {
+    let due = {
+        let mut data = instance.data.lock().await;
+        if data.held_messages.is_empty() {
+            return Ok(());
+        }
+        let (held, due): (Vec<HeldMessage>, Vec<HeldMessage>) = std::mem::take(&mut data.held_messages)
+            .into_iter()
+            .partition(|message| data.is_quiet(message.chat_id));
+        data.held_messages = held;
+        due
+    };
+    // Stored only once sent, so a restart meanwhile sends them again instead of losing them
+    send(bot, instance, due).await
+}
+
+/// Sends the messages, remembering those about an item for `reactions`.
+/// Those that can't be sent are held for the next tick.
+async fn send(bot: &Bot, instance: &Instance, messages: Vec<HeldMessage>) -> anyhow::Result<()> {
+    if messages.is_empty() {
+        return Ok(());
+    }
+    let mut item_messages = Vec::new();
+    let mut unsent = Vec::new();
+    let mut first_error = None;
+    for message in messages {
+        let chat_id = message.chat_id;
+        let mut request = bot.send_message(ChatId::Id(chat_id), message.text.clone()).parse_mode(ParseMode::Html);
+        let item = message.markup.as_ref().and_then(reactions::item_of);
+        if let Some(markup) = message.markup.clone() {
+            request = request.reply_markup(markup);
+        }
+        match request.send_queued(bot).await {
+            Ok(sent) => {
+                if let Some(item) = item {
+                    item_messages.push(reactions::ItemMessage { chat_id, message_id: sent.id, item });
+                }
+            }
+            Err(error) => {
+                log::warn!("Couldn't send a scheduled message to chat {}, trying again later: {:?}", chat_id, error);
+                first_error.get_or_insert(error);
+                unsent.push(message);
+            }
+        }
+    }
+    {
+        let mut data = instance.data.lock().await;
+        for message in item_messages {
+            reactions::remember(&mut data.item_messages, message);
+        }
+        data.held_messages.extend(unsent);
+    }
+    store_data(instance).await;
+    match first_error {
+        Some(error) => Err(error.into()),
+        None => Ok(()),
+    }
+}
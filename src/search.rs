@@ -0,0 +1,41 @@
+//! Finds items for the 🔍 filter, forgiving a typo or two.
+
+/// Edit distance between `a` and `b`, counted in characters.
+pub fn distance(a: &str, b: &str) -> usize {
+    let b: Vec<char> = b.chars().collect();
+    let mut previous: Vec<usize> = (0..=b.len()).collect();
+    for (i, a) in a.chars().enumerate() {
+        let mut current = vec![i + 1];
+        for (j, b) in b.iter().enumerate() {
+            let substitution = previous[j] + if a == *b { 0 } else { 1 };
+            current.push(substitution.min(previous[j + 1] + 1).min(current[j] + 1));
+        }
+        previous = current;
+    }
+    previous[b.len()]
+}
+
+/// Typos allowed in a word, none for short ones where everything would match.
+pub fn tolerance(word: &str) -> usize {
+    match word.chars().count() {
+        0..=3 => 0,
+        4..=7 => 1,
+        _ => 2,
+    }
+}
+
+/// Whether the item is found by the query, e.g. "milch" finds "Hafermilch" and "mlich" finds "Milch".
+pub fn matches(query: &str, name: &str) -> bool {
+    let query = query.trim().to_lowercase();
+    let name = name.to_lowercase();
+    if name.contains(&query) {
+        return true;
+    }
+    let tolerance = tolerance(&query);
+    let length = query.chars().count();
+    // Against the start of every word, so "mlich" also finds "Milchreis"
+    tolerance > 0 && name.split_whitespace().any(|word| {
+        let start: String = word.chars().take(length).collect();
+        distance(&query, &start) <= tolerance
+    })
+}
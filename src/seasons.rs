@@ -0,0 +1,79 @@
+//! Seasonal produce calendar for `/seasonal` and the 🌱/❄️ hints.
+//!
+//! The region is set with `SEASON_REGION`, currently `de` (default) and `au`
+//! for the southern hemisphere.
+
+use std::env;
+
+/// Produce with the months (1 to 12) it is in season in Germany.
+const CALENDAR: &[(&str, &[u32])] = &[
+    ("Spargel", &[4, 5, 6]),
+    ("Erdbeeren", &[5, 6, 7]),
+    ("Kirschen", &[6, 7, 8]),
+    ("Tomaten", &[7, 8, 9, 10]),
+    ("Zucchini", &[6, 7, 8, 9]),
+    ("Gurken", &[6, 7, 8, 9]),
+    ("Paprika", &[7, 8, 9, 10]),
+    ("Bohnen", &[7, 8, 9]),
+    ("Mais", &[8, 9, 10]),
+    ("Pflaumen", &[8, 9]),
+    ("Äpfel", &[8, 9, 10, 11]),
+    ("Birnen", &[8, 9, 10, 11]),
+    ("Kürbis", &[9, 10, 11, 12]),
+    ("Rosenkohl", &[10, 11, 12, 1, 2]),
+    ("Grünkohl", &[11, 12, 1, 2]),
+    ("Rotkohl", &[9, 10, 11, 12, 1, 2]),
+    ("Feldsalat", &[10, 11, 12, 1, 2, 3]),
+    // Before "Lauch", which it contains
+    ("Bärlauch", &[3, 4, 5]),
+    ("Lauch", &[8, 9, 10, 11, 12, 1, 2, 3]),
+    ("Pastinaken", &[10, 11, 12, 1, 2, 3]),
+    ("Rhabarber", &[4, 5, 6]),
+    ("Radieschen", &[4, 5, 6, 7, 8, 9]),
+    ("Blumenkohl", &[6, 7, 8, 9, 10]),
+    ("Brokkoli", &[6, 7, 8, 9, 10]),
+    ("Spinat", &[3, 4, 5, 9, 10, 11]),
+    ("Heidelbeeren", &[7, 8, 9]),
+];
+
+/// Months are shifted by half a year on the southern hemisphere.
+fn region_offset() -> u32 {
+    match env::var("SEASON_REGION").as_deref() {
+        Ok("au") => 6,
+        _ => 0,
+    }
+}
+
+fn in_season(months: &[u32], month: u32) -> bool {
+    let month = (month + 12 - region_offset() - 1) % 12 + 1;
+    months.contains(&month)
+}
+
+/// Produce that is in season in `month`, with its index for `produce`.
+pub fn in_season_now(month: u32) -> Vec<(usize, &'static str)> {
+    CALENDAR.iter()
+        .enumerate()
+        .filter(|(_, (_, months))| in_season(months, month))
+        .map(|(i, (name, _))| (i, *name))
+        .collect()
+}
+
+/// Everything in the calendar.
+pub fn produce_names() -> impl Iterator<Item = &'static str> {
+    CALENDAR.iter().map(|(name, _)| *name)
+}
+
+pub fn produce(index: usize) -> Option<&'static str> {
+    CALENDAR.get(index).map(|(name, _)| *name)
+}
+
+/// 🌱 for produce in season in `month`, ❄️ for produce that isn't, `None` for anything not in the calendar.
+pub fn hint(name: &str, month: u32) -> Option<&'static str> {
+    let name = name.to_lowercase();
+    let (_, months) = CALENDAR.iter().find(|(produce, _)| {
+        let produce = produce.to_lowercase();
+        // "Tomaten" should also cover "Tomate" and "Cherrytomaten"
+        name.contains(&produce) || name.contains(produce.trim_end_matches('n'))
+    })?;
+    Some(if in_season(months, month) { "🌱" } else { "❄️" })
+}
@@ -0,0 +1,244 @@
+//! Moving the data between the data file, Redis and Postgres without stopping
+//! the bot.
+//!
+//! `STORAGE_SHADOW=redis` or `STORAGE_SHADOW=postgres` keeps the storage in
+//! charge and writes every change to Redis (`REDIS_URL`) or Postgres
+//! (`DATABASE_URL`) as well. `STORAGE_SHADOW=json` does the same with the data
+//! file, while Redis or Postgres is in charge. At startup every list is copied over, then once an
+//! hour the copy of the shared list is compared with what was written. After
+//! `STORAGE_SHADOW_DAYS` (7 by default), `migrate --cutover` compares every
+//! list once more, and if they match the bot can be restarted without
+//! `STORAGE_SHADOW` on the new storage alone.
+//!
+//! The data file as the shadow is meant for a single replica, the others
+//! wouldn't see it.
+
+use std::collections::HashMap;
+use std::sync::Mutex;
+use std::time::{Duration, Instant};
+
+use chrono::{DateTime, Utc};
+use lazy_static::lazy_static;
+use serde::{Deserialize, Serialize};
+
+use crate::{personal, postgres, redis, storage, Instance};
+
+const VERIFY_INTERVAL: Duration = Duration::from_secs(60 * 60);
+
+#[derive(Serialize, Deserialize, Clone, Copy, PartialEq, Eq, Debug)]
+#[serde(rename_all = "lowercase")]
+pub enum Backend {
+    Json,
+    Redis,
+    Postgres,
+}
+
+impl std::fmt::Display for Backend {
+    fn fmt(&self, f: &mut std::fmt::Formatter<'_>) -> std::fmt::Result {
+        match self {
+            Backend::Json => write!(f, "the data file"),
+            Backend::Redis => write!(f, "Redis"),
+            Backend::Postgres => write!(f, "Postgres"),
+        }
+    }
+}
+
+/// Progress of a move, in `<data file>.shadow`.
+#[derive(Serialize, Deserialize)]
+struct State {
+    backend: Backend,
+    /// When the lists were first copied to the shadow.
+    since: DateTime<Utc>,
+    #[serde(default, skip_serializing_if = "Option::is_none")]
+    verified: Option<DateTime<Utc>>,
+    /// Fields that differed at the last comparison.
+    #[serde(default, skip_serializing_if = "Vec::is_empty")]
+    differences: Vec<String>,
+    #[serde(default, skip_serializing_if = "Option::is_none")]
+    cut_over: Option<DateTime<Utc>>,
+}
+
+lazy_static! {
+    static ref BACKEND: Option<Backend> = {
+        let backend = match std::env::var("STORAGE_SHADOW").as_deref() {
+            Ok("redis") => Backend::Redis,
+            Ok("postgres") => Backend::Postgres,
+            Ok("json") => Backend::Json,
+            Ok("") | Err(_) => return None,
+            Ok(other) => {
+                log::error!("STORAGE_SHADOW has to be redis, postgres or json, not {:?}", other);
+                return None;
+            }
+        };
+        let available = match backend {
+            Backend::Redis => redis::available(),
+            Backend::Postgres => postgres::available(),
+            // Only the data file can be in charge otherwise
+            Backend::Json => redis::available() || postgres::available(),
+        };
+        if !available {
+            log::error!("STORAGE_SHADOW is {}, but it isn't set up, see REDIS_URL and DATABASE_URL", backend);
+            return None;
+        }
+        Some(backend)
+    };
+    /// When the shared list of each bot was compared last, by data file.
+    static ref VERIFIED: Mutex<HashMap<String, Instant>> = Mutex::new(HashMap::new());
+}
+
+/// The storage that is to take over, `None` if no move is going on.
+pub fn backend() -> Option<Backend> {
+    *BACKEND
+}
+
+/// The longest `STORAGE_SHADOW_DAYS` can be.
+const MAX_DAYS: i64 = 3650;
+
+fn days() -> i64 {
+    std::env::var("STORAGE_SHADOW_DAYS").ok()
+        .and_then(|days| days.parse::<i64>().ok())
+        .map(|days| days.clamp(0, MAX_DAYS))
+        .unwrap_or(7)
+}
+
+/// When the lists were written to the shadow long enough to cut over.
+fn ready(state: &State) -> anyhow::Result<DateTime<Utc>> {
+    state.since.checked_add_signed(chrono::Duration::days(days()))
+        .ok_or_else(|| anyhow::anyhow!("{} days after {} is out of range", days(), state.since))
+}
+
+fn state_path(path: &str) -> String {
+    format!("{}.shadow", path)
+}
+
+async fn read_state(path: &str) -> anyhow::Result<Option<State>> {
+    match tokio::fs::read_to_string(state_path(path)).await {
+        Ok(string) => Ok(Some(serde_json::from_str(&string)?)),
+        Err(error) if error.kind() == std::io::ErrorKind::NotFound => Ok(None),
+        Err(error) => Err(error.into()),
+    }
+}
+
+async fn write_state(path: &str, state: &State) -> anyhow::Result<()> {
+    tokio::fs::write(state_path(path), serde_json::to_string_pretty(state)?).await?;
+    Ok(())
+}
+
+/// Copies every list of the bot to the shadow, so it starts out complete.
+pub async fn start(instance: &Instance) -> anyhow::Result<()> {
+    let backend = match backend() {
+        Some(backend) => backend,
+        None => return Ok(()),
+    };
+    let data = instance.data.lock().await;
+    storage::save_shadow(&instance.data_path, &data).await?;
+    // Personal lists are only written once they change, so the others are copied here
+    for user_id in data.personal_lists.iter() {
+        let path = personal::data_path(&instance.namespace, *user_id);
+        match storage::load(&path).await? {
+            Some(list) => storage::save_shadow(&path, &list).await?,
+            // Only possible with Redis or Postgres in charge, files left behind aren't what the bot uses
+            None if storage::on_disk(&path) => log::warn!("{} is on disk, but not in the storage in charge, not copying it", path),
+            None => {}
+        }
+        storage::forget(&path);
+    }
+    let state = match read_state(&instance.data_path).await? {
+        Some(state) if state.backend == backend => state,
+        _ => {
+            let state = State { backend, since: Utc::now(), verified: None, differences: Vec::new(), cut_over: None };
+            write_state(&instance.data_path, &state).await?;
+            state
+        }
+    };
+    log::info!(
+        "Writing the data of {:?} to {} as well since {}, cutover possible from {}",
+        instance.namespace, backend, state.since, ready(&state)?,
+    );
+    Ok(())
+}
+
+/// Compares the shadow with the shared list once an hour.
+pub async fn verify(instance: &Instance) {
+    if backend().is_none() {
+        return;
+    }
+    let due = VERIFIED.lock().unwrap().get(&instance.data_path).is_none_or(|at| at.elapsed() >= VERIFY_INTERVAL);
+    if !due {
+        return;
+    }
+    VERIFIED.lock().unwrap().insert(instance.data_path.clone(), Instant::now());
+    let result = {
+        // Nothing is written meanwhile
+        let _data = instance.data.lock().await;
+        storage::compare_shadow(&instance.data_path).await
+    };
+    let differences = match result {
+        Ok(Some(differences)) => differences,
+        Ok(None) => return,
+        Err(error) => {
+            log::error!("Couldn't compare the shadow copy of {:?}: {:?}", instance.namespace, error);
+            return;
+        }
+    };
+    if differences.is_empty() {
+        log::debug!("Shadow copy of {:?} matches", instance.namespace);
+    } else {
+        log::error!("Shadow copy of {:?} differs in {}", instance.namespace, differences.join(", "));
+    }
+    let result = match read_state(&instance.data_path).await {
+        Ok(Some(mut state)) => {
+            state.verified = Some(Utc::now());
+            state.differences = differences;
+            write_state(&instance.data_path, &state).await
+        }
+        Ok(None) => Ok(()),
+        Err(error) => Err(error),
+    };
+    if let Err(error) = result {
+        log::error!("Couldn't record the comparison of {:?}: {:?}", instance.namespace, error);
+    }
+}
+
+/// Checks that the shadow holds every list of the bot as it is, before it takes over.
+pub async fn cutover(path: &str, namespace: &str) -> anyhow::Result<()> {
+    let backend = backend().ok_or_else(|| anyhow::anyhow!("STORAGE_SHADOW isn't set, there is nothing to cut over to"))?;
+    let mut state = match read_state(path).await? {
+        Some(state) if state.backend == backend => state,
+        _ => anyhow::bail!("The bot hasn't started writing to {} yet", backend),
+    };
+    let ready = ready(&state)?;
+    if Utc::now() < ready {
+        anyhow::bail!("Writing to {} since {}, cutover is possible from {}", backend, state.since, ready);
+    }
+    let data = storage::load(path).await?
+        .ok_or_else(|| anyhow::anyhow!("{} does not exist", path))?;
+    let mut paths = vec![path.to_string()];
+    paths.extend(data.personal_lists.iter().map(|user_id| personal::data_path(namespace, *user_id)));
+    let mut problems = Vec::new();
+    for path in paths.iter() {
+        match (storage::load(path).await?, storage::load_shadow(path).await?) {
+            (Some(ours), Some(theirs)) => {
+                let differences = storage::differences(&ours, &theirs)?;
+                if !differences.is_empty() {
+                    problems.push(format!("{} differs in {}", path, differences.join(", ")));
+                }
+            }
+            (Some(_), None) => problems.push(format!("{} is missing in {}", path, backend)),
+            // Only possible with Redis or Postgres in charge, the files would be lost after the cutover
+            (None, _) if storage::on_disk(path) => problems.push(format!("{} is on disk, but not in the storage in charge", path)),
+            (None, _) => {}
+        }
+    }
+    if !problems.is_empty() {
+        anyhow::bail!("Not cutting over, {} of {} list(s) don't match:\n{}", problems.len(), paths.len(), problems.join("\n"));
+    }
+    state.cut_over = Some(Utc::now());
+    write_state(path, &state).await?;
+    println!("All {} list(s) match in {}", paths.len(), backend);
+    match backend {
+        Backend::Redis | Backend::Postgres => println!("Restart the bot without STORAGE_SHADOW to keep the data in {}", backend),
+        Backend::Json => println!("Restart the bot without STORAGE_SHADOW, REDIS_URL and DATABASE_URL to keep the data in the data file"),
+    }
+    Ok(())
+}
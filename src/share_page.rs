@@ -0,0 +1,53 @@
+//! Read-only web page of the list at `/list/<token>`, with the token from
+//! `/sharepage`. It reloads itself, so it can stay open on a smart display or
+//! be sent to someone who does the shopping without being in the chat.
+
+use teloxide::utils::html;
+
+use crate::Data;
+
+/// Seconds between reloads.
+const REFRESH_SECONDS: u32 = 30;
+
+pub fn to_html(data: &Data) -> String {
+    let title = match data.store() {
+        Some(store) => format!("Einkaufsliste {}", store),
+        None => "Einkaufsliste".to_string(),
+    };
+    let mut body = String::new();
+    let mut in_list = false;
+    for item in data.list.items.iter() {
+        if item.header {
+            if in_list {
+                body.push_str("</ul>\n");
+                in_list = false;
+            }
+            body.push_str(&format!("<h2>{}</h2>\n", html::escape(&item.name)));
+            continue;
+        }
+        if !in_list {
+            body.push_str("<ul>\n");
+            in_list = true;
+        }
+        let quantity = if item.quantity > 1 { format!("{}× ", item.quantity) } else { String::new() };
+        let class = if item.done { " class=\"done\"" } else { "" };
+        body.push_str(&format!("<li{}>{}{}</li>\n", class, quantity, html::escape(&item.name)));
+    }
+    if in_list {
+        body.push_str("</ul>\n");
+    }
+    if data.list.items.iter().all(|item| item.header) {
+        body.push_str("<p>Die Liste ist leer.</p>\n");
+    }
+    format!(
+        "<!DOCTYPE html>\n<html lang=\"de\">\n<head>\n<meta charset=\"utf-8\">\n\
+         <meta name=\"viewport\" content=\"width=device-width, initial-scale=1\">\n\
+         <meta http-equiv=\"refresh\" content=\"{refresh}\">\n<title>{title}</title>\n\
+         <style>body {{ font-family: sans-serif; font-size: 1.4em; margin: 1em; }} \
+         li {{ margin: 0.3em 0; }} .done {{ text-decoration: line-through; color: #888; }}</style>\n\
+         </head>\n<body>\n<h1>{title}</h1>\n{body}</body>\n</html>\n",
+        refresh = REFRESH_SECONDS,
+        title = html::escape(&title),
+        body = body,
+    )
+}
@@ -0,0 +1,198 @@
+//! Optional mirror of the list and the purchase history into a Google Sheet,
+//! compiled in with the `google-sheets` feature.
+//!
+//! Needs `GOOGLE_SHEET_ID` and `GOOGLE_SERVICE_ACCOUNT_FILE` (the JSON key of a
+//! service account the sheet is shared with). The sheet needs the tabs `Liste`
+//! and `Verlauf`, prefixed with the bot's namespace when running several bots.
+
+use std::sync::Arc;
+
+use crate::Instance;
+
+#[cfg(feature = "google-sheets")]
+mod enabled {
+    use std::sync::Arc;
+    use std::time::Duration;
+
+    use chrono::Utc;
+    use jsonwebtoken::{Algorithm, EncodingKey, Header};
+    use serde::{Deserialize, Serialize};
+    use serde_json::{json, Value};
+
+    use crate::{Data, Instance};
+
+    const SYNC_INTERVAL: Duration = Duration::from_secs(60);
+    const SCOPE: &str = "https://www.googleapis.com/auth/spreadsheets";
+    const API_URL: &str = "https://sheets.googleapis.com/v4/spreadsheets";
+
+    #[derive(Deserialize)]
+    struct ServiceAccount {
+        client_email: String,
+        private_key: String,
+        token_uri: String,
+    }
+
+    #[derive(Serialize)]
+    struct Claims<'a> {
+        iss: &'a str,
+        scope: &'a str,
+        aud: &'a str,
+        iat: i64,
+        exp: i64,
+    }
+
+    struct Sheet {
+        id: String,
+        account: ServiceAccount,
+        list_tab: String,
+        history_tab: String,
+    }
+
+    /// Syncs the sheet once a minute if the list or the history changed.
+    pub async fn run(instance: Arc<Instance>, sheet_id: String, key_file: String) {
+        let account = match load_account(&key_file).await {
+            Ok(account) => account,
+            Err(error) => {
+                log::error!("Couldn't read service account {}: {:?}", key_file, error);
+                return;
+            }
+        };
+        let prefix = if instance.namespace.is_empty() { String::new() } else { format!("{} ", instance.namespace) };
+        let sheet = Sheet {
+            id: sheet_id,
+            account,
+            list_tab: format!("{}Liste", prefix),
+            history_tab: format!("{}Verlauf", prefix),
+        };
+        let mut last_synced = None;
+        let mut interval = tokio::time::interval(SYNC_INTERVAL);
+        loop {
+            interval.tick().await;
+            let data = instance.snapshot.load_full();
+            let fingerprint = serde_json::to_string(&(&data.list.items, &data.list.history)).ok();
+            if fingerprint == last_synced {
+                continue;
+            }
+            match sheet.sync(&data).await {
+                Ok(()) => last_synced = fingerprint,
+                Err(error) => log::warn!("Google Sheets sync of {:?} failed: {:?}", instance.namespace, error),
+            }
+        }
+    }
+
+    async fn load_account(path: &str) -> anyhow::Result<ServiceAccount> {
+        let json = tokio::fs::read_to_string(path).await?;
+        Ok(serde_json::from_str(&json)?)
+    }
+
+    impl Sheet {
+        async fn access_token(&self, client: &reqwest::Client) -> anyhow::Result<String> {
+            let now = Utc::now().timestamp();
+            let claims = Claims {
+                iss: &self.account.client_email,
+                scope: SCOPE,
+                aud: &self.account.token_uri,
+                iat: now,
+                exp: now + 3600,
+            };
+            let key = EncodingKey::from_rsa_pem(self.account.private_key.as_bytes())?;
+            let assertion = jsonwebtoken::encode(&Header::new(Algorithm::RS256), &claims, &key)?;
+            let response: Value = client.post(self.account.token_uri.as_str())
+                .form(&[("grant_type", "urn:ietf:params:oauth:grant-type:jwt-bearer"), ("assertion", assertion.as_str())])
+                .send()
+                .await?
+                .error_for_status()?
+                .json()
+                .await?;
+            response.get("access_token")
+                .and_then(|token| token.as_str())
+                .map(|token| token.to_string())
+                .ok_or_else(|| anyhow::anyhow!("No access token in {}", response))
+        }
+
+        /// Replaces the list tab and appends the purchases the history tab doesn't have yet.
+        async fn sync(&self, data: &Data) -> anyhow::Result<()> {
+            let client = reqwest::Client::new();
+            let token = self.access_token(&client).await?;
+            let url = format!("{}/{}/values", API_URL, self.id);
+            let money = data.money();
+
+            let mut list = vec![json!(["Artikel", "Menge", "Erledigt", "Zuständig", "Preis"])];
+            for item in data.list.items.iter().filter(|item| !item.header) {
+                let assignee = item.assignee.and_then(|id| data.users.get(&id)).map(|user| user.name());
+                list.push(json!([
+                    item.name,
+                    item.quantity,
+                    item.done,
+                    assignee.unwrap_or_default(),
+                    item.price.map(|cents| money.format(cents)).unwrap_or_default(),
+                ]));
+            }
+            client.post(format!("{}/{}:clear", url, self.list_tab).as_str())
+                .bearer_auth(&token)
+                .send()
+                .await?
+                .error_for_status()?;
+            client.put(format!("{}/{}!A1", url, self.list_tab).as_str())
+                .bearer_auth(&token)
+                .query(&[("valueInputOption", "RAW")])
+                .json(&json!({ "values": list }))
+                .send()
+                .await?
+                .error_for_status()?;
+
+            let existing: Value = client.get(format!("{}/{}!A:A", url, self.history_tab).as_str())
+                .bearer_auth(&token)
+                .send()
+                .await?
+                .error_for_status()?
+                .json()
+                .await?;
+            let rows = existing.get("values").and_then(|values| values.as_array()).map(|values| values.len()).unwrap_or(0);
+            let timezone = data.timezone();
+            let mut new_rows = Vec::new();
+            if rows == 0 {
+                new_rows.push(json!(["Datum", "Artikel", "Gekauft von", "Preis", "Kategorie", "Gelöscht"]));
+            }
+            for purchase in data.list.history.iter().skip(rows.saturating_sub(1)) {
+                let buyer = purchase.bought_by.and_then(|id| data.users.get(&id)).map(|user| user.name());
+                new_rows.push(json!([
+                    purchase.at.with_timezone(&timezone).format("%d.%m.%Y %H:%M").to_string(),
+                    purchase.name,
+                    buyer.unwrap_or_default(),
+                    purchase.price.map(|cents| money.format(cents)).unwrap_or_default(),
+                    purchase.category.map(|category| category.name()).unwrap_or_default(),
+                    if purchase.bought() { "" } else { "ja" },
+                ]));
+            }
+            if new_rows.is_empty() {
+                return Ok(());
+            }
+            client.post(format!("{}/{}!A1:append", url, self.history_tab).as_str())
+                .bearer_auth(&token)
+                .query(&[("valueInputOption", "RAW"), ("insertDataOption", "INSERT_ROWS")])
+                .json(&json!({ "values": new_rows }))
+                .send()
+                .await?
+                .error_for_status()?;
+            Ok(())
+        }
+    }
+}
+
+/// Starts the sync for every bot if a sheet is configured.
+pub fn spawn(instances: &[Arc<Instance>]) {
+    let (sheet_id, key_file) = match (std::env::var("GOOGLE_SHEET_ID"), std::env::var("GOOGLE_SERVICE_ACCOUNT_FILE")) {
+        (Ok(sheet_id), Ok(key_file)) => (sheet_id, key_file),
+        _ => return,
+    };
+    #[cfg(feature = "google-sheets")]
+    for instance in instances {
+        tokio::spawn(enabled::run(instance.clone(), sheet_id.clone(), key_file.clone()));
+    }
+    #[cfg(not(feature = "google-sheets"))]
+    {
+        let _ = (instances, sheet_id, key_file);
+        log::warn!("GOOGLE_SHEET_ID is set, but this build lacks the google-sheets feature");
+    }
+}
@@ -0,0 +1,33 @@
+//! Emoji that stand for an item in a chat: with `/shortcut 🍺 Bier Kasten`,
+//! sending just 🍺 puts Bier Kasten on the list. `/shortcut` alone shows the
+//! shortcuts with buttons to delete them.
+
+use std::collections::HashMap;
+
+use crate::render;
+
+/// Longest shortcut, a family emoji is made of seven characters.
+const MAX_KEY_LENGTH: usize = 8;
+
+/// Whether the text is nothing but emoji and short enough to be a shortcut.
+pub fn valid_key(text: &str) -> bool {
+    let count = text.chars().count();
+    count > 0 && count <= MAX_KEY_LENGTH && text.chars().all(render::is_emoji)
+}
+
+/// The emoji without the variation selector, which some keyboards add and others don't.
+pub fn key(text: &str) -> String {
+    text.trim().chars().filter(|c| *c != '\u{fe0f}').collect()
+}
+
+/// The item the emoji stands for, if the message is a shortcut.
+pub fn find<'a>(shortcuts: &'a HashMap<String, String>, text: &str) -> Option<&'a String> {
+    shortcuts.get(&key(text))
+}
+
+/// The shortcuts sorted by their emoji, for the buttons and their indices.
+pub fn sorted(shortcuts: Option<&HashMap<String, String>>) -> Vec<(&String, &String)> {
+    let mut shortcuts: Vec<(&String, &String)> = shortcuts.into_iter().flatten().collect();
+    shortcuts.sort();
+    shortcuts
+}
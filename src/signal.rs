@@ -0,0 +1,158 @@
+//! Signal group for the list, so members without Telegram can use it too.
+//! Talks JSON-RPC to `signal-cli -a <number> daemon --tcp`, whose address is
+//! `SIGNAL_CLI_ADDR`, in the group `SIGNAL_GROUP_ID`. With several bots,
+//! `SIGNAL_NAMESPACE` picks the list, the default bot's otherwise.
+//!
+//! Every line sent in the group is added to the list. `liste` posts the open
+//! items, one message each, since Signal has no buttons: reacting to an item
+//! checks it off, taking the reaction back unchecks it.
+
+use std::collections::{HashMap, VecDeque};
+use std::sync::Arc;
+use std::time::Duration;
+
+use serde_json::{json, Value};
+use teloxide::prelude::*;
+use tokio::io::{AsyncBufReadExt, AsyncWriteExt, BufReader, Lines};
+use tokio::net::TcpStream;
+use tokio::net::tcp::{OwnedReadHalf, OwnedWriteHalf};
+
+use crate::{batch, Instance};
+
+const RECONNECT_DELAY: Duration = Duration::from_secs(30);
+const LIST_COMMANDS: [&str; 2] = ["liste", "/liste"];
+
+/// Connects to signal-cli if it is configured.
+pub fn spawn(bots: &[(Bot, Arc<Instance>)]) {
+    let (addr, group) = match (std::env::var("SIGNAL_CLI_ADDR"), std::env::var("SIGNAL_GROUP_ID")) {
+        (Ok(addr), Ok(group)) => (addr, group),
+        _ => return,
+    };
+    let namespace = std::env::var("SIGNAL_NAMESPACE").unwrap_or_default();
+    match bots.iter().find(|(_, instance)| instance.namespace == namespace) {
+        Some((bot, instance)) => {
+            tokio::spawn(run(bot.clone(), instance.clone(), addr, group));
+        }
+        None => log::error!("SIGNAL_NAMESPACE {:?} isn't a bot", namespace),
+    }
+}
+
+async fn run(bot: Bot, instance: Arc<Instance>, addr: String, group: String) {
+    // Item of each message posted by `liste`, by its timestamp
+    let mut posted: HashMap<u64, String> = HashMap::new();
+    loop {
+        match Connection::open(&addr).await {
+            Ok(mut connection) => {
+                log::info!("Connected to signal-cli at {}", addr);
+                if let Err(error) = connection.serve(&bot, &instance, &group, &mut posted).await {
+                    log::error!("Lost signal-cli at {}: {:?}", addr, error);
+                }
+            }
+            Err(error) => log::error!("Couldn't connect to signal-cli at {}: {:?}", addr, error),
+        }
+        tokio::time::sleep(RECONNECT_DELAY).await;
+    }
+}
+
+struct Connection {
+    lines: Lines<BufReader<OwnedReadHalf>>,
+    writer: OwnedWriteHalf,
+    next_id: u64,
+    /// Notifications read while waiting for the answer to a call.
+    received: VecDeque<Value>,
+}
+
+impl Connection {
+    async fn open(addr: &str) -> anyhow::Result<Self> {
+        let (reader, writer) = TcpStream::connect(addr).await?.into_split();
+        Ok(Connection { lines: BufReader::new(reader).lines(), writer, next_id: 0, received: VecDeque::new() })
+    }
+
+    async fn read(&mut self) -> anyhow::Result<Value> {
+        match self.lines.next_line().await? {
+            Some(line) => Ok(serde_json::from_str(&line)?),
+            None => anyhow::bail!("Connection closed"),
+        }
+    }
+
+    async fn call(&mut self, method: &str, params: Value) -> anyhow::Result<Value> {
+        self.next_id += 1;
+        let mut request = serde_json::to_string(&json!({ "jsonrpc": "2.0", "id": self.next_id, "method": method, "params": params }))?;
+        request.push('\n');
+        self.writer.write_all(request.as_bytes()).await?;
+        loop {
+            let message = self.read().await?;
+            if message.get("id").and_then(|id| id.as_u64()) != Some(self.next_id) {
+                self.received.push_back(message);
+                continue;
+            }
+            if let Some(error) = message.get("error") {
+                anyhow::bail!("{} failed: {}", method, error);
+            }
+            return Ok(message.get("result").cloned().unwrap_or(Value::Null));
+        }
+    }
+
+    /// Sends a message to the group, returns its timestamp.
+    async fn send(&mut self, group: &str, text: &str) -> anyhow::Result<u64> {
+        let result = self.call("send", json!({ "groupId": group, "message": text })).await?;
+        result.get("timestamp").and_then(|timestamp| timestamp.as_u64())
+            .ok_or_else(|| anyhow::anyhow!("No timestamp in {}", result))
+    }
+
+    async fn serve(&mut self, bot: &Bot, instance: &Instance, group: &str, posted: &mut HashMap<u64, String>) -> anyhow::Result<()> {
+        loop {
+            let message = match self.received.pop_front() {
+                Some(message) => message,
+                None => self.read().await?,
+            };
+            let data_message = match message.pointer("/params/envelope/dataMessage") {
+                Some(data_message) if message.get("method").and_then(|method| method.as_str()) == Some("receive") => data_message,
+                _ => continue,
+            };
+            if data_message.pointer("/groupInfo/groupId").and_then(|id| id.as_str()) != Some(group) {
+                continue;
+            }
+            if let Some(reaction) = data_message.get("reaction") {
+                let target = reaction.get("targetSentTimestamp").and_then(|timestamp| timestamp.as_u64());
+                if let Some(item) = target.and_then(|target| posted.get(&target)) {
+                    let removed = reaction.get("isRemove").and_then(|removed| removed.as_bool()).unwrap_or(false);
+                    let kind = if removed { batch::Kind::Uncheck } else { batch::Kind::Check };
+                    batch::apply(bot, instance, vec![batch::Operation::now(kind, item)]).await;
+                }
+                continue;
+            }
+            let text = data_message.get("message").and_then(|text| text.as_str()).unwrap_or_default().trim();
+            if LIST_COMMANDS.contains(&text.to_lowercase().as_str()) {
+                let open: Vec<String> = {
+                    let data = instance.data.lock().await;
+                    data.list.items.iter().filter(|item| !item.header && !item.done).map(|item| item.name.clone()).collect()
+                };
+                posted.clear();
+                if open.is_empty() {
+                    self.send(group, "Die Einkaufsliste ist leer.").await?;
+                    continue;
+                }
+                self.send(group, "🛒 Einkaufsliste, reagiere auf einen Eintrag, um ihn abzuhaken:").await?;
+                for item in open {
+                    let timestamp = self.send(group, &item).await?;
+                    posted.insert(timestamp, item);
+                }
+            } else if !text.is_empty() {
+                let operations = text.lines()
+                    .map(|line| line.trim())
+                    .filter(|line| !line.is_empty())
+                    .map(|line| batch::Operation::now(batch::Kind::Add, line))
+                    .collect();
+                let (added, rejected) = batch::apply(bot, instance, operations).await;
+                let added: Vec<String> = added.into_iter().map(|operation| operation.item).collect();
+                if !added.is_empty() {
+                    self.send(group, &format!("➕ {}", added.join(", "))).await?;
+                }
+                if !rejected.is_empty() {
+                    self.send(group, &format!("⚠️ {}", rejected.join("\n"))).await?;
+                }
+            }
+        }
+    }
+}
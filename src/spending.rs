@@ -0,0 +1,121 @@
+use std::collections::HashMap;
+
+use chrono::Datelike;
+use chrono_tz::Tz;
+use serde::{Deserialize, Serialize};
+
+use crate::history::Purchase;
+use crate::money::MoneyFormat;
+
+const BAR_WIDTH: u64 = 16;
+/// Prices elsewhere are pointed out from this percentage of the price at the list's store down.
+const CHEAPER_PERCENT: u64 = 80;
+
+#[derive(Serialize, Deserialize, Clone, Copy, PartialEq, Debug)]
+pub enum Category {
+    Lebensmittel,
+    Drogerie,
+    Sonstiges,
+}
+
+pub const CATEGORIES: [Category; 3] = [Category::Lebensmittel, Category::Drogerie, Category::Sonstiges];
+
+impl Category {
+    pub fn name(&self) -> &'static str {
+        match self {
+            Category::Lebensmittel => "Lebensmittel",
+            Category::Drogerie => "Drogerie",
+            Category::Sonstiges => "Sonstiges",
+        }
+    }
+
+    /// Accepts any unambiguous prefix, e.g. `leb` or `dro`.
+    pub fn parse(text: &str) -> Option<Self> {
+        let text = text.to_lowercase();
+        if text.is_empty() {
+            return None;
+        }
+        CATEGORIES.iter()
+            .copied()
+            .find(|category| category.name().to_lowercase().starts_with(&text))
+    }
+}
+
+/// Last known price and category of an item, by lowercase item name.
+#[derive(Serialize, Deserialize, Clone)]
+pub struct PriceEntry {
+    pub cents: u64,
+    pub category: Category,
+    /// Last known price per store, an empty name for lists without a store.
+    #[serde(default)]
+    pub stores: HashMap<String, u64>,
+}
+
+impl PriceEntry {
+    /// The store with the lowest price if it is significantly cheaper than `store`.
+    pub fn cheaper_elsewhere(&self, store: &str) -> Option<(&str, u64)> {
+        let here = *self.stores.get(store)?;
+        self.stores.iter()
+            .filter(|(other, cents)| other.as_str() != store && !other.is_empty() && **cents * 100 <= here * CHEAPER_PERCENT)
+            .min_by_key(|(other, cents)| (**cents, other.as_str()))
+            .map(|(other, cents)| (other.as_str(), *cents))
+    }
+
+    /// The prices at every store, cheapest first.
+    pub fn comparison(&self, money: &MoneyFormat) -> String {
+        let mut stores: Vec<(&String, &u64)> = self.stores.iter().collect();
+        stores.sort_by(|a, b| a.1.cmp(b.1).then(a.0.cmp(b.0)));
+        stores.iter()
+            .map(|(store, cents)| {
+                let store = if store.is_empty() { "ohne Laden" } else { store.as_str() };
+                format!(" - {}: {}", store, money.format(**cents))
+            })
+            .collect::<Vec<_>>()
+            .join("\n")
+    }
+}
+
+/// Parses amounts like `2,49`, `2.49`, `3`, `1.234,56 €` or `$1,234.56`.
+pub fn parse_price(text: &str) -> Option<u64> {
+    let text = text.trim_matches(|c: char| !c.is_ascii_digit() && !matches!(c, '-' | ',' | '.'));
+    // The last separator is the decimal one if one or two digits follow, the others group thousands
+    let (units, cents) = match text.rfind([',', '.']) {
+        Some(index) if text.len() - index <= 3 => (&text[..index], &text[index + 1..]),
+        _ => (text, ""),
+    };
+    let units: String = units.chars().filter(|c| *c != ',' && *c != '.').collect();
+    let cents = match cents.len() {
+        0 => 0,
+        1 => cents.parse::<u64>().ok()? * 10,
+        _ => cents.parse::<u64>().ok()?,
+    };
+    Some(units.parse::<u64>().ok()? * 100 + cents)
+}
+
+/// Spending per category in the given month as a monospace bar chart.
+pub fn monthly_report(history: &[Purchase], year: i32, month: u32, timezone: Tz, money: &MoneyFormat) -> String {
+    let mut totals = [0u64; 3];
+    for purchase in history.iter().filter(|purchase| purchase.bought()) {
+        let at = purchase.at.with_timezone(&timezone);
+        if at.year() != year || at.month() != month {
+            continue;
+        }
+        if let Some(cents) = purchase.price {
+            let category = purchase.category.unwrap_or(Category::Sonstiges);
+            let index = CATEGORIES.iter().position(|c| *c == category).unwrap();
+            totals[index] += cents;
+        }
+    }
+    let sum: u64 = totals.iter().sum();
+    if sum == 0 {
+        return format!("Keine Preise erfasst für {:02}/{}.", month, year);
+    }
+    let max = *totals.iter().max().unwrap();
+    let mut lines = Vec::new();
+    for (category, total) in CATEGORIES.iter().zip(totals.iter()) {
+        let bar = "█".repeat((total * BAR_WIDTH / max) as usize);
+        lines.push(format!("{:<12} {:<16} {:>10}", category.name(), bar, money.format(*total)));
+    }
+    lines.push(format!("{:<29} {:>10}", "Summe", money.format(sum)));
+    format!("Ausgaben {:02}/{}:\n<pre>{}</pre>", month, year, lines.join("\n"))
+}
@@ -0,0 +1,54 @@
+//! Items the store didn't have, marked with ❌ in the edit view. Counted per
+//! store, so an item that is missing again and again can go on the list of
+//! another store, see `transfer`.
+
+use std::collections::HashMap;
+
+use serde::{Deserialize, Serialize};
+
+/// Stock-outs at the same store before moving the item is suggested.
+pub const SUGGEST_AFTER: u32 = 3;
+/// Items listed by `/stats`.
+const SHOWN_ITEMS: usize = 10;
+
+#[derive(Serialize, Deserialize, Clone, Default)]
+pub struct StockOuts {
+    /// Per lowercase item name the stock-outs per store, an empty store name for lists without one.
+    counts: HashMap<String, HashMap<String, u32>>,
+}
+
+impl StockOuts {
+    /// Counts a stock-out and returns how often the item was missing at the store.
+    pub fn record(&mut self, name: &str, store: Option<&str>) -> u32 {
+        let count = self.counts.entry(name.to_lowercase())
+            .or_default()
+            .entry(store.unwrap_or_default().to_string())
+            .or_insert(0);
+        *count += 1;
+        *count
+    }
+
+    /// The items missing most often, with the stores, for `/stats`.
+    pub fn describe(&self) -> Option<String> {
+        let mut items: Vec<(&String, u32, String)> = self.counts.iter()
+            .map(|(name, stores)| {
+                let mut stores: Vec<(&String, &u32)> = stores.iter().collect();
+                stores.sort_by(|a, b| b.1.cmp(a.1).then(a.0.cmp(b.0)));
+                let total = stores.iter().map(|(_, count)| **count).sum();
+                let stores: Vec<String> = stores.iter()
+                    .map(|(store, count)| if store.is_empty() { format!("{}×", count) } else { format!("{}× {}", count, store) })
+                    .collect();
+                (name, total, stores.join(", "))
+            })
+            .collect();
+        if items.is_empty() {
+            return None;
+        }
+        items.sort_by(|a, b| b.1.cmp(&a.1).then(a.0.cmp(b.0)));
+        let lines: Vec<String> = items.iter()
+            .take(SHOWN_ITEMS)
+            .map(|(name, _, stores)| format!(" - {}: {}", name, stores))
+            .collect();
+        Some(format!("Gab es nicht:\n{}", lines.join("\n")))
+    }
+}
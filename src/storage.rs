@@ -0,0 +1,892 @@
+use std::collections::{BTreeMap, HashMap};
+use std::io::ErrorKind;
+use std::path::{Path, PathBuf};
+use std::sync::Mutex;
+
+use chrono::Utc;
+use chrono_tz::Tz;
+use lazy_static::lazy_static;
+use serde::{Deserialize, Serialize};
+use serde_json::{json, Map, Value};
+use tokio::fs::{File, OpenOptions};
+use tokio::io::{AsyncReadExt, AsyncWriteExt};
+
+use crate::{aliases, Data, delta, features, i18n, membership, merge, money, notifications, onboarding, pause, postgres, quick_keyboard, quiet, redis, shadow, webhooks};
+
+/// Version of the data file layout written by this build.
+pub const SCHEMA_VERSION: u32 = 3;
+
+/// Fields of `Data` kept per chat, each chat in its own file next to the data file,
+/// so a change in one chat doesn't rewrite the others.
+pub const CHAT_FIELDS: [&str; 14] = ["languages", "onboarding_choices", "timezones", "archived_chats", "paused_chats", "quiet_hours", "currencies", "accessible_chats", "webhooks", "features", "quick_keyboards", "aliases", "shortcuts", "seen_versions"];
+
+/// Everything stored about a single chat.
+#[derive(Serialize, Deserialize, Default)]
+struct ChatState {
+    #[serde(default, skip_serializing_if = "Option::is_none")]
+    language: Option<i18n::Language>,
+    #[serde(default, skip_serializing_if = "Option::is_none")]
+    onboarding: Option<onboarding::Choices>,
+    #[serde(default, skip_serializing_if = "Option::is_none")]
+    timezone: Option<Tz>,
+    #[serde(default, skip_serializing_if = "Option::is_none")]
+    archived: Option<membership::ArchivedChat>,
+    #[serde(default, skip_serializing_if = "Option::is_none")]
+    paused: Option<pause::Pause>,
+    #[serde(default, skip_serializing_if = "Option::is_none")]
+    quiet: Option<quiet::QuietHours>,
+    #[serde(default, skip_serializing_if = "Option::is_none")]
+    currency: Option<money::MoneyFormat>,
+    #[serde(default, skip_serializing_if = "std::ops::Not::not")]
+    accessible: bool,
+    #[serde(default, skip_serializing_if = "Option::is_none")]
+    webhook: Option<webhooks::Webhook>,
+    #[serde(default, skip_serializing_if = "HashMap::is_empty")]
+    features: HashMap<features::Feature, bool>,
+    #[serde(default, skip_serializing_if = "Vec::is_empty")]
+    quick_keyboard: Vec<quick_keyboard::QuickAction>,
+    #[serde(default, skip_serializing_if = "HashMap::is_empty")]
+    aliases: HashMap<String, aliases::Target>,
+    #[serde(default, skip_serializing_if = "HashMap::is_empty")]
+    shortcuts: HashMap<String, String>,
+    #[serde(default, skip_serializing_if = "Option::is_none")]
+    seen_version: Option<String>,
+}
+
+/// Log entries written before the log is compacted into the data file.
+const COMPACT_AFTER: usize = 200;
+
+/// A line of the log: the top-level fields of `Data` that changed, either
+/// whole or, for the larger lists and maps, only what changed in them.
+#[derive(Serialize, Deserialize)]
+struct Entry {
+    sequence: u64,
+    #[serde(default, skip_serializing_if = "Map::is_empty")]
+    fields: Map<String, Value>,
+    #[serde(default, skip_serializing_if = "BTreeMap::is_empty")]
+    deltas: BTreeMap<String, delta::Delta>,
+}
+
+/// State of the log of a data file.
+#[derive(Default)]
+struct Log {
+    /// Sequence number of the newest entry.
+    sequence: u64,
+    /// Entries since the data file was written.
+    entries: usize,
+    /// Fields as of the newest entry.
+    fields: Map<String, Value>,
+    /// Whether the data file was written, otherwise the log is all there is.
+    snapshot: bool,
+}
+
+/// Another replica wrote the data since this one loaded it.
+#[derive(Debug)]
+pub struct Conflict(pub String);
+
+impl std::fmt::Display for Conflict {
+    fn fmt(&self, f: &mut std::fmt::Formatter<'_>) -> std::fmt::Result {
+        write!(f, "{} was changed by another replica", self.0)
+    }
+}
+
+impl std::error::Error for Conflict {}
+
+lazy_static! {
+    /// Content of the files as last written, to skip writing unchanged ones.
+    static ref WRITTEN: Mutex<HashMap<PathBuf, String>> = Mutex::new(HashMap::new());
+    static ref LOGS: Mutex<HashMap<String, Log>> = Mutex::new(HashMap::new());
+    /// Fields of the data in Redis or Postgres as last loaded or written, the base to merge changes against.
+    static ref SHARED: Mutex<HashMap<String, Map<String, Value>>> = Mutex::new(HashMap::new());
+    /// Data as last written to the shadow storage, see `shadow`.
+    static ref SHADOWED: Mutex<HashMap<String, String>> = Mutex::new(HashMap::new());
+}
+
+fn fields(data: &Data) -> anyhow::Result<Map<String, Value>> {
+    match serde_json::to_value(data)? {
+        Value::Object(fields) => Ok(fields),
+        _ => anyhow::bail!("Data isn't serialized as an object"),
+    }
+}
+
+/// Changes since the data file was written, one JSON entry per line, `data.json.wal` for `data.json`.
+fn log_path(path: &str) -> PathBuf {
+    PathBuf::from(format!("{}.wal", path))
+}
+
+/// The directory with a file per chat, `data.json.chats` for `data.json`.
+fn chats_directory(path: &str) -> PathBuf {
+    PathBuf::from(format!("{}.chats", path))
+}
+
+/// Whether there is a data file, a log or a chats directory for the path.
+pub fn on_disk(path: &str) -> bool {
+    Path::new(path).exists() || log_path(path).exists() || chats_directory(path).exists()
+}
+
+/// Whether the data is kept in Redis or Postgres, where replicas can share it, instead of the data file.
+fn shared() -> bool {
+    postgres::configured() || redis::configured()
+}
+
+/// Reads the data file or the data in Redis or Postgres, `None` if there is none yet.
+pub async fn load(path: &str) -> anyhow::Result<Option<Data>> {
+    if postgres::configured() {
+        let data = postgres::load(path).await?;
+        if let Some(data) = &data {
+            SHARED.lock().unwrap().insert(path.to_string(), fields(data)?);
+        }
+        return Ok(data);
+    }
+    if redis::configured() {
+        return match redis::load(path).await? {
+            Some(string) => {
+                let data: Data = serde_json::from_str(&string)?;
+                SHARED.lock().unwrap().insert(path.to_string(), fields(&data)?);
+                Ok(Some(data))
+            }
+            None => Ok(None),
+        };
+    }
+    load_file(path).await
+}
+
+/// Reads the data file, its chats and its log.
+async fn load_file(path: &str) -> anyhow::Result<Option<Data>> {
+    let (mut data, sequence, snapshot) = match read(Path::new(path)).await? {
+        Some(string) => {
+            let snapshot: Value = serde_json::from_str(string.as_str())?;
+            let sequence = snapshot.get("wal_sequence").and_then(|sequence| sequence.as_u64()).unwrap_or(0);
+            (serde_json::from_value(snapshot)?, sequence, true)
+        }
+        // Before the first compaction, the log and the chats are all there is
+        None if log_path(path).exists() || chats_directory(path).exists() => (Data::default(), 0, false),
+        None => return Ok(None),
+    };
+    load_chats(path, &mut data).await?;
+    replay_log(path, data, sequence, snapshot).await.map(Some)
+}
+
+/// Applies the log entries newer than the data file.
+async fn replay_log(path: &str, data: Data, snapshot_sequence: u64, snapshot: bool) -> anyhow::Result<Data> {
+    let mut fields = fields(&data)?;
+    let mut log = Log { sequence: snapshot_sequence, snapshot, ..Log::default() };
+    let lines = match tokio::fs::read_to_string(log_path(path)).await {
+        Ok(lines) => lines,
+        Err(error) if error.kind() == ErrorKind::NotFound => String::new(),
+        Err(error) => return Err(error.into()),
+    };
+    for line in lines.lines() {
+        let entry: Entry = match serde_json::from_str(line) {
+            Ok(entry) => entry,
+            Err(error) => {
+                // Only the last line can be cut off, by a crash while appending it
+                log::warn!("Ignoring the rest of {}: {}", log_path(path).display(), error);
+                break;
+            }
+        };
+        // Left over from a crash between writing the data file and removing the log
+        if entry.sequence <= log.sequence {
+            continue;
+        }
+        log.sequence = entry.sequence;
+        log.entries += 1;
+        fields.extend(entry.fields);
+        for (field, delta) in entry.deltas {
+            let old = fields.get(&field).cloned().unwrap_or(Value::Null);
+            fields.insert(field.clone(), delta::apply(&old, delta)
+                .map_err(|error| error.context(format!("Entry {} of {} changes {}", log.sequence, log_path(path).display(), field)))?);
+        }
+    }
+    let data = if log.entries > 0 {
+        log::info!("Replayed {} change(s) from {}", log.entries, log_path(path).display());
+        serde_json::from_value(Value::Object(fields.clone()))?
+    } else {
+        data
+    };
+    log.fields = fields;
+    LOGS.lock().unwrap().insert(path.to_string(), log);
+    Ok(data)
+}
+
+/// Merges the files of the chats into `data`.
+async fn load_chats(path: &str, data: &mut Data) -> anyhow::Result<()> {
+    // Files written before the split still have the chats inline, those are kept
+    let mut entries = match tokio::fs::read_dir(chats_directory(path)).await {
+        Ok(entries) => entries,
+        Err(error) if error.kind() == ErrorKind::NotFound => return Ok(()),
+        Err(error) => return Err(error.into()),
+    };
+    while let Some(entry) = entries.next_entry().await? {
+        let chat_id = match chat_id(&entry.path()) {
+            Some(chat_id) => chat_id,
+            None => continue,
+        };
+        let string = match read(&entry.path()).await? {
+            Some(string) => string,
+            None => continue,
+        };
+        let state: ChatState = match serde_json::from_str(&string) {
+            Ok(state) => state,
+            Err(error) => {
+                log::warn!("Setting aside {}: {}", entry.path().display(), error);
+                quarantine(path, &format!("unreadable settings of chat {}", chat_id), Value::String(string)).await?;
+                continue;
+            }
+        };
+        apply_chat_state(data, chat_id, state);
+    }
+    Ok(())
+}
+
+fn apply_chat_state(data: &mut Data, chat_id: i64, state: ChatState) {
+    if let Some(language) = state.language {
+        data.languages.insert(chat_id, language);
+    }
+    if let Some(choices) = state.onboarding {
+        data.onboarding_choices.insert(chat_id, choices);
+    }
+    if let Some(timezone) = state.timezone {
+        data.timezones.insert(chat_id, timezone);
+    }
+    if let Some(archived) = state.archived {
+        data.archived_chats.insert(chat_id, archived);
+    }
+    if let Some(paused) = state.paused {
+        data.paused_chats.insert(chat_id, paused);
+    }
+    if let Some(quiet) = state.quiet {
+        data.quiet_hours.insert(chat_id, quiet);
+    }
+    if let Some(currency) = state.currency {
+        data.currencies.insert(chat_id, currency);
+    }
+    if state.accessible {
+        data.accessible_chats.insert(chat_id);
+    }
+    if let Some(webhook) = state.webhook {
+        data.webhooks.insert(chat_id, webhook);
+    }
+    if !state.features.is_empty() {
+        data.features.insert(chat_id, state.features);
+    }
+    if !state.quick_keyboard.is_empty() {
+        data.quick_keyboards.insert(chat_id, state.quick_keyboard);
+    }
+    if !state.aliases.is_empty() {
+        data.aliases.insert(chat_id, state.aliases);
+    }
+    if !state.shortcuts.is_empty() {
+        data.shortcuts.insert(chat_id, state.shortcuts);
+    }
+    if let Some(version) = state.seen_version {
+        data.seen_versions.insert(chat_id, version);
+    }
+}
+
+/// Appends the fields changed since the last call to the log and makes sure they are on disk.
+/// Every `COMPACT_AFTER` entries, the log is compacted into the data file.
+pub async fn log(path: &str, data: &mut Data) -> anyhow::Result<()> {
+    if shared() {
+        // Redis and Postgres take care of durability themselves
+        save_shared(path, data).await?;
+    } else {
+        append(path, data).await?;
+    }
+    if let Err(error) = save_shadow(path, data).await {
+        // The shadow storage isn't in charge yet, the comparison will notice
+        log::error!("Couldn't write the shadow copy of {}: {:?}", path, error);
+    }
+    Ok(())
+}
+
+async fn append(path: &str, data: &mut Data) -> anyhow::Result<()> {
+    let fields = fields(data)?;
+    // The log only takes the entry once it is on disk, otherwise the next one
+    // would be a change from something that was never written
+    let (entry, compact) = {
+        let logs = LOGS.lock().unwrap();
+        let empty = Log::default();
+        let log = logs.get(path).unwrap_or(&empty);
+        let mut entry = Entry { sequence: log.sequence + 1, fields: Map::new(), deltas: BTreeMap::new() };
+        for (field, value) in fields.iter() {
+            match log.fields.get(field) {
+                Some(old) if old == value => {}
+                // After a crash while compacting, the chat files can be newer than the
+                // data file and the log is replayed over them, which only whole values fit
+                Some(_) if CHAT_FIELDS.contains(&field.as_str()) => {
+                    entry.fields.insert(field.clone(), value.clone());
+                }
+                Some(old) => match delta::diff(old, value) {
+                    Some(delta) => {
+                        entry.deltas.insert(field.clone(), delta);
+                    }
+                    None => {
+                        entry.fields.insert(field.clone(), value.clone());
+                    }
+                },
+                None => {
+                    entry.fields.insert(field.clone(), value.clone());
+                }
+            }
+        }
+        if entry.fields.is_empty() && entry.deltas.is_empty() {
+            return Ok(());
+        }
+        // Without a data file the log would only ever grow from nothing
+        (entry, log.entries + 1 >= COMPACT_AFTER || !log.snapshot)
+    };
+    let mut line = serde_json::to_string(&entry)?;
+    line.push('\n');
+    let mut file = OpenOptions::new()
+        .create(true)
+        .append(true)
+        .open(log_path(path)).await?;
+    file.write_all(line.as_bytes()).await?;
+    file.sync_data().await?;
+    {
+        let mut logs = LOGS.lock().unwrap();
+        let log = logs.entry(path.to_string()).or_default();
+        log.sequence = entry.sequence;
+        log.entries += 1;
+        log.fields = fields;
+    }
+    if compact {
+        save(path, data).await?;
+    }
+    Ok(())
+}
+
+/// Writes the data to Redis or Postgres, failing with a `Conflict` if another replica wrote it meanwhile.
+async fn write_shared(path: &str, data: &Data) -> anyhow::Result<()> {
+    if postgres::configured() {
+        return postgres::save(path, data).await;
+    }
+    redis::save(path, &serde_json::to_string(data)?).await
+}
+
+/// The fields of the data in Redis or Postgres, empty if there is none.
+async fn read_shared(path: &str) -> anyhow::Result<Map<String, Value>> {
+    if postgres::configured() {
+        return match postgres::load(path).await? {
+            Some(data) => fields(&data),
+            None => Ok(Map::new()),
+        };
+    }
+    match redis::load(path).await? {
+        Some(string) => match serde_json::from_str(&string)? {
+            Value::Object(theirs) => Ok(theirs),
+            _ => anyhow::bail!("Data isn't serialized as an object"),
+        },
+        None => Ok(Map::new()),
+    }
+}
+
+/// Writes the data to Redis or Postgres. If another replica wrote it meanwhile,
+/// the change is merged into that version, which then replaces `data`.
+async fn save_shared(path: &str, data: &mut Data) -> anyhow::Result<()> {
+    let ours = fields(data)?;
+    let error = match write_shared(path, data).await {
+        Ok(()) => {
+            SHARED.lock().unwrap().insert(path.to_string(), ours);
+            return Ok(());
+        }
+        Err(error) if error.is::<Conflict>() => error,
+        Err(error) => return Err(error),
+    };
+    let base = match SHARED.lock().unwrap().get(path).cloned() {
+        Some(base) => base,
+        None => return Err(error.context("Nothing to merge against, dropping this change")),
+    };
+    let theirs = read_shared(path).await?;
+    let merged = merge::merge(&base, &ours, &theirs);
+    *data = serde_json::from_value(Value::Object(merged.clone()))?;
+    // If yet another replica wrote meanwhile, the change is dropped after all
+    write_shared(path, data).await?;
+    log::info!("{}, merged this change into it", error);
+    SHARED.lock().unwrap().insert(path.to_string(), merged);
+    Ok(())
+}
+
+/// Writes the data to Redis or Postgres, or the data file, which makes the log obsolete.
+pub async fn save(path: &str, data: &Data) -> anyhow::Result<()> {
+    if shared() {
+        return write_shared(path, data).await;
+    }
+    save_file(path, data).await
+}
+
+async fn save_file(path: &str, data: &Data) -> anyhow::Result<()> {
+    let mut shared = serde_json::to_value(data)?;
+    let sequence = LOGS.lock().unwrap().get(path).map(|log| log.sequence).unwrap_or(0);
+    if let Some(fields) = shared.as_object_mut() {
+        for field in CHAT_FIELDS.iter() {
+            fields.remove(*field);
+        }
+        // Log entries up to this one are contained in the data file
+        fields.insert("wal_sequence".to_string(), sequence.into());
+    }
+    // The chats first: until the data file with the new sequence is written,
+    // the log is replayed on load and has every change of them as well
+    save_chats(path, &chat_states(data)).await?;
+    write(Path::new(path), serde_json::to_string_pretty(&shared)?).await?;
+    // The log last, once everything in it is in the files
+    match tokio::fs::remove_file(log_path(path)).await {
+        Ok(()) => {}
+        Err(error) if error.kind() == ErrorKind::NotFound => {}
+        Err(error) => return Err(error.into()),
+    }
+    if let Some(log) = LOGS.lock().unwrap().get_mut(path) {
+        log.entries = 0;
+        log.snapshot = true;
+    }
+    Ok(())
+}
+
+/// What is stored about each chat in the chats directory.
+fn chat_states(data: &Data) -> HashMap<i64, ChatState> {
+    let mut chats: HashMap<i64, ChatState> = HashMap::new();
+    for (chat_id, language) in data.languages.iter() {
+        chats.entry(*chat_id).or_default().language = Some(*language);
+    }
+    for (chat_id, choices) in data.onboarding_choices.iter() {
+        chats.entry(*chat_id).or_default().onboarding = Some(choices.clone());
+    }
+    for (chat_id, timezone) in data.timezones.iter() {
+        chats.entry(*chat_id).or_default().timezone = Some(*timezone);
+    }
+    for (chat_id, archived) in data.archived_chats.iter() {
+        chats.entry(*chat_id).or_default().archived = Some(archived.clone());
+    }
+    for (chat_id, paused) in data.paused_chats.iter() {
+        chats.entry(*chat_id).or_default().paused = Some(paused.clone());
+    }
+    for (chat_id, quiet) in data.quiet_hours.iter() {
+        chats.entry(*chat_id).or_default().quiet = Some(*quiet);
+    }
+    for (chat_id, currency) in data.currencies.iter() {
+        chats.entry(*chat_id).or_default().currency = Some(currency.clone());
+    }
+    for chat_id in data.accessible_chats.iter() {
+        chats.entry(*chat_id).or_default().accessible = true;
+    }
+    for (chat_id, webhook) in data.webhooks.iter() {
+        chats.entry(*chat_id).or_default().webhook = Some(webhook.clone());
+    }
+    for (chat_id, features) in data.features.iter() {
+        chats.entry(*chat_id).or_default().features = features.clone();
+    }
+    for (chat_id, actions) in data.quick_keyboards.iter() {
+        chats.entry(*chat_id).or_default().quick_keyboard = actions.clone();
+    }
+    for (chat_id, aliases) in data.aliases.iter() {
+        chats.entry(*chat_id).or_default().aliases = aliases.clone();
+    }
+    for (chat_id, shortcuts) in data.shortcuts.iter() {
+        chats.entry(*chat_id).or_default().shortcuts = shortcuts.clone();
+    }
+    for (chat_id, version) in data.seen_versions.iter() {
+        chats.entry(*chat_id).or_default().seen_version = Some(version.clone());
+    }
+    chats
+}
+
+/// What is stored about a chat, as in its file in the chats directory.
+pub fn chat_state(data: &Data, chat_id: i64) -> Value {
+    chat_states(data).remove(&chat_id)
+        .and_then(|state| serde_json::to_value(state).ok())
+        .unwrap_or_else(|| Value::Object(Map::new()))
+}
+
+/// What is stored about each chat, for the chats table of `postgres`.
+#[cfg(feature = "postgres-storage")]
+pub fn chat_rows(data: &Data) -> anyhow::Result<Vec<(i64, Value)>> {
+    chat_states(data).into_iter()
+        .map(|(chat_id, state)| Ok((chat_id, serde_json::to_value(state)?)))
+        .collect()
+}
+
+/// Merges chats as returned by `chat_rows` into `data`.
+#[cfg(feature = "postgres-storage")]
+pub fn apply_chat_rows(data: &mut Data, rows: Vec<(i64, Value)>) -> anyhow::Result<()> {
+    for (chat_id, state) in rows {
+        apply_chat_state(data, chat_id, serde_json::from_value(state)?);
+    }
+    Ok(())
+}
+
+async fn save_chats(path: &str, chats: &HashMap<i64, ChatState>) -> anyhow::Result<()> {
+    let directory = chats_directory(path);
+    if chats.is_empty() && !directory.exists() {
+        return Ok(());
+    }
+    tokio::fs::create_dir_all(&directory).await?;
+    for (chat_id, state) in chats.iter() {
+        write(&directory.join(format!("{}.json", chat_id)), serde_json::to_string_pretty(state)?).await?;
+    }
+    // Chats nothing is stored about anymore
+    let mut entries = tokio::fs::read_dir(&directory).await?;
+    while let Some(entry) = entries.next_entry().await? {
+        if chat_id(&entry.path()).map(|chat_id| !chats.contains_key(&chat_id)) == Some(true) {
+            tokio::fs::remove_file(entry.path()).await?;
+            WRITTEN.lock().unwrap().remove(&entry.path());
+        }
+    }
+    Ok(())
+}
+
+/// The chat a file in the chats directory belongs to.
+fn chat_id(path: &Path) -> Option<i64> {
+    if path.extension()? != "json" {
+        return None;
+    }
+    path.file_stem()?.to_str()?.parse().ok()
+}
+
+async fn read(path: &Path) -> anyhow::Result<Option<String>> {
+    let mut file = match File::open(path).await {
+        Ok(file) => file,
+        Err(error) if error.kind() == ErrorKind::NotFound => return Ok(None),
+        Err(error) => return Err(error.into()),
+    };
+    let mut string = String::new();
+    file.read_to_string(&mut string).await?;
+    WRITTEN.lock().unwrap().insert(path.to_path_buf(), string.clone());
+    Ok(Some(string))
+}
+
+/// Replaces the file as a whole, a crash leaves either the old or the new content.
+async fn write(path: &Path, string: String) -> anyhow::Result<()> {
+    if WRITTEN.lock().unwrap().get(path) == Some(&string) {
+        return Ok(());
+    }
+    let mut temporary = path.as_os_str().to_owned();
+    temporary.push(".tmp");
+    let temporary = PathBuf::from(temporary);
+    let mut file = OpenOptions::new()
+        .create(true)
+        .write(true)
+        .truncate(true)
+        .open(&temporary).await?;
+    file.write_all(string.as_bytes()).await?;
+    file.sync_all().await?;
+    tokio::fs::rename(&temporary, path).await?;
+    // The rename itself has to be on disk before the log is removed
+    let directory = match path.parent() {
+        Some(parent) if !parent.as_os_str().is_empty() => parent,
+        _ => Path::new("."),
+    };
+    File::open(directory).await?.sync_all().await?;
+    WRITTEN.lock().unwrap().insert(path.to_path_buf(), string);
+    Ok(())
+}
+
+/// Drops what is kept in memory about the data file, for data that is unloaded.
+/// It has to be loaded again before it is stored.
+pub fn forget(path: &str) {
+    let chats = chats_directory(path);
+    WRITTEN.lock().unwrap().retain(|written, _| written != Path::new(path) && !written.starts_with(&chats));
+    LOGS.lock().unwrap().remove(path);
+    SHARED.lock().unwrap().remove(path);
+    SHADOWED.lock().unwrap().remove(path);
+    postgres::forget(path);
+}
+
+/// Writes the data to the storage that is to take over, if it changed since the last time.
+pub async fn save_shadow(path: &str, data: &Data) -> anyhow::Result<()> {
+    let backend = match shadow::backend() {
+        Some(backend) => backend,
+        None => return Ok(()),
+    };
+    let json = serde_json::to_string(data)?;
+    if SHADOWED.lock().unwrap().get(path) == Some(&json) {
+        return Ok(());
+    }
+    match backend {
+        // Nobody else writes there yet, so there is nothing to merge
+        shadow::Backend::Redis => redis::overwrite(path, &json).await?,
+        shadow::Backend::Postgres => postgres::overwrite(path, data).await?,
+        shadow::Backend::Json => save_file(path, data).await?,
+    }
+    SHADOWED.lock().unwrap().insert(path.to_string(), json);
+    Ok(())
+}
+
+/// Reads the data from the storage that is to take over.
+pub async fn load_shadow(path: &str) -> anyhow::Result<Option<Data>> {
+    match shadow::backend() {
+        Some(shadow::Backend::Redis) => match redis::load(path).await? {
+            Some(string) => Ok(Some(serde_json::from_str(&string)?)),
+            None => Ok(None),
+        },
+        Some(shadow::Backend::Postgres) => postgres::load(path).await,
+        Some(shadow::Backend::Json) => load_file(path).await,
+        None => anyhow::bail!("STORAGE_SHADOW isn't set"),
+    }
+}
+
+/// Fields as they went through JSON, so numbers compare the same on both sides.
+fn normalized(fields: &Map<String, Value>) -> anyhow::Result<Map<String, Value>> {
+    Ok(serde_json::from_str(&serde_json::to_string(fields)?)?)
+}
+
+/// Top-level fields that differ between two versions of the data.
+pub fn differences(ours: &Data, theirs: &Data) -> anyhow::Result<Vec<String>> {
+    let (ours, theirs) = (normalized(&fields(ours)?)?, normalized(&fields(theirs)?)?);
+    let mut differences: Vec<String> = ours.keys().chain(theirs.keys())
+        .filter(|field| ours.get(*field) != theirs.get(*field))
+        .cloned()
+        .collect();
+    differences.sort();
+    differences.dedup();
+    Ok(differences)
+}
+
+/// Compares the shadow storage with what was last written to the storage in
+/// charge, `None` if nothing was loaded or written yet.
+pub async fn compare_shadow(path: &str) -> anyhow::Result<Option<Vec<String>>> {
+    let written = if shared() {
+        SHARED.lock().unwrap().get(path).cloned()
+    } else {
+        LOGS.lock().unwrap().get(path).map(|log| log.fields.clone()).filter(|fields| !fields.is_empty())
+    };
+    let written: Data = match written {
+        Some(fields) => serde_json::from_value(Value::Object(normalized(&fields)?))?,
+        None => return Ok(None),
+    };
+    let shadow = load_shadow(path).await?.unwrap_or_default();
+    differences(&written, &shadow).map(Some)
+}
+
+/// Bytes of the data file with its log and the files of its chats.
+pub async fn size(path: &str) -> u64 {
+    async fn file_size(path: &Path) -> u64 {
+        tokio::fs::metadata(path).await.map(|metadata| metadata.len()).unwrap_or(0)
+    }
+    let mut size = file_size(Path::new(path)).await + file_size(&log_path(path)).await;
+    if let Ok(mut entries) = tokio::fs::read_dir(chats_directory(path)).await {
+        while let Ok(Some(entry)) = entries.next_entry().await {
+            size += file_size(&entry.path()).await;
+        }
+    }
+    size
+}
+
+/// Whether another replica changed the data since it was loaded, only possible with Redis or Postgres.
+pub async fn outdated(path: &str) -> anyhow::Result<bool> {
+    if postgres::configured() {
+        return postgres::outdated(path).await;
+    }
+    if redis::configured() {
+        return redis::outdated(path).await;
+    }
+    Ok(false)
+}
+
+/// Records that couldn't be loaded or were removed by `load_repairing`, one JSON entry per line.
+fn quarantine_path(path: &str) -> PathBuf {
+    PathBuf::from(format!("{}.quarantine", path))
+}
+
+async fn quarantine(path: &str, reason: &str, record: Value) -> anyhow::Result<()> {
+    let mut line = serde_json::to_string(&json!({ "at": Utc::now(), "reason": reason, "record": record }))?;
+    line.push('\n');
+    let mut file = OpenOptions::new().create(true).append(true).open(quarantine_path(path)).await?;
+    file.write_all(line.as_bytes()).await?;
+    Ok(())
+}
+
+/// Like `load`, but a damaged data file or damaged records in it don't stop the bot: what
+/// can be fixed is fixed, the rest is set aside in `<path>.quarantine`. Returns what was done.
+pub async fn load_repairing(path: &str) -> anyhow::Result<(Option<Data>, Vec<String>)> {
+    let mut report = Vec::new();
+    let mut data = match load(path).await {
+        Ok(Some(data)) => data,
+        Ok(None) => return Ok((None, report)),
+        // Redis and Postgres have no file to salvage from
+        Err(error) if shared() => return Err(error),
+        Err(error) => {
+            report.push(format!("Couldn't load {}: {}", path, error));
+            salvage(path, &mut report).await?
+        }
+    };
+    repair(path, &mut data, &mut report).await?;
+    if !report.is_empty() {
+        report.push(format!("Anything removed is kept in {}", quarantine_path(path).display()));
+    }
+    Ok((Some(data), report))
+}
+
+/// Reads what is readable of a damaged data file, field by field.
+async fn salvage(path: &str, report: &mut Vec<String>) -> anyhow::Result<Data> {
+    let string = match read(Path::new(path)).await? {
+        Some(string) => string,
+        None => return salvage_log(path, Data::default(), 0, false, report).await,
+    };
+    let snapshot = match serde_json::from_str(&string) {
+        Ok(Value::Object(snapshot)) => snapshot,
+        _ => {
+            quarantine(path, "unreadable data file", Value::String(string)).await?;
+            report.push(format!("{} isn't a JSON object, starting with an empty list", path));
+            return Ok(Data::default());
+        }
+    };
+    let sequence = snapshot.get("wal_sequence").and_then(|sequence| sequence.as_u64()).unwrap_or(0);
+    let mut fields = match serde_json::to_value(Data::default())? {
+        Value::Object(fields) => fields,
+        _ => anyhow::bail!("Data isn't serialized as an object"),
+    };
+    for (name, value) in snapshot {
+        let mut candidate = fields.clone();
+        candidate.insert(name.clone(), value.clone());
+        if serde_json::from_value::<Data>(Value::Object(candidate.clone())).is_ok() {
+            fields = candidate;
+        } else {
+            quarantine(path, &format!("unreadable field {}", name), value).await?;
+            report.push(format!("Field {} couldn't be read", name));
+        }
+    }
+    let data: Data = serde_json::from_value(Value::Object(fields))?;
+    salvage_log(path, data, sequence, true, report).await
+}
+
+/// Applies the log to what could be read of the data file, or sets the log aside if it doesn't apply.
+async fn salvage_log(path: &str, mut data: Data, sequence: u64, snapshot: bool, report: &mut Vec<String>) -> anyhow::Result<Data> {
+    load_chats(path, &mut data).await?;
+    let fallback = data.clone();
+    match replay_log(path, data, sequence, snapshot).await {
+        Ok(data) => Ok(data),
+        Err(error) => {
+            let log = tokio::fs::read_to_string(log_path(path)).await.unwrap_or_default();
+            quarantine(path, "log that doesn't apply", Value::String(log)).await?;
+            report.push(format!("Changes in {} couldn't be applied: {}", log_path(path).display(), error));
+            Ok(fallback)
+        }
+    }
+}
+
+/// Fixes records that can't be right and removes those that can't be fixed.
+async fn repair(path: &str, data: &mut Data, report: &mut Vec<String>) -> anyhow::Result<()> {
+    let (items, unnamed): (Vec<_>, Vec<_>) = data.list.items.drain(..).partition(|item| !item.name.trim().is_empty());
+    data.list.items = items;
+    for item in unnamed {
+        quarantine(path, "item without a name", serde_json::to_value(&item)?).await?;
+        report.push("Removed an item without a name".to_string());
+    }
+    let users = &data.users;
+    for item in data.list.items.iter_mut() {
+        if item.quantity == 0 {
+            item.quantity = 1;
+            report.push(format!("Set the quantity of {} from 0 to 1", item.name));
+        }
+        if let Some(assignee) = item.assignee.filter(|assignee| !users.contains_key(assignee)) {
+            item.assignee = None;
+            report.push(format!("Unassigned {} from unknown user {}", item.name, assignee));
+        }
+    }
+    let broken: Vec<String> = data.list.recipes.iter()
+        .filter(|(name, recipe)| name.trim().is_empty() || recipe.ingredients.is_empty())
+        .map(|(name, _)| name.clone())
+        .collect();
+    for name in broken {
+        if let Some(ingredients) = data.list.recipes.remove(&name) {
+            quarantine(path, "recipe without a name or ingredients", json!({ "name": name, "ingredients": ingredients })).await?;
+            report.push(format!("Removed recipe {:?} without a name or ingredients", name));
+        }
+    }
+    let empty: Vec<String> = data.templates.iter().filter(|(_, entries)| entries.is_empty()).map(|(name, _)| name.clone()).collect();
+    for name in empty {
+        data.templates.remove(&name);
+        report.push(format!("Removed empty template {}", name));
+    }
+    if data.current_recipe.is_some() && data.naming_template {
+        data.naming_template = false;
+        report.push("Stopped naming a template while a recipe is being entered".to_string());
+    }
+    if !(1..=3).contains(&data.settings.columns) {
+        report.push(format!("Reset the column count {} to 1", data.settings.columns));
+        data.settings.columns = 1;
+    }
+    // The list of a chat the bot was removed from can't be edited anymore
+    let archived = &data.archived_chats;
+    for message in [&mut data.active_message, &mut data.menu_message] {
+        if let Some((chat_id, _)) = message.filter(|(chat_id, _)| archived.contains_key(chat_id)) {
+            *message = None;
+            report.push(format!("Forgot the message in archived chat {}", chat_id));
+        }
+    }
+    let (held, orphaned): (Vec<_>, Vec<_>) = data.held_messages.drain(..).partition(|held| !archived.contains_key(&held.chat_id));
+    data.held_messages = held;
+    for held in orphaned {
+        quarantine(path, "held message for an archived chat", serde_json::to_value(&held)?).await?;
+        report.push(format!("Removed a held message for archived chat {}", held.chat_id));
+    }
+    Ok(())
+}
+
+/// Upgrades data written by older versions and returns a description of each step taken.
+pub fn migrate(data: &mut Data) -> Vec<String> {
+    let mut steps = Vec::new();
+    if data.version < 1 {
+        // Items stored as (name, done) tuples are converted while deserializing
+        steps.push("0 → 1: items converted from tuples to objects".to_string());
+        data.version = 1;
+    }
+    if data.version < 2 {
+        let names: Vec<String> = data.list.recipes.keys().chain(data.templates.keys()).cloned().collect();
+        for name in names {
+            data.names.register(&name);
+        }
+        steps.push("1 → 2: IDs registered for recipe and template buttons".to_string());
+        data.version = 2;
+    }
+    if data.version < 3 {
+        for user_id in std::mem::take(&mut data.settings.nudge_opt_out) {
+            data.notifications.entry(user_id).or_default().set(notifications::Kind::Reminders, false);
+        }
+        steps.push("2 → 3: nudge opt-outs moved to the notification preferences".to_string());
+        data.version = 3;
+    }
+    steps
+}
+
+/// Lists integrity problems that the bot would trip over.
+pub fn validate(data: &Data) -> Vec<String> {
+    let mut problems = Vec::new();
+    if data.version > SCHEMA_VERSION {
+        problems.push(format!("Schema version {} is newer than this build ({})", data.version, SCHEMA_VERSION));
+    }
+    for (i, item) in data.list.items.iter().enumerate() {
+        if item.name.trim().is_empty() {
+            problems.push(format!("Item {} has an empty name", i + 1));
+        }
+        if let Some(assignee) = item.assignee {
+            if !data.users.contains_key(&assignee) {
+                problems.push(format!("Item {} ({}) is assigned to unknown user {}", i + 1, item.name, assignee));
+            }
+        }
+        if item.quantity == 0 {
+            problems.push(format!("Item {} ({}) has a quantity of 0", i + 1, item.name));
+        }
+    }
+    for (name, recipe) in data.list.recipes.iter() {
+        if name.trim().is_empty() {
+            problems.push("Recipe with an empty name".to_string());
+        }
+        if recipe.ingredients.is_empty() {
+            problems.push(format!("Recipe {} has no ingredients", name));
+        }
+    }
+    for (name, entries) in data.templates.iter() {
+        if entries.is_empty() {
+            problems.push(format!("Template {} is empty", name));
+        }
+    }
+    if data.current_recipe.is_some() && data.naming_template {
+        problems.push("Both a recipe and a template name are being entered".to_string());
+    }
+    if !(1..=3).contains(&data.settings.columns) {
+        problems.push(format!("Invalid column count {}", data.settings.columns));
+    }
+    problems
+}
@@ -0,0 +1,57 @@
+//! Built-in dictionary of ingredients that belong to the same group, used to
+//! recognize items like "Schlagsahne" as a dairy product.
+
+/// Group name and words that items of the group contain, all lowercase.
+const GROUPS: &[(&str, &[&str])] = &[
+    ("milch", &["milch", "sahne", "butter", "käse", "joghurt", "quark", "schmand", "mozzarella", "parmesan", "frischkäse"]),
+    ("gluten", &["weizen", "mehl", "brot", "brötchen", "nudeln", "pasta", "spaghetti", "lasagne", "roggen", "gerste", "dinkel", "couscous"]),
+    ("nüsse", &["nuss", "nüsse", "mandel", "haselnuss", "walnuss", "cashew", "pistazie"]),
+    ("erdnüsse", &["erdnuss", "erdnüsse", "erdnussbutter"]),
+    ("fleisch", &["fleisch", "hack", "wurst", "schinken", "speck", "salami", "hähnchen", "huhn", "pute", "rind", "schwein", "lamm"]),
+    ("fisch", &["fisch", "lachs", "thunfisch", "forelle", "hering", "kabeljau", "garnele", "shrimps"]),
+    ("ei", &["ei", "eier", "eigelb", "eiweiß", "mayonnaise"]),
+    ("soja", &["soja", "tofu", "edamame"]),
+];
+
+/// Diets that exclude whole groups.
+const DIETS: &[(&str, &[&str])] = &[
+    ("vegetarisch", &["fleisch", "fisch"]),
+    ("vegan", &["fleisch", "fisch", "milch", "ei"]),
+    ("laktose", &["milch"]),
+    ("laktosefrei", &["milch"]),
+    ("glutenfrei", &["gluten"]),
+];
+
+fn group(name: &str) -> Option<&'static [&'static str]> {
+    GROUPS.iter()
+        .find(|(group, words)| *group == name || words.contains(&name))
+        .map(|(_, words)| *words)
+}
+
+/// Words an item must not contain under the restriction, which is a diet, a
+/// group or any other ingredient.
+pub fn excluded_words(restriction: &str) -> Vec<String> {
+    let restriction = restriction.to_lowercase();
+    if let Some((_, groups)) = DIETS.iter().find(|(diet, _)| *diet == restriction) {
+        return groups.iter()
+            .filter_map(|name| group(name))
+            .flat_map(|words| words.iter().map(|word| word.to_string()))
+            .collect();
+    }
+    match group(&restriction) {
+        Some(words) => words.iter().map(|word| word.to_string()).collect(),
+        None => vec![restriction],
+    }
+}
+
+/// Whether `name` contains one of the words, compound words like "Schlagsahne" included.
+pub fn matches(name: &str, words: &[String]) -> bool {
+    let name = name.to_lowercase();
+    name.split(|c: char| !c.is_alphanumeric())
+        .any(|part| words.iter().any(|word| if word.chars().count() < 3 { part == word } else { part.contains(word.as_str()) }))
+}
+
+/// Every word of the dictionary, e.g. for autocompletion.
+pub fn words() -> impl Iterator<Item = &'static str> {
+    GROUPS.iter().flat_map(|(_, words)| words.iter().copied())
+}
@@ -0,0 +1,120 @@
+//! The list as it was on a past day, for `/asof 2024-12-24`. Every change of
+//! an item is recorded when the data is stored, replaying them up to a moment
+//! gives the list of that moment. Changes older than the history retention are
+//! folded into the list they led to, see `compact`, as are the oldest ones
+//! once there are more than `MAX_CHANGES`.
+
+use chrono::{DateTime, Utc};
+use serde::{Deserialize, Serialize};
+
+use crate::webhooks::Event;
+
+#[derive(Serialize, Deserialize, Clone, Copy, PartialEq)]
+#[serde(rename_all = "snake_case")]
+pub enum Kind {
+    Added,
+    Checked,
+    Unchecked,
+    Removed,
+}
+
+#[derive(Serialize, Deserialize, Clone)]
+pub struct Change {
+    pub at: DateTime<Utc>,
+    pub item: String,
+    pub kind: Kind,
+}
+
+/// The timeline is stored with the rest of the data on every change, beyond
+/// this the oldest half is folded into the list it led to.
+pub const MAX_CHANGES: usize = 5000;
+
+/// Appends the changes of items among the events, recipes aren't part of the list.
+pub fn record(timeline: &mut Vec<Change>, events: &[Event], at: DateTime<Utc>) {
+    for event in events {
+        let (item, kind) = match event {
+            Event::ItemAdded { item } => (item, Kind::Added),
+            Event::ItemChecked { item } => (item, Kind::Checked),
+            Event::ItemUnchecked { item } => (item, Kind::Unchecked),
+            Event::ItemRemoved { item } => (item, Kind::Removed),
+            Event::RecipeSaved { .. } | Event::Test { .. } => continue,
+        };
+        timeline.push(Change { at, item: item.clone(), kind });
+    }
+    if timeline.len() > MAX_CHANGES {
+        let cutoff = timeline[timeline.len() - MAX_CHANGES / 2].at;
+        compact(timeline, cutoff);
+    }
+}
+
+/// The changes that recreate a list, as the start of a timeline.
+pub fn starting_with(items: &[(String, bool)], at: DateTime<Utc>) -> Vec<Change> {
+    let mut timeline = Vec::new();
+    for (item, done) in items {
+        timeline.push(Change { at, item: item.clone(), kind: Kind::Added });
+        if *done {
+            timeline.push(Change { at, item: item.clone(), kind: Kind::Checked });
+        }
+    }
+    timeline
+}
+
+/// When the recording starts, nothing is known about the list before.
+pub fn since(timeline: &[Change]) -> Option<DateTime<Utc>> {
+    timeline.first().map(|change| change.at)
+}
+
+/// Names of the items on the list right before `at` and whether they were
+/// checked, `None` if the recording started later.
+pub fn list_at(timeline: &[Change], at: DateTime<Utc>) -> Option<Vec<(String, bool)>> {
+    if since(timeline)? >= at {
+        return None;
+    }
+    let mut items: Vec<(String, bool)> = Vec::new();
+    for change in timeline.iter().take_while(|change| change.at < at) {
+        let position = items.iter().position(|(item, _)| item.to_lowercase() == change.item.to_lowercase());
+        match (change.kind, position) {
+            (Kind::Added, None) => items.push((change.item.clone(), false)),
+            (Kind::Checked, Some(position)) => items[position].1 = true,
+            (Kind::Unchecked, Some(position)) => items[position].1 = false,
+            (Kind::Removed, Some(position)) => {
+                items.remove(position);
+            }
+            _ => {}
+        }
+    }
+    Some(items)
+}
+
+/// Replaces the changes before `cutoff` with the list they led to, returns whether anything changed.
+pub fn compact(timeline: &mut Vec<Change>, cutoff: DateTime<Utc>) -> bool {
+    let items = match list_at(timeline, cutoff) {
+        Some(items) => items,
+        None => return false,
+    };
+    let mut compacted = starting_with(&items, cutoff);
+    compacted.extend(timeline.drain(..).skip_while(|change| change.at < cutoff));
+    *timeline = compacted;
+    true
+}
+
+#[cfg(test)]
+mod tests {
+    use chrono::Duration;
+
+    use super::*;
+
+    #[test]
+    fn capped() {
+        let start = Utc::now();
+        let mut timeline = starting_with(&[("Milch".to_string(), false)], start);
+        for minute in 1..=MAX_CHANGES as i64 {
+            let item = format!("Item {}", minute);
+            record(&mut timeline, &[Event::ItemAdded { item: item.clone() }], start + Duration::minutes(minute));
+            record(&mut timeline, &[Event::ItemRemoved { item }], start + Duration::minutes(minute));
+        }
+        assert!(timeline.len() <= MAX_CHANGES);
+        let at = start + Duration::minutes(MAX_CHANGES as i64 + 1);
+        assert_eq!(list_at(&timeline, at), Some(vec![("Milch".to_string(), false)]));
+    }
+}
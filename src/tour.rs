@@ -0,0 +1,42 @@
+//! `/tour` walks a chat through the main flows: adding an item, checking it
+//! off, clearing what is done and writing a recipe. Each step is explained
+//! once the previous one was actually done in the chat, `/tour off` ends it.
+
+use serde::{Deserialize, Serialize};
+
+#[derive(Serialize, Deserialize, Clone, Copy, PartialEq, Debug)]
+pub enum Step {
+    AddItem,
+    CheckItem,
+    RemoveDone,
+    CreateRecipe,
+}
+
+const STEPS: [Step; 4] = [Step::AddItem, Step::CheckItem, Step::RemoveDone, Step::CreateRecipe];
+
+pub const FINISHED_TEXT: &str = "🎉 Das war die Tour, viel Spaß mit eurer Liste!";
+pub const ENDED_TEXT: &str = "Tour beendet, mit /tour geht es von vorne los.";
+
+impl Step {
+    pub fn first() -> Self {
+        STEPS[0]
+    }
+
+    fn position(self) -> usize {
+        STEPS.iter().position(|step| *step == self).unwrap_or_default()
+    }
+
+    pub fn next(self) -> Option<Self> {
+        STEPS.get(self.position() + 1).copied()
+    }
+
+    pub fn instructions(self) -> String {
+        let text = match self {
+            Step::AddItem => "Schreibt etwas, das ihr braucht, zum Beispiel „Milch“, und es kommt auf die Liste.",
+            Step::CheckItem => "Tippt in der Liste auf einen Eintrag, um ihn abzuhaken.",
+            Step::RemoveDone => "Nach dem Einkauf räumt 💚 alles Abgehakte von der Liste.",
+            Step::CreateRecipe => "Unter 📝🛒 legt ihr Rezepte an: Namen und Zutaten schreiben, dann fertig tippen. Ein Rezept bringt später alle Zutaten auf einmal auf die Liste.",
+        };
+        format!("🧭 Schritt {}/{}: {}", self.position() + 1, STEPS.len(), text)
+    }
+}
@@ -0,0 +1,153 @@
+//! Moving and copying items to another list: the list of every bot the user
+//! uses and their personal list, see `personal`.
+//!
+//! Both lists are locked one after the other, never at the same time, so two
+//! transfers in opposite directions can't wait for each other.
+
+use std::sync::{Arc, Mutex, Weak};
+
+use lazy_static::lazy_static;
+use teloxide::prelude::*;
+use teloxide::types::{ChatId, InlineKeyboardMarkup};
+
+use crate::callback::Action;
+use crate::{personal, store_data, Data, Instance, Item, View, ENVIRONMENT};
+
+lazy_static! {
+    /// The shared list of every bot, with the bot to render it.
+    static ref LISTS: Mutex<Vec<(Bot, Weak<Instance>)>> = Mutex::new(Vec::new());
+}
+
+pub fn register(bot: &Bot, instance: &Arc<Instance>) {
+    LISTS.lock().unwrap().push((bot.clone(), Arc::downgrade(instance)));
+}
+
+struct Target {
+    /// Stays the same while the user joins or leaves lists, see `targets`.
+    key: usize,
+    name: String,
+    bot: Bot,
+    instance: Arc<Instance>,
+}
+
+fn name(data: &Data, namespace: &str) -> String {
+    if data.owner.is_some() {
+        "Meine Liste".to_string()
+    } else if let Some(store) = data.store() {
+        store.to_string()
+    } else if !namespace.is_empty() {
+        namespace.to_string()
+    } else {
+        "Gemeinsame Liste".to_string()
+    }
+}
+
+/// Every list the user can put items on. Buttons refer to them by their key,
+/// the position of the bot and whether it is the personal list.
+async fn targets(user_id: i64) -> Vec<Target> {
+    let lists: Vec<(usize, Bot, Arc<Instance>)> = LISTS.lock().unwrap().iter()
+        .enumerate()
+        .filter_map(|(position, (bot, instance))| Some((position, bot.clone(), instance.upgrade()?)))
+        .collect();
+    let mut targets = Vec::new();
+    for (position, bot, instance) in lists {
+        let (member, personal, shared_name) = {
+            let data = instance.data.lock().await;
+            (data.users.contains_key(&user_id), data.personal_lists.contains(&user_id), name(&data, &instance.namespace))
+        };
+        if member {
+            targets.push(Target { key: position * 2, name: shared_name, bot: bot.clone(), instance: instance.clone() });
+        }
+        if personal {
+            let list = personal::list_for_chat(&instance, user_id).await;
+            let name = name(&*list.data.lock().await, &list.namespace);
+            targets.push(Target { key: position * 2 + 1, name, bot, instance: list });
+        }
+    }
+    targets
+}
+
+/// Shows the lists the item picked with `Data::transfer_item` can go to.
+pub async fn show_targets(bot: Bot, instance: Arc<Instance>, chat_id: i64, user_id: i64) {
+    let targets = targets(user_id).await;
+    let ctx = UpdateWithCx { requester: bot, update: ChatId::Id(chat_id) };
+    {
+        let mut guard = instance.data.lock().await;
+        let label = match guard.transfer_item.and_then(|id| guard.list.items.iter().find(|item| item.id == id)) {
+            Some(item) => item.label(),
+            None => return,
+        };
+        let markup = targets.iter()
+            .filter(|target| !Arc::ptr_eq(&target.instance, &instance))
+            .fold(InlineKeyboardMarkup::default(), |markup, target| {
+                markup.append_row(vec![
+                    Action::MoveTo(target.key).button(format!("➡️ {}", target.name)),
+                    Action::CopyTo(target.key).button("⧉"),
+                ])
+            });
+        let text = if markup.inline_keyboard.is_empty() {
+            "Du nutzt keine andere Liste. Eine eigene gibt es mit /personal im privaten Chat.".to_string()
+        } else {
+            format!("Wohin mit {}? ⧉ kopiert es nur.", teloxide::utils::html::escape(&label))
+        };
+        if let Err(error) = guard.replace_menu(&ctx, text, Some(markup)).await {
+            log::error!("Couldn't show the lists: {:?}", error);
+        }
+    }
+    store_data(&instance).await
+}
+
+/// Moves or copies the picked item to the target list and answers the button with the outcome.
+///
+/// A moved item is only removed once the target list with it is stored, so a
+/// crash in between leaves it on both lists rather than on none.
+pub async fn transfer(bot: Bot, query_id: String, instance: Arc<Instance>, chat_id: i64, user_id: i64, key: usize, copy: bool) {
+    let target = match targets(user_id).await.into_iter().find(|target| target.key == key) {
+        Some(target) if !Arc::ptr_eq(&target.instance, &instance) => target,
+        _ => return,
+    };
+    let item = {
+        let guard = instance.data.lock().await;
+        let id = guard.transfer_item;
+        match guard.list.items.iter().find(|item| item.id != 0 && Some(item.id) == id) {
+            Some(item) => item.clone(),
+            None => return,
+        }
+    };
+    let label = item.label();
+    let delivered = {
+        let mut target_guard = target.instance.data.lock().await;
+        match ENVIRONMENT.input_limits.check_item_count(target_guard.list.items.len() + 1) {
+            Ok(()) => {
+                target_guard.list.items.push(Item { id: 0, ..item.clone() });
+                target_guard.show_outside_change(&target.bot, "another list").await;
+                Ok(())
+            }
+            Err(error) => Err(error),
+        }
+    };
+    store_data(&target.instance).await;
+
+    let ctx = UpdateWithCx { requester: bot.clone(), update: ChatId::Id(chat_id) };
+    let text = {
+        let mut guard = instance.data.lock().await;
+        let text = match delivered {
+            Ok(()) => {
+                guard.transfer_item = None;
+                if !copy {
+                    guard.list.items.retain(|kept| kept.id != item.id);
+                }
+                format!("{} {} → {}", if copy { "⧉" } else { "➡️" }, label, target.name)
+            }
+            Err(error) => error.to_string(),
+        };
+        if let Err(error) = guard.render_view(&ctx, View::Transfer).await {
+            log::error!("Couldn't render the list: {:?}", error);
+        }
+        text
+    };
+    store_data(&instance).await;
+    if let Err(error) = bot.answer_callback_query(query_id).text(text).send().await {
+        log::warn!("Couldn't answer the transfer: {:?}", error);
+    }
+}
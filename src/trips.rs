@@ -0,0 +1,100 @@
+//! Shopping trips: what was checked off when the checked items were cleared,
+//! browsable with `/trips` and addable to the list again in one go. A trip can
+//! be timed by starting it in the menu and ending it when the shopping is done.
+
+use std::collections::HashMap;
+
+use chrono::{DateTime, Utc};
+use chrono_tz::Tz;
+use serde::{Deserialize, Serialize};
+use teloxide::utils::html;
+
+use crate::history::Purchase;
+use crate::domain::KnownUser;
+
+/// Older trips are dropped, so the data file doesn't grow forever.
+pub const MAX_TRIPS: usize = 50;
+/// Trips shown by `/trips`.
+pub const SHOWN_TRIPS: usize = 10;
+/// Purchases this close to a trip are part of it.
+const PURCHASE_SECONDS: i64 = 60;
+
+#[derive(Serialize, Deserialize, Clone)]
+pub struct TripItem {
+    pub name: String,
+    pub quantity: u32,
+}
+
+#[derive(Serialize, Deserialize, Clone)]
+pub struct Trip {
+    /// Counts up, so buttons keep referring to the same trip when old ones are dropped.
+    pub id: u32,
+    pub at: DateTime<Utc>,
+    pub store: Option<String>,
+    /// Everyone who checked off an item.
+    pub shoppers: Vec<i64>,
+    pub items: Vec<TripItem>,
+    /// When the trip was started in the menu, if it was.
+    #[serde(default)]
+    pub started: Option<DateTime<Utc>>,
+}
+
+impl Trip {
+    /// "16.10. Rewe", the store only if one was set.
+    pub fn name(&self, timezone: Tz) -> String {
+        let date = self.at.with_timezone(&timezone).format("%d.%m.").to_string();
+        match &self.store {
+            Some(store) => format!("{} {}", date, store),
+            None => date,
+        }
+    }
+
+    /// What the items of the trip cost, if any of their prices are known, e.g. from the receipt.
+    pub fn spent(&self, history: &[Purchase]) -> Option<u64> {
+        // The purchases are recorded along with the trip, when the checked items are cleared
+        let prices: Vec<u64> = history.iter()
+            .filter(|purchase| purchase.bought() && (self.at - purchase.at).num_seconds().abs() < PURCHASE_SECONDS)
+            .filter_map(|purchase| purchase.price)
+            .collect();
+        if prices.is_empty() {
+            None
+        } else {
+            Some(prices.iter().sum())
+        }
+    }
+
+    /// How long the trip took, if it was timed.
+    pub fn minutes(&self) -> Option<i64> {
+        self.started.map(|started| (self.at - started).num_minutes())
+    }
+
+    pub fn describe(&self, users: &HashMap<i64, KnownUser>, timezone: Tz) -> String {
+        let shoppers: Vec<String> = self.shoppers.iter()
+            .filter_map(|id| users.get(id))
+            .map(KnownUser::name)
+            .collect();
+        let mut shoppers = if shoppers.is_empty() { String::new() } else { format!(" ({})", shoppers.join(", ")) };
+        if let Some(minutes) = self.minutes() {
+            shoppers = format!("{} in {} min", shoppers, minutes);
+        }
+        let items: Vec<&str> = self.items.iter().map(|item| item.name.as_str()).collect();
+        format!(
+            "<b>{}</b>{}: {}",
+            html::escape(&self.name(timezone)),
+            html::escape(&shoppers),
+            html::escape(&items.join(", ")),
+        )
+    }
+}
+
+/// Records a trip, dropping the oldest ones beyond `MAX_TRIPS`.
+pub fn record(trips: &mut Vec<Trip>, store: Option<String>, shoppers: Vec<i64>, items: Vec<TripItem>, started: Option<DateTime<Utc>>) {
+    if items.is_empty() {
+        return;
+    }
+    let id = trips.last().map(|trip| trip.id + 1).unwrap_or(0);
+    trips.push(Trip { id, at: Utc::now(), store, shoppers, items, started });
+    if trips.len() > MAX_TRIPS {
+        trips.drain(..trips.len() - MAX_TRIPS);
+    }
+}
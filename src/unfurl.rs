@@ -0,0 +1,195 @@
+//! Links in new items: the link goes to a 🔗 button on the item's row, and an
+//! item that is nothing but a link is named after the title of the page.
+//!
+//! The links come from users, so only public addresses are fetched, not the
+//! bot's own machine or network.
+
+use std::net::{IpAddr, Ipv4Addr, Ipv6Addr, SocketAddr};
+use std::time::Duration;
+
+use reqwest::header::{CONTENT_TYPE, LOCATION};
+use reqwest::redirect::Policy;
+use reqwest::{Response, Url};
+
+const TIMEOUT: Duration = Duration::from_secs(5);
+/// Redirects followed to the page, each one is checked like the link.
+const MAX_REDIRECTS: usize = 5;
+/// The title is in the head, pages aren't read any further than this.
+const MAX_BYTES: usize = 64 * 1024;
+const MAX_TITLE_CHARS: usize = 80;
+
+/// A link-only item named after the host until the title of the page is known.
+#[derive(Clone)]
+pub struct Pending {
+    pub item_id: u64,
+    pub user_id: i64,
+    pub url: String,
+}
+
+/// Takes the first link out of the text, which is kept as typed if there is none.
+pub fn split_url(text: &str) -> (String, Option<String>) {
+    let url = match text.split_whitespace().find(|word| is_url(word)) {
+        Some(url) => url.to_string(),
+        None => return (text.to_string(), None),
+    };
+    let rest: Vec<&str> = text.split_whitespace().filter(|word| *word != url).collect();
+    (rest.join(" "), Some(url))
+}
+
+fn is_url(word: &str) -> bool {
+    (word.starts_with("https://") || word.starts_with("http://")) && Url::parse(word).is_ok()
+}
+
+/// The host of the link, the name of an item if the page has no title.
+pub fn host(url: &str) -> String {
+    Url::parse(url).ok()
+        .and_then(|url| url.host_str().map(|host| host.trim_start_matches("www.").to_string()))
+        .unwrap_or_else(|| url.to_string())
+}
+
+/// The IPv4 address an IPv6 address leads to: mapped and compatible ones
+/// (`::ffff:a.b.c.d`, `::a.b.c.d`), NAT64 (`64:ff9b::a.b.c.d`) and 6to4 (`2002:ab:cd::`).
+fn embedded_ipv4(ip: Ipv6Addr) -> Option<Ipv4Addr> {
+    let octets = ip.octets();
+    match ip.segments() {
+        [0x64, 0xff9b, 0, 0, 0, 0, _, _] => Some(Ipv4Addr::new(octets[12], octets[13], octets[14], octets[15])),
+        [0x2002, ..] => Some(Ipv4Addr::new(octets[2], octets[3], octets[4], octets[5])),
+        _ => ip.to_ipv4(),
+    }
+}
+
+/// Whether an address is reachable from anywhere, not one of the loopback,
+/// private, link-local or otherwise special ranges.
+fn is_public(ip: IpAddr) -> bool {
+    match ip {
+        IpAddr::V4(ip) => {
+            let [first, second, ..] = ip.octets();
+            !(ip.is_unspecified()
+                || ip.is_loopback()
+                || ip.is_private()
+                || ip.is_link_local()
+                || ip.is_broadcast()
+                || ip.is_documentation()
+                || ip.is_multicast()
+                // Shared address space of carrier-grade NAT
+                || (first == 100 && (64..128).contains(&second))
+                || first == 0
+                || first >= 240)
+        }
+        IpAddr::V6(ip) => {
+            if let Some(ip) = embedded_ipv4(ip) {
+                return is_public(IpAddr::V4(ip));
+            }
+            let first = ip.segments()[0];
+            !(ip.is_unspecified()
+                || ip.is_loopback()
+                || ip.is_multicast()
+                // Unique local and link-local addresses
+                || (first & 0xfe00) == 0xfc00
+                || (first & 0xffc0) == 0xfe80)
+        }
+    }
+}
+
+/// The addresses the host of `url` resolves to, if it is a web address and they are all public.
+///
+/// Also used for the targets of webhooks, see `webhooks`.
+pub async fn public_addresses(url: &Url) -> anyhow::Result<Vec<SocketAddr>> {
+    if !matches!(url.scheme(), "http" | "https") {
+        anyhow::bail!("Not a web page");
+    }
+    let host = url.host_str().ok_or_else(|| anyhow::anyhow!("No host"))?;
+    let port = url.port_or_known_default().unwrap_or(80);
+    let addresses: Vec<SocketAddr> = tokio::net::lookup_host((host.trim_start_matches('[').trim_end_matches(']'), port)).await?.collect();
+    if addresses.is_empty() || !addresses.iter().all(|address| is_public(address.ip())) {
+        anyhow::bail!("{} isn't a public address", host);
+    }
+    Ok(addresses)
+}
+
+/// Requests `url` from the addresses its host resolves to, if they are all public.
+async fn get(url: &Url) -> anyhow::Result<Response> {
+    let addresses = public_addresses(url).await?;
+    let host = url.host_str().ok_or_else(|| anyhow::anyhow!("No host"))?;
+    // The addresses that were checked are used, the host isn't looked up again
+    let client = reqwest::Client::builder()
+        .redirect(Policy::none())
+        // A proxy would connect on its own, to whatever the host resolves to there
+        .no_proxy()
+        .resolve_to_addrs(host, &addresses)
+        .timeout(TIMEOUT)
+        .build()?;
+    Ok(client.get(url.clone()).send().await?)
+}
+
+/// Follows redirects, checking the address of each one.
+async fn fetch(url: &str) -> anyhow::Result<Response> {
+    let mut url = Url::parse(url)?;
+    for _ in 0..=MAX_REDIRECTS {
+        let response = get(&url).await?;
+        if !response.status().is_redirection() {
+            return Ok(response);
+        }
+        let location = response.headers()
+            .get(LOCATION)
+            .and_then(|location| location.to_str().ok())
+            .ok_or_else(|| anyhow::anyhow!("Redirect without a location"))?;
+        url = url.join(location)?;
+    }
+    anyhow::bail!("More than {} redirects", MAX_REDIRECTS)
+}
+
+/// The title of the HTML page at `url`, `None` if it can't be read in time.
+pub async fn title(url: &str) -> Option<String> {
+    let response = tokio::time::timeout(TIMEOUT, fetch(url)).await;
+    let mut response = match response {
+        Ok(Ok(response)) => response,
+        Err(_) => {
+            log::warn!("Couldn't fetch the title of {} in time", url);
+            return None;
+        }
+        Ok(Err(error)) => {
+            log::warn!("Couldn't fetch the title of {}: {:?}", url, error);
+            return None;
+        }
+    };
+    let is_html = response.headers()
+        .get(CONTENT_TYPE)
+        .and_then(|value| value.to_str().ok())
+        .map(|value| value.starts_with("text/html"))
+        .unwrap_or(false);
+    if !is_html {
+        return None;
+    }
+    let mut body = Vec::new();
+    while body.len() < MAX_BYTES {
+        match response.chunk().await {
+            Ok(Some(chunk)) => body.extend_from_slice(&chunk),
+            _ => break,
+        }
+        if find_title(&String::from_utf8_lossy(&body)).is_some() {
+            break;
+        }
+    }
+    find_title(&String::from_utf8_lossy(&body))
+}
+
+/// The text of the `<title>` element, unescaped and shortened.
+fn find_title(html: &str) -> Option<String> {
+    // ASCII only, so the positions stay the same in `html`
+    let lowercase = html.to_ascii_lowercase();
+    let start = lowercase.find("<title")?;
+    let start = start + lowercase[start..].find('>')? + 1;
+    let end = start + lowercase[start..].find("</title")?;
+    let title = html[start..end].split_whitespace().collect::<Vec<_>>().join(" ");
+    let title = title
+        .replace("&quot;", "\"")
+        .replace("&#39;", "'")
+        .replace("&lt;", "<")
+        .replace("&gt;", ">")
+        .replace("&amp;", "&");
+    if title.is_empty() {
+        return None;
+    }
+    Some(title.chars().take(MAX_TITLE_CHARS).collect())
+}
@@ -0,0 +1,158 @@
+//! Pack sizes typed as arithmetic, "Mehl 3*500g" becomes "Mehl 1,5 kg", or
+//! "1.5 kg" in English. Numbers may use a decimal comma like "2x1,5l". Units
+//! after a number are written the chat's way, "2 tbsp" becomes "2 EL".
+
+use crate::i18n::{self, Language};
+
+/// Operators between the numbers of a pack size, `+` between several packs.
+const TIMES: &[char] = &['*', 'x', '×'];
+
+#[derive(Clone, Copy, PartialEq)]
+enum Dimension {
+    /// In grams.
+    Mass,
+    /// In milliliters.
+    Volume,
+}
+
+/// The dimension of the unit and its size in grams or milliliters.
+fn unit(name: &str) -> Option<(Dimension, f64)> {
+    Some(match name.to_lowercase().as_str() {
+        "g" => (Dimension::Mass, 1.0),
+        "kg" => (Dimension::Mass, 1000.0),
+        "ml" => (Dimension::Volume, 1.0),
+        "cl" => (Dimension::Volume, 10.0),
+        "l" => (Dimension::Volume, 1000.0),
+        _ => return None,
+    })
+}
+
+/// A unit like `EL` or `tbsp` in either language, with or without the dot.
+fn count_unit(name: &str) -> Option<i18n::Unit> {
+    let name = name.trim_end_matches('.').to_lowercase();
+    i18n::UNITS.iter().copied().find(|unit| {
+        i18n::LANGUAGES.iter().any(|language| i18n::unit(*language, *unit).trim_end_matches('.').to_lowercase() == name)
+    })
+}
+
+/// Parses `1,5` as well as `1.5`.
+fn number(text: &str) -> Option<f64> {
+    if text.is_empty() || !text.chars().all(|c| c.is_ascii_digit() || c == ',' || c == '.') {
+        return None;
+    }
+    text.replace(',', ".").parse().ok()
+}
+
+/// A factor like `500g`, `500` or `1,5kg`, with its unit if it has one.
+fn factor(text: &str) -> Option<(f64, Option<(Dimension, f64)>)> {
+    let split = text.find(|c: char| c.is_alphabetic()).unwrap_or(text.len());
+    let (value, suffix) = text.split_at(split);
+    let value = number(value)?;
+    if suffix.is_empty() {
+        Some((value, None))
+    } else {
+        Some((value, Some(unit(suffix)?)))
+    }
+}
+
+/// One pack size like `3*500g`, in grams or milliliters.
+fn term(text: &str) -> Option<(Dimension, f64)> {
+    let mut total = 1.0;
+    let mut dimension = None;
+    for part in text.split(TIMES) {
+        let (value, unit) = factor(part)?;
+        match unit {
+            Some((found, size)) if dimension.is_none() => {
+                dimension = Some(found);
+                total *= value * size;
+            }
+            // Only one of the numbers has a unit
+            Some(_) => return None,
+            None => total *= value,
+        }
+    }
+    Some((dimension?, total))
+}
+
+/// The total of an expression like `3*500g` or `1kg+500g`, `None` if it isn't one.
+pub fn evaluate(expression: &str, language: Language) -> Option<String> {
+    if !expression.contains(|c: char| TIMES.contains(&c) || c == '+') {
+        return None;
+    }
+    let mut sum = None;
+    for text in expression.split('+') {
+        let (dimension, value) = term(text)?;
+        sum = match sum {
+            None => Some((dimension, value)),
+            Some((previous, total)) if previous == dimension => Some((dimension, total + value)),
+            Some(_) => return None,
+        };
+    }
+    let (dimension, total) = sum?;
+    Some(format(dimension, total, language))
+}
+
+/// `1500` grams as `1,5 kg` or `1.5 kg`.
+fn format(dimension: Dimension, total: f64, language: Language) -> String {
+    let (value, unit) = match (dimension, total >= 1000.0) {
+        (Dimension::Mass, true) => (total / 1000.0, "kg"),
+        (Dimension::Mass, false) => (total, "g"),
+        (Dimension::Volume, true) => (total / 1000.0, "l"),
+        (Dimension::Volume, false) => (total, "ml"),
+    };
+    format!("{} {}", language.decimal(value), unit)
+}
+
+/// Replaces pack size expressions in an item with their total, spaces around
+/// the operators are allowed: "Mehl 3 * 500 g" becomes "Mehl 1,5 kg".
+pub fn normalize(text: &str, language: Language) -> String {
+    let is_operator = |word: &str| word.chars().count() == 1 && word.chars().all(|c| TIMES.contains(&c) || c == '+');
+    let starts_with_digit = |word: &str| word.chars().next().map(|c| c.is_ascii_digit()) == Some(true);
+    // Candidate expressions with the words they were joined from, to keep those if it isn't one
+    let mut groups: Vec<(String, Vec<&str>)> = Vec::new();
+    let mut after_operator = false;
+    for word in text.split_whitespace() {
+        let operator = is_operator(word);
+        match groups.last_mut() {
+            Some((expression, words)) if starts_with_digit(expression) && (
+                operator
+                    || (after_operator && starts_with_digit(word))
+                    || (unit(word).is_some() && expression.contains(|c: char| TIMES.contains(&c) || c == '+'))
+            ) => {
+                expression.push_str(word);
+                words.push(word);
+            }
+            _ => groups.push((word.to_string(), vec![word])),
+        }
+        after_operator = operator;
+    }
+    groups.iter()
+        .enumerate()
+        .map(|(i, (expression, words))| {
+            let after_number = i > 0 && groups[i - 1].0.chars().next().map(|c| c.is_ascii_digit()) == Some(true);
+            match count_unit(expression) {
+                Some(unit) if after_number => i18n::unit(language, unit).to_string(),
+                _ => evaluate(expression, language).unwrap_or_else(|| words.join(" ")),
+            }
+        })
+        .collect::<Vec<_>>()
+        .join(" ")
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn decimal_commas() {
+        assert_eq!(evaluate("2x1,5l", Language::German).as_deref(), Some("3 l"));
+        assert_eq!(evaluate("3*500g", Language::German).as_deref(), Some("1,5 kg"));
+        assert_eq!(evaluate("3*500g", Language::English).as_deref(), Some("1.5 kg"));
+        assert_eq!(evaluate("2x0,75l", Language::German).as_deref(), Some("1,5 l"));
+        assert_eq!(evaluate("1,5kg+250g", Language::German).as_deref(), Some("1,75 kg"));
+        assert_eq!(evaluate("2x1,5", Language::German), None);
+        assert_eq!(normalize("Mehl 3 * 500 g", Language::German), "Mehl 1,5 kg");
+        assert_eq!(normalize("Wasser 6x1,5l", Language::German), "Wasser 9 l");
+        assert_eq!(normalize("Milch 1,5%", Language::German), "Milch 1,5%");
+    }
+}
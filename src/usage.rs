@@ -0,0 +1,54 @@
+//! Numbers for the operator's `/usage`, counted in the process and read from
+//! the data files, nothing is sent anywhere.
+
+use std::sync::atomic::{AtomicU64, Ordering};
+
+use chrono::{DateTime, Utc};
+use lazy_static::lazy_static;
+
+use crate::{postgres, redis, storage, ENVIRONMENT};
+
+static REQUESTS: AtomicU64 = AtomicU64::new(0);
+static FAILED_REQUESTS: AtomicU64 = AtomicU64::new(0);
+
+lazy_static! {
+    static ref STARTED: DateTime<Utc> = Utc::now();
+}
+
+/// Remembers when the bot started.
+pub fn start() {
+    lazy_static::initialize(&STARTED);
+}
+
+/// Counts a message or edit sent to Telegram, see `outbox`.
+pub fn record_request(ok: bool) {
+    REQUESTS.fetch_add(1, Ordering::Relaxed);
+    if !ok {
+        FAILED_REQUESTS.fetch_add(1, Ordering::Relaxed);
+    }
+}
+
+/// Requests to Telegram since the start, how many failed and when the bot started.
+pub fn requests() -> (u64, u64, DateTime<Utc>) {
+    (REQUESTS.load(Ordering::Relaxed), FAILED_REQUESTS.load(Ordering::Relaxed), *STARTED)
+}
+
+/// Bytes of the data files of every bot, `None` with Redis or Postgres.
+pub async fn storage_size() -> Option<u64> {
+    if redis::configured() || postgres::configured() {
+        return None;
+    }
+    let mut size = 0;
+    for bot in ENVIRONMENT.bots.iter() {
+        size += storage::size(&ENVIRONMENT.data_path(&bot.namespace)).await;
+    }
+    Some(size)
+}
+
+pub fn describe_size(bytes: u64) -> String {
+    match bytes {
+        0..=1023 => format!("{} B", bytes),
+        1024..=1_048_575 => format!("{:.1} KB", bytes as f64 / 1024.0),
+        _ => format!("{:.1} MB", bytes as f64 / 1_048_576.0),
+    }
+}
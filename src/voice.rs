@@ -0,0 +1,191 @@
+//! Bridge for voice assistants: an Alexa skill can use `/voice/alexa` as its
+//! endpoint, a Google Assistant action `/voice/google` as the fulfillment of
+//! its Dialogflow agent. Both need these intents, with the slots or
+//! parameters in parentheses:
+//!
+//! - `AddItemIntent` (`item`): "füge Milch zur Einkaufsliste hinzu"
+//! - `RemoveItemIntent` (`item`): "nimm Milch von der Einkaufsliste"
+//! - `CheckItemIntent` (`item`): "hake Milch ab"
+//! - `ListItemsIntent`: "was steht auf der Einkaufsliste"
+//! - `LinkIntent` (`code`): "verbinde mit Code 123456"
+//!
+//! An assistant user belongs to no household until they said the code `/voice`
+//! shows in the household's chat, see `Data::voice_users`. Requests are
+//! checked in `voice_auth`.
+
+use std::collections::HashMap;
+use std::sync::Mutex;
+use std::time::Instant;
+
+use chrono::{DateTime, Utc};
+use lazy_static::lazy_static;
+use rand::Rng;
+use serde::{Deserialize, Serialize};
+use serde_json::{json, Value};
+
+/// Minutes a linking code is valid.
+pub const CODE_MINUTES: i64 = 10;
+/// Wrong codes said while a code is valid before it is dropped, `/voice` makes a new one.
+pub const MAX_FAILED_ATTEMPTS: u32 = 5;
+/// Wrong codes an assistant user may say within `CODE_MINUTES`.
+const MAX_ATTEMPTS_PER_USER: usize = 5;
+
+lazy_static! {
+    /// When each assistant user said a wrong code.
+    static ref FAILED_ATTEMPTS: Mutex<HashMap<String, Vec<Instant>>> = Mutex::new(HashMap::new());
+}
+
+pub enum Intent {
+    /// The skill was opened without saying what to do.
+    Launch,
+    Link(String),
+    Add(String),
+    Remove(String),
+    Check(String),
+    List,
+    Help,
+    Stop,
+}
+
+#[derive(Clone, Copy)]
+pub enum Assistant {
+    Alexa,
+    Google,
+}
+
+impl Assistant {
+    /// The assistant of the last part of the endpoint's path.
+    pub fn parse(name: &str) -> Option<Self> {
+        match name {
+            "alexa" => Some(Assistant::Alexa),
+            "google" => Some(Assistant::Google),
+            _ => None,
+        }
+    }
+
+    pub fn request(&self, request: &Value) -> Option<Request> {
+        match self {
+            Assistant::Alexa => from_alexa(request),
+            Assistant::Google => from_dialogflow(request),
+        }
+    }
+
+    pub fn response(&self, text: &str, end_session: bool) -> Value {
+        match self {
+            Assistant::Alexa => to_alexa(text, end_session),
+            Assistant::Google => to_dialogflow(text, end_session),
+        }
+    }
+}
+
+/// A request of either assistant.
+pub struct Request {
+    /// ID of the assistant user, stable across sessions.
+    pub user: String,
+    pub intent: Intent,
+}
+
+#[derive(Serialize, Deserialize, Clone)]
+pub struct LinkCode {
+    pub code: String,
+    pub expires: DateTime<Utc>,
+    /// Wrong codes said while this one was valid.
+    #[serde(default)]
+    pub failed_attempts: u32,
+}
+
+impl LinkCode {
+    pub fn new(now: DateTime<Utc>) -> Self {
+        LinkCode {
+            code: format!("{:06}", rand::thread_rng().gen_range(0..1_000_000)),
+            expires: now + chrono::Duration::minutes(CODE_MINUTES),
+            failed_attempts: 0,
+        }
+    }
+
+    /// Whether `spoken` is the code, assistants may put spaces between the digits
+    /// or, taking it for a number, drop leading zeros.
+    pub fn matches(&self, spoken: &str, now: DateTime<Utc>) -> bool {
+        let digits: String = spoken.chars().filter(|c| c.is_ascii_digit()).collect();
+        now < self.expires && !digits.is_empty() && digits.trim_start_matches('0') == self.code.trim_start_matches('0')
+    }
+}
+
+/// Whether the user said too many wrong codes lately to try again.
+pub fn is_throttled(user: &str) -> bool {
+    let window = std::time::Duration::from_secs(CODE_MINUTES as u64 * 60);
+    let mut attempts = FAILED_ATTEMPTS.lock().unwrap();
+    attempts.retain(|_, times| {
+        times.retain(|time| time.elapsed() < window);
+        !times.is_empty()
+    });
+    attempts.get(user).is_some_and(|times| times.len() >= MAX_ATTEMPTS_PER_USER)
+}
+
+pub fn record_failed_attempt(user: &str) {
+    FAILED_ATTEMPTS.lock().unwrap().entry(user.to_string()).or_default().push(Instant::now());
+}
+
+fn intent(name: &str, slot: impl Fn(&str) -> Option<String>) -> Option<Intent> {
+    let item = || slot("item").filter(|item| !item.trim().is_empty());
+    Some(match name {
+        "AddItemIntent" => Intent::Add(item()?),
+        "RemoveItemIntent" => Intent::Remove(item()?),
+        "CheckItemIntent" => Intent::Check(item()?),
+        "ListItemsIntent" => Intent::List,
+        "LinkIntent" => Intent::Link(slot("code")?),
+        "AMAZON.StopIntent" | "AMAZON.CancelIntent" => Intent::Stop,
+        _ => Intent::Help,
+    })
+}
+
+fn string(value: &Value, pointer: &str) -> Option<String> {
+    value.pointer(pointer).and_then(|value| value.as_str()).map(|value| value.to_string())
+}
+
+/// Reads the request envelope of an Alexa skill, `None` if it isn't one or lacks a slot.
+fn from_alexa(request: &Value) -> Option<Request> {
+    let user = string(request, "/session/user/userId").or_else(|| string(request, "/context/System/user/userId"))?;
+    let intent = match request.pointer("/request/type")?.as_str()? {
+        "LaunchRequest" => Intent::Launch,
+        "IntentRequest" => {
+            let name = string(request, "/request/intent/name")?;
+            intent(&name, |slot| string(request, &format!("/request/intent/slots/{}/value", slot)))?
+        }
+        _ => Intent::Stop,
+    };
+    Some(Request { user, intent })
+}
+
+fn to_alexa(text: &str, end_session: bool) -> Value {
+    json!({
+        "version": "1.0",
+        "response": {
+            "outputSpeech": { "type": "PlainText", "text": text },
+            "shouldEndSession": end_session,
+        },
+    })
+}
+
+/// Reads a Dialogflow fulfillment request coming from the Google Assistant.
+fn from_dialogflow(request: &Value) -> Option<Request> {
+    let user = string(request, "/originalDetectIntentRequest/payload/user/userId")?;
+    let name = string(request, "/queryResult/intent/displayName")?;
+    let intent = match name.as_str() {
+        "Default Welcome Intent" => Intent::Launch,
+        name => intent(name, |parameter| {
+            let value = request.pointer(&format!("/queryResult/parameters/{}", parameter))?;
+            // Numbers come as numbers, like the code
+            value.as_str().map(|value| value.to_string())
+                .or_else(|| value.as_f64().map(|number| format!("{}", number as u64)))
+        })?,
+    };
+    Some(Request { user, intent })
+}
+
+fn to_dialogflow(text: &str, end_session: bool) -> Value {
+    json!({
+        "fulfillmentText": text,
+        "payload": { "google": { "expectUserResponse": !end_session } },
+    })
+}
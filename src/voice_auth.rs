@@ -0,0 +1,168 @@
+//! Checks that requests to `/voice` come from the assistants.
+//!
+//! Alexa signs each request with a certificate from Amazon, the signature and
+//! the URL of the certificate chain come in headers. The request also has to be
+//! for the skill in `ALEXA_SKILL_ID`. Dialogflow sends the basic auth
+//! credentials set up for the fulfillment, `DIALOGFLOW_AUTH` as
+//! `user:password`. Without these variables every request of the assistant is
+//! refused.
+
+use std::collections::HashMap;
+use std::sync::Mutex;
+use std::time::{Duration, Instant};
+
+use chrono::{DateTime, Utc};
+use lazy_static::lazy_static;
+use openssl::asn1::Asn1Time;
+use openssl::hash::MessageDigest;
+use openssl::sign::Verifier;
+use openssl::stack::Stack;
+use openssl::x509::store::X509StoreBuilder;
+use openssl::x509::{X509, X509StoreContext};
+use serde_json::Value;
+use warp::http::HeaderMap;
+
+use crate::voice::Assistant;
+
+/// How far the timestamp of an Alexa request may be off.
+const MAX_AGE_SECONDS: i64 = 150;
+/// Where Amazon keeps the certificate chains.
+const CERTIFICATE_HOST: &str = "s3.amazonaws.com";
+const CERTIFICATE_PATH: &str = "/echo.api/";
+/// The name the signing certificate has to be for.
+const CERTIFICATE_NAME: &str = "echo-api.amazon.com";
+const MAX_CERTIFICATE_BYTES: usize = 64 * 1024;
+/// Checked certificates are used this long before they are downloaded again.
+const CERTIFICATE_TTL: Duration = Duration::from_secs(60 * 60);
+
+lazy_static! {
+    /// Signing certificates whose chain was checked, by URL.
+    static ref CERTIFICATES: Mutex<HashMap<String, (X509, Instant)>> = Mutex::new(HashMap::new());
+}
+
+/// Fails unless the request was made by the assistant for this bot.
+pub async fn verify(assistant: Assistant, headers: &HeaderMap, body: &[u8], request: &Value) -> anyhow::Result<()> {
+    match assistant {
+        Assistant::Alexa => verify_alexa(headers, body, request).await,
+        Assistant::Google => verify_dialogflow(headers),
+    }
+}
+
+fn header<'a>(headers: &'a HeaderMap, name: &str) -> anyhow::Result<&'a str> {
+    headers.get(name)
+        .ok_or_else(|| anyhow::anyhow!("No {} header", name))?
+        .to_str()
+        .map_err(|_| anyhow::anyhow!("The {} header isn't text", name))
+}
+
+async fn verify_alexa(headers: &HeaderMap, body: &[u8], request: &Value) -> anyhow::Result<()> {
+    let skill_id = std::env::var("ALEXA_SKILL_ID").map_err(|_| anyhow::anyhow!("ALEXA_SKILL_ID isn't set"))?;
+    let application = request.pointer("/session/application/applicationId")
+        .or_else(|| request.pointer("/context/System/application/applicationId"))
+        .and_then(Value::as_str);
+    if application != Some(skill_id.as_str()) {
+        anyhow::bail!("Request for skill {:?}", application);
+    }
+    let timestamp = request.pointer("/request/timestamp")
+        .and_then(Value::as_str)
+        .and_then(|timestamp| DateTime::parse_from_rfc3339(timestamp).ok())
+        .ok_or_else(|| anyhow::anyhow!("Request without a timestamp"))?;
+    if Utc::now().signed_duration_since(timestamp).num_seconds().abs() > MAX_AGE_SECONDS {
+        anyhow::bail!("Request from {}, too far from now", timestamp);
+    }
+    let certificate = certificate(header(headers, "SignatureCertChainUrl")?).await?;
+    let signature = base64::decode(header(headers, "Signature-256")?)?;
+    let key = certificate.public_key()?;
+    let mut verifier = Verifier::new(MessageDigest::sha256(), &key)?;
+    verifier.update(body)?;
+    if !verifier.verify(&signature)? {
+        anyhow::bail!("Wrong signature");
+    }
+    Ok(())
+}
+
+/// Whether the certificate chain is where Amazon keeps them, `https://s3.amazonaws.com/echo.api/...`.
+fn is_amazon_url(url: &reqwest::Url) -> bool {
+    // The URL is normalized, with the scheme and host in lower case and without `..`
+    url.scheme() == "https"
+        && url.host_str() == Some(CERTIFICATE_HOST)
+        && url.port().is_none_or(|port| port == 443)
+        && url.path().starts_with(CERTIFICATE_PATH)
+}
+
+/// The signing certificate at `url`, after checking it and its chain.
+async fn certificate(url: &str) -> anyhow::Result<X509> {
+    let now = Asn1Time::days_from_now(0)?;
+    if let Some((certificate, checked)) = CERTIFICATES.lock().unwrap().get(url) {
+        if checked.elapsed() < CERTIFICATE_TTL && certificate.not_after() > now {
+            return Ok(certificate.clone());
+        }
+    }
+    let parsed = reqwest::Url::parse(url)?;
+    if !is_amazon_url(&parsed) {
+        anyhow::bail!("Certificate chain from {}", url);
+    }
+    let client = reqwest::Client::builder()
+        .timeout(Duration::from_secs(5))
+        .redirect(reqwest::redirect::Policy::none())
+        .build()?;
+    let pem = client.get(parsed).send().await?.error_for_status()?.bytes().await?;
+    if pem.len() > MAX_CERTIFICATE_BYTES {
+        anyhow::bail!("Certificate chain of {} bytes", pem.len());
+    }
+    let certificates = X509::stack_from_pem(&pem)?;
+    let (certificate, intermediates) = certificates.split_first()
+        .ok_or_else(|| anyhow::anyhow!("No certificate at {}", url))?;
+    let mut chain = Stack::new()?;
+    for intermediate in intermediates {
+        chain.push(intermediate.clone())?;
+    }
+    let mut store = X509StoreBuilder::new()?;
+    store.set_default_paths()?;
+    let store = store.build();
+    let mut context = X509StoreContext::new()?;
+    // Also checks that every certificate is valid now
+    if !context.init(&store, certificate, &chain, |context| context.verify_cert())? {
+        anyhow::bail!("The certificate chain at {} isn't trusted", url);
+    }
+    let for_alexa = certificate.subject_alt_names()
+        .is_some_and(|names| names.iter().any(|name| name.dnsname() == Some(CERTIFICATE_NAME)));
+    if !for_alexa {
+        anyhow::bail!("The certificate at {} isn't for {}", url, CERTIFICATE_NAME);
+    }
+    CERTIFICATES.lock().unwrap().insert(url.to_string(), (certificate.clone(), Instant::now()));
+    Ok(certificate.clone())
+}
+
+fn verify_dialogflow(headers: &HeaderMap) -> anyhow::Result<()> {
+    let credentials = std::env::var("DIALOGFLOW_AUTH").map_err(|_| anyhow::anyhow!("DIALOGFLOW_AUTH isn't set"))?;
+    let expected = format!("Basic {}", base64::encode(credentials));
+    let given = header(headers, "Authorization")?;
+    // Compared in constant time, so the credentials can't be guessed byte by byte
+    if given.len() != expected.len() || !openssl::memcmp::eq(given.as_bytes(), expected.as_bytes()) {
+        anyhow::bail!("Wrong credentials");
+    }
+    Ok(())
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    fn is_amazon(url: &str) -> bool {
+        is_amazon_url(&reqwest::Url::parse(url).unwrap())
+    }
+
+    #[test]
+    fn certificate_urls() {
+        assert!(is_amazon("https://s3.amazonaws.com/echo.api/echo-api-cert.pem"));
+        assert!(is_amazon("https://s3.amazonaws.com:443/echo.api/echo-api-cert.pem"));
+        assert!(is_amazon("HTTPS://s3.AmazonAWS.com/echo.api/../echo.api/echo-api-cert.pem"));
+        assert!(!is_amazon("http://s3.amazonaws.com/echo.api/echo-api-cert.pem"));
+        assert!(!is_amazon("https://notamazon.com/echo.api/echo-api-cert.pem"));
+        assert!(!is_amazon("https://s3.amazonaws.com/EcHo.aPi/echo-api-cert.pem"));
+        assert!(!is_amazon("https://s3.amazonaws.com/invalid.path/echo-api-cert.pem"));
+        assert!(!is_amazon("https://s3.amazonaws.com/echo.api/../invalid.path/echo-api-cert.pem"));
+        assert!(!is_amazon("https://s3.amazonaws.com:563/echo.api/echo-api-cert.pem"));
+    }
+}
@@ -0,0 +1,235 @@
+//! Outgoing webhooks, set per chat with `/webhook <url>`: changes to the list
+//! are POSTed as JSON events to the URL, like
+//!
+//! `{"event": "item_added", "item": "Milch", "at": "2021-06-01T10:00:00Z"}`
+//!
+//! Events are found by comparing the list with its last version whenever the
+//! data is stored, and wait in an outbox until the scheduler delivered them.
+//! Every request has an `X-Signature: sha256=<hex>` header, the HMAC of the
+//! body with the secret shown by `/webhook`, so the receiver can check it came
+//! from the bot. Failed deliveries are retried with a growing delay.
+//!
+//! For IFTTT or n8n, `/webhook simple` switches to the three values their
+//! webhook triggers take, `{"value1": "item_added", "value2": "Milch", "value3": ""}`,
+//! with the ingredients of a recipe as the third. `/webhook test` sends an
+//! event right away to try the setup.
+
+use std::collections::HashMap;
+use std::fmt::Write;
+use std::time::Duration;
+
+use chrono::{DateTime, Utc};
+use hmac::{Hmac, Mac};
+use reqwest::redirect::Policy;
+use serde::{Deserialize, Serialize};
+use sha2::Sha256;
+
+use crate::domain::Recipe;
+use crate::unfurl;
+
+const TIMEOUT: Duration = Duration::from_secs(10);
+/// Deliveries given up after this many failed attempts, about an hour in.
+pub const MAX_ATTEMPTS: u32 = 7;
+pub const SIGNATURE_HEADER: &str = "X-Signature";
+
+#[derive(Serialize, Deserialize, Clone)]
+pub struct Webhook {
+    pub url: String,
+    pub secret: String,
+    #[serde(default)]
+    pub format: Format,
+}
+
+#[derive(Serialize, Deserialize, Clone, Copy, PartialEq, Default)]
+#[serde(rename_all = "lowercase")]
+pub enum Format {
+    /// The event with its fields.
+    #[default]
+    Raw,
+    /// `value1` to `value3`.
+    Simple,
+}
+
+impl Format {
+    pub fn describe(&self) -> &'static str {
+        match self {
+            Format::Raw => "JSON mit allen Feldern",
+            Format::Simple => "value1 bis value3 (IFTTT, n8n)",
+        }
+    }
+}
+
+#[derive(Serialize, Clone, Debug)]
+#[serde(tag = "event", rename_all = "snake_case")]
+pub enum Event {
+    ItemAdded { item: String },
+    ItemChecked { item: String },
+    ItemUnchecked { item: String },
+    ItemRemoved { item: String },
+    RecipeSaved { recipe: String, ingredients: Vec<String> },
+    /// Sent by `/webhook test`.
+    Test { item: String },
+}
+
+impl Event {
+    pub fn name(&self) -> &'static str {
+        match self {
+            Event::ItemAdded { .. } => "item_added",
+            Event::ItemChecked { .. } => "item_checked",
+            Event::ItemUnchecked { .. } => "item_unchecked",
+            Event::ItemRemoved { .. } => "item_removed",
+            Event::RecipeSaved { .. } => "recipe_saved",
+            Event::Test { .. } => "test",
+        }
+    }
+
+    /// The item, or the recipe.
+    pub fn subject(&self) -> &str {
+        match self {
+            Event::ItemAdded { item }
+            | Event::ItemChecked { item }
+            | Event::ItemUnchecked { item }
+            | Event::ItemRemoved { item }
+            | Event::Test { item } => item,
+            Event::RecipeSaved { recipe, .. } => recipe,
+        }
+    }
+
+    fn simple(&self) -> Simple {
+        let details = match self {
+            Event::RecipeSaved { ingredients, .. } => ingredients.join(", "),
+            _ => String::new(),
+        };
+        Simple { value1: self.name(), value2: self.subject().to_string(), value3: details }
+    }
+}
+
+#[derive(Serialize)]
+struct Payload<'a> {
+    #[serde(flatten)]
+    event: &'a Event,
+    at: DateTime<Utc>,
+}
+
+#[derive(Serialize)]
+struct Simple {
+    value1: &'static str,
+    value2: String,
+    value3: String,
+}
+
+/// The event with its time, as sent to raw webhooks and WebSocket clients, see `push`.
+pub fn to_json(event: &Event, at: DateTime<Utc>) -> serde_json::Result<String> {
+    serde_json::to_string(&Payload { event, at })
+}
+
+/// List and recipes as of the last events, see `Data::queue_webhook_events`.
+#[derive(Clone, Default)]
+pub struct Snapshot {
+    /// Whether each item is checked, by lowercase name.
+    pub items: HashMap<String, (String, bool)>,
+    pub recipes: HashMap<String, Recipe>,
+}
+
+impl Snapshot {
+    /// The events that turn `self` into `newer`.
+    pub fn events(&self, newer: &Snapshot) -> Vec<Event> {
+        let mut events = Vec::new();
+        for (key, (name, done)) in newer.items.iter() {
+            let item = name.clone();
+            match self.items.get(key) {
+                None => events.push(Event::ItemAdded { item }),
+                Some((_, was_done)) if *was_done && !done => events.push(Event::ItemUnchecked { item }),
+                Some((_, was_done)) if !was_done && *done => events.push(Event::ItemChecked { item }),
+                Some(_) => {}
+            }
+        }
+        for (key, (name, _)) in self.items.iter() {
+            if !newer.items.contains_key(key) {
+                events.push(Event::ItemRemoved { item: name.clone() });
+            }
+        }
+        for (name, recipe) in newer.recipes.iter() {
+            if self.recipes.get(name) != Some(recipe) {
+                events.push(Event::RecipeSaved { recipe: name.clone(), ingredients: recipe.ingredients.clone() });
+            }
+        }
+        events
+    }
+}
+
+/// An event waiting to be delivered.
+#[derive(Serialize, Deserialize, Clone)]
+pub struct Delivery {
+    pub url: String,
+    pub secret: String,
+    pub body: String,
+    #[serde(default)]
+    pub attempts: u32,
+    pub next_try: DateTime<Utc>,
+}
+
+impl Delivery {
+    pub fn new(webhook: &Webhook, event: &Event, now: DateTime<Utc>) -> anyhow::Result<Self> {
+        Ok(Delivery {
+            url: webhook.url.clone(),
+            secret: webhook.secret.clone(),
+            body: match webhook.format {
+                Format::Raw => to_json(event, now)?,
+                Format::Simple => serde_json::to_string(&event.simple())?,
+            },
+            attempts: 0,
+            next_try: now,
+        })
+    }
+
+    /// Schedules the next attempt after a failed one, `false` if it is given up.
+    pub fn retry_later(&mut self, now: DateTime<Utc>) -> bool {
+        self.attempts += 1;
+        // 1, 2, 4, … minutes
+        self.next_try = now + chrono::Duration::minutes(1 << (self.attempts - 1).min(10));
+        self.attempts < MAX_ATTEMPTS
+    }
+
+    /// POSTs the event if the target is still a public address. Redirects
+    /// aren't followed, they could lead anywhere.
+    pub async fn send(&self) -> anyhow::Result<()> {
+        let url = reqwest::Url::parse(&self.url)?;
+        let addresses = unfurl::public_addresses(&url).await?;
+        let host = url.host_str().ok_or_else(|| anyhow::anyhow!("No host"))?;
+        // The addresses that were checked are used, the host isn't looked up again
+        let client = reqwest::Client::builder()
+            .redirect(Policy::none())
+            .no_proxy()
+            .resolve_to_addrs(host, &addresses)
+            .timeout(TIMEOUT)
+            .build()?;
+        client.post(url)
+            .header(reqwest::header::CONTENT_TYPE, "application/json")
+            .header(SIGNATURE_HEADER, format!("sha256={}", signature(&self.secret, &self.body)))
+            .body(self.body.clone())
+            .send().await?
+            .error_for_status()?;
+        Ok(())
+    }
+}
+
+/// Hex encoded HMAC-SHA256 of the body.
+fn signature(secret: &str, body: &str) -> String {
+    let mut mac = Hmac::<Sha256>::new_from_slice(secret.as_bytes()).expect("HMAC takes keys of any length");
+    mac.update(body.as_bytes());
+    mac.finalize().into_bytes().iter().fold(String::new(), |mut hex, byte| {
+        write!(hex, "{:02x}", byte).unwrap();
+        hex
+    })
+}
+
+/// Only plain web addresses of public hosts are accepted as targets, not the
+/// bot's own machine or network.
+pub async fn check_url(url: &str) -> anyhow::Result<()> {
+    let parsed = reqwest::Url::parse(url).map_err(|_| anyhow::anyhow!("{} ist keine Webadresse", url))?;
+    match tokio::time::timeout(TIMEOUT, unfurl::public_addresses(&parsed)).await {
+        Ok(Ok(_)) => Ok(()),
+        _ => anyhow::bail!("{} ist keine öffentliche Webadresse", url),
+    }
+}
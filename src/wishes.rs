@@ -0,0 +1,83 @@
+//! Wish lists, switched on with `/wishes on`: the items are gifts with a link
+//! and a priority instead of groceries, and nothing is checked off and cleared.
+//! Birthdays set with `/birthday` are announced a week ahead together with the
+//! most wanted wishes, see `scheduler`.
+
+use chrono::{Datelike, NaiveDate};
+use serde::{Deserialize, Serialize};
+
+pub const MAX_PRIORITY: u8 = 3;
+/// Days before a birthday it is announced.
+pub const REMIND_DAYS: i64 = 7;
+/// Wishes suggested with a birthday.
+const SUGGESTED: usize = 3;
+
+/// Splits a new wish into its name and the priority, one star per `!`:
+/// "Lego Zug !!" is the Lego Zug with two stars. Links are taken out by `unfurl`.
+pub fn parse(text: &str) -> (String, u8) {
+    let mut priority = 0;
+    let mut words = Vec::new();
+    for word in text.split_whitespace() {
+        if word.chars().all(|c| c == '!') {
+            priority += word.len();
+        } else {
+            words.push(word);
+        }
+    }
+    (words.join(" "), priority.min(MAX_PRIORITY as usize) as u8)
+}
+
+pub fn stars(priority: u8) -> String {
+    "⭐".repeat(priority as usize)
+}
+
+/// The most wanted wishes first, up to a few for a birthday reminder.
+pub fn suggestions<'a>(wishes: impl Iterator<Item = (&'a str, u8)>) -> Vec<&'a str> {
+    let mut wishes: Vec<(&str, u8)> = wishes.collect();
+    // Stable, so wishes of the same priority stay in list order
+    wishes.sort_by_key(|(_, priority)| std::cmp::Reverse(*priority));
+    wishes.into_iter().take(SUGGESTED).map(|(name, _)| name).collect()
+}
+
+#[derive(Serialize, Deserialize, Clone)]
+pub struct Birthday {
+    pub name: String,
+    pub day: u32,
+    pub month: u32,
+    /// Year of the birthday that was last announced.
+    #[serde(default)]
+    pub reminded: Option<i32>,
+}
+
+impl Birthday {
+    /// `/birthday` arguments like `Anna 12.3.`, the name may have several words.
+    pub fn parse(args: &[&str]) -> Option<Self> {
+        let (date, name) = args.split_last()?;
+        let mut parts = date.trim_end_matches('.').split('.');
+        let day: u32 = parts.next()?.parse().ok()?;
+        let month: u32 = parts.next()?.parse().ok()?;
+        // Any leap year, so the 29th of February is accepted
+        NaiveDate::from_ymd_opt(2000, month, day)?;
+        if parts.next().is_some() || name.is_empty() {
+            return None;
+        }
+        Some(Birthday { name: name.join(" "), day, month, reminded: None })
+    }
+
+    /// The next birthday from `today` on, the 29th of February is the 1st of March in other years.
+    pub fn next(&self, today: NaiveDate) -> NaiveDate {
+        let on = |year: i32| NaiveDate::from_ymd_opt(year, self.month, self.day)
+            .or_else(|| NaiveDate::from_ymd_opt(year, 3, 1))
+            .unwrap();
+        let this_year = on(today.year());
+        if this_year >= today {
+            this_year
+        } else {
+            on(today.year() + 1)
+        }
+    }
+
+    pub fn describe(&self) -> String {
+        format!("{}: {}.{}.", self.name, self.day, self.month)
+    }
+}
@@ -0,0 +1,60 @@
+//! Optional blocklist for lists shared with kids, set up by admins with
+//! `/filter`. Items and recipes containing a blocked word are rejected, or
+//! have the word masked like "S*****" with `/filter mask`. Admins aren't filtered.
+
+use serde::{Deserialize, Serialize};
+
+#[derive(Serialize, Deserialize, Clone, Default)]
+pub struct WordFilter {
+    /// Lowercase, also found inside compound words.
+    pub words: Vec<String>,
+    /// Mask blocked words instead of rejecting the text.
+    pub mask: bool,
+}
+
+impl WordFilter {
+    /// Adds the word, or removes it if it is blocked already.
+    pub fn toggle(&mut self, word: &str) {
+        let word = word.to_lowercase();
+        match self.words.iter().position(|existing| *existing == word) {
+            Some(index) => {
+                self.words.remove(index);
+            }
+            None => self.words.push(word),
+        }
+    }
+
+    pub fn describe(&self) -> String {
+        if self.words.is_empty() {
+            return "Kein Wortfilter. Wörter sperren mit /filter <Wort> …".to_string();
+        }
+        let mode = if self.mask { "werden maskiert" } else { "werden abgelehnt" };
+        format!("Gesperrte Wörter ({}): {}", mode, self.words.join(", "))
+    }
+
+    /// The text as it may be added, with blocked words masked, or an error if it is rejected.
+    pub fn apply(&self, text: &str) -> anyhow::Result<String> {
+        let mut chars: Vec<char> = text.chars().collect();
+        let lowercase: Vec<char> = chars.iter().map(|c| c.to_lowercase().next().unwrap_or(*c)).collect();
+        let mut found = false;
+        for word in &self.words {
+            let word: Vec<char> = word.chars().collect();
+            if word.is_empty() || word.len() > lowercase.len() {
+                continue;
+            }
+            for start in 0..=lowercase.len() - word.len() {
+                if lowercase[start..start + word.len()] == word[..] {
+                    found = true;
+                    // The first letter stays, so it is still clear that something was there
+                    for c in &mut chars[start + 1..start + word.len()] {
+                        *c = '*';
+                    }
+                }
+            }
+        }
+        if found && !self.mask {
+            anyhow::bail!("Das kommt nicht auf die Liste.");
+        }
+        Ok(chars.into_iter().collect())
+    }
+}